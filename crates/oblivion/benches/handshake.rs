@@ -0,0 +1,52 @@
+use anyhow::Result;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::future::try_join_all;
+use oblivion::models::{
+    client::Client,
+    router::Router,
+    server::{Server, ServerConfig},
+};
+use tokio::runtime::Runtime;
+
+/// Connections opened at once by every iteration.
+const BURST: usize = 64;
+
+async fn connect(port: u16) -> Result<()> {
+    let client = Client::connect(&format!("oblivion://127.0.0.1:{port}")).await?;
+    client.recv().await?;
+    client.close().await?;
+    Ok(())
+}
+
+async fn burst(port: u16) -> Result<()> {
+    try_join_all((0..BURST).map(|_| connect(port))).await?;
+    Ok(())
+}
+
+fn criterion_benchmark_handshake(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut group = c.benchmark_group("handshake");
+    group.throughput(Throughput::Elements(BURST as u64));
+    let mut limits = vec![Some(1), Some(cores), Some(cores * 4)];
+    limits.dedup();
+    for concurrency in std::iter::once(None).chain(limits) {
+        let config = match concurrency {
+            Some(max) => ServerConfig::new().handshake_concurrency(max),
+            None => ServerConfig::new(),
+        };
+        let server = Server::new("127.0.0.1", 0, Router::new()).with_config(config);
+        let server = rt.block_on(server.bind()).unwrap();
+        let port = server.local_addr().unwrap().port();
+        let server = rt.spawn(server.serve());
+        let name = concurrency.map_or("unlimited".to_string(), |max| max.to_string());
+        group.bench_function(BenchmarkId::new("burst", name), |b| {
+            b.to_async(&rt).iter(|| burst(port))
+        });
+        server.abort();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark_handshake);
+criterion_main!(benches);
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use oblivion::utils::generator::{generate_key_pair, generate_random_salt, SharedKey};
+use oblivion::utils::generator::{generate_key_pair, generate_random_salt, SharedKey, SALT_LEN};
 use tokio::runtime::Runtime;
 
 use ring::agreement::{EphemeralPrivateKey, PublicKey, UnparsedPublicKey, X25519};
@@ -39,7 +39,7 @@ fn criterion_benchmark_kdf(c: &mut Criterion) {
     c.bench_function("kdf", |b| {
         b.to_async(Runtime::new().unwrap()).iter(|| async {
             let (prk, puk) = generate_key_pair();
-            let salt = generate_random_salt();
+            let salt = generate_random_salt(SALT_LEN);
             hkdf(black_box(prk), black_box(puk), black_box(salt.to_vec())).await
         })
     });
@@ -49,7 +49,7 @@ fn criterion_benchmark_scrypt(c: &mut Criterion) {
     c.bench_function("scrypt", |b| {
         b.to_async(Runtime::new().unwrap()).iter(|| async {
             let (prk, puk) = generate_key_pair();
-            let salt = generate_random_salt();
+            let salt = generate_random_salt(SALT_LEN);
             scrypt(black_box(prk), black_box(puk), black_box(salt)).await
         })
     });
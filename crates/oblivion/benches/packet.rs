@@ -1,8 +1,11 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use oblivion::{models::packet::OED, utils::generator::generate_random_salt};
+use oblivion::{
+    models::packet::OED,
+    utils::generator::{generate_random_salt, SALT_LEN},
+};
 
 fn criterion_benchmark_oed(c: &mut Criterion) {
-    let aes_key = generate_random_salt();
+    let aes_key = generate_random_salt(SALT_LEN);
     let long_data = vec![0u8; 1024 * 1024];
     c.bench_function("oed", |b| {
         b.iter(|| {
@@ -1,8 +1,8 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use oblivion::utils::generator::generate_random_salt;
+use oblivion::utils::generator::{generate_random_salt, SALT_LEN};
 
 fn criterion_benchmark_salt(c: &mut Criterion) {
-    c.bench_function("salt", |b| b.iter(generate_random_salt));
+    c.bench_function("salt", |b| b.iter(|| generate_random_salt(SALT_LEN)));
 }
 
 criterion_group!(benches, criterion_benchmark_salt);
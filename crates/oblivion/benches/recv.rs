@@ -0,0 +1,125 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use criterion::{
+    criterion_group, criterion_main, BenchmarkId, Criterion, SamplingMode, Throughput,
+};
+use oblivion::models::session::Session;
+use oblivion::utils::gear::Socket;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::{Builder, Runtime};
+
+/// Messages received by every iteration, a gigabyte in total.
+const MESSAGES: usize = 16 * 1024;
+/// Bytes of every message.
+const MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Allocator counting the allocations of every thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn count(size: usize) {
+    let _ = ALLOCATIONS.try_with(|allocations| {
+        let (count, bytes) = allocations.get();
+        allocations.set((count + 1, bytes + size as u64));
+    });
+}
+
+fn allocations() -> (u64, u64) {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// Session receiving messages, and a thread of its own endlessly sending them so that only
+/// the allocations made to receive are counted.
+fn session(rt: &Runtime) -> Result<Session> {
+    let listener = rt.block_on(TcpListener::bind("127.0.0.1:0"))?;
+    let address = listener.local_addr()?;
+    thread::spawn(move || {
+        let rt = Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut session = Session::new(Socket::new(stream))?;
+            session.handshake(1).await?;
+            let message = vec![7; MESSAGE_SIZE];
+            loop {
+                session.send(message.clone()).await?;
+            }
+            #[allow(unreachable_code)]
+            anyhow::Ok(())
+        })
+    });
+    rt.block_on(async {
+        let stream = TcpStream::connect(address).await?;
+        let header = "CONNECT / Oblivion/2.0".to_string();
+        let mut session = Session::new_with_header(header, Socket::new(stream))?;
+        session.handshake(0).await?;
+        Ok(session)
+    })
+}
+
+async fn receive(session: &Session) -> Result<()> {
+    for _ in 0..MESSAGES {
+        let response = session.recv().await?;
+        assert_eq!(response.content.len(), MESSAGE_SIZE);
+    }
+    Ok(())
+}
+
+fn criterion_benchmark_recv(c: &mut Criterion) {
+    let rt = Builder::new_current_thread().enable_all().build().unwrap();
+    let session = session(&rt).unwrap();
+    let mut group = c.benchmark_group("recv");
+    group
+        .throughput(Throughput::Bytes((MESSAGES * MESSAGE_SIZE) as u64))
+        .sampling_mode(SamplingMode::Flat)
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(20));
+
+    let (count, bytes) = allocations();
+    let mut messages = 0;
+    group.bench_function(BenchmarkId::new("session", "1 GiB"), |b| {
+        b.iter(|| {
+            messages += MESSAGES;
+            rt.block_on(receive(&session)).unwrap()
+        })
+    });
+    let (count, bytes) = {
+        let (total, total_bytes) = allocations();
+        (total - count, total_bytes - bytes)
+    };
+    println!(
+        "{:.1} allocations and {:.1} KiB allocated per message of {} KiB",
+        count as f64 / messages as f64,
+        bytes as f64 / messages as f64 / 1024.0,
+        MESSAGE_SIZE / 1024
+    );
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark_recv);
+criterion_main!(benches);
@@ -18,7 +18,7 @@ fn criterion_benchmark_router(c: &mut Criterion) {
     }
     c.bench_function("router", |b| {
         b.iter(|| {
-            router.get_handler("/500").unwrap();
+            router.get_handler("GET", "/500").unwrap();
         })
     });
 }
@@ -30,7 +30,7 @@ fn criterion_benchmark_router_less(c: &mut Criterion) {
     }
     c.bench_function("router_less", |b| {
         b.iter(|| {
-            router.get_handler("/5").unwrap();
+            router.get_handler("GET", "/5").unwrap();
         })
     });
 }
@@ -0,0 +1,141 @@
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use oblivion::models::packet::{Packet, StatusCode, OED, OSC};
+use oblivion::models::session::SessionFlag;
+use oblivion::utils::gear::{Peer, Socket};
+use oblivion::utils::generator::{generate_random_salt, SALT_LEN};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+
+/// Bytes of every message, split in chunks of 1 KiB by [`OED`].
+const MESSAGE_SIZE: usize = 4 * 1024;
+
+/// Stream counting the writes that reached the transport.
+struct Counted {
+    stream: TcpStream,
+    writes: Arc<AtomicUsize>,
+}
+
+impl Counted {
+    fn count<T>(&self, poll: Poll<io::Result<T>>) -> Poll<io::Result<T>> {
+        if poll.is_ready() {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl AsyncRead for Counted {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Counted {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write(cx, buf);
+        self.count(poll)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write_vectored(cx, bufs);
+        self.count(poll)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Socket counting its writes, whose peer discards whatever it receives.
+async fn sender(writes: Arc<AtomicUsize>) -> io::Result<Socket> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let stream = TcpStream::connect(listener.local_addr()?).await?;
+    let (mut receiver, _) = listener.accept().await?;
+    tokio::spawn(async move {
+        let mut buffer = vec![0; 64 * 1024];
+        while receiver.read(&mut buffer).await.is_ok_and(|read| read > 0) {}
+    });
+    stream.set_nodelay(true)?;
+    let peer = Peer::Tcp(stream.peer_addr()?);
+    Ok(Socket::from_stream(Counted { stream, writes }, peer))
+}
+
+/// Send a message the way sessions did before writing it at once, a write for every part.
+async fn separate(socket: &Socket, oed: &OED<'_>) -> anyhow::Result<()> {
+    socket.send(&OSC::from_u32(SessionFlag::Data).to_bytes()).await?;
+    socket.send(&oed.plain_data()?).await?;
+    let ciphertext = vec![0; MESSAGE_SIZE];
+    for chunk in ciphertext.chunks(1024) {
+        socket.send(&(chunk.len() as u32).to_be_bytes()).await?;
+        socket.send(chunk).await?;
+    }
+    socket.send(&[0; 4]).await?;
+    socket.send(&OSC::from_u32(StatusCode::OK).to_bytes()).await
+}
+
+async fn vectored(socket: &Socket, oed: &OED<'_>) -> anyhow::Result<()> {
+    let leading = OSC::from_u32(SessionFlag::Data).to_bytes();
+    let trailing = OSC::from_u32(StatusCode::OK).to_bytes();
+    oed.write_between(socket, &leading, &trailing).await
+}
+
+fn criterion_benchmark_send(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let aes_key = generate_random_salt(SALT_LEN);
+    let mut oed = OED::new(&aes_key);
+    oed.from_bytes(vec![0; MESSAGE_SIZE]).unwrap();
+    let mut group = c.benchmark_group("send");
+    group.throughput(Throughput::Bytes(MESSAGE_SIZE as u64));
+
+    for name in ["separate", "vectored"] {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let socket = rt.block_on(sender(Arc::clone(&writes))).unwrap();
+        let mut messages = 0;
+        group.bench_function(BenchmarkId::new("message", name), |b| {
+            b.iter(|| {
+                messages += 1;
+                rt.block_on(async {
+                    match name {
+                        "separate" => separate(&socket, &oed).await.unwrap(),
+                        _ => vectored(&socket, &oed).await.unwrap(),
+                    }
+                })
+            })
+        });
+        println!(
+            "{name}: {:.1} writes per message",
+            writes.load(Ordering::Relaxed) as f64 / messages as f64
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark_send);
+criterion_main!(benches);
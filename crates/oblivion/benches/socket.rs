@@ -3,8 +3,8 @@ use criterion::{criterion_group, criterion_main, Criterion};
 use oblivion::models::{client::Client, router::Router, server::Server};
 use tokio::runtime::Runtime;
 
-async fn connect() -> Result<()> {
-    let client = Client::connect("oblivion://127.0.0.1:7076").await?;
+async fn connect(port: u16) -> Result<()> {
+    let client = Client::connect(&format!("oblivion://127.0.0.1:{port}")).await?;
     client.recv().await?;
     client.close().await?;
     Ok(())
@@ -12,12 +12,14 @@ async fn connect() -> Result<()> {
 
 fn criterion_benchmark_socket(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
-    let server = rt.spawn(async move {
-        Server::new("127.0.0.1", 7076, Router::new()).run().await.unwrap()
-    });
+    let server = rt
+        .block_on(Server::new("127.0.0.1", 0, Router::new()).bind())
+        .unwrap();
+    let port = server.local_addr().unwrap().port();
+    let server = rt.spawn(server.serve());
     c.bench_function("socket", |b| {
         b.to_async(&rt)
-            .iter(|| async { connect().await })
+            .iter(|| async { connect(port).await })
     });
     server.abort();
 }
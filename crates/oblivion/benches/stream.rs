@@ -0,0 +1,142 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use oblivion::utils::gear::{Peer, Socket};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+
+/// Messages sent at once by every iteration.
+const MESSAGES: usize = 256;
+/// Bytes of every message, after its length prefix.
+const MESSAGE_SIZE: usize = 32;
+
+/// Stream counting the reads that reached the transport.
+struct Counted {
+    stream: TcpStream,
+    reads: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for Counted {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.stream).poll_read(cx, buf);
+        if poll.is_ready() {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for Counted {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Receiving side of a connection, and the sending side writing its messages.
+async fn pair() -> Result<(TcpStream, TcpStream)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let sender = TcpStream::connect(listener.local_addr()?).await?;
+    let (receiver, _) = listener.accept().await?;
+    Ok((receiver, sender))
+}
+
+fn burst() -> Vec<u8> {
+    let message = [&(MESSAGE_SIZE as u32).to_be_bytes()[..], &[0; MESSAGE_SIZE]].concat();
+    message.repeat(MESSAGES)
+}
+
+/// Receive every message like the socket did before buffering its reads.
+async fn unbuffered(stream: &mut Counted) -> Result<()> {
+    for _ in 0..MESSAGES {
+        let mut length = [0; 4];
+        stream.read_exact(&mut length).await?;
+        let mut message = vec![0; u32::from_be_bytes(length) as usize];
+        stream.read_exact(&mut message).await?;
+    }
+    Ok(())
+}
+
+async fn buffered(socket: &Socket) -> Result<()> {
+    for _ in 0..MESSAGES {
+        let length = socket.recv_usize().await?;
+        socket.recv(length).await?;
+    }
+    Ok(())
+}
+
+fn criterion_benchmark_stream(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let burst = burst();
+    let mut group = c.benchmark_group("stream");
+    group.throughput(Throughput::Elements(MESSAGES as u64));
+
+    let (receiver, mut sender) = rt.block_on(pair()).unwrap();
+    let reads = Arc::new(AtomicUsize::new(0));
+    let peer = Peer::Tcp(receiver.peer_addr().unwrap());
+    let stream = Counted {
+        stream: receiver,
+        reads: Arc::clone(&reads),
+    };
+    let socket = Socket::from_stream(stream, peer);
+    let mut iterations = 0;
+    group.bench_function(BenchmarkId::new("small", "buffered"), |b| {
+        b.iter(|| {
+            iterations += 1;
+            rt.block_on(async {
+                sender.write_all(&burst).await.unwrap();
+                buffered(&socket).await.unwrap();
+            })
+        })
+    });
+    println!(
+        "buffered: {:.1} reads per {MESSAGES} messages",
+        reads.load(Ordering::Relaxed) as f64 / iterations as f64
+    );
+
+    let (receiver, mut sender) = rt.block_on(pair()).unwrap();
+    let reads = Arc::new(AtomicUsize::new(0));
+    let mut stream = Counted {
+        stream: receiver,
+        reads: Arc::clone(&reads),
+    };
+    let mut iterations = 0;
+    group.bench_function(BenchmarkId::new("small", "unbuffered"), |b| {
+        b.iter(|| {
+            iterations += 1;
+            rt.block_on(async {
+                sender.write_all(&burst).await.unwrap();
+                unbuffered(&mut stream).await.unwrap();
+            })
+        })
+    });
+    println!(
+        "unbuffered: {:.1} reads per {MESSAGES} messages",
+        reads.load(Ordering::Relaxed) as f64 / iterations as f64
+    );
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark_stream);
+criterion_main!(benches);
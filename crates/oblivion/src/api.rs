@@ -0,0 +1,153 @@
+//! One-shot requests with the default client options, each on its own connection.
+//! They go through [`ClientBuilder::send`] like requests built with [`Request`], use a
+//! [`ClientBuilder`] to change the options.
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::models::client::{ClientBuilder, Request, Response};
+
+/// Request `url` with the `GET` method.
+///
+/// ```rust
+/// use oblivion::api;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn echo(mut session: Session) -> ServerResponse {
+/// #     let method = session.request.get_method().to_string();
+/// #     let body = String::from_utf8_lossy(session.request.body()).into_owned();
+/// #     Ok(BaseResponse::TextResponse(format!("{method} {body}")))
+/// # }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/echo" => echo);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let url = format!("olps://127.0.0.1:{port}/echo");
+///
+/// assert_eq!(api::get(&url).await?.text()?, "GET ");
+/// # Ok(())
+/// # }
+/// ```
+pub async fn get(url: &str) -> Result<Response> {
+    ClientBuilder::new().send(Request::get(url).build()).await
+}
+
+/// Send `body` to `url` with the `POST` method.
+///
+/// ```rust
+/// use oblivion::api;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn echo(mut session: Session) -> ServerResponse {
+/// #     let method = session.request.get_method().to_string();
+/// #     let body = String::from_utf8_lossy(session.request.body()).into_owned();
+/// #     Ok(BaseResponse::TextResponse(format!("{method} {body}")))
+/// # }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/echo" => echo);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let url = format!("olps://127.0.0.1:{port}/echo");
+///
+/// let response = api::post(&url, b"hello".to_vec()).await?;
+/// assert_eq!(response.text()?, "POST hello");
+/// # Ok(())
+/// # }
+/// ```
+pub async fn post(url: &str, body: Vec<u8>) -> Result<Response> {
+    ClientBuilder::new()
+        .send(Request::post(url).body(body).build())
+        .await
+}
+
+/// Send `value` serialized as JSON to `url` with the `POST` method.
+///
+/// ```rust
+/// use oblivion::api;
+/// use serde_json::json;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn echo(mut session: Session) -> ServerResponse {
+/// #     let method = session.request.get_method().to_string();
+/// #     let body = String::from_utf8_lossy(session.request.body()).into_owned();
+/// #     Ok(BaseResponse::TextResponse(format!("{method} {body}")))
+/// # }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/echo" => echo);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let url = format!("olps://127.0.0.1:{port}/echo");
+///
+/// let response = api::post_json(&url, json!({"name": "oblivion"})).await?;
+/// assert_eq!(response.text()?, r#"POST {"name":"oblivion"}"#);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn post_json(url: &str, value: Value) -> Result<Response> {
+    ClientBuilder::new()
+        .send(Request::post(url).json(value).build())
+        .await
+}
+
+/// Send `body` to `url` with the `PUT` method.
+///
+/// ```rust
+/// use oblivion::api;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn echo(mut session: Session) -> ServerResponse {
+/// #     let method = session.request.get_method().to_string();
+/// #     let body = String::from_utf8_lossy(session.request.body()).into_owned();
+/// #     Ok(BaseResponse::TextResponse(format!("{method} {body}")))
+/// # }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/echo" => echo);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let url = format!("olps://127.0.0.1:{port}/echo");
+///
+/// let response = api::put(&url, b"hello".to_vec()).await?;
+/// assert_eq!(response.text()?, "PUT hello");
+/// # Ok(())
+/// # }
+/// ```
+pub async fn put(url: &str, body: Vec<u8>) -> Result<Response> {
+    ClientBuilder::new()
+        .send(Request::put(url).body(body).build())
+        .await
+}
@@ -1,11 +1,17 @@
 //! # Oblivion exception
 //! All exceptions to the Oblivion function return `OblivionException`.
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 use ring::error::Unspecified;
 use scrypt::errors::InvalidOutputLen;
 use thiserror::Error;
 
+use crate::utils::generator::KexAlgorithm;
+
 /// ## Oblivion exception iterator
 /// Use an iterator as the type of exception returned by a function.
 #[derive(Error, Debug, Clone, PartialEq)]
@@ -14,10 +20,24 @@ pub enum Exception {
     InvalidHeader(String),
     #[error("Link requests to the server are denied, either due to insufficient privileges or an attack on the server.")]
     ConnectionRefusedError,
+    #[error("There is no route to {address}.")]
+    Unreachable { address: SocketAddr },
     #[error("Wrong Oblivion address: {entrance}")]
     InvalidOblivion { entrance: String },
     #[error("Exceeded expected packet size: {size}")]
     DataTooLarge { size: usize },
+    #[error("Request headers are {size} bytes, at most {limit} bytes are allowed.")]
+    HeadersTooLarge { size: usize, limit: usize },
+    #[error("Peer announced a frame of {declared} bytes, at most {limit} bytes are allowed.")]
+    FrameTooLarge { declared: usize, limit: usize },
+    #[error("Peer announced a payload of {declared} bytes, at most {limit} bytes are allowed.")]
+    PayloadTooLarge { declared: usize, limit: usize },
+    #[error("Length prefix {:?} is malformed, 4 bytes are expected.", hex(.prefix))]
+    BadLengthPrefix { prefix: Vec<u8> },
+    #[error("Route {route} conflicts with a route that is already registered.")]
+    RouteConflict { route: String },
+    #[error("No state of type {type_name} was given to the server.")]
+    MissingState { type_name: String },
     #[error("Method [{method}] is not supported yet.")]
     UnsupportedMethod { method: String },
     #[error("Exception during shared key generation: {error:?}")]
@@ -29,8 +49,192 @@ pub enum Exception {
     EncryptError { error: Unspecified },
     #[error("Exception while decrypting: {error:?}")]
     DecryptError { error: Unspecified },
+    #[error("Peer exchanges keys with {peer}, {local} is expected.")]
+    KexMismatch {
+        local: KexAlgorithm,
+        peer: KexAlgorithm,
+    },
+    #[error("Packet failed authentication, it was tampered with or corrupted.")]
+    Tampered,
+    #[error("Received message {received} instead of {expected}, it was replayed or reordered.")]
+    ReplayDetected { expected: u64, received: u64 },
+    #[error("Every nonce of the session was used, it must be closed.")]
+    NoncesExhausted,
+    #[error("File is corrupted at byte {offset}: {reason}.")]
+    CorruptedFile { offset: u64, reason: String },
+    #[error("Payload can't be compressed or decompressed: {reason}")]
+    CompressionError { reason: String },
+    #[error("Decompressed payload exceeds {limit} bytes.")]
+    DecompressedTooLarge { limit: usize },
     #[error("Trying to read or write a closed connection.")]
     ConnectionClosed,
+    #[error("The peer reset the connection.")]
+    PeerReset,
+    #[error("Stream ended after {frames} frames and {received} bytes, before its final frame.")]
+    TruncatedStream { frames: u64, received: u64 },
+    #[error("Frames of a stream can't be empty, a chunk size of at least 1 byte is required.")]
+    EmptyChunkSize,
+    #[error("Connection closed while reading {reading} after {got} of {expected} bytes.")]
+    UnexpectedEof {
+        reading: String,
+        expected: usize,
+        got: usize,
+    },
+    #[error("Sending was shut down on this side of the connection.")]
+    WriteShutdown,
+    #[error("The peer finished sending, nothing more will be received.")]
+    EndOfStream,
+    #[error("Session has no header, the handshake was not performed or the session was created without one.")]
+    NoHandshake,
+    #[error("Timed out while waiting for the peer during {phase}.")]
+    Timeout { phase: TimeoutPhase },
+    #[error("Payload is not valid UTF-8: {preview:?}")]
+    InvalidUtf8 { preview: String },
+    #[error("Payload is not valid JSON ({error}): {preview:?}")]
+    InvalidJson { error: String, preview: String },
+    #[error("Payload is not a valid {type_name} ({error}): {preview:?}")]
+    InvalidData {
+        type_name: String,
+        error: String,
+        preview: String,
+    },
+    #[error("Response with status {status_code} is invalid: {error}")]
+    InvalidResponse {
+        status_code: u32,
+        error: Box<Exception>,
+    },
+    #[error("Gave up following redirects after {limit} redirects, at {location}.")]
+    TooManyRedirects { limit: usize, location: String },
+    #[error("The request was cancelled.")]
+    Cancelled,
+    #[error("Invalid network: {network}")]
+    InvalidNetwork { network: String },
+    #[error("No address found for {host}.")]
+    UnresolvedHost { host: String },
+    #[error("No address of {host} can be reached from {local}, they are of different families.")]
+    AddressFamilyMismatch { local: IpAddr, host: String },
+    #[error("The server presented {}, which is not pinned.", presented_key(.presented))]
+    KeyPinMismatch { presented: Option<[u8; 32]> },
+    #[error("Salt of {len} bytes is outside of {min} to {max} bytes.")]
+    InvalidSaltLength { len: usize, min: usize, max: usize },
+    #[error("Invalid identity key: {reason}")]
+    InvalidIdentityKey { reason: String },
+    #[error("The peer did not authenticate with an identity key.")]
+    Unauthenticated,
+    #[error("The identity signature of the peer does not match the handshake.")]
+    InvalidSignature,
+    #[error("The peer presented identity {}, which is not trusted.", hex(.fingerprint))]
+    UntrustedIdentity { fingerprint: [u8; 32] },
+    #[error("Proxy negotiation failed: {reason}")]
+    ProxyError { reason: String },
+    #[error("TLS handshake failed: {reason}")]
+    TlsError { reason: String },
+    #[error("The peer does not support {feature}.")]
+    Unsupported { feature: String },
+    #[error("I/O error on the connection: {message}")]
+    IoError { kind: ErrorKind, message: String },
+}
+
+/// Lowercase hexadecimal representation of `bytes`.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Identity key fingerprint of [`Exception::KeyPinMismatch`], for servers that presented one.
+fn presented_key(fingerprint: &Option<[u8; 32]>) -> String {
+    match fingerprint {
+        Some(fingerprint) => format!("identity key {}", hex(fingerprint)),
+        None => "no identity key".to_string(),
+    }
+}
+
+/// Step that ran out of time, see [`Exception::Timeout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeoutPhase {
+    /// Opening the connection to the server.
+    Connect,
+    /// Exchanging keys once connected.
+    Handshake,
+    /// Waiting for a message or for the response to a request.
+    Receive,
+    /// Neither side sent anything for the idle timeout of the session.
+    Idle,
+    /// The peer didn't answer a keepalive probe.
+    Keepalive,
+    /// Reading from the socket, see
+    /// [`Socket::set_read_timeout`](crate::utils::gear::Socket::set_read_timeout).
+    Read,
+    /// Writing to the socket, see
+    /// [`Socket::set_write_timeout`](crate::utils::gear::Socket::set_write_timeout).
+    Write,
+}
+
+impl fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Connect => "connect",
+            Self::Handshake => "handshake",
+            Self::Receive => "receive",
+            Self::Idle => "idle",
+            Self::Keepalive => "keepalive",
+            Self::Read => "read",
+            Self::Write => "write",
+        })
+    }
+}
+
+impl Exception {
+    /// Whether the failure is likely to go away on a fresh connection.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::ConnectionRefusedError
+            | Self::Unreachable { .. }
+            | Self::ConnectionClosed
+            | Self::PeerReset
+            | Self::UnexpectedEof { .. } => true,
+            Self::Timeout { phase } => {
+                matches!(phase, TimeoutPhase::Connect | TimeoutPhase::Handshake)
+            }
+            Self::IoError { kind, .. } => matches!(
+                kind,
+                ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::UnexpectedEof
+                    | ErrorKind::TimedOut
+                    | ErrorKind::Interrupted
+            ),
+            _ => false,
+        }
+    }
+
+    /// Recover the exception behind `error`, other failures become [`Exception::IoError`].
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        if let Some(exception) = error.downcast_ref::<Exception>() {
+            return exception.clone();
+        }
+        let kind = match error.downcast_ref::<std::io::Error>() {
+            Some(error) => error.kind(),
+            None => ErrorKind::Other,
+        };
+        Self::from_io(kind, error.to_string())
+    }
+
+    /// I/O failure of kind `kind`, [`Exception::PeerReset`] if the peer dropped the connection.
+    fn from_io(kind: ErrorKind, message: String) -> Self {
+        match kind {
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe => {
+                Self::PeerReset
+            }
+            _ => Self::IoError { kind, message },
+        }
+    }
+}
+
+impl From<std::io::Error> for Exception {
+    fn from(error: std::io::Error) -> Self {
+        Self::from_io(error.kind(), error.to_string())
+    }
 }
 
 #[cfg(feature = "pyo3")]
@@ -12,6 +12,9 @@ pub extern crate proc_macro;
 /// # Oblivion Exceptions
 pub mod exceptions;
 
+/// # Oblivion API
+pub mod api;
+
 /// # Oblivion Export Types
 pub mod types;
 
@@ -22,11 +25,15 @@ pub mod prelude;
 ///
 /// Oblivion utility classes provide key creation, data encryption and decryption, and request resolution processing methods.
 pub mod utils {
+    pub mod cancel;
+    pub mod compression;
     pub mod decryptor;
     pub mod encryptor;
     pub mod gear;
     pub mod generator;
+    pub mod identity;
     pub mod parser;
+    pub mod throttle;
 }
 
 /// # Oblivion Models
@@ -49,7 +56,7 @@ pub mod models;
 /// #[async_route]
 /// fn welcome(mut session: Session) -> ServerResponse {
 ///     Ok(BaseResponse::TextResponse(
-///        format!("欢迎进入信息绝对安全区, 来自[{}]的朋友", session.get_ip()),
+///        format!("欢迎进入信息绝对安全区, 来自[{}]的朋友", session.get_ip()?),
 ///     ))
 /// }
 ///
@@ -84,7 +91,7 @@ macro_rules! path_route {
 /// #[async_route]
 /// fn welcome(mut session: Session) -> ServerResponse {
 ///     Ok(BaseResponse::TextResponse(
-///        format!("欢迎进入信息绝对安全区, 来自[{}]的朋友", session.get_ip()),
+///        format!("欢迎进入信息绝对安全区, 来自[{}]的朋友", session.get_ip()?),
 ///     ))
 /// }
 ///
@@ -122,7 +129,7 @@ macro_rules! startswith_route {
 /// #[async_route]
 /// fn welcome(mut session: Session) -> ServerResponse {
 ///     Ok(BaseResponse::TextResponse(
-///        format!("欢迎进入信息绝对安全区, 来自[{}]的朋友", session.get_ip()),
+///        format!("欢迎进入信息绝对安全区, 来自[{}]的朋友", session.get_ip()?),
 ///     ))
 /// }
 ///
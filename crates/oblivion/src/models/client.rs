@@ -1,30 +1,64 @@
 //! # Oblivion Client
-use std::sync::Arc;
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use anyhow::{Error, Result};
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use futures::stream::FuturesUnordered;
+use futures::{Stream, StreamExt, TryStreamExt};
 #[cfg(feature = "serde")]
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
     net::TcpStream,
     sync::mpsc::{Receiver, Sender},
+    sync::Mutex,
     task::JoinHandle,
 };
 
-use crate::exceptions::Exception;
 #[cfg(feature = "pyo3")]
 use crate::exceptions::PyOblivionException;
+use crate::exceptions::{Exception, TimeoutPhase};
 
-use crate::utils::gear::Socket;
-use crate::utils::parser::OblivionPath;
+use crate::utils::cancel::CancellationToken;
+use crate::utils::compression::Compression;
+use crate::utils::gear::{dial, Socket, TcpOptions};
+use crate::utils::generator::KexAlgorithm;
+use crate::utils::identity::{self, IdentityKey};
+#[cfg(all(feature = "serde", not(feature = "pyo3")))]
+use crate::utils::parser::parse_into;
+#[cfg(not(feature = "pyo3"))]
+use crate::utils::parser::parse_json;
+use crate::utils::parser::{encode_metadata, preview, OblivionPath, CONTENT_LENGTH};
 
 #[cfg(feature = "pyo3")]
 use pyo3::prelude::*;
 #[cfg(not(feature = "pyo3"))]
-use serde_json::{from_slice, Value};
+use serde_json::Value;
 #[cfg(feature = "pyo3")]
 use serde_json::{json, Value};
 
-use super::session::Session;
+use super::interceptor::Interceptor;
+use super::packet::{MessageFlags, StatusCode};
+use super::proxy::Proxy;
+use super::resolver::{Resolver, SystemResolver};
+use super::session::{
+    read_chunk, Capabilities, Session, SessionBuilder, SessionFlag, PREAMBLE_FEATURE,
+    PROTOCOL_VERSION,
+};
+#[cfg(feature = "tls")]
+use super::tls::{rustls::RootCertStore, TlsConnector};
 
 #[cfg_attr(feature = "pyo3", pyclass)]
 #[derive(Debug, Default)]
@@ -33,53 +67,222 @@ pub struct Response {
     #[cfg_attr(feature = "pyo3", pyo3(get))]
     pub header: Option<String>,
     #[cfg_attr(feature = "pyo3", pyo3(get))]
-    pub content: Vec<u8>,
+    /// Content of the message, sharing the buffer it was received in, see
+    /// [`Response::into_vec`].
+    pub content: Bytes,
     #[cfg_attr(feature = "pyo3", pyo3(get))]
     pub entrance: Option<String>,
     #[cfg_attr(feature = "pyo3", pyo3(get))]
-    pub flag: u32,
+    pub status_code: u32,
+    pub flag: SessionFlag,
+    /// Flags the peer sent along with the status code, see [`Session::send_with_flags`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flags: MessageFlags,
+    /// Attempts the client made to get this response, `0` for messages that don't answer a request.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub attempts: u32,
+    /// URLs the request was sent to while following redirects, ending with the one that sent
+    /// this response, see [`ClientBuilder::follow_redirects`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub visited: Vec<String>,
+    /// Content decoded by [`Response::text`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    text: OnceLock<Result<String, Exception>>,
 }
 
 #[cfg(not(feature = "pyo3"))]
 impl Response {
     pub fn new(
         header: Option<String>,
-        content: Vec<u8>,
+        content: impl Into<Bytes>,
         entrance: Option<String>,
-        flag: u32,
+        status_code: u32,
+        flag: SessionFlag,
     ) -> Self {
         Self {
             header,
-            content,
+            content: content.into(),
             entrance,
+            status_code,
             flag,
+            flags: MessageFlags::empty(),
+            attempts: 0,
+            visited: Vec::new(),
+            text: OnceLock::new(),
         }
     }
 
-    pub fn text(&self) -> Result<String> {
-        Ok(String::from_utf8(self.content.to_vec())?)
+    /// Content as a vector, copied only if its buffer is still shared with other messages.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.content.into()
+    }
+
+    /// Typed form of [`Response::status_code`].
+    pub fn status(&self) -> StatusCode {
+        StatusCode::new(self.status_code)
+    }
+
+    /// Status codes below `400`, as for Python's `Response.ok`.
+    pub fn is_success(&self) -> bool {
+        self.status().is_success()
+    }
+
+    /// Status codes from `300` to `399`, the content being the location to go to instead.
+    pub fn is_redirect(&self) -> bool {
+        self.status().is_redirect()
+    }
+
+    /// Status codes from `400` to `499`.
+    pub fn is_client_error(&self) -> bool {
+        self.status().is_client_error()
+    }
+
+    /// Status codes from `500` on.
+    pub fn is_server_error(&self) -> bool {
+        self.status().is_server_error()
+    }
+
+    /// Turn an unsuccessful response into a [`StatusError`], keeping the response in it.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::{Response, StatusError};
+    /// # use oblivion::models::session::SessionFlag;
+    /// let response = Response::new(None, b"missing".to_vec(), None, 404, SessionFlag::Data);
+    /// assert!(response.is_client_error());
+    ///
+    /// let error = response.error_for_status().unwrap_err();
+    /// assert_eq!(error.to_string(), "Server answered with status 404: \"missing\"");
+    /// let error = error.downcast::<StatusError>()?;
+    /// assert_eq!(error.status_code(), 404);
+    /// assert_eq!(error.into_response().text()?, "missing");
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn error_for_status(self) -> Result<Self> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        Err(StatusError { response: self }.into())
+    }
+
+    /// Whether the peer asked to close the connection after this response.
+    pub fn is_final(&self) -> bool {
+        self.flag == SessionFlag::CloseAfter
+    }
+
+    /// Content as UTF-8 text, validated on the first call only.
+    ///
+    /// Failures are reported as [`Exception::InvalidResponse`] with the status code of the
+    /// response, since an unexpected payload is often an error sent by the server.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::Response;
+    /// # use oblivion::models::session::SessionFlag;
+    /// let response = Response::new(None, b"\xff".to_vec(), None, 500, SessionFlag::Data);
+    /// let error = response.text().unwrap_err();
+    /// assert!(matches!(
+    ///     error.downcast_ref(),
+    ///     Some(Exception::InvalidResponse { status_code: 500, error })
+    ///         if matches!(**error, Exception::InvalidUtf8 { .. })
+    /// ));
+    /// ```
+    pub fn text(&self) -> Result<&str> {
+        let text = self.text.get_or_init(|| {
+            String::from_utf8(self.content.to_vec()).map_err(|_| {
+                self.invalid(Exception::InvalidUtf8 {
+                    preview: preview(&self.content),
+                })
+            })
+        });
+        match text {
+            Ok(text) => Ok(text),
+            Err(error) => Err(error.clone().into()),
+        }
     }
 
     pub fn json(&self) -> Result<Value> {
-        Ok(from_slice(&self.content)?)
+        parse_json(&self.content).map_err(|error| self.invalid(error).into())
+    }
+
+    /// Deserialize the content as JSON into `T`.
+    ///
+    /// Failures are reported like [`Response::text`], wrapping [`Exception::InvalidUtf8`]
+    /// or [`Exception::InvalidData`].
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Response;
+    /// # use oblivion::models::session::SessionFlag;
+    /// let response = Response::new(None, b"[1, 2]".to_vec(), None, 200, SessionFlag::Data);
+    /// assert_eq!(response.json_into::<Vec<u32>>()?, [1, 2]);
+    /// assert!(response.json_into::<String>().is_err());
+    /// # anyhow::Ok(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn json_into<T: DeserializeOwned>(&self) -> Result<T> {
+        let text = self.text()?;
+        parse_into(text.as_bytes()).map_err(|error| self.invalid(error).into())
     }
+
+    fn invalid(&self, error: Exception) -> Exception {
+        Exception::InvalidResponse {
+            status_code: self.status_code,
+            error: Box::new(error),
+        }
+    }
+}
+
+/// Unsuccessful [`Response`], see [`Response::error_for_status`].
+#[derive(Debug)]
+pub struct StatusError {
+    response: Response,
 }
 
+impl StatusError {
+    pub fn status_code(&self) -> u32 {
+        self.response.status_code
+    }
+
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+
+    pub fn into_response(self) -> Response {
+        self.response
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Server answered with status {}: {:?}",
+            self.response.status_code,
+            preview(&self.response.content)
+        )
+    }
+}
+
+impl std::error::Error for StatusError {}
+
 impl PartialEq for Response {
     fn eq(&self, other: &Self) -> bool {
-        if self.entrance.is_none() {
-            self.header == other.header
-                && self.content == other.content
-                && self.entrance == other.entrance
-                && self.flag == other.flag
-        } else if other.entrance.is_none() {
-            false
-        } else {
-            self.header == other.header
-                && self.content == other.content
-                && self.entrance.as_ref().unwrap().trim_end_matches("/")
-                    == other.entrance.as_ref().unwrap().trim_end_matches("/")
-                && self.flag == other.flag
+        match (&self.entrance, &other.entrance) {
+            (None, None) => {
+                self.header == other.header
+                    && self.content == other.content
+                    && self.status_code == other.status_code
+                    && self.flag == other.flag
+                    && self.flags == other.flags
+            }
+            (Some(entrance), Some(other_entrance)) => {
+                self.header == other.header
+                    && self.content == other.content
+                    && entrance.trim_end_matches("/") == other_entrance.trim_end_matches("/")
+                    && self.status_code == other.status_code
+                    && self.flag == other.flag
+                    && self.flags == other.flags
+            }
+            _ => false,
         }
     }
 }
@@ -112,49 +315,1495 @@ impl Response {
     }
 }
 
+/// Time the server has to answer the protocol preamble before it is taken for a server that
+/// predates version negotiation, see [`SessionBuilder::preamble_timeout`].
+const PREAMBLE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Whether `error` reports a server that didn't answer the preamble.
+fn unanswered_preamble(error: &Error) -> bool {
+    matches!(
+        error.downcast_ref(),
+        Some(Exception::Unsupported { feature }) if feature == PREAMBLE_FEATURE
+    )
+}
+
+/// Options of a [`Client`], no timeout is applied unless configured.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use oblivion::exceptions::{Exception, TimeoutPhase};
+/// # use oblivion::models::client::ClientBuilder;
+/// # use tokio::net::TcpListener;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// // A server that accepts connections but never answers the handshake.
+/// let listener = TcpListener::bind("127.0.0.1:0").await?;
+/// let address = listener.local_addr()?;
+/// # let _server = tokio::spawn(async move {
+/// #     let (stream, _) = listener.accept().await?;
+/// #     tokio::time::sleep(Duration::from_secs(10)).await;
+/// #     drop(stream);
+/// #     anyhow::Ok(())
+/// # });
+///
+/// let error = ClientBuilder::new()
+///     .connect_timeout(Duration::from_millis(100))
+///     .connect(&format!("olps://{address}/"))
+///     .await
+///     .err()
+///     .unwrap();
+/// let timeout = Exception::Timeout { phase: TimeoutPhase::Handshake };
+/// assert_eq!(error.downcast_ref(), Some(&timeout));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    retry: Option<RetryPolicy>,
+    proxy: Option<Proxy>,
+    resolver: Option<SharedResolver>,
+    local_address: Option<IpAddr>,
+    address_family: AddressFamily,
+    pinned_keys: Vec<[u8; 32]>,
+    interceptors: Vec<SharedInterceptor>,
+    max_redirects: usize,
+    max_frame_size: Option<usize>,
+    max_payload_size: Option<usize>,
+    compression: Compression,
+    kex_algorithm: KexAlgorithm,
+    identity: Option<IdentityKey>,
+    trusted_identities: Vec<[u8; 32]>,
+    tcp: TcpOptions,
+    #[cfg(unix)]
+    unix_socket: Option<PathBuf>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConnector>,
+}
+
+/// Resolver set with [`ClientBuilder::resolver`].
+#[derive(Clone)]
+struct SharedResolver(Arc<dyn Resolver>);
+
+impl fmt::Debug for SharedResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Resolver")
+    }
+}
+
+/// Interceptor registered with [`ClientBuilder::interceptor`].
+#[derive(Clone)]
+struct SharedInterceptor(Arc<dyn Interceptor>);
+
+impl fmt::Debug for SharedInterceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Interceptor")
+    }
+}
+
+/// Delay before racing the next address of a host against the pending attempts.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Family of the addresses a client connects to, see [`ClientBuilder::address_family`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Race the addresses of both families, alternating between them.
+    #[default]
+    Any,
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    fn allows(self, ip: IpAddr) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Ipv4 => ip.is_ipv4(),
+            Self::Ipv6 => ip.is_ipv6(),
+        }
+    }
+}
+
+/// Order `addresses` alternating between families, starting with the family of the first one.
+fn interleave(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addresses.first() else {
+        return addresses;
+    };
+    let first_v4 = first.is_ipv4();
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv4() == first_v4);
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    loop {
+        match (preferred.pop(), other.pop()) {
+            (None, None) => return ordered,
+            (first, second) => ordered.extend(first.into_iter().chain(second)),
+        }
+    }
+}
+
+/// Retry policy of a [`ClientBuilder`], each attempt runs on a new connection.
+///
+/// Failures to connect or to perform the handshake are retried when the predicate accepts
+/// them, by default when the [`Exception`] behind them [`is_transient`](Exception::is_transient).
+/// Once a request body was sent, the request is only retried if it is idempotent, see
+/// [`RequestBuilder::idempotent`]. Errors of requests that gave up carry the number of
+/// attempts as [`Attempts`].
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use oblivion::models::client::{Attempts, ClientBuilder, Request, RetryPolicy};
+/// # use oblivion::models::session::Session;
+/// # use oblivion::utils::gear::Socket;
+/// # use tokio::net::TcpListener;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// // A server that drops the first two connections before answering the third one.
+/// let listener = TcpListener::bind("127.0.0.1:0").await?;
+/// let address = listener.local_addr()?;
+/// # let server = tokio::spawn(async move {
+/// #     for _ in 0..2 {
+/// #         drop(listener.accept().await?);
+/// #     }
+/// #     let (stream, _) = listener.accept().await?;
+/// #     let mut session = Session::new(Socket::new(stream))?;
+/// #     session.handshake(1).await?;
+/// #     session.send_and_close(b"ok".to_vec(), 200).await?;
+/// #     drop(listener.accept().await?);
+/// #     anyhow::Ok(())
+/// # });
+///
+/// let client = ClientBuilder::new()
+///     .retry(RetryPolicy::new(3).base_delay(Duration::from_millis(10)));
+/// let request = Request::get(&format!("olps://{address}/")).build();
+///
+/// let response = client.send(request.clone()).await?;
+/// assert_eq!(response.text()?, "ok");
+/// assert_eq!(response.attempts, 3);
+///
+/// let error = ClientBuilder::new()
+///     .retry(RetryPolicy::new(1))
+///     .send(request)
+///     .await
+///     .unwrap_err();
+/// assert_eq!(error.downcast_ref(), Some(&Attempts(1)));
+/// # server.await??;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retryable: Arc<dyn Fn(&Error) -> bool + Send + Sync>,
+}
+
+impl RetryPolicy {
+    /// Make up to `max_attempts` attempts, waiting 100ms before the first retry and doubling
+    /// the delay after each one.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.0,
+            retryable: Arc::new(|error| Exception::from_error(error).is_transient()),
+        }
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Longest delay between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Shorten each delay by a random fraction of it, at most `jitter` between `0` and `1`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Only retry errors accepted by `retryable`.
+    pub fn retry_if(mut self, retryable: impl Fn(&Error) -> bool + Send + Sync + 'static) -> Self {
+        self.retryable = Arc::new(retryable);
+        self
+    }
+
+    /// Delay before the attempt following attempt number `attempt`.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        delay.mul_f64(1.0 - self.jitter * rand::random::<f64>())
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Number of attempts made by a request that failed, attached to its error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attempts(pub u32);
+
+impl fmt::Display for Attempts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Request failed after {} attempts", self.0)
+    }
+}
+
+/// Failure of one attempt, telling whether the request may have been processed already.
+pub(crate) struct Failure {
+    error: Error,
+    pub(crate) sent: bool,
+}
+
+impl Failure {
+    pub(crate) fn unsent(error: Error) -> Self {
+        Self { error, sent: false }
+    }
+
+    pub(crate) fn sent(error: Error) -> Self {
+        Self { error, sent: true }
+    }
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bound opening the connection and the handshake, each failing with a timeout of
+    /// phase [`TimeoutPhase::Connect`] or [`TimeoutPhase::Handshake`].
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Bound waiting for the response to a request, failing with a timeout of phase
+    /// [`TimeoutPhase::Receive`].
+    ///
+    /// The connection is closed on timeout, the next request opens a new one.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Retry failed requests according to `policy`, failures are returned right away by default.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Send from `address`, choosing the interface of outgoing connections.
+    ///
+    /// Only addresses of the same family as `address` are connected to, a host without any
+    /// fails with [`Exception::AddressFamilyMismatch`]. Proxies and Unix domain sockets are
+    /// connected to from any address.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::{ClientBuilder, Request};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use tokio::net::TcpListener;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// # let address = listener.local_addr()?;
+    /// # let server = tokio::spawn(async move {
+    /// #     let (stream, peer) = listener.accept().await?;
+    /// #     let mut session = Session::new(Socket::new(stream))?;
+    /// #     session.handshake(1).await?;
+    /// #     session.send_and_close(peer.ip().to_string().into_bytes(), 200).await?;
+    /// #     anyhow::Ok(())
+    /// # });
+    /// let request = Request::get(&format!("olps://{address}/")).build();
+    ///
+    /// let client = ClientBuilder::new().local_address("127.0.0.1".parse()?);
+    /// assert_eq!(client.send(request.clone()).await?.text()?, "127.0.0.1");
+    ///
+    /// let client = ClientBuilder::new().local_address("::1".parse()?);
+    /// let error = client.send(request).await.unwrap_err();
+    /// assert!(matches!(
+    ///     error.downcast_ref(),
+    ///     Some(Exception::AddressFamilyMismatch { .. })
+    /// ));
+    /// # server.await??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn local_address(mut self, address: IpAddr) -> Self {
+        self.local_address = Some(address);
+        self
+    }
+
+    /// Only connect to addresses of `family`.
+    ///
+    /// By default all the addresses of a host are raced, starting with the first one resolved
+    /// and alternating between families, each attempt starting once the previous one failed
+    /// or after 250 milliseconds. Hosts without any address of `family` fail with
+    /// [`Exception::UnresolvedHost`].
+    ///
+    /// ```rust
+    /// # use std::time::{Duration, Instant};
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::{AddressFamily, ClientBuilder, Request};
+    /// # use oblivion::models::resolver::StaticResolver;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use tokio::net::TcpListener;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// # let port = listener.local_addr()?.port();
+    /// # let server = tokio::spawn(async move {
+    /// #     let (stream, _) = listener.accept().await?;
+    /// #     let mut session = Session::new(Socket::new(stream))?;
+    /// #     session.handshake(1).await?;
+    /// #     session.send_and_close(b"raced".to_vec(), 200).await?;
+    /// #     anyhow::Ok(())
+    /// # });
+    /// // The first address never answers, the second one is tried while it is pending.
+    /// let (unreachable, address) = ("2001:db8::1".parse()?, "127.0.0.1".parse()?);
+    /// let resolver = StaticResolver::new().with_host("dual.internal", [unreachable, address]);
+    /// let request = Request::get(&format!("olps://dual.internal:{port}/")).build();
+    ///
+    /// let client = ClientBuilder::new()
+    ///     .resolver(resolver.clone())
+    ///     .connect_timeout(Duration::from_secs(5));
+    /// let started = Instant::now();
+    /// assert_eq!(client.send(request.clone()).await?.text()?, "raced");
+    /// assert!(started.elapsed() < Duration::from_secs(2));
+    ///
+    /// let resolver = StaticResolver::new().with_host("v4.internal", [address]);
+    /// let error = ClientBuilder::new()
+    ///     .resolver(resolver)
+    ///     .address_family(AddressFamily::Ipv6)
+    ///     .send(Request::get(&format!("olps://v4.internal:{port}/")).build())
+    ///     .await
+    ///     .unwrap_err();
+    /// assert!(matches!(
+    ///     error.downcast_ref(),
+    ///     Some(Exception::UnresolvedHost { .. })
+    /// ));
+    /// # server.await??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn address_family(mut self, family: AddressFamily) -> Self {
+        self.address_family = family;
+        self
+    }
+
+    /// Only accept servers signing the handshake with the identity key of SHA-256
+    /// `fingerprint`, see [`ServerConfig::identity`](super::server::ServerConfig::identity).
+    ///
+    /// Pin several fingerprints to rotate keys, a server signing with any of them is accepted.
+    /// Servers signing with another key, or not signing at all, are disconnected before the
+    /// metadata and the body of the request are sent, failing with
+    /// [`Exception::KeyPinMismatch`] which carries the fingerprint of the presented key, see
+    /// [`SessionBuilder::pin_key`].
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::{ClientBuilder, Request};
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::utils::identity::IdentityKey;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let key = IdentityKey::from_seed(&[7; 32])?;
+    /// let fingerprint = key.fingerprint();
+    /// let config = ServerConfig::new().identity(key);
+    /// # let server = Server::new("127.0.0.1", 0, Router::new()).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let url = format!("olps://127.0.0.1:{port}/");
+    /// let request = || Request::post(&url).body(b"secret".to_vec());
+    ///
+    /// let client = ClientBuilder::new().pin_server_key(fingerprint);
+    /// assert!(client.send(request().build()).await.is_ok());
+    ///
+    /// let client = ClientBuilder::new()
+    ///     .pin_server_key([1; 32])
+    ///     .pin_server_key([2; 32]);
+    /// let error = client.send(request().build()).await.unwrap_err();
+    /// assert_eq!(
+    ///     error.downcast_ref(),
+    ///     Some(&Exception::KeyPinMismatch {
+    ///         presented: Some(fingerprint)
+    ///     })
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pin_server_key(mut self, fingerprint: [u8; 32]) -> Self {
+        self.pinned_keys.push(fingerprint);
+        self
+    }
+
+    /// Follow up to `max` redirects in [`ClientBuilder::send`], on a new connection each.
+    ///
+    /// A redirect is a response with a status code from `300` to `399` whose content is the
+    /// location to send the request to, as sent by a
+    /// [`RedirectResponse`](super::render::BaseResponse::RedirectResponse). Locations
+    /// starting with `/` are entrances of the same server. The URLs visited are kept in
+    /// [`Response::visited`], and one redirect more than `max` fails with
+    /// [`Exception::TooManyRedirects`], which also ends redirect loops.
+    ///
+    /// A request with a body is only sent again if it is
+    /// [`idempotent`](RequestBuilder::idempotent), otherwise the redirect is returned as the
+    /// response. Once redirected to another protocol, host or port, the request is sent
+    /// without its [`CREDENTIAL_HEADERS`], even if redirected back. Redirects are not followed
+    /// by default.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::{ClientBuilder, Request};
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn moved(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::RedirectResponse("/users".to_string()))
+    /// # }
+    /// # #[async_route]
+    /// # fn users(session: Session) -> ServerResponse {
+    /// #     let body = String::from_utf8_lossy(session.request.body()).into_owned();
+    /// #     Ok(BaseResponse::TextResponse(format!("users {body}")))
+    /// # }
+    /// # #[async_route]
+    /// # fn looping(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::RedirectResponse("/loop".to_string()))
+    /// # }
+    /// # static PORT: std::sync::OnceLock<u16> = std::sync::OnceLock::new();
+    /// # #[async_route]
+    /// # fn away(_session: Session) -> ServerResponse {
+    /// #     let port = PORT.get().unwrap();
+    /// #     Ok(BaseResponse::RedirectResponse(format!("olps://localhost:{port}/token")))
+    /// # }
+    /// # #[async_route]
+    /// # fn token(session: Session) -> ServerResponse {
+    /// #     let token = session.request.get_header("authorization").unwrap_or("none");
+    /// #     Ok(BaseResponse::TextResponse(token.to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/old-users" => moved);
+    /// # path_route!(&mut router, "/users" => users);
+    /// # path_route!(&mut router, "/loop" => looping);
+    /// # path_route!(&mut router, "/away" => away);
+    /// # path_route!(&mut router, "/token" => token);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # PORT.set(port).unwrap();
+    /// # tokio::spawn(server.serve());
+    /// let client = ClientBuilder::new().follow_redirects(3);
+    /// let url = |entrance| format!("olps://127.0.0.1:{port}{entrance}");
+    ///
+    /// let response = client.send(Request::get(&url("/old-users")).build()).await?;
+    /// assert_eq!(response.text()?, "users ");
+    /// assert_eq!(response.visited, [url("/old-users"), url("/users")]);
+    ///
+    /// // Bodies are only sent again when the request is idempotent.
+    /// let create = Request::post(&url("/old-users")).body(b"alice".to_vec());
+    /// let response = client.send(create.clone().build()).await?;
+    /// assert!(response.is_redirect());
+    /// assert_eq!(response.text()?, "/users");
+    /// let response = client.send(create.idempotent(true).build()).await?;
+    /// assert_eq!(response.text()?, "users alice");
+    ///
+    /// // Credentials stay with the origin they were meant for.
+    /// let token = |entrance| Request::get(&url(entrance)).header("Authorization", "secret");
+    /// assert_eq!(client.send(token("/token").build()).await?.text()?, "secret");
+    /// // `/away` redirects to `olps://localhost:{port}/token`.
+    /// assert_eq!(client.send(token("/away").build()).await?.text()?, "none");
+    ///
+    /// let error = client.send(Request::get(&url("/loop")).build()).await.unwrap_err();
+    /// assert!(matches!(
+    ///     error.downcast_ref(),
+    ///     Some(Exception::TooManyRedirects { limit: 3, .. })
+    /// ));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn follow_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    /// Refuse length prefixes over `size` bytes from servers, defaults to
+    /// [`MAX_FRAME_SIZE`](crate::utils::gear::MAX_FRAME_SIZE), see
+    /// [`Socket::set_max_frame_size`].
+    ///
+    /// Large bodies are sent in small frames, only a larger header or key needs it raised.
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = Some(size);
+        self
+    }
+
+    /// Refuse responses whose encrypted payload is over `size` bytes, defaults to
+    /// [`MAX_PAYLOAD_SIZE`](super::session::MAX_PAYLOAD_SIZE), see
+    /// [`Session::set_max_payload`].
+    pub fn max_payload_size(mut self, size: usize) -> Self {
+        self.max_payload_size = Some(size);
+        self
+    }
+
+    /// Compress request bodies for servers that support it, see
+    /// [`SessionBuilder::compression`].
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Exchange keys with `algorithm`, which the server must use as well, see
+    /// [`SessionBuilder::kex_algorithm`].
+    pub fn kex_algorithm(mut self, algorithm: KexAlgorithm) -> Self {
+        self.kex_algorithm = algorithm;
+        self
+    }
+
+    /// Sign handshakes with `key` for servers verifying their clients, see
+    /// [`SessionBuilder::verify_client_identity`].
+    pub fn identity(mut self, key: IdentityKey) -> Self {
+        self.identity = Some(key);
+        self
+    }
+
+    /// Only accept servers signing the handshake with the identity `public_key`, see
+    /// [`SessionBuilder::identity`]. Trust several keys to rotate them.
+    ///
+    /// Servers signing with another key fail with [`Exception::UntrustedIdentity`], see
+    /// [`ClientBuilder::pin_server_key`] to fail with [`Exception::KeyPinMismatch`] instead.
+    pub fn trust_server_identity(self, public_key: [u8; 32]) -> Self {
+        self.trust_server_fingerprint(identity::fingerprint(&public_key))
+    }
+
+    /// Only accept servers signing the handshake with the identity key of SHA-256
+    /// `fingerprint`, see [`IdentityKey::fingerprint`].
+    pub fn trust_server_fingerprint(mut self, fingerprint: [u8; 32]) -> Self {
+        self.trusted_identities.push(fingerprint);
+        self
+    }
+
+    /// Disable Nagle's algorithm on TCP connections if `nodelay`, which is the default, see
+    /// [`Socket::set_nodelay`].
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp.nodelay = nodelay;
+        self
+    }
+
+    /// Probe TCP connections once idle for `time`, defaults to
+    /// [`TCP_KEEPALIVE`](crate::utils::gear::TCP_KEEPALIVE), see [`Socket::set_tcp_keepalive`].
+    pub fn tcp_keepalive(mut self, time: Option<Duration>) -> Self {
+        self.tcp.keepalive = time;
+        self
+    }
+
+    /// Wait up to `linger` for unsent data of closed TCP connections, defaults to `None`, see
+    /// [`Socket::set_linger`].
+    pub fn linger(mut self, linger: Option<Duration>) -> Self {
+        self.tcp.linger = linger;
+        self
+    }
+
+    /// Tunnel connections through a SOCKS5 proxy.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Resolve host names with `resolver` instead of the [`SystemResolver`].
+    ///
+    /// It resolves the address of the proxy too, and the host names of servers reached through
+    /// it unless the proxy resolves them itself, see [`Proxy::connect_with`].
+    pub fn resolver(mut self, resolver: impl Resolver + 'static) -> Self {
+        self.resolver = Some(SharedResolver(Arc::new(resolver)));
+        self
+    }
+
+    /// Run `interceptor` around every request, after the interceptors registered before it.
+    pub fn interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors
+            .push(SharedInterceptor(Arc::new(interceptor)));
+        self
+    }
+
+    /// Connect to the Unix domain socket at `path` instead of the host of the entrance.
+    ///
+    /// The proxy is ignored for such connections.
+    #[cfg(unix)]
+    pub fn unix_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.unix_socket = Some(path.into());
+        self
+    }
+
+    /// Wrap connections in TLS, trusting the certificates of `roots`, see
+    /// [`ServerConfig::tls`](super::server::ServerConfig::tls).
+    ///
+    /// The certificate of the server has to be valid for the host of the entrance. Failures
+    /// of the TLS handshake are reported as [`Exception::TlsError`]. Connections over a
+    /// [Unix domain socket](ClientBuilder::unix_socket) stay in the clear.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, roots: RootCertStore) -> Self {
+        self.tls = Some(TlsConnector::new(roots));
+        self
+    }
+
+    pub async fn connect(self, entrance: &str) -> Result<Client> {
+        let path = OblivionPath::new(entrance)?;
+        let header = format!("CONNECT {} Oblivion/2.0", path.get_entrance());
+        let (session, _) = self
+            .run(false, || async {
+                self.establish(&path, &header, &[])
+                    .await
+                    .map_err(Failure::unsent)
+            })
+            .await?;
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(1024);
+        Ok(Client {
+            entrance: entrance.to_string(),
+            path,
+            session: ArcSwap::from_pointee(session),
+            outstanding: Arc::new(AtomicBool::new(true)),
+            requests: Mutex::new(()),
+            options: self,
+            sender: Arc::new(sender),
+            receiver,
+        })
+    }
+
+    /// Send `request` on its own connection and read the last message answering it.
+    ///
+    /// Redirects are followed on new connections, see [`ClientBuilder::follow_redirects`].
+    pub async fn send(&self, mut request: Request) -> Result<Response> {
+        let mut visited = vec![request.entrance.clone()];
+        loop {
+            let mut response = self.send_once(request.clone()).await?;
+            let resend = request.idempotent || !request.has_body();
+            if self.max_redirects == 0 || !response.is_redirect() || !resend {
+                response.visited = visited;
+                return Ok(response);
+            }
+            let location = redirect_location(&request.entrance, response.text()?)?;
+            if visited.len() > self.max_redirects {
+                return Err(Exception::TooManyRedirects {
+                    limit: self.max_redirects,
+                    location,
+                }
+                .into());
+            }
+            if !same_origin(&request.entrance, &location)? {
+                request.headers.retain(|(key, _)| {
+                    !CREDENTIAL_HEADERS
+                        .iter()
+                        .any(|credential| key.eq_ignore_ascii_case(credential))
+                });
+            }
+            visited.push(location.clone());
+            request.entrance = location;
+        }
+    }
+
+    /// Send `request` once, without following redirects.
+    async fn send_once(&self, mut request: Request) -> Result<Response> {
+        self.before(&mut request).await?;
+        let path = OblivionPath::new(&request.entrance)?;
+        let header = request.header(path.get_entrance()).await?;
+        let current = StdMutex::new(None);
+        let attempts = self.run(request.idempotent, || async {
+            let session = self
+                .establish(&path, &header, &request.headers)
+                .await
+                .map_err(Failure::unsent)?;
+            *current.lock().unwrap() = Some(session.fork());
+            let response = self
+                .deliver(&session, &request)
+                .await
+                .map_err(Failure::sent)?;
+            session.close().await.map_err(Failure::sent)?;
+            Ok(response)
+        });
+        let (mut response, attempts) =
+            cancellable(request.cancellation.as_ref(), &current, attempts).await?;
+        response.attempts = attempts;
+        self.after(&request, &mut response).await?;
+        Ok(response)
+    }
+
+    /// Send `request` on its own connection and receive the body of the response as it arrives.
+    ///
+    /// Unlike [`ClientBuilder::send`], frames are yielded one by one instead of being joined
+    /// into a single response, so large bodies such as files sent by a
+    /// [`FileResponse`](super::render::BaseResponse::FileResponse) use constant memory. Only
+    /// opening the connection is retried, and the request timeout bounds the wait for every
+    /// frame instead of the whole response. Interceptors only run before the request is sent.
+    ///
+    /// ```rust
+    /// # use futures::TryStreamExt;
+    /// # use oblivion::models::client::{ClientBuilder, Request};
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn download(_session: Session) -> ServerResponse {
+    /// #     let path = std::env::temp_dir().join("oblivion-stream-source");
+    /// #     Ok(BaseResponse::FileResponse(path.to_string_lossy().into_owned()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/download" => download);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+    /// tokio::fs::write(std::env::temp_dir().join("oblivion-stream-source"), &data).await?;
+    /// let url = format!("olps://127.0.0.1:{port}/download");
+    /// let client = ClientBuilder::new();
+    ///
+    /// let mut stream = client.stream(Request::get(&url).build()).await?;
+    /// let mut chunks = Vec::new();
+    /// while let Some(chunk) = stream.try_next().await? {
+    ///     chunks.push(chunk);
+    /// }
+    /// assert!(chunks.len() > 1);
+    /// assert_eq!(chunks.concat(), data);
+    /// assert_eq!(stream.status_code(), Some(200));
+    ///
+    /// let target = std::env::temp_dir().join("oblivion-stream-target");
+    /// assert_eq!(client.download_to_file(&url, &target).await?, (200_000, 200));
+    /// assert_eq!(tokio::fs::read(&target).await?, data);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn stream(&self, mut request: Request) -> Result<ResponseStream> {
+        self.before(&mut request).await?;
+        let path = OblivionPath::new(&request.entrance)?;
+        let header = request.header(path.get_entrance()).await?;
+        let current = StdMutex::new(None);
+        let attempts = self.run(request.idempotent, || async {
+            let session = self
+                .establish(&path, &header, &request.headers)
+                .await
+                .map_err(Failure::unsent)?;
+            *current.lock().unwrap() = Some(session.fork());
+            request.send_body(&session).await.map_err(Failure::sent)?;
+            Ok(session)
+        });
+        let token = request.cancellation.clone();
+        let (session, _) = cancellable(token.as_ref(), &current, attempts).await?;
+        Ok(ResponseStream::new(session, self.request_timeout, token))
+    }
+
+    /// Download the body of `entrance` into the file at `path`, see [`ClientBuilder::stream`].
+    ///
+    /// Returns the number of bytes written and the status code of the response. A failed
+    /// download leaves the bytes received so far in the file.
+    pub async fn download_to_file(
+        &self,
+        entrance: &str,
+        path: impl AsRef<Path>,
+    ) -> Result<(u64, u32)> {
+        let request = Request::get(entrance).idempotent(true).build();
+        let mut stream = self.stream(request).await?;
+        let mut file = File::create(path).await?;
+        let mut size = 0;
+        while let Some(chunk) = stream.try_next().await? {
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+        }
+        file.flush().await?;
+        Ok((size, stream.status_code().unwrap_or_default()))
+    }
+
+    /// Run the interceptors on `request` before it is sent.
+    pub(crate) async fn before(&self, request: &mut Request) -> Result<()> {
+        for SharedInterceptor(interceptor) in &self.interceptors {
+            interceptor.before(request).await?;
+        }
+        Ok(())
+    }
+
+    /// Run the interceptors on the `response` answering `request`.
+    pub(crate) async fn after(&self, request: &Request, response: &mut Response) -> Result<()> {
+        for SharedInterceptor(interceptor) in &self.interceptors {
+            interceptor.after(request, response).await?;
+        }
+        Ok(())
+    }
+
+    /// Run `attempt` until it succeeds or the retry policy gives up, counting the attempts.
+    pub(crate) async fn run<T, F, Fut>(&self, idempotent: bool, mut attempt: F) -> Result<(T, u32)>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Failure>>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let failure = match attempt().await {
+                Ok(value) => return Ok((value, attempts)),
+                Err(failure) => failure,
+            };
+            let Some(policy) = &self.retry else {
+                return Err(failure.error);
+            };
+            let retry = attempts < policy.max_attempts
+                && (idempotent || !failure.sent)
+                && (policy.retryable)(&failure.error);
+            if !retry {
+                return Err(failure.error.context(Attempts(attempts)));
+            }
+            tokio::time::sleep(policy.delay(attempts)).await;
+        }
+    }
+
+    /// Open a connection to the server behind `path`, sending `header` and `metadata` during
+    /// the handshake.
+    ///
+    /// Servers that don't answer the preamble within [`PREAMBLE_TIMEOUT`] are talked to in the
+    /// original protocol on a new connection. Such a server offers none of the [`Capabilities`],
+    /// so clients relying on one of them, like [`ClientBuilder::trust_server_identity`], still fail.
+    pub(crate) async fn establish(
+        &self,
+        path: &OblivionPath,
+        header: &str,
+        metadata: &[(String, String)],
+    ) -> Result<Session> {
+        let handshake = self
+            .handshake(path, header, metadata, PROTOCOL_VERSION)
+            .await;
+        match handshake {
+            Err(error) if unanswered_preamble(&error) => {
+                self.handshake(path, header, metadata, 0).await
+            }
+            handshake => handshake,
+        }
+    }
+
+    /// Open a connection to the server behind `path` and perform the handshake in `version`.
+    async fn handshake(
+        &self,
+        path: &OblivionPath,
+        header: &str,
+        metadata: &[(String, String)],
+        version: u32,
+    ) -> Result<Session> {
+        let mut socket = self.open(path).await?;
+        if let Some(size) = self.max_frame_size {
+            socket.set_max_frame_size(size);
+        }
+        let mut builder = SessionBuilder::new()
+            .header(header)
+            .compression(self.compression)
+            .kex_algorithm(self.kex_algorithm)
+            .protocol_version(version)
+            .preamble_timeout(PREAMBLE_TIMEOUT);
+        if let Some(size) = self.max_payload_size {
+            builder = builder.max_payload(size);
+        }
+        let mut builder = self
+            .pinned_keys
+            .iter()
+            .fold(builder, |builder, fingerprint| builder.pin_key(*fingerprint));
+        if let Some(key) = &self.identity {
+            builder = builder.identity(key.clone());
+        }
+        for fingerprint in &self.trusted_identities {
+            builder = builder.trust_fingerprint(*fingerprint);
+        }
+        let handshake = metadata
+            .iter()
+            .fold(builder, |builder, (key, value)| builder.metadata(key, value))
+            .establish(socket, 0);
+        within(self.connect_timeout, TimeoutPhase::Handshake, handshake).await?
+    }
+
+    /// Open the transport to the server behind `path`.
+    async fn open(&self, path: &OblivionPath) -> Result<Socket> {
+        #[cfg(unix)]
+        if let Some(socket) = &self.unix_socket {
+            let connect = UnixStream::connect(socket);
+            return match within(self.connect_timeout, TimeoutPhase::Connect, connect).await? {
+                Ok(stream) => Ok(Socket::from_unix(stream)),
+                Err(error) if error.kind() == std::io::ErrorKind::ConnectionRefused => {
+                    Err(Error::from(Exception::ConnectionRefusedError))
+                }
+                // A missing or inaccessible socket file won't be fixed by retrying.
+                Err(error) => Err(error.into()),
+            };
+        }
+
+        let port = path
+            .get_port()
+            .parse()
+            .map_err(|_| Exception::InvalidOblivion {
+                entrance: path.get_port().to_string(),
+            })?;
+        let tcp = match &self.proxy {
+            Some(proxy) => {
+                let connect = proxy.connect_with(self.host_resolver(), path.get_host(), port);
+                within(self.connect_timeout, TimeoutPhase::Connect, connect).await??
+            }
+            None => {
+                let connect = self.connect_tcp(path.get_host(), port);
+                within(self.connect_timeout, TimeoutPhase::Connect, connect).await??
+            }
+        };
+        tcp.set_ttl(20)?;
+        self.tcp.apply(&tcp)?;
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let connect = tls.connect(path.get_host(), tcp);
+            return within(self.connect_timeout, TimeoutPhase::Handshake, connect).await?;
+        }
+        Socket::from_tcp(tcp)
+    }
+
+    /// Resolver set with [`ClientBuilder::resolver`], the [`SystemResolver`] otherwise.
+    fn host_resolver(&self) -> &dyn Resolver {
+        match &self.resolver {
+            Some(SharedResolver(resolver)) => resolver.as_ref(),
+            None => &SystemResolver,
+        }
+    }
+
+    /// Connect to the first address of `host` accepting the connection.
+    async fn connect_tcp(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let addresses = self.host_resolver().resolve(host, port).await?;
+        let addresses: Vec<_> = addresses
+            .into_iter()
+            .filter(|address| self.address_family.allows(address.ip()))
+            .filter(|address| match self.local_address {
+                Some(local) => address.is_ipv4() == local.is_ipv4(),
+                None => true,
+            })
+            .collect();
+        if addresses.is_empty() {
+            return Err(Error::from(match self.local_address {
+                Some(local) => Exception::AddressFamilyMismatch {
+                    local,
+                    host: host.to_string(),
+                },
+                None => Exception::UnresolvedHost {
+                    host: host.to_string(),
+                },
+            }));
+        }
+
+        // Attempts are started one after the other, each one as soon as the previous one
+        // failed or after `CONNECTION_ATTEMPT_DELAY`, the first to connect wins (RFC 8305).
+        // The last failure is reported if every attempt fails.
+        let mut attempts = FuturesUnordered::new();
+        let mut failure = Exception::ConnectionRefusedError;
+        for address in interleave(addresses) {
+            attempts.push(dial(address, self.local_address));
+            let delay = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY);
+            tokio::select! {
+                Some(result) = attempts.next() => match result {
+                    Ok(tcp) => return Ok(tcp),
+                    Err(error) => failure = error,
+                },
+                _ = delay => {}
+            }
+        }
+        while let Some(result) = attempts.next().await {
+            match result {
+                Ok(tcp) => return Ok(tcp),
+                Err(error) => failure = error,
+            }
+        }
+        Err(Error::from(failure))
+    }
+
+    /// Send another request on an open `session`, see [`Capabilities::REQUESTS`].
+    pub(crate) async fn exchange(
+        &self,
+        session: &Session,
+        header: &str,
+        request: &Request,
+    ) -> Result<Response, Failure> {
+        session
+            .send_with_flag(header.as_bytes().to_vec(), 200, SessionFlag::Request)
+            .await
+            .map_err(Failure::unsent)?;
+        async {
+            session.send_headers(&request.headers).await?;
+            self.deliver(session, request).await
+        }
+        .await
+        .map_err(Failure::sent)
+    }
+
+    /// Send the body of `request` on `session` and read the response to it.
+    ///
+    /// The response is read while the body is sent, servers answer some requests before
+    /// reading their body, such as bodies over their limit, and close the connection on the
+    /// rest of it.
+    async fn deliver(&self, session: &Session, request: &Request) -> Result<Response> {
+        tokio::select! {
+            // A response arriving before a failed send is the answer to the request.
+            biased;
+            response = self.read_response(session) => response,
+            sent = request.send_body(session) => {
+                sent?;
+                self.read_response(session).await
+            }
+        }
+    }
+
+    /// Read the messages answering a request up to the last one.
+    pub(crate) async fn read_response(&self, session: &Session) -> Result<Response> {
+        let response = async {
+            loop {
+                let response = session.recv().await?;
+                if ends_request(&response) {
+                    return Ok(response);
+                }
+            }
+        };
+        let response = match within(self.request_timeout, TimeoutPhase::Receive, response).await {
+            Ok(response) => response,
+            Err(error) => Err(error.into()),
+        };
+        if response.is_err() {
+            // The rest of the response would be mistaken for the next one.
+            session.abort().await?;
+        }
+        response
+    }
+}
+
+/// Body of a response received frame by frame, see [`ClientBuilder::stream`].
+///
+/// Yields the content of every frame up to the last one of the response, whose status code
+/// is then available from [`ResponseStream::status_code`]. Failures, including a connection
+/// closed before the last frame or a cancelled request, end the stream after being yielded
+/// as errors. Messages flagged with [`SessionFlag::Broadcast`] are skipped.
+pub struct ResponseStream {
+    frames: Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>,
+    status_code: Arc<OnceLock<u32>>,
+}
+
+impl ResponseStream {
+    fn new(session: Session, timeout: Option<Duration>, token: Option<CancellationToken>) -> Self {
+        let status_code = Arc::new(OnceLock::new());
+        let state = (session, Arc::clone(&status_code), token, false);
+        let frames = futures::stream::unfold(
+            state,
+            move |(session, status_code, token, done)| async move {
+                if done {
+                    return None;
+                }
+                let frame = async {
+                    let frame = async {
+                        loop {
+                            let frame = session.next_part().await?;
+                            // Sent to every session, not part of the response.
+                            if frame.flag != SessionFlag::Broadcast {
+                                return Ok::<_, Error>(frame);
+                            }
+                        }
+                    };
+                    match within(timeout, TimeoutPhase::Receive, frame).await {
+                        Ok(frame) => frame,
+                        Err(error) => Err(error.into()),
+                    }
+                };
+                let frame = match &token {
+                    Some(cancellation) => tokio::select! {
+                        frame = frame => frame,
+                        _ = cancellation.cancelled() => {
+                            let _ = session.close().await;
+                            let error = Exception::Cancelled.into();
+                            return Some((Err(error), (session, status_code, token, true)));
+                        }
+                    },
+                    None => frame.await,
+                };
+                let chunk = match frame {
+                    Ok(frame) if ends_request(&frame) => {
+                        let _ = status_code.set(frame.status_code);
+                        let _ = session.close().await;
+                        return Some((Ok(frame.content), (session, status_code, token, true)));
+                    }
+                    Ok(frame) => Ok(frame.content),
+                    Err(error) => {
+                        let _ = session.abort().await;
+                        return Some((Err(error), (session, status_code, token, true)));
+                    }
+                };
+                Some((chunk, (session, status_code, token, false)))
+            },
+        );
+        Self {
+            frames: Box::pin(frames),
+            status_code,
+        }
+    }
+
+    /// Status code of the response, known once its last frame was received.
+    pub fn status_code(&self) -> Option<u32> {
+        self.status_code.get().copied()
+    }
+}
+
+impl Stream for ResponseStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.frames.as_mut().poll_next(cx)
+    }
+}
+
+/// Run `future` within `timeout`, failing with a timeout of `phase` once it elapses.
+async fn within<T>(
+    timeout: Option<Duration>,
+    phase: TimeoutPhase,
+    future: impl std::future::Future<Output = T>,
+) -> Result<T, Exception> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| Exception::Timeout { phase }),
+        None => Ok(future.await),
+    }
+}
+
+/// Run `future` unless `token` is cancelled first, failing with [`Exception::Cancelled`].
+///
+/// The session last stored in `current` is closed on cancellation, so the server stops
+/// waiting for the rest of the request.
+pub(crate) async fn cancellable<T>(
+    token: Option<&CancellationToken>,
+    current: &StdMutex<Option<Session>>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(token) = token else {
+        return future.await;
+    };
+    tokio::select! {
+        result = future => result,
+        _ = token.cancelled() => {
+            let session = current.lock().unwrap().take();
+            if let Some(session) = session {
+                let _ = session.close().await;
+            }
+            Err(Exception::Cancelled.into())
+        }
+    }
+}
+
+/// Oblivion Client
+///
+/// A client keeps its connection to the server open and reuses it for every request made
+/// with [`Client::get`], so the handshake is only paid once. A new connection is opened
+/// transparently once the server closes the current one, or if it doesn't support
+/// [`Capabilities::REQUESTS`]. Requests made concurrently on one client are serialized.
+///
+/// Connecting requests the path of the entrance, its response is returned by
+/// [`Client::recv`] or skipped by the next [`Client::get`].
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use oblivion::models::client::Client;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn first(_session: Session) -> ServerResponse {
+/// #     Ok(BaseResponse::TextResponse("first".to_string()))
+/// # }
+/// # #[async_route]
+/// # fn second(_session: Session) -> ServerResponse {
+/// #     Ok(BaseResponse::TextResponse("second".to_string()))
+/// # }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/first" => first);
+/// # path_route!(&mut router, "/second" => second);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let client = Client::connect(&format!("olps://127.0.0.1:{port}")).await?;
+/// let connection = client.session();
+///
+/// assert_eq!(client.get("/first").await?.text()?, "first");
+/// assert_eq!(client.get("/second").await?.text()?, "second");
+/// assert_eq!(client.get("/first").await?.text()?, "first");
+/// // Every request went over the connection opened by `connect`.
+/// assert!(Arc::ptr_eq(&connection, &client.session()));
+/// # client.close().await?;
+/// # Ok(())
+/// # }
+/// ```
 pub struct Client {
     pub entrance: String,
     pub path: OblivionPath,
-    pub session: Arc<Session>,
+    session: ArcSwap<Session>,
+    /// Whether the response to the request made while connecting is still unread.
+    outstanding: Arc<AtomicBool>,
+    requests: Mutex<()>,
+    options: ClientBuilder,
     sender: Arc<Sender<Response>>,
     receiver: Receiver<Response>,
 }
 
 impl Client {
+    /// Connect with the default options, see [`ClientBuilder`].
     pub async fn connect(entrance: &str) -> Result<Self> {
-        let path = OblivionPath::new(entrance)?;
-        let header = format!("CONNECT {} Oblivion/2.0", path.get_entrance());
+        ClientBuilder::new().connect(entrance).await
+    }
 
-        let tcp = match TcpStream::connect(format!("{}:{}", path.get_host(), path.get_port())).await
-        {
-            Ok(tcp) => {
-                tcp.set_ttl(20)?;
-                tcp.set_nodelay(true)?;
-                socket2::SockRef::from(&tcp).set_keepalive(true)?;
-                tcp
-            }
-            Err(_) => return Err(Error::from(Exception::ConnectionRefusedError)),
+    /// Connect to the server listening on the Unix domain socket at `path`, requesting `entrance`.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Client;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use tokio::net::UnixListener;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let path = std::env::temp_dir().join(format!("oblivion-{}.sock", std::process::id()));
+    /// # let _ = std::fs::remove_file(&path);
+    /// let listener = UnixListener::bind(&path)?;
+    /// # let server = tokio::spawn(async move {
+    /// #     let (stream, _) = listener.accept().await?;
+    /// #     let mut session = Session::new(Socket::from_unix(stream))?;
+    /// #     session.handshake(1).await?;
+    /// #     let ip = session.get_ip()?.to_string();
+    /// #     let answer = format!("{} {}", ip, session.request.get_entrance());
+    /// #     session.send_and_close(answer.into_bytes(), 200).await?;
+    /// #     anyhow::Ok(())
+    /// # });
+    ///
+    /// let client = Client::connect_unix(&path, "/local").await?;
+    /// assert_eq!(client.recv().await?.text()?, "127.0.0.1 /local");
+    /// # server.await??;
+    /// # std::fs::remove_file(&path)?;
+    ///
+    /// let error = Client::connect_unix(&path, "/local").await.err().unwrap();
+    /// let error = error.downcast_ref::<std::io::Error>().unwrap();
+    /// assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(unix)]
+    pub async fn connect_unix(path: impl Into<PathBuf>, entrance: &str) -> Result<Self> {
+        ClientBuilder::new()
+            .unix_socket(path)
+            .connect(&format!("olps://localhost{}", entrance))
+            .await
+    }
+
+    /// Session of the current connection, replaced whenever the client reconnects.
+    pub fn session(&self) -> Arc<Session> {
+        self.session.load_full()
+    }
+
+    /// Request `entrance` on the server, returning the last message answering it.
+    ///
+    /// Messages the handler sends before its response are skipped. The request is considered
+    /// idempotent by the retry policy.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Client;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn list(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("list".to_string()))
+    /// # }
+    /// # #[async_route]
+    /// # fn create(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("created".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut router = Router::new();
+    /// router.get("/things", list).post("/new", create);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    ///
+    /// let client = Client::connect(&format!("olps://127.0.0.1:{port}/things")).await?;
+    /// assert_eq!(client.recv().await?.text()?, "list");
+    /// assert_eq!(client.get("/things").await?.text()?, "list");
+    /// assert_eq!(client.get("/new").await?.status_code, 405);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get(&self, entrance: &str) -> Result<Response> {
+        self.fetch(entrance, None).await
+    }
+
+    /// Request `entrance` like [`Client::get`] until `token` is cancelled.
+    ///
+    /// On cancellation the connection is closed, so the answer to the cancelled request
+    /// can't be mistaken for the next one, and the request fails with [`Exception::Cancelled`].
+    /// The next request opens a new connection.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::Client;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion::utils::cancel::CancellationToken;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn slow(_session: Session) -> ServerResponse {
+    /// #     tokio::time::sleep(Duration::from_millis(500)).await;
+    /// #     Ok(BaseResponse::TextResponse("slow".to_string()))
+    /// # }
+    /// # #[async_route]
+    /// # fn fast(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("fast".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/slow" => slow);
+    /// # path_route!(&mut router, "/fast" => fast);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let client = Client::connect(&format!("olps://127.0.0.1:{port}/fast")).await?;
+    /// let token = CancellationToken::new();
+    /// let canceller = token.clone();
+    /// tokio::spawn(async move {
+    ///     tokio::time::sleep(Duration::from_millis(100)).await;
+    ///     canceller.cancel();
+    /// });
+    ///
+    /// let error = client.get_cancellable("/slow", &token).await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::Cancelled));
+    /// assert_eq!(client.get("/fast").await?.text()?, "fast");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_cancellable(
+        &self,
+        entrance: &str,
+        token: &CancellationToken,
+    ) -> Result<Response> {
+        self.fetch(entrance, Some(token)).await
+    }
+
+    async fn fetch(&self, entrance: &str, token: Option<&CancellationToken>) -> Result<Response> {
+        let _request = self.requests.lock().await;
+        let url = format!(
+            "{}://{}:{}{}",
+            self.path.get_protocol(),
+            self.path.get_host(),
+            self.path.get_port(),
+            entrance
+        );
+        let mut request = RequestBuilder::new("CONNECT", &url)
+            .idempotent(true)
+            .build();
+        self.options.before(&mut request).await?;
+        let header = request.header(entrance).await?;
+        let attempts = self.options.run(true, || self.request(&header, &request));
+        let (mut response, attempts) = match token {
+            Some(token) => tokio::select! {
+                result = attempts => result?,
+                _ = token.cancelled() => {
+                    // The request may have been answered partially on the current connection.
+                    let _ = self.session.load_full().close().await;
+                    return Err(Exception::Cancelled.into());
+                }
+            },
+            None => attempts.await?,
         };
+        response.attempts = attempts;
+        self.options.after(&request, &mut response).await?;
+        Ok(response)
+    }
 
-        let mut session = Session::new_with_header(header, Socket::new(tcp))?;
+    /// Send `request` on the current connection, or on a new one if it can't be reused.
+    async fn request(&self, header: &str, request: &Request) -> Result<Response, Failure> {
+        let session = self.session.load_full();
+        if self.outstanding.swap(false, Ordering::SeqCst) {
+            // A failure closes the session, so it won't be reused below.
+            let _ = self.options.read_response(&session).await;
+        }
 
-        session.handshake(0).await?;
+        if !session.closed().await && session.capabilities().contains(Capabilities::REQUESTS) {
+            match self.options.exchange(&session, header, request).await {
+                // Nothing reached the server, so it is safe to send it on a new connection.
+                Err(failure) if !failure.sent => {}
+                result => return result,
+            }
+        }
 
-        let (sender, receiver) = tokio::sync::mpsc::channel(1024);
-        Ok(Self {
-            entrance: entrance.to_string(),
-            path,
-            session: Arc::new(session),
-            sender: Arc::new(sender),
-            receiver,
-        })
+        let session = self
+            .options
+            .establish(&self.path, header, &request.headers)
+            .await
+            .map_err(Failure::unsent)?;
+        let session = Arc::new(session);
+        self.session.store(Arc::clone(&session));
+        self.options
+            .read_response(&session)
+            .await
+            .map_err(Failure::sent)
     }
 
     pub async fn listen(&self) -> JoinHandle<()> {
-        let session = self.session.clone();
+        let session = self.session.load_full();
         let sender = self.sender.clone();
+        let outstanding = Arc::clone(&self.outstanding);
         tokio::spawn(async move {
             let response = session.recv().await.unwrap();
+            if ends_request(&response) {
+                outstanding.store(false, Ordering::SeqCst);
+            }
             sender.send(response).await.unwrap();
         })
     }
@@ -164,18 +1813,344 @@ impl Client {
     }
 
     pub async fn send(&self, data: Vec<u8>) -> Result<()> {
-        self.session.send(data).await
+        self.session.load().send(data).await
     }
 
     pub async fn send_json(&self, json: Value) -> Result<()> {
-        self.session.send_json(json).await
+        self.session.load().send_json(json).await
     }
 
     pub async fn recv(&self) -> Result<Response> {
-        self.session.recv().await
+        let response = self.session.load_full().recv().await?;
+        if ends_request(&response) {
+            self.outstanding.store(false, Ordering::SeqCst);
+        }
+        Ok(response)
     }
 
     pub async fn close(&self) -> Result<()> {
-        self.session.close().await
+        self.session.load().close().await
+    }
+
+    /// SHA-256 fingerprint of the key the server presented, see [`Session::peer_key_fingerprint`].
+    pub fn peer_key_fingerprint(&self) -> Option<[u8; 32]> {
+        self.session.load().peer_key_fingerprint()
+    }
+}
+
+/// Request sent on its own connection, built with [`RequestBuilder`].
+///
+/// Metadata set with [`RequestBuilder::header`] travels in the request header and is read by
+/// the server with [`OblivionRequest::get_header`](crate::utils::parser::OblivionRequest::get_header).
+/// The body is sent as the first message after the handshake and read with
+/// [`OblivionRequest::body`](crate::utils::parser::OblivionRequest::body).
+///
+/// ```rust
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # use serde_json::json;
+/// # #[async_route]
+/// # fn echo(session: Session) -> ServerResponse {
+/// #     let request = &session.request;
+/// #     Ok(BaseResponse::JsonResponse(json!({
+/// #         "trace": request.get_header("x-trace"),
+/// #         "size": request.body().len(),
+/// #     })))
+/// # }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/echo" => echo);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let response = Request::post(&format!("olps://127.0.0.1:{port}/echo"))
+///     .json(json!({ "message": "x".repeat(4096) }))
+///     .header("X-Trace", "id 42")
+///     .send()
+///     .await?;
+/// assert_eq!(response.json()?["trace"], "id 42");
+/// assert!(response.json()?["size"].as_u64().unwrap() > 4096);
+///
+/// let response = Request::post(&format!("olps://127.0.0.1:{port}/echo"))
+///     .body(Vec::new())
+///     .send()
+///     .await?;
+/// assert_eq!(response.json()?["size"], 0);
+/// assert!(response.json()?["trace"].is_null());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub entrance: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// File streamed as the body instead of `body`, see [`RequestBuilder::body_file`].
+    pub body_file: Option<PathBuf>,
+    /// Whether the request may be sent again once it reached the server, see [`RetryPolicy`].
+    pub idempotent: bool,
+    /// Token cancelling the request, see [`RequestBuilder::cancellation_token`].
+    pub cancellation: Option<CancellationToken>,
+    on_progress: Option<Progress>,
+}
+
+/// Callback registered with [`RequestBuilder::on_progress`].
+#[derive(Clone)]
+struct Progress(Arc<dyn Fn(u64, u64) + Send + Sync>);
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Progress")
+    }
+}
+
+impl Request {
+    pub fn get(entrance: &str) -> RequestBuilder {
+        RequestBuilder::new("GET", entrance)
+    }
+
+    pub fn post(entrance: &str) -> RequestBuilder {
+        RequestBuilder::new("POST", entrance)
+    }
+
+    pub fn put(entrance: &str) -> RequestBuilder {
+        RequestBuilder::new("PUT", entrance)
+    }
+
+    /// Attach a metadata entry like [`RequestBuilder::header`].
+    pub fn set_header(&mut self, key: &str, value: &str) {
+        let key = key.to_lowercase();
+        self.headers.retain(|(name, _)| *name != key);
+        self.headers.push((key, value.to_string()));
+    }
+
+    /// Header line requesting `path`, announcing the length of the body.
+    ///
+    /// The other metadata is sent encrypted after the header line, see
+    /// [`SessionBuilder::metadata`].
+    pub(crate) async fn header(&self, path: &str) -> Result<String> {
+        let mut header = format!("{} {} Oblivion/2.0", self.method, path);
+        if let Some(length) = self.body_length().await? {
+            header.push(' ');
+            header.push_str(&encode_metadata(CONTENT_LENGTH, &length.to_string()));
+        }
+        Ok(header)
+    }
+
+    fn has_body(&self) -> bool {
+        self.body.is_some() || self.body_file.is_some()
+    }
+
+    async fn body_length(&self) -> Result<Option<u64>> {
+        if let Some(path) = &self.body_file {
+            return Ok(Some(tokio::fs::metadata(path).await?.len()));
+        }
+        Ok(self.body.as_ref().map(|body| body.len() as u64))
+    }
+
+    /// Send the body of the request on `session`, if it has one.
+    ///
+    /// Files are streamed in frames, reporting the progress once every frame was written.
+    pub(crate) async fn send_body(&self, session: &Session) -> Result<()> {
+        let progress = |sent, total| {
+            if let Some(Progress(callback)) = &self.on_progress {
+                callback(sent, total)
+            }
+        };
+        if let Some(body) = &self.body {
+            session.send(body.clone()).await?;
+            progress(body.len() as u64, body.len() as u64);
+        }
+        let Some(path) = &self.body_file else {
+            return Ok(());
+        };
+
+        let file = File::open(path).await?;
+        let total = file.metadata().await?.len();
+        let chunks = futures::stream::unfold((file, 0), |(mut file, sent)| async move {
+            // Polled for the next chunk once the previous one was written.
+            progress(sent, total);
+            match read_chunk(&mut file).await {
+                Ok(chunk) if chunk.is_empty() => None,
+                Ok(chunk) => {
+                    let size = chunk.len() as u64;
+                    Some((Ok(Bytes::from(chunk)), (file, sent + size)))
+                }
+                Err(error) => Some((Err(error), (file, sent))),
+            }
+        });
+        session.send_stream(chunks, 200).await
+    }
+
+    /// Connect with the default options, see [`ClientBuilder::send`].
+    pub async fn send(self) -> Result<Response> {
+        ClientBuilder::new().send(self).await
+    }
+}
+
+/// Builder of a [`Request`].
+#[derive(Debug, Clone)]
+pub struct RequestBuilder {
+    request: Request,
+}
+
+impl RequestBuilder {
+    pub fn new(method: &str, entrance: &str) -> Self {
+        Self {
+            request: Request {
+                method: method.to_uppercase(),
+                entrance: entrance.to_string(),
+                headers: Vec::new(),
+                body: None,
+                body_file: None,
+                idempotent: false,
+                cancellation: None,
+                on_progress: None,
+            },
+        }
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.request.body = Some(body);
+        self.request.body_file = None;
+        self
+    }
+
+    /// Stream the file at `path` as the body, without loading it into memory.
+    ///
+    /// ```rust
+    /// # use std::sync::{Arc, Mutex};
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn upload(session: Session) -> ServerResponse {
+    /// #     let size = session.request.body().len();
+    /// #     Ok(BaseResponse::TextResponse(size.to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/upload" => upload);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let path = std::env::temp_dir().join("oblivion-upload-source");
+    /// tokio::fs::write(&path, vec![7; 200_000]).await?;
+    ///
+    /// let progress = Arc::new(Mutex::new(Vec::new()));
+    /// let reported = Arc::clone(&progress);
+    /// let response = Request::post(&format!("olps://127.0.0.1:{port}/upload"))
+    ///     .body_file(&path)
+    ///     .on_progress(move |sent, total| reported.lock().unwrap().push((sent, total)))
+    ///     .send()
+    ///     .await?;
+    ///
+    /// assert_eq!(response.text()?, "200000");
+    /// let progress = progress.lock().unwrap();
+    /// assert!(progress.len() > 2);
+    /// assert_eq!(progress.last(), Some(&(200_000, 200_000)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn body_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.request.body_file = Some(path.into());
+        self.request.body = None;
+        self
+    }
+
+    /// Call `callback` with the bytes of the body sent so far and its total size as it is sent.
+    ///
+    /// The callback runs on the task sending the request between two frames, so it should
+    /// return quickly. Dropping the request while it is sent closes the connection, so the
+    /// server doesn't wait for the rest of the body.
+    pub fn on_progress(mut self, callback: impl Fn(u64, u64) + Send + Sync + 'static) -> Self {
+        self.request.on_progress = Some(Progress(Arc::new(callback)));
+        self
+    }
+
+    pub fn json(self, json: Value) -> Self {
+        self.body(json.to_string().into_bytes())
+    }
+
+    /// Attach a metadata entry, keys are case-insensitive and a later entry replaces an earlier one.
+    ///
+    /// Entries are sent encrypted after the header line, see [`SessionBuilder::metadata`].
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.request.set_header(key, value);
+        self
+    }
+
+    /// Allow retrying the request after it reached the server.
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.request.idempotent = idempotent;
+        self
+    }
+
+    /// Cancel the request once `token` is, failing it with [`Exception::Cancelled`].
+    ///
+    /// The connection of a cancelled request is closed, so the server doesn't wait for the
+    /// rest of the request and a pooled connection is never reused halfway through a response.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.request.cancellation = Some(token);
+        self
+    }
+
+    pub fn build(self) -> Request {
+        self.request
+    }
+
+    pub async fn send(self) -> Result<Response> {
+        self.request.send().await
+    }
+}
+
+/// Whether `response` is the last message the server sends for a request.
+fn ends_request(response: &Response) -> bool {
+    matches!(
+        response.flag,
+        SessionFlag::Response | SessionFlag::CloseAfter
+    )
+}
+
+/// Metadata left out of requests redirected to another origin, see
+/// [`ClientBuilder::follow_redirects`].
+pub const CREDENTIAL_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
+/// Whether `entrance` and `other` share their protocol, host and port.
+fn same_origin(entrance: &str, other: &str) -> Result<bool> {
+    let (entrance, other) = (OblivionPath::new(entrance)?, OblivionPath::new(other)?);
+    Ok(entrance.get_protocol() == other.get_protocol()
+        && entrance.get_host().eq_ignore_ascii_case(other.get_host())
+        && entrance.get_port() == other.get_port())
+}
+
+/// URL a redirect sent by the server behind `current` points to.
+fn redirect_location(current: &str, location: &str) -> Result<String> {
+    let location = location.trim();
+    if !location.starts_with('/') {
+        OblivionPath::new(location)?;
+        return Ok(location.to_string());
     }
+    let current = OblivionPath::new(current)?;
+    Ok(format!(
+        "{}://{}:{}{}",
+        current.get_protocol(),
+        current.get_host(),
+        current.get_port(),
+        location
+    ))
 }
@@ -0,0 +1,161 @@
+//! # Oblivion Extensions
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Values attached to a request by type, see
+/// [`OblivionRequest::extensions_mut`](crate::utils::parser::OblivionRequest::extensions_mut).
+///
+/// Middleware store what they learned about a request, such as the authenticated user, for
+/// the layers after them and the handler to read. Extensions are created with the request and
+/// dropped with it, the next request of a persistent session starts without any.
+///
+/// ```rust
+/// # use futures::future::BoxFuture;
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::middleware::{Middleware, Next};
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion_codegen::async_route;
+/// #[derive(Clone)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// /// Authenticates requests by their token.
+/// struct Auth;
+///
+/// impl Middleware for Auth {
+///     fn handle<'a>(
+///         &'a self,
+///         mut session: Session,
+///         next: Next<'a>,
+///     ) -> BoxFuture<'a, anyhow::Result<BaseResponse>> {
+///         Box::pin(async move {
+///             if let Some(token) = session.request.get_header("authorization") {
+///                 let user = User { name: token.trim_start_matches("token-").to_string() };
+///                 session.request.extensions_mut().insert(user);
+///             }
+///             next.run(session).await
+///         })
+///     }
+/// }
+///
+/// #[async_route]
+/// fn whoami(session: Session) -> String {
+///     tokio::task::yield_now().await;
+///     match session.request.extensions().get::<User>() {
+///         Some(user) => format!("hello {}", user.name),
+///         None => "hello stranger".to_string(),
+///     }
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+///
+/// let mut router = Router::new();
+/// router.layer(Auth);
+/// path_route!(&mut router, "/whoami" => whoami);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let url = format!("olps://127.0.0.1:{port}/whoami");
+///
+/// let response = Request::get(&url).header("authorization", "token-alice").send().await?;
+/// assert_eq!(response.text()?, "hello alice");
+/// assert_eq!(Request::get(&url).send().await?.text()?, "hello stranger");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Extension>>,
+}
+
+/// Value stored in [`Extensions`], cloned along with the request.
+trait Extension: Any + Send + Sync {
+    fn clone_box(&self) -> Box<dyn Extension>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Clone + Send + Sync + 'static> Extension for T {
+    fn clone_box(&self) -> Box<dyn Extension> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Clone for Box<dyn Extension> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `value`, returning the value of the same type it replaced.
+    ///
+    /// ```rust
+    /// # use oblivion::models::extensions::Extensions;
+    /// let mut extensions = Extensions::new();
+    /// assert_eq!(extensions.insert(1u32), None);
+    /// assert_eq!(extensions.insert(2u32), Some(1));
+    /// assert_eq!(extensions.get::<u32>(), Some(&2));
+    /// assert_eq!(extensions.get::<u64>(), None);
+    /// ```
+    pub fn insert<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.into_any().downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        let value = self.map.get(&TypeId::of::<T>())?;
+        (**value).as_any().downcast_ref()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        let value = self.map.get_mut(&TypeId::of::<T>())?;
+        (**value).as_any_mut().downcast_mut()
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        let value = self.map.remove(&TypeId::of::<T>())?;
+        value.into_any().downcast().ok().map(|value| *value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish_non_exhaustive()
+    }
+}
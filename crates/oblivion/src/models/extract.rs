@@ -0,0 +1,119 @@
+//! # Oblivion Extractors
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+#[cfg(feature = "serde")]
+use crate::utils::parser::{parse_into, OblivionRequest};
+
+#[cfg(feature = "serde")]
+use super::packet::StatusCode;
+use super::render::{BaseResponse, IntoResponse};
+
+/// Bodies deserialized by [`Json`] are at most this large unless the route allows more, see
+/// [`Route::json_limit`](super::router::Route::json_limit).
+pub const JSON_LIMIT: usize = 1024 * 1024;
+
+/// Failure of a handler that should be answered instead of being reported as an error.
+///
+/// Handlers returning it through `?` answer with its status and message, middleware sees
+/// that response like any other.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{message}")]
+pub struct Rejection {
+    pub status_code: u32,
+    pub message: String,
+}
+
+impl Rejection {
+    pub fn new(status_code: impl Into<u32>, message: impl Into<String>) -> Self {
+        Self {
+            status_code: status_code.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn into_response(self) -> BaseResponse {
+        BaseResponse::StatusResponse(self.status_code, self.message.into_bytes())
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> anyhow::Result<BaseResponse> {
+        Ok(Rejection::into_response(self))
+    }
+}
+
+/// Body of a request deserialized from JSON.
+///
+/// Bodies that don't match `T` are rejected with status `400` and the deserialization
+/// error, bodies over the limit of the route with status `413`.
+///
+/// ```rust
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::extract::Json;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::{Route, RoutePath, RouteType, Router};
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # use serde::Deserialize;
+/// # use serde_json::json;
+/// #[derive(Deserialize)]
+/// struct Login {
+///     user: String,
+/// }
+///
+/// #[async_route]
+/// fn login(session: Session) -> ServerResponse {
+///     let Json(login) = Json::<Login>::from_request(&session.request)?;
+///     Ok(BaseResponse::TextResponse(format!("welcome {}", login.user)))
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+///
+/// let mut router = Router::new();
+/// let route = Route::new(login).json_limit(64);
+/// router.register(RoutePath::new("/login", RouteType::Path), route);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let post = |body| Request::post(&format!("olps://127.0.0.1:{port}/login")).json(body).send();
+///
+/// let response = post(json!({ "user": "alice" })).await?;
+/// assert_eq!(response.text()?, "welcome alice");
+///
+/// let response = post(json!({ "name": "alice" })).await?;
+/// assert_eq!(response.status_code, 400);
+/// assert!(response.text()?.contains("missing field `user`"));
+///
+/// let response = post(json!({ "user": "a".repeat(64) })).await?;
+/// assert_eq!(response.status_code, 413);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: DeserializeOwned> Json<T> {
+    /// Deserialize the body of `request` within the limit of its route.
+    pub fn from_request(request: &OblivionRequest) -> Result<Self, Rejection> {
+        let body = request.body();
+        let limit = request.json_limit();
+        if body.len() > limit {
+            return Err(Rejection::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "Body is {} bytes, at most {limit} bytes are allowed.",
+                    body.len()
+                ),
+            ));
+        }
+        parse_into(body)
+            .map(Json)
+            .map_err(|error| Rejection::new(StatusCode::BAD_REQUEST, error.to_string()))
+    }
+}
@@ -0,0 +1,134 @@
+//! # Oblivion Static Files
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio::fs::File;
+
+use super::extract::Rejection;
+use super::middleware::{Middleware, Next};
+use super::packet::StatusCode;
+use super::render::BaseResponse;
+use super::session::Session;
+
+/// Parameter of the routes of [`Router::serve_dir`](super::router::Router::serve_dir)
+/// capturing the path of the file.
+pub const FILE_PARAM: &str = "file";
+
+/// Answer requests with the files of a directory, see
+/// [`Router::serve_dir`](super::router::Router::serve_dir).
+///
+/// The path of the file is the [`FILE_PARAM`] parameter of the route, or the directory itself
+/// without it. Paths with `..` or other components that don't name an entry of their parent
+/// are answered with status `403`, missing files with status `404`. Files are opened once and
+/// streamed in frames rather than read whole, see [`BaseResponse::OpenFileResponse`].
+#[derive(Debug, Clone)]
+pub struct ServeDir {
+    root: PathBuf,
+    index: Option<String>,
+    follow_symlinks: bool,
+}
+
+impl ServeDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            index: Some("index.html".to_string()),
+            follow_symlinks: false,
+        }
+    }
+
+    /// Answer requests for a directory with its file `name`, defaults to `index.html`.
+    pub fn index(mut self, name: Option<&str>) -> Self {
+        self.index = name.map(str::to_string);
+        self
+    }
+
+    /// Serve files whose symbolic links lead out of the directory instead of answering them
+    /// with status `403`.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Open the file of the directory at `path`, a path relative to it with `/` as separator.
+    pub async fn open(&self, path: &str) -> Result<File, Rejection> {
+        let forbidden = || {
+            Rejection::new(
+                StatusCode::FORBIDDEN,
+                format!("Access to {path} is forbidden."),
+            )
+        };
+        let not_found =
+            || Rejection::new(StatusCode::NOT_FOUND, format!("File {path} was not found."));
+
+        let mut file = self.root.clone();
+        for part in path
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+        {
+            // Anything but a plain name, such as `..`, a drive or a nested separator.
+            let mut components = Path::new(part).components();
+            let plain = matches!(components.next(), Some(Component::Normal(name)) if name == part)
+                && components.next().is_none()
+                && !part.contains('\\');
+            if !plain {
+                return Err(forbidden());
+            }
+            file.push(part);
+        }
+
+        let open = |file: PathBuf| async move {
+            let opened = match File::open(&file).await {
+                Ok(opened) => opened,
+                Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+                    return Err(forbidden())
+                }
+                Err(_) => return Err(not_found()),
+            };
+            let metadata = opened.metadata().await.map_err(|_| not_found())?;
+            Ok((file, opened, metadata))
+        };
+        let (mut file, mut opened, mut metadata) = open(file).await?;
+        if metadata.is_dir() {
+            let Some(index) = &self.index else {
+                return Err(not_found());
+            };
+            file.push(index);
+            (file, opened, metadata) = open(file).await?;
+        }
+        if !metadata.is_file() {
+            return Err(not_found());
+        }
+
+        if !self.follow_symlinks {
+            let root = tokio::fs::canonicalize(&self.root).await;
+            let canonical = tokio::fs::canonicalize(&file).await;
+            match (root, canonical) {
+                (Ok(root), Ok(canonical)) if canonical.starts_with(&root) => {}
+                _ => return Err(forbidden()),
+            }
+        }
+        Ok(opened)
+    }
+}
+
+impl Middleware for ServeDir {
+    fn handle<'a>(
+        &'a self,
+        session: Session,
+        _next: Next<'a>,
+    ) -> BoxFuture<'a, Result<BaseResponse>> {
+        Box::pin(async move {
+            let path = session.request.param(FILE_PARAM).unwrap_or_default();
+            match self.open(path).await {
+                Ok(file) => Ok(BaseResponse::OpenFileResponse(Arc::new(
+                    file.into_std().await,
+                ))),
+                Err(rejection) => Ok(rejection.into_response()),
+            }
+        })
+    }
+}
@@ -0,0 +1,182 @@
+//! # Oblivion Address Filters
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::exceptions::Exception;
+
+/// Network of addresses sharing a prefix, such as `10.0.0.0/8` or `fd00::/8`.
+///
+/// ```rust
+/// # use oblivion::models::filter::IpNet;
+/// let private: IpNet = "10.0.0.0/8".parse()?;
+/// assert!(private.contains("10.1.2.3".parse()?));
+/// assert!(!private.contains("192.168.0.1".parse()?));
+///
+/// // Addresses without a prefix are networks of one address.
+/// let local: IpNet = "::1".parse()?;
+/// assert!(local.contains("::1".parse()?));
+/// assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+/// # anyhow::Ok(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    /// Network of the addresses whose first `prefix` bits are those of `address`.
+    pub fn new(address: IpAddr, prefix: u8) -> Result<Self, Exception> {
+        let bits = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > bits {
+            return Err(Exception::InvalidNetwork {
+                network: format!("{address}/{prefix}"),
+            });
+        }
+        Ok(Self { address, prefix })
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// The same network with the bits of the address past the prefix cleared.
+    ///
+    /// ```rust
+    /// # use oblivion::models::filter::IpNet;
+    /// let network: IpNet = "10.1.2.3/8".parse()?;
+    /// assert_eq!(network.trunc().to_string(), "10.0.0.0/8");
+    /// assert_eq!(network.trunc(), "10.0.0.0/8".parse()?);
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn trunc(&self) -> Self {
+        let address = match self.address {
+            IpAddr::V4(address) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                IpAddr::V4((u32::from(address) & mask).into())
+            }
+            IpAddr::V6(address) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                IpAddr::V6((u128::from(address) & mask).into())
+            }
+        };
+        Self {
+            address,
+            prefix: self.prefix,
+        }
+    }
+
+    /// Whether `address` is in the network, addresses of the other family never are.
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = Exception;
+
+    fn from_str(network: &str) -> Result<Self, Self::Err> {
+        let invalid = || Exception::InvalidNetwork {
+            network: network.to_string(),
+        };
+        let (address, prefix) = match network.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (network, None),
+        };
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let prefix = match (prefix, address) {
+            (Some(prefix), _) => prefix.parse().map_err(|_| invalid())?,
+            (None, IpAddr::V4(_)) => 32,
+            (None, IpAddr::V6(_)) => 128,
+        };
+        Self::new(address, prefix).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// Networks a server accepts connections from, see
+/// [`ServerConfig::allow`](super::server::ServerConfig::allow).
+///
+/// Clones share the same lists, so they can be replaced while the server runs.
+#[derive(Debug, Clone, Default)]
+pub struct AddressFilter {
+    inner: Arc<Filter>,
+}
+
+#[derive(Debug, Default)]
+struct Filter {
+    lists: RwLock<Lists>,
+    rejected: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Lists {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl AddressFilter {
+    /// Only admit addresses in `networks`, or every address if it is empty.
+    ///
+    /// Networks are stored [truncated](IpNet::trunc) to their prefix.
+    pub fn set_allow(&self, networks: impl IntoIterator<Item = IpNet>) {
+        self.inner.lists.write().unwrap().allow = truncated(networks);
+    }
+
+    /// Never admit addresses in `networks`, even if they are allowed.
+    ///
+    /// Networks are stored [truncated](IpNet::trunc) to their prefix.
+    pub fn set_deny(&self, networks: impl IntoIterator<Item = IpNet>) {
+        self.inner.lists.write().unwrap().deny = truncated(networks);
+    }
+
+    /// Whether connections from `address` are admitted, counting it as rejected otherwise.
+    pub fn admits(&self, address: IpAddr) -> bool {
+        let lists = self.inner.lists.read().unwrap();
+        let denied = lists.deny.iter().any(|network| network.contains(address));
+        let allowed =
+            lists.allow.is_empty() || lists.allow.iter().any(|network| network.contains(address));
+        if denied || !allowed {
+            self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    /// Number of connections rejected so far.
+    pub fn rejected(&self) -> u64 {
+        self.inner.rejected.load(Ordering::Relaxed)
+    }
+}
+
+fn truncated(networks: impl IntoIterator<Item = IpNet>) -> Vec<IpNet> {
+    networks
+        .into_iter()
+        .map(|network| network.trunc())
+        .collect()
+}
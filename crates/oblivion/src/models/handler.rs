@@ -1,7 +1,10 @@
 //! # Oblivion Default Handler
+use std::any::Any;
+
 use crate::types::ServerResponse;
+use crate::utils::parser::OblivionRequest;
 
-use super::{render::BaseResponse, session::Session};
+use super::{packet::StatusCode, render::BaseResponse, session::Session};
 use oblivion_codegen::internal_handler;
 
 /// Not Found Handler
@@ -11,8 +14,43 @@ use oblivion_codegen::internal_handler;
 pub fn not_found(session: Session) -> ServerResponse {
     let entrance = session.request.get_ip();
 
-    Ok(BaseResponse::TextResponse(format!(
-        "Path {} is not found, error with code 404.",
-        entrance
-    )))
+    Ok(BaseResponse::StatusResponse(
+        StatusCode::NOT_FOUND.as_u32(),
+        format!("Path {} is not found, error with code 404.", entrance).into_bytes(),
+    ))
+}
+
+/// Panic caught while handling a request, see
+/// [`Server::panic_handler`](super::server::Server::panic_handler).
+#[derive(Debug)]
+pub struct Panic {
+    message: String,
+}
+
+impl Panic {
+    pub(crate) fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "Box<dyn Any>".to_string(),
+            },
+        };
+        Self { message }
+    }
+
+    /// Message given to `panic!`, or `Box<dyn Any>` for payloads other than strings.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Internal Error Handler
+///
+/// Answering a request whose handler panicked, without revealing why.
+pub fn internal_error(_panic: &Panic, _request: &OblivionRequest) -> BaseResponse {
+    BaseResponse::StatusResponse(
+        StatusCode::INTERNAL_ERROR.as_u32(),
+        b"Internal server error, error with code 500.".to_vec(),
+    )
 }
@@ -0,0 +1,96 @@
+//! # Oblivion Interceptors
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+use super::client::{Request, Response};
+
+/// Hooks running around every request sent by a client, see
+/// [`ClientBuilder::interceptor`](super::client::ClientBuilder::interceptor).
+///
+/// Interceptors run in the order they were registered, [`Interceptor::before`] once before
+/// the request is sent and [`Interceptor::after`] once its response arrived, retries
+/// included. An error returned by either hook fails the request with that error, a request
+/// refused by `before` is never sent.
+///
+/// ```rust
+/// # use std::sync::atomic::{AtomicU32, Ordering};
+/// # use std::sync::Arc;
+/// # use futures::future::BoxFuture;
+/// # use oblivion::models::client::{ClientBuilder, Request, Response};
+/// # use oblivion::models::interceptor::Interceptor;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn whoami(session: Session) -> ServerResponse {
+/// #     let token = session.request.get_header("authorization").unwrap_or("anonymous");
+/// #     Ok(BaseResponse::TextResponse(token.to_string()))
+/// # }
+/// struct Auth(Option<String>);
+///
+/// impl Interceptor for Auth {
+///     fn before<'a>(&'a self, request: &'a mut Request) -> BoxFuture<'a, anyhow::Result<()>> {
+///         Box::pin(async move {
+///             let token = self.0.as_deref().ok_or(anyhow::anyhow!("No credentials"))?;
+///             request.set_header("Authorization", token);
+///             Ok(())
+///         })
+///     }
+/// }
+///
+/// struct Count(Arc<AtomicU32>);
+///
+/// impl Interceptor for Count {
+///     fn after<'a>(
+///         &'a self,
+///         _request: &'a Request,
+///         _response: &'a mut Response,
+///     ) -> BoxFuture<'a, anyhow::Result<()>> {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///         Box::pin(async { Ok(()) })
+///     }
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/whoami" => whoami);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let request = Request::get(&format!("olps://127.0.0.1:{port}/whoami")).build();
+/// let responses = Arc::new(AtomicU32::new(0));
+///
+/// let client = ClientBuilder::new()
+///     .interceptor(Auth(Some("token".to_string())))
+///     .interceptor(Count(Arc::clone(&responses)));
+/// assert_eq!(client.send(request.clone()).await?.text()?, "token");
+///
+/// let refused = ClientBuilder::new()
+///     .interceptor(Auth(None))
+///     .interceptor(Count(Arc::clone(&responses)));
+/// assert!(refused.send(request).await.is_err());
+/// assert_eq!(responses.load(Ordering::Relaxed), 1);
+/// # Ok(())
+/// # }
+/// ```
+pub trait Interceptor: Send + Sync {
+    /// Inspect or modify `request` before it is sent.
+    fn before<'a>(&'a self, request: &'a mut Request) -> BoxFuture<'a, Result<()>> {
+        let _ = request;
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Inspect or modify the `response` answering `request`.
+    fn after<'a>(
+        &'a self,
+        request: &'a Request,
+        response: &'a mut Response,
+    ) -> BoxFuture<'a, Result<()>> {
+        let _ = (request, response);
+        Box::pin(async { Ok(()) })
+    }
+}
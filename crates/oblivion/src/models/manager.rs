@@ -0,0 +1,157 @@
+//! # Oblivion Session Manager
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use anyhow::Result;
+use futures::future::join_all;
+
+use super::session::{Session, SessionFlag};
+
+/// Sessions currently connected to a server, see
+/// [`Server::sessions`](super::server::Server::sessions).
+///
+/// Sessions keeping their connection open for further requests, see
+/// [`Capabilities::REQUESTS`](super::session::Capabilities::REQUESTS), are registered once
+/// their handshake completes and removed as soon as they are closed, by either side, or once
+/// the server stops serving them. Sessions closed after their only request never are. Clones share the same
+/// sessions, so a manager can be given to handlers through
+/// [`Server::with_state`](super::server::Server::with_state) and used from anywhere else.
+///
+/// ```rust
+/// # use std::sync::Mutex;
+/// # use oblivion::models::client::Client;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::{Session, SessionFlag};
+/// # use oblivion::path_route;
+/// # use oblivion_codegen::async_route;
+/// /// Sessions subscribed to the news.
+/// #[derive(Default)]
+/// struct Subscribers(Mutex<Vec<u64>>);
+///
+/// #[async_route]
+/// fn subscribe(session: Session) -> anyhow::Result<String> {
+///     let subscribers = session.request.state::<Subscribers>()?;
+///     subscribers.0.lock().unwrap().push(session.id());
+///     Ok(format!("session {} subscribed", session.id()))
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/subscribe" => subscribe);
+///
+/// let server = Server::new("127.0.0.1", 0, router).with_state(Subscribers::default());
+/// let sessions = server.sessions();
+/// # let server = server.bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let client = Client::connect(&format!("olps://127.0.0.1:{port}/subscribe")).await?;
+/// assert!(client.recv().await?.text()?.ends_with("subscribed"));
+/// assert_eq!(sessions.count(), 1);
+///
+/// assert_eq!(sessions.broadcast(b"breaking news".to_vec(), 200).await, 1);
+/// let news = client.recv().await?;
+/// assert_eq!(news.flag, SessionFlag::Broadcast);
+/// assert_eq!(news.text()?, "breaking news");
+///
+/// let id = sessions.iter().next().unwrap().id();
+/// assert!(sessions.close(id).await?);
+/// assert_eq!(sessions.count(), 0);
+/// assert!(client.recv().await.is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct SessionManager {
+    sessions: Arc<StdMutex<HashMap<u64, Arc<Session>>>>,
+}
+
+impl SessionManager {
+    /// Track `session` until it is closed or the returned guard is dropped.
+    pub(crate) fn register(&self, session: Session) -> Registration {
+        let id = session.id();
+        let sessions = self.clone();
+        session.on_close(move |_| sessions.remove(id));
+        self.sessions.lock().unwrap().insert(id, Arc::new(session));
+        Registration {
+            sessions: self.clone(),
+            id,
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        let removed = self.sessions.lock().unwrap().remove(&id);
+        // Dropping the last handle of a session runs its close hooks, one of which removes
+        // it from the manager, so it must happen once the lock is released.
+        drop(removed);
+    }
+
+    /// Sessions connected when called, sessions connecting or closing meanwhile don't
+    /// change what it yields.
+    pub fn iter(&self) -> impl Iterator<Item = Arc<Session>> {
+        let sessions: Vec<_> = self.sessions.lock().unwrap().values().cloned().collect();
+        sessions.into_iter()
+    }
+
+    pub fn count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Session>> {
+        self.sessions.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Close the session `id`, returning whether it was connected.
+    pub async fn close(&self, id: u64) -> Result<bool> {
+        let Some(session) = self.get(id) else {
+            return Ok(false);
+        };
+        session.close().await?;
+        Ok(true)
+    }
+
+    /// Send `data` with `status_code` to every connected session, returning how many
+    /// received it.
+    ///
+    /// Messages are flagged with [`SessionFlag::Broadcast`], so a client waiting for the answer
+    /// to a request doesn't take them for it.
+    ///
+    /// Sessions are sent to concurrently, one failing or closing meanwhile doesn't keep the
+    /// others from receiving the message.
+    pub async fn broadcast(&self, data: Vec<u8>, status_code: u32) -> usize {
+        let sends = self.iter().map(|session| {
+            let data = data.clone();
+            async move {
+                session
+                    .send_with_flag(data, status_code, SessionFlag::Broadcast)
+                    .await
+            }
+        });
+        join_all(sends)
+            .await
+            .into_iter()
+            .filter(Result::is_ok)
+            .count()
+    }
+}
+
+impl fmt::Debug for SessionManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionManager")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+/// Session registered with a [`SessionManager`], removed once dropped.
+pub(crate) struct Registration {
+    sessions: SessionManager,
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.sessions.remove(self.id);
+    }
+}
@@ -0,0 +1,604 @@
+//! # Oblivion Middleware
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio::time::Instant;
+
+use crate::types::Handler;
+use crate::utils::gear::Peer;
+use crate::utils::parser::OblivionRequest;
+
+use super::extract::Rejection;
+use super::packet::StatusCode;
+use super::render::BaseResponse;
+use super::session::Session;
+
+/// Layer wrapping the handlers of a router, see [`Router::layer`](super::router::Router::layer)
+/// and [`Route::layer`](super::router::Route::layer).
+///
+/// A middleware receives the session of every request before its handler and decides what
+/// to do with it: answer right away without calling [`Next::run`], modify the request before
+/// passing it on, or modify the response the rest of the chain returned. Layers of the router
+/// run first, in the order they were added, then those of nested routers and of the route,
+/// and unwind in reverse order. Router layers also run for requests answered with status
+/// `404` or `405`, the layers of the route for requests whose body is over its limit, which
+/// reach [`PAYLOAD_TOO_LARGE_STATUS`](super::server::PAYLOAD_TOO_LARGE_STATUS) rather than
+/// the handler. Every layer sees the response of a [`Rejection`] returned by the handler.
+///
+/// ```rust
+/// # use std::sync::{Arc, Mutex};
+/// # use futures::future::BoxFuture;
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::middleware::{Middleware, Next};
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::{Route, RoutePath, RouteType, Router};
+/// # use oblivion::models::server::{Server, PAYLOAD_TOO_LARGE_STATUS};
+/// # use oblivion::models::session::Session;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// #[async_route]
+/// fn whoami(session: Session) -> ServerResponse {
+///     let user = session.request.get_header("x-user").unwrap_or("nobody");
+///     Ok(BaseResponse::TextResponse(user.to_string()))
+/// }
+///
+/// /// Rejects requests without a token, names the user of the others.
+/// struct Auth;
+///
+/// impl Middleware for Auth {
+///     fn handle<'a>(
+///         &'a self,
+///         mut session: Session,
+///         next: Next<'a>,
+///     ) -> BoxFuture<'a, anyhow::Result<BaseResponse>> {
+///         Box::pin(async move {
+///             if session.request.get_header("authorization") != Some("secret") {
+///                 return Ok(BaseResponse::StatusResponse(401, b"Unauthorized".to_vec()));
+///             }
+///             session.request.set_header("X-User", "alice");
+///             next.run(session).await
+///         })
+///     }
+/// }
+///
+/// /// Records the order it runs in and marks the responses it sees.
+/// struct Trace(&'static str, Arc<Mutex<Vec<String>>>);
+///
+/// impl Middleware for Trace {
+///     fn handle<'a>(
+///         &'a self,
+///         session: Session,
+///         next: Next<'a>,
+///     ) -> BoxFuture<'a, anyhow::Result<BaseResponse>> {
+///         Box::pin(async move {
+///             self.1.lock().unwrap().push(format!("{} in", self.0));
+///             let response = next.run(session).await?;
+///             self.1.lock().unwrap().push(format!("{} out", self.0));
+///             match response {
+///                 BaseResponse::TextResponse(text) => {
+///                     Ok(BaseResponse::TextResponse(format!("{text} [{}]", self.0)))
+///                 }
+///                 response => Ok(response),
+///             }
+///         })
+///     }
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+///
+/// let trace = Arc::new(Mutex::new(Vec::new()));
+/// let mut router = Router::new();
+/// router
+///     .layer(Trace("outer", Arc::clone(&trace)))
+///     .layer(Trace("inner", Arc::clone(&trace)));
+/// let route = Route::new(whoami).layer(Auth).max_body_size(16);
+/// router.register(RoutePath::new("/whoami", RouteType::Path), route);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let request = Request::get(&format!("olps://127.0.0.1:{port}/whoami"));
+///
+/// let response = request.clone().header("Authorization", "secret").send().await?;
+/// assert_eq!(response.text()?, "alice [inner] [outer]");
+/// assert_eq!(*trace.lock().unwrap(), ["outer in", "inner in", "inner out", "outer out"]);
+///
+/// let response = request.clone().send().await?;
+/// assert_eq!((response.status_code, response.text()?), (401, "Unauthorized"));
+///
+/// // Oversized bodies go through the layers of the route too.
+/// let oversized = request.body(vec![0; 64]);
+/// assert_eq!(oversized.clone().send().await?.status_code, 401);
+/// let response = oversized.header("Authorization", "secret").send().await?;
+/// assert_eq!(response.status_code, PAYLOAD_TOO_LARGE_STATUS);
+/// # Ok(())
+/// # }
+/// ```
+pub trait Middleware: Send + Sync {
+    /// Answer the request behind `session`, usually by passing it to `next`.
+    fn handle<'a>(
+        &'a self,
+        session: Session,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, Result<BaseResponse>>;
+}
+
+pub(crate) type SharedMiddleware = Arc<dyn Middleware>;
+
+/// Rest of the chain behind a [`Middleware`], ending with the handler of the route.
+pub struct Next<'a> {
+    layers: &'a [SharedMiddleware],
+    terminal: Terminal,
+}
+
+/// What answers the request once every layer passed it on.
+pub(crate) enum Terminal {
+    Handler(Handler),
+    Response(BaseResponse),
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(layers: &'a [SharedMiddleware], terminal: Terminal) -> Self {
+        Self { layers, terminal }
+    }
+
+    /// Pass `session` to the next layer, or to the handler after the last one.
+    pub fn run(self, session: Session) -> BoxFuture<'a, Result<BaseResponse>> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => layer.handle(
+                session,
+                Next {
+                    layers,
+                    terminal: self.terminal,
+                },
+            ),
+            None => match self.terminal {
+                Terminal::Handler(handler) => Box::pin(async move {
+                    match handler(session).await {
+                        Err(error) => match error.downcast::<Rejection>() {
+                            Ok(rejection) => Ok(rejection.into_response()),
+                            Err(error) => Err(error),
+                        },
+                        response => response,
+                    }
+                }),
+                Terminal::Response(response) => Box::pin(async move { Ok(response) }),
+            },
+        }
+    }
+}
+
+/// How answering a request ended, see [`Completion`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The response was sent.
+    Answered,
+    /// The handler or sending its response failed, the connection was closed.
+    Failed(String),
+    /// The handler panicked with this message, the response of the
+    /// [panic handler](super::server::Server::panic_handler) was sent.
+    Panicked(String),
+    /// The handler ran out of its [timeout](super::server::ServerConfig::handler_timeout).
+    TimedOut,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Answered => f.write_str("answered"),
+            Self::Failed(error) => write!(f, "failed: {error}"),
+            Self::Panicked(message) => write!(f, "panicked: {message}"),
+            Self::TimedOut => f.write_str("timed out"),
+        }
+    }
+}
+
+/// End of a request, given to the hooks of [`OblivionRequest::on_complete`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Status of the response, `None` if none was sent.
+    pub status_code: Option<u32>,
+    /// Bytes of the response body that were sent.
+    pub size: usize,
+    /// Time from receiving the request to sending the end of its response.
+    pub latency: Duration,
+    pub outcome: Outcome,
+}
+
+type CompletionHook = Box<dyn FnOnce(&Completion) + Send>;
+
+/// Hooks of [`OblivionRequest::on_complete`], shared by every copy of the request.
+#[derive(Clone, Default)]
+pub(crate) struct Completions(Arc<StdMutex<Vec<CompletionHook>>>);
+
+impl Completions {
+    pub(crate) fn push(&self, hook: CompletionHook) {
+        self.0.lock().unwrap().push(hook);
+    }
+
+    /// Run every hook with `completion`, each one runs at most once.
+    pub(crate) fn complete(&self, completion: &Completion) {
+        let hooks = std::mem::take(&mut *self.0.lock().unwrap());
+        for hook in hooks {
+            hook(completion);
+        }
+    }
+}
+
+impl fmt::Debug for Completions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Completions")
+            .field("len", &self.0.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// Custom field of an [`AccessLog`].
+type Field = Arc<dyn Fn(&OblivionRequest) -> Option<String> + Send + Sync>;
+
+/// Middleware logging a line per request through [`tracing`], including requests that
+/// failed or panicked.
+///
+/// Lines are `INFO` events of the `oblivion::access` target with the fields `peer`, `method`,
+/// `entrance`, `status` (left out if no response was sent), `size` of the response body,
+/// `latency_us` from receiving the request to sending the end of its response, `outcome`
+/// and `fields`, holding the [custom fields](AccessLog::field) as `name=value` pairs. Added
+/// to a router, it logs the requests answered with status `404` and `405` as well.
+///
+/// ```rust
+/// # use std::sync::{Arc, Mutex};
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::middleware::AccessLog;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # use tracing::field::Field;
+/// # use tracing::span::{Attributes, Id, Record};
+/// # use tracing::{Event, Metadata};
+/// # struct Lines(Arc<Mutex<Vec<String>>>);
+/// # impl tracing::Subscriber for Lines {
+/// #     fn enabled(&self, _: &Metadata<'_>) -> bool { true }
+/// #     fn new_span(&self, _: &Attributes<'_>) -> Id { Id::from_u64(1) }
+/// #     fn record(&self, _: &Id, _: &Record<'_>) {}
+/// #     fn record_follows_from(&self, _: &Id, _: &Id) {}
+/// #     fn event(&self, event: &Event<'_>) {
+/// #         let mut line = Vec::new();
+/// #         event.record(&mut |field: &Field, value: &dyn std::fmt::Debug| {
+/// #             line.push(format!("{}={:?}", field.name(), value));
+/// #         });
+/// #         self.0.lock().unwrap().push(line.join(" "));
+/// #     }
+/// #     fn enter(&self, _: &Id) {}
+/// #     fn exit(&self, _: &Id) {}
+/// # }
+/// #[async_route]
+/// fn hello(_session: Session) -> ServerResponse {
+///     Ok(BaseResponse::TextResponse("hello".to_string()))
+/// }
+///
+/// #[async_route]
+/// fn broken(_session: Session) -> ServerResponse {
+///     Err(anyhow::anyhow!("database is gone"))
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let lines = Arc::new(Mutex::new(Vec::new()));
+/// # tracing::subscriber::set_global_default(Lines(Arc::clone(&lines)))?;
+///
+/// let mut router = Router::new();
+/// path_route!(&mut router, "/hello" => hello);
+/// path_route!(&mut router, "/broken" => broken);
+/// router.layer(AccessLog::new().field("user", |request| {
+///     request.get_header("x-user").map(str::to_string)
+/// }));
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// # let url = |entrance| format!("olps://127.0.0.1:{port}{entrance}");
+///
+/// Request::get(&url("/hello")).header("X-User", "alice").send().await?;
+/// assert!(Request::get(&url("/broken")).send().await.is_err());
+/// # tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+///
+/// let lines = lines.lock().unwrap();
+/// assert!(lines[0].contains("entrance=/hello status=200 size=5"));
+/// assert!(lines[0].contains("outcome=answered fields=user=alice"));
+/// assert!(lines[1].contains("entrance=/broken size=0"));
+/// assert!(lines[1].contains("outcome=failed: database is gone"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct AccessLog {
+    fields: Vec<(String, Field)>,
+}
+
+impl AccessLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `name` to the fields of every line, with the value `extract` finds in the request.
+    ///
+    /// Requests it finds nothing in are logged without the field.
+    pub fn field(
+        mut self,
+        name: &str,
+        extract: impl Fn(&OblivionRequest) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.fields.push((name.to_string(), Arc::new(extract)));
+        self
+    }
+}
+
+impl Middleware for AccessLog {
+    fn handle<'a>(
+        &'a self,
+        mut session: Session,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, Result<BaseResponse>> {
+        Box::pin(async move {
+            let request = &session.request;
+            let fields: Vec<_> = self
+                .fields
+                .iter()
+                .filter_map(|(name, extract)| Some(format!("{name}={}", extract(request)?)))
+                .collect();
+            let fields = fields.join(" ");
+            let peer = request.get_ip().to_string();
+            let method = request.get_method().to_string();
+            let entrance = request.get_entrance().to_string();
+            session.request.on_complete(move |completion| {
+                tracing::info!(
+                    target: "oblivion::access",
+                    peer = %peer,
+                    method = %method,
+                    entrance = %entrance,
+                    status = completion.status_code,
+                    size = completion.size,
+                    latency_us = completion.latency.as_micros() as u64,
+                    outcome = %completion.outcome,
+                    fields = %fields,
+                );
+            });
+            next.run(session).await
+        })
+    }
+}
+
+/// Status of the response to requests over the limit of a [`RateLimit`].
+pub const RATE_LIMIT_STATUS: u32 = StatusCode::TOO_MANY_REQUESTS.as_u32();
+
+/// Middleware limiting the requests of every client address with a token bucket.
+///
+/// Every address may send `requests` requests at once, then regains one every `window`
+/// divided by `requests`. Requests over the limit are answered with [`RATE_LIMIT_STATUS`]
+/// and the number of seconds to wait before the next one is allowed. Only the most
+/// recently seen addresses are tracked, see [`RateLimit::capacity`], so clients rotating
+/// their address can't exhaust memory.
+///
+/// Peers without an address of their own, such as over Unix domain sockets, all share one
+/// bucket apart from the one of [`LOCAL_PEER`](crate::utils::gear::LOCAL_PEER), unless
+/// [`RateLimit::local_per_connection`] gives each of their connections its own.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use oblivion::models::client::{ClientBuilder, Request};
+/// # use oblivion::models::middleware::{RateLimit, RATE_LIMIT_STATUS};
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn hello(_session: Session) -> ServerResponse {
+/// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+/// # }
+/// # #[cfg(unix)]
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let mut router = Router::new();
+/// router.layer(RateLimit::new(1, Duration::from_secs(60)));
+/// path_route!(&mut router, "/hello" => hello);
+/// let path = std::env::temp_dir().join(format!("oblivion-limit-{}.sock", std::process::id()));
+/// # let server = Server::new("127.0.0.1", 0, router).bind_unix(&path).await?;
+/// # let handle = server.shutdown_handle();
+/// # let running = tokio::spawn(server.serve());
+///
+/// // Every request is sent on a connection of its own, all of them count against one bucket.
+/// let client = ClientBuilder::new().unix_socket(&path);
+/// let response = client.send(Request::get("olps://localhost/hello").build()).await?;
+/// assert_eq!(response.status_code, 200);
+/// let response = client.send(Request::get("olps://localhost/hello").build()).await?;
+/// assert_eq!(response.status_code, RATE_LIMIT_STATUS);
+/// # handle.shutdown();
+/// # running.await??;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+///
+/// Clones share their buckets. Layers of the router and of a route both apply, so a
+/// stricter limit can be layered on the routes that are expensive to answer.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::middleware::{RateLimit, RATE_LIMIT_STATUS};
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::{Route, RoutePath, RouteType, Router};
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn hello(_session: Session) -> ServerResponse {
+/// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+/// # }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let mut router = Router::new();
+/// router.layer(RateLimit::new(3, Duration::from_secs(60)));
+/// path_route!(&mut router, "/hello" => hello);
+/// let search = Route::new(hello).layer(RateLimit::new(1, Duration::from_secs(60)));
+/// router.register(RoutePath::new("/search", RouteType::Path), search);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// # let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+///
+/// assert_eq!(get("/search").await?.status_code, 200);
+/// let limited = get("/search").await?;
+/// assert_eq!(limited.status_code, RATE_LIMIT_STATUS);
+/// assert_eq!(limited.text()?, "Too many requests, retry after 60 seconds.");
+///
+/// // The requests to `/search` counted against the limit of the router as well.
+/// assert_eq!(get("/hello").await?.status_code, 200);
+/// assert_eq!(get("/hello").await?.status_code, RATE_LIMIT_STATUS);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RateLimit {
+    burst: f64,
+    /// Tokens regained per second.
+    rate: f64,
+    local_per_connection: bool,
+    buckets: Arc<StdMutex<Buckets>>,
+}
+
+/// Buckets of the most recently seen addresses.
+struct Buckets {
+    capacity: usize,
+    buckets: HashMap<String, Bucket>,
+    /// Addresses by the order they were last seen in, oldest first.
+    recent: BTreeMap<u64, String>,
+    seen: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    seen: u64,
+}
+
+impl RateLimit {
+    /// Allow `requests` requests per `window` to every address, tracking up to 10000
+    /// addresses.
+    pub fn new(requests: u32, window: Duration) -> Self {
+        let burst = requests.max(1) as f64;
+        Self {
+            burst,
+            rate: burst / window.as_secs_f64(),
+            local_per_connection: false,
+            buckets: Arc::new(StdMutex::new(Buckets {
+                capacity: 10_000,
+                buckets: HashMap::new(),
+                recent: BTreeMap::new(),
+                seen: 0,
+            })),
+        }
+    }
+
+    /// Track the buckets of at most `addresses` addresses, forgetting the least recently
+    /// seen one beyond that, which starts over with a full bucket.
+    pub fn capacity(self, addresses: usize) -> Self {
+        self.buckets.lock().unwrap().capacity = addresses.max(1);
+        self
+    }
+
+    /// Give every connection of peers without an address of their own a bucket of its own,
+    /// rather than one shared by all of them.
+    ///
+    /// Only meant for local clients that can be trusted to keep their connections open, the
+    /// others would get a full bucket by reconnecting.
+    pub fn local_per_connection(mut self, enabled: bool) -> Self {
+        self.local_per_connection = enabled;
+        self
+    }
+
+    /// Take a token of `address`, or return how long until one is available.
+    fn acquire(&self, address: &str) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        let seen = buckets.touch(address, self.burst, now);
+        let bucket = buckets
+            .buckets
+            .get_mut(address)
+            .expect("bucket was just touched");
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+        bucket.seen = seen;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+    }
+}
+
+impl Buckets {
+    /// Mark `address` as the most recently seen one, adding a full bucket for it if needed.
+    fn touch(&mut self, address: &str, burst: f64, now: Instant) -> u64 {
+        self.seen += 1;
+        let seen = self.seen;
+        match self.buckets.get(address) {
+            Some(bucket) => {
+                self.recent.remove(&bucket.seen);
+            }
+            None => {
+                while self.buckets.len() >= self.capacity {
+                    let Some((_, oldest)) = self.recent.pop_first() else {
+                        break;
+                    };
+                    self.buckets.remove(&oldest);
+                }
+                let bucket = Bucket {
+                    tokens: burst,
+                    updated: now,
+                    seen,
+                };
+                self.buckets.insert(address.to_string(), bucket);
+            }
+        }
+        self.recent.insert(seen, address.to_string());
+        seen
+    }
+}
+
+impl Middleware for RateLimit {
+    fn handle<'a>(
+        &'a self,
+        session: Session,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, Result<BaseResponse>> {
+        Box::pin(async move {
+            let key = match session.socket.peer() {
+                Ok(Peer::Tcp(_)) => session.request.get_ip().to_string(),
+                _ if self.local_per_connection => format!("connection {}", session.id()),
+                _ => "local".to_string(),
+            };
+            if let Err(retry_after) = self.acquire(&key) {
+                let seconds = retry_after.as_secs_f64().ceil() as u64;
+                let message = format!("Too many requests, retry after {seconds} seconds.");
+                return Ok(BaseResponse::StatusResponse(
+                    RATE_LIMIT_STATUS,
+                    message.into_bytes(),
+                ));
+            }
+            next.run(session).await
+        })
+    }
+}
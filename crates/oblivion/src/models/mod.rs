@@ -1,7 +1,20 @@
 pub mod client;
+pub mod extensions;
+pub mod extract;
+pub mod files;
+pub mod filter;
 pub mod handler;
+pub mod interceptor;
+pub mod manager;
+pub mod middleware;
 pub mod packet;
+pub mod pool;
+pub mod proxy;
+pub mod reconnect;
 pub mod render;
+pub mod resolver;
 pub mod router;
 pub mod server;
 pub mod session;
+#[cfg(feature = "tls")]
+pub mod tls;
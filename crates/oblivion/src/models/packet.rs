@@ -1,36 +1,496 @@
 //! # Oblivion Packets Encapsulation
 use crate::exceptions::Exception;
-use crate::utils::decryptor::decrypt_bytes;
-use crate::utils::encryptor::{encrypt_bytes, encrypt_plaintext};
-use crate::utils::gear::Socket;
-use crate::utils::generator::{generate_random_salt, SharedKey};
+use crate::utils::compression::{Algorithm, Compression};
+use crate::utils::decryptor::decrypt_in_place_with_aad;
+use crate::utils::encryptor::{encrypt_in_place, encrypt_in_place_with_nonce, encrypt_plaintext};
+use crate::utils::gear::{reading, Framing, Socket};
+use crate::utils::generator::{
+    check_salt_len, generate_random_salt, KexAlgorithm, SessionKeys, SharedKey, SALT_LEN,
+};
 use crate::utils::parser::length;
 
-use anyhow::Result;
+use std::borrow::Cow;
+use std::fmt;
+use std::future::Future;
+use std::io::IoSlice;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use bytes::{Buf, Bytes, BytesMut};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
 
-use ring::agreement::{EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::aead::{MAX_TAG_LEN, NONCE_LEN};
+use ring::agreement::{EphemeralPrivateKey, UnparsedPublicKey};
+use ring::rand::{SecureRandom, SystemRandom};
 
 const STOP_FLAG: [u8; 4] = u32::MIN.to_be_bytes();
 
+/// Sent instead of the nonce length of an [`OED`] packet streamed in frames, see
+/// [`OED::stream_from_reader`]. Peers that don't stream read it as an oversized frame.
+pub const STREAM_MARKER: u32 = u32::MAX;
+
+/// Bytes of the random identifier sent after [`STREAM_MARKER`], authenticated with every frame
+/// of the stream.
+const STREAM_ID_LEN: usize = 16;
+
+/// Flag of a streamed frame followed by more frames.
+const MORE_FRAMES: u32 = 1;
+/// Flag of the final frame of a stream.
+const LAST_FRAME: u32 = 0;
+
+/// Bytes of ciphertext in every chunk of an [`OED`] packet.
+const CHUNK_SIZE: usize = 1024;
+
+/// Packet of the protocol, sent to a [`Socket`] or encoded to bytes offline.
+///
+/// A packet is encoded to the bytes it is sent as, so that one end may store or relay them
+/// while the other reads them from a socket. Decoding may depend on more than these bytes,
+/// such as the key of an [`OED`], which the [`PacketContext`] provides.
+///
+/// ```rust
+/// # use oblivion::models::packet::{MessageFlags, Packet, PacketContext, OED, OKE, OSC};
+/// # use oblivion::utils::gear::Socket;
+/// # use oblivion::utils::generator::KexAlgorithm;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let key = [7; 16];
+/// let ctx = PacketContext::new(&key).message_flags(true);
+///
+/// let mut oed = OED::with_context(&ctx);
+/// oed.from_bytes(b"hello".to_vec())?;
+/// let bytes = oed.encode()?;
+/// assert_eq!(OED::decode(&bytes, &ctx)?.as_slice(), b"hello");
+/// assert!(OED::decode(&bytes[..bytes.len() - 1], &ctx).is_err());
+///
+/// let osc = OSC::from_u32(404u32).with_flags(MessageFlags::FINAL_FRAME);
+/// let decoded = OSC::decode(&osc.encode()?, &ctx)?;
+/// assert_eq!((decoded.status_code, decoded.flags), (404, osc.flags));
+///
+/// let (_, public_key) = KexAlgorithm::P256.generate_key_pair();
+/// let oke = OKE::new(None, KexAlgorithm::P256.public_key(public_key.as_ref().to_vec()));
+/// let ctx = ctx.kex_algorithm(KexAlgorithm::P256);
+/// let decoded = OKE::decode(&oke.encode()?, &ctx)?;
+/// assert_eq!(decoded.encode()?, oke.encode()?);
+///
+/// // Bytes encoded offline are read from a socket all the same.
+/// let (left, right) = Socket::pair();
+/// left.send(&bytes).await?;
+/// osc.write_to(&left).await?;
+/// oke.write_to(&left).await?;
+/// assert_eq!(OED::read_from(&right, &ctx).await?.as_slice(), b"hello");
+/// assert_eq!(OSC::read_from(&right, &ctx).await?.status_code, 404);
+/// let received = OKE::read_from(&right, &ctx).await?;
+/// assert_eq!(received.plain_data()?, oke.plain_data()?);
+/// # Ok(())
+/// # }
+/// ```
+pub trait Packet: Sized {
+    /// The packet decoded with a context borrowed for `'c`, `Self` unless it borrows it.
+    type Decoded<'c>;
+
+    /// Send the packet right after `leading` and before `trailing`, such as the bytes of the
+    /// packets around it, in a single write.
+    fn write_between(
+        &self,
+        socket: &Socket,
+        leading: &[u8],
+        trailing: &[u8],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Send the packet in a single write.
+    fn write_to(&self, socket: &Socket) -> impl Future<Output = Result<()>> + Send {
+        self.write_between(socket, &[], &[])
+    }
+
+    fn read_from<'c>(
+        socket: &Socket,
+        ctx: &'c PacketContext<'c>,
+    ) -> impl Future<Output = Result<Self::Decoded<'c>>> + Send;
+
+    /// Bytes of the packet on the wire.
+    fn encode(&self) -> Result<Vec<u8>>;
+
+    /// Packet encoded by [`Packet::encode`], failing unless `bytes` hold exactly one.
+    fn decode<'c>(bytes: &[u8], ctx: &'c PacketContext<'c>) -> Result<Self::Decoded<'c>>;
+
+    /// Decode a packet like [`Packet::decode`], taking `bytes` so that it may be decoded
+    /// within them rather than copied.
+    fn decode_owned<'c>(bytes: BytesMut, ctx: &'c PacketContext<'c>) -> Result<Self::Decoded<'c>> {
+        Self::decode(&bytes, ctx)
+    }
+}
+
+/// What decoding a [`Packet`] depends on besides its bytes, shared by the packets received
+/// in a direction of a session.
+#[derive(Clone, Copy, Default)]
+pub struct PacketContext<'a> {
+    /// Key of [`OED`] packets.
+    pub aes_key: &'a [u8],
+    /// Largest payload of [`OED`] packets, see [`OED::set_limit`].
+    pub limit: Option<usize>,
+    /// See [`OED::set_max_frame_size`].
+    pub max_frame_size: Option<usize>,
+    /// See [`OED::set_compression`].
+    pub compression: Option<Compression>,
+    /// See [`OED::set_aad`].
+    pub aad: &'a [u8],
+    /// Algorithm of the public key of [`OKE`] packets.
+    pub kex_algorithm: KexAlgorithm,
+    /// Whether [`OSC`] packets carry [`MessageFlags`], see [`OSC::to_bytes_with_flags`].
+    pub message_flags: bool,
+}
+
+impl<'a> PacketContext<'a> {
+    pub fn new(aes_key: &'a [u8]) -> Self {
+        Self {
+            aes_key,
+            ..Self::default()
+        }
+    }
+
+    pub fn limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn max_frame_size(mut self, size: Option<usize>) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    pub fn compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn aad(mut self, aad: &'a [u8]) -> Self {
+        self.aad = aad;
+        self
+    }
+
+    pub fn kex_algorithm(mut self, algorithm: KexAlgorithm) -> Self {
+        self.kex_algorithm = algorithm;
+        self
+    }
+
+    pub fn message_flags(mut self, enabled: bool) -> Self {
+        self.message_flags = enabled;
+        self
+    }
+}
+
+/// Status code of a message, carried by the [`OSC`] following its data.
+///
+/// Codes follow HTTP, those below `400` are successes and redirects, those from `400` on
+/// are errors of the client or of the server. Any `u32` is a code, those without a constant
+/// convert back and forth unchanged. The [`OSC`] preceding the data of a message carries its
+/// [`SessionFlag`](super::session::SessionFlag) instead.
+///
+/// ```rust
+/// # use oblivion::models::packet::{StatusCode, OSC};
+/// assert!(StatusCode::OK.is_success());
+/// assert!(StatusCode::NOT_FOUND.is_client_error());
+/// assert_eq!(StatusCode::INTERNAL_ERROR.to_string(), "500 Internal Server Error");
+///
+/// let teapot = StatusCode::from(418);
+/// assert!(teapot.is_error());
+/// assert_eq!(teapot.to_string(), "418");
+/// assert_eq!(OSC::from_u32(teapot).status(), teapot);
+/// assert_eq!(u32::from(teapot), 418);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StatusCode(u32);
+
+impl StatusCode {
+    /// Answer of a successful request, and status of the messages of the protocol itself.
+    pub const OK: Self = Self(200);
+    pub const CREATED: Self = Self(201);
+    /// Status of [`BaseResponse::RedirectResponse`](super::render::BaseResponse).
+    pub const TEMPORARY_REDIRECT: Self = Self(307);
+    pub const BAD_REQUEST: Self = Self(400);
+    pub const FORBIDDEN: Self = Self(403);
+    pub const NOT_FOUND: Self = Self(404);
+    pub const PAYLOAD_TOO_LARGE: Self = Self(413);
+    pub const TOO_MANY_REQUESTS: Self = Self(429);
+    pub const INTERNAL_ERROR: Self = Self(500);
+    pub const NOT_IMPLEMENTED: Self = Self(501);
+    pub const SERVICE_UNAVAILABLE: Self = Self(503);
+    pub const GATEWAY_TIMEOUT: Self = Self(504);
+
+    pub const fn new(code: u32) -> Self {
+        Self(code)
+    }
+
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    /// Codes below `400`, as for [`Response::is_success`](super::client::Response::is_success).
+    pub const fn is_success(self) -> bool {
+        self.0 < 400
+    }
+
+    /// Codes from `300` to `399`.
+    pub const fn is_redirect(self) -> bool {
+        300 <= self.0 && self.0 < 400
+    }
+
+    /// Codes from `400` on.
+    pub const fn is_error(self) -> bool {
+        self.0 >= 400
+    }
+
+    /// Codes from `400` to `499`.
+    pub const fn is_client_error(self) -> bool {
+        400 <= self.0 && self.0 < 500
+    }
+
+    /// Codes from `500` on.
+    pub const fn is_server_error(self) -> bool {
+        self.0 >= 500
+    }
+
+    /// Reason phrase of the codes with a constant.
+    pub const fn reason(self) -> Option<&'static str> {
+        Some(match self.0 {
+            200 => "OK",
+            201 => "Created",
+            307 => "Temporary Redirect",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            413 => "Payload Too Large",
+            429 => "Too Many Requests",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            _ => return None,
+        })
+    }
+}
+
+impl Default for StatusCode {
+    fn default() -> Self {
+        Self::OK
+    }
+}
+
+impl From<u32> for StatusCode {
+    fn from(code: u32) -> Self {
+        Self(code)
+    }
+}
+
+impl From<StatusCode> for u32 {
+    fn from(status: StatusCode) -> Self {
+        status.0
+    }
+}
+
+impl PartialEq<u32> for StatusCode {
+    fn eq(&self, code: &u32) -> bool {
+        self.0 == *code
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reason() {
+            Some(reason) => write!(f, "{} {}", self.0, reason),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Flags of a message carried after its status code, once both peers negotiated
+/// [`Capabilities::MESSAGE_FLAGS`](super::session::Capabilities::MESSAGE_FLAGS), see
+/// [`Session::send_with_flags`](super::session::Session::send_with_flags).
+///
+/// Their meaning is up to the application, sessions neither set them nor act on them. Bits
+/// without a constant are kept as they are.
+///
+/// ```rust
+/// # use oblivion::models::packet::{MessageFlags, StatusCode, OSC};
+/// # use oblivion::utils::gear::Socket;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let flags = MessageFlags::COMPRESSED | MessageFlags::FINAL_FRAME;
+/// assert!(flags.contains(MessageFlags::COMPRESSED));
+/// assert!(!flags.contains(MessageFlags::REKEY_FOLLOWS));
+///
+/// let osc = OSC::from_u32(StatusCode::OK).with_flags(flags | MessageFlags::from_bits(1 << 15));
+/// assert_eq!(osc.to_bytes_with_flags(), [0, 0, 0, 200, 0x80, 0b11]);
+/// // The legacy format carries the status code alone.
+/// assert_eq!(osc.to_bytes(), [0, 0, 0, 200]);
+///
+/// let (sender, receiver) = Socket::pair();
+/// sender.send(&osc.to_bytes_with_flags()).await?;
+/// let received = OSC::from_stream_with_flags(&receiver).await?;
+/// assert_eq!(received.status(), StatusCode::OK);
+/// assert_eq!(received.flags, osc.flags);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "u16", into = "u16")
+)]
+pub struct MessageFlags(u16);
+
+impl MessageFlags {
+    /// The application compressed the content itself.
+    pub const COMPRESSED: Self = Self(1);
+    /// Last message of a series sent by the application.
+    pub const FINAL_FRAME: Self = Self(1 << 1);
+    /// The sender rekeys after this message, see
+    /// [`Session::rekey`](super::session::Session::rekey).
+    pub const REKEY_FOLLOWS: Self = Self(1 << 2);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Flags from raw bits, keeping bits without a constant.
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for MessageFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl From<u16> for MessageFlags {
+    fn from(bits: u16) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<MessageFlags> for u16 {
+    fn from(flags: MessageFlags) -> Self {
+        flags.0
+    }
+}
+
 pub struct OSC {
     pub status_code: u32,
+    /// Flags following the status code, `None` in the legacy format without them.
+    pub flags: Option<MessageFlags>,
 }
 
 impl OSC {
-    pub fn from_u32(status_code: u32) -> Self {
-        Self { status_code }
+    /// Packet of a status code or of a [`SessionFlag`](super::session::SessionFlag).
+    pub fn from_u32(status_code: impl Into<u32>) -> Self {
+        Self {
+            status_code: status_code.into(),
+            flags: None,
+        }
+    }
+
+    /// Packet in the format of [`OSC::to_bytes_with_flags`].
+    pub fn with_flags(mut self, flags: MessageFlags) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        StatusCode(self.status_code)
     }
 
     pub async fn from_stream(stream: &Socket) -> Result<Self> {
-        Ok(Self {
-            status_code: stream.recv_u32().await?,
-        })
+        Self::read_from(stream, &PacketContext::default()).await
+    }
+
+    /// Read a packet sent by [`OSC::to_bytes_with_flags`].
+    pub async fn from_stream_with_flags(stream: &Socket) -> Result<Self> {
+        Self::read_from(stream, &PacketContext::default().message_flags(true)).await
     }
 
     pub async fn to_stream(&self, stream: &Socket) -> Result<()> {
-        stream.send(&self.status_code.to_be_bytes()).await?;
-        Ok(())
+        self.write_to(stream).await
+    }
+
+    /// Bytes of the packet on the wire, to send it along with others, see
+    /// [`Packet::write_between`].
+    pub fn to_bytes(&self) -> [u8; 4] {
+        self.status_code.to_be_bytes()
+    }
+
+    /// Bytes of the packet followed by its flags, the format of the [`OSC`] following the data
+    /// of messages once peers negotiated
+    /// [`Capabilities::MESSAGE_FLAGS`](super::session::Capabilities::MESSAGE_FLAGS).
+    pub fn to_bytes_with_flags(&self) -> [u8; 6] {
+        let mut bytes = [0; 6];
+        bytes[..4].copy_from_slice(&self.status_code.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.flags.unwrap_or_default().0.to_be_bytes());
+        bytes
+    }
+}
+
+impl Packet for OSC {
+    type Decoded<'c> = Self;
+
+    async fn write_between(&self, socket: &Socket, leading: &[u8], trailing: &[u8]) -> Result<()> {
+        write_between(socket, leading, &self.encode()?, trailing).await
+    }
+
+    async fn read_from(socket: &Socket, ctx: &PacketContext<'_>) -> Result<Self> {
+        let status_code = socket
+            .recv_u32()
+            .await
+            .map_err(reading("OSC status code"))?;
+        let flags = match ctx.message_flags {
+            true => {
+                let mut flags = socket.recv(2).await.map_err(reading("OSC flags"))?;
+                Some(MessageFlags(flags.get_u16()))
+            }
+            false => None,
+        };
+        Ok(Self { status_code, flags })
+    }
+
+    /// The status code, followed by the flags if the packet has any.
+    fn encode(&self) -> Result<Vec<u8>> {
+        Ok(match self.flags {
+            Some(_) => self.to_bytes_with_flags().to_vec(),
+            None => self.to_bytes().to_vec(),
+        })
+    }
+
+    fn decode(mut bytes: &[u8], ctx: &PacketContext<'_>) -> Result<Self> {
+        let len = if ctx.message_flags { 6 } else { 4 };
+        if bytes.len() != len {
+            return Err(anyhow!(
+                "OSC packet of {} bytes, {len} are expected",
+                bytes.len()
+            ));
+        }
+        let status_code = bytes.get_u32();
+        let flags = ctx.message_flags.then(|| MessageFlags(bytes.get_u16()));
+        Ok(Self { status_code, flags })
     }
 }
 
@@ -39,7 +499,8 @@ pub struct OKE {
     private_key: Option<EphemeralPrivateKey>,
     salt: Vec<u8>,
     remote_public_key: Option<UnparsedPublicKey<Vec<u8>>>,
-    shared_aes_key: Option<[u8; 16]>,
+    protocol_version: u32,
+    keys: Option<SessionKeys>,
 }
 
 impl OKE {
@@ -50,50 +511,123 @@ impl OKE {
         Self {
             public_key,
             private_key,
-            salt: generate_random_salt(),
+            salt: generate_random_salt(SALT_LEN),
             remote_public_key: None,
-            shared_aes_key: None,
+            protocol_version: 0,
+            keys: None,
         }
     }
 
+    /// Send a random salt of `len` bytes, see [`OKE::with_salt`].
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::packet::OKE;
+    /// # use oblivion::utils::generator::generate_key_pair;
+    /// # use ring::agreement::{UnparsedPublicKey, X25519};
+    /// let (private_key, public_key) = generate_key_pair();
+    /// let public_key = UnparsedPublicKey::new(&X25519, public_key.as_ref().to_vec());
+    /// let oke = OKE::new(Some(private_key), public_key).with_salt_len(32)?;
+    /// assert_eq!(oke.get_salt().len(), 32);
+    ///
+    /// let error = oke.with_salt_len(8).err().unwrap();
+    /// assert_eq!(error, Exception::InvalidSaltLength { len: 8, min: 16, max: 64 });
+    /// # Ok::<(), Exception>(())
+    /// ```
+    pub fn with_salt_len(self, len: usize) -> Result<Self, Exception> {
+        check_salt_len(len)?;
+        self.with_salt(generate_random_salt(len))
+    }
+
+    /// Send `salt` rather than a random one, such as one of a
+    /// [`SaltSource`](crate::utils::generator::SaltSource).
+    ///
+    /// Fails with [`Exception::InvalidSaltLength`] unless it is between
+    /// [`MIN_SALT_LEN`](crate::utils::generator::MIN_SALT_LEN) and
+    /// [`MAX_SALT_LEN`](crate::utils::generator::MAX_SALT_LEN) bytes long, the range of salts
+    /// accepted from the peer whatever the length of those sent.
+    pub fn with_salt(mut self, salt: Vec<u8>) -> Result<Self, Exception> {
+        check_salt_len(salt.len())?;
+        self.salt = salt;
+        Ok(self)
+    }
+
+    /// Derive the keys for `version` of the protocol, see [`SharedKey::session_keys`].
+    pub fn set_protocol_version(&mut self, version: u32) -> &mut Self {
+        self.protocol_version = version;
+        self
+    }
+
     pub fn from_public_key_bytes(&mut self, public_key_bytes: &[u8]) -> Result<&mut Self> {
-        self.public_key = UnparsedPublicKey::new(&X25519, public_key_bytes.to_owned());
+        let algorithm = self.public_key.algorithm();
+        self.public_key = UnparsedPublicKey::new(algorithm, public_key_bytes.to_owned());
         Ok(self)
     }
 
+    /// Packet of the public key of the peer, received or decoded, without any private key.
+    fn received(public_key: UnparsedPublicKey<Vec<u8>>) -> Self {
+        Self {
+            public_key,
+            private_key: None,
+            salt: Vec::new(),
+            remote_public_key: None,
+            protocol_version: 0,
+            keys: None,
+        }
+    }
+
+    /// Receive the public key of the peer, of the same algorithm as the one of this side.
+    async fn recv_remote_public_key(&mut self, stream: &Socket) -> Result<()> {
+        let received = Self::read_from(stream, &PacketContext::default()).await?;
+        let algorithm = self.public_key.algorithm();
+        let bytes = received.public_key.bytes().to_vec();
+        self.remote_public_key = Some(UnparsedPublicKey::new(algorithm, bytes));
+        Ok(())
+    }
+
     pub async fn from_stream(&mut self, stream: &Socket) -> Result<&mut Self> {
-        let remote_public_key_length = stream.recv_usize().await?;
-        let remote_public_key_bytes = stream.recv(remote_public_key_length).await?;
-        self.remote_public_key = Some(UnparsedPublicKey::new(&X25519, remote_public_key_bytes));
+        self.recv_remote_public_key(stream).await?;
         let mut shared_key = SharedKey::new(
             self.private_key.take().unwrap(),
             self.remote_public_key.as_ref().unwrap(),
         )?;
-        self.shared_aes_key = Some(shared_key.hkdf(&self.salt));
+        self.keys = Some(shared_key.session_keys(
+            &self.salt,
+            self.protocol_version,
+            self.remote_public_key.as_ref().unwrap().bytes(),
+            self.public_key.bytes(),
+        ));
         Ok(self)
     }
 
     pub async fn from_stream_with_salt(&mut self, stream: &Socket) -> Result<&mut Self> {
-        let remote_public_key_length = stream.recv_usize().await?;
-        let remote_public_key_bytes = stream.recv(remote_public_key_length).await?;
-        self.remote_public_key = Some(UnparsedPublicKey::new(&X25519, remote_public_key_bytes));
-        let salt_length = stream.recv_usize().await?;
-        self.salt = stream.recv(salt_length).await?;
+        self.recv_remote_public_key(stream).await?;
+        let len = stream.recv_usize().await.map_err(reading("OKE salt"))?;
+        check_salt_len(len)?;
+        self.salt = stream
+            .recv(len)
+            .await
+            .map_err(reading("OKE salt"))?
+            .to_vec();
         let mut shared_key = SharedKey::new(
             self.private_key.take().unwrap(),
             self.remote_public_key.as_ref().unwrap(),
         )?;
-        self.shared_aes_key = Some(shared_key.hkdf(&self.salt));
+        self.keys = Some(shared_key.session_keys(
+            &self.salt,
+            self.protocol_version,
+            self.public_key.bytes(),
+            self.remote_public_key.as_ref().unwrap().bytes(),
+        ));
         Ok(self)
     }
 
     pub async fn to_stream(&self, stream: &Socket) -> Result<()> {
-        stream.send(&self.plain_data()?).await?;
-        Ok(())
+        self.write_to(stream).await
     }
 
     pub async fn to_stream_with_salt(&self, stream: &Socket) -> Result<()> {
-        stream.send(&self.plain_data()?).await?;
+        self.write_to(stream).await?;
         stream.send(&self.plain_salt()?).await?;
         Ok(())
     }
@@ -111,40 +645,274 @@ impl OKE {
         Ok(plain_salt_bytes)
     }
 
+    /// Salt generated by the server and sent after its public key.
+    pub fn get_salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    /// Key of the messages of the client, the only one below
+    /// [`DIRECTIONAL_KEYS_VERSION`](crate::utils::generator::DIRECTIONAL_KEYS_VERSION).
     pub fn get_aes_key(&self) -> [u8; 16] {
-        self.shared_aes_key.unwrap()
+        self.keys.unwrap().client_to_server
+    }
+
+    pub fn get_session_keys(&self) -> SessionKeys {
+        self.keys.unwrap()
+    }
+
+    /// Raw public key presented by the peer, once received.
+    pub fn get_remote_public_key(&self) -> Option<&[u8]> {
+        self.remote_public_key.as_ref().map(|key| key.bytes().as_slice())
+    }
+}
+
+/// The public key of a side, the salt the server follows it with is not part of the packet.
+impl Packet for OKE {
+    type Decoded<'c> = Self;
+
+    async fn write_between(&self, socket: &Socket, leading: &[u8], trailing: &[u8]) -> Result<()> {
+        write_between(socket, leading, &self.plain_data()?, trailing).await
+    }
+
+    async fn read_from(socket: &Socket, ctx: &PacketContext<'_>) -> Result<Self> {
+        let public_key = recv_chunk(socket, "OKE public key").await?;
+        Ok(Self::received(ctx.kex_algorithm.public_key(public_key)))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        self.plain_data()
+    }
+
+    fn decode(bytes: &[u8], ctx: &PacketContext<'_>) -> Result<Self> {
+        let len = read_u32(bytes, 0).ok_or_else(|| anyhow!("Truncated OKE packet"))?;
+        if bytes.len() != 4 + len {
+            return Err(anyhow!(
+                "OKE packet of {} bytes, {} are expected",
+                bytes.len(),
+                4 + len
+            ));
+        }
+        let public_key = ctx.kex_algorithm.public_key(bytes[4..].to_vec());
+        Ok(Self::received(public_key))
     }
 }
 
 pub struct OED<'a> {
-    aes_key: &'a [u8],
-    data: Option<Vec<u8>>,
+    /// Key, limits and associated data, borrowed from a [`PacketContext`] until a setter
+    /// changes them.
+    ctx: Cow<'a, PacketContext<'a>>,
+    data: Option<Bytes>,
     encrypted_data: Vec<u8>,
     tag: Vec<u8>,
     nonce: Vec<u8>,
     chunk_count: u32,
+    /// Size of the packet once received, its ciphertext being decrypted in place.
+    frame_size: Option<usize>,
+    /// Algorithm the data was compressed with, tagged in the header along with compression.
+    algorithm: Algorithm,
+    fixed_nonce: Option<[u8; NONCE_LEN]>,
 }
 
 impl<'a> OED<'a> {
+    /// Packet encrypted or decrypted with the key of `ctx`, within its limits and along with
+    /// its associated data, see [`Packet::read_from`].
+    pub fn with_context(ctx: &'a PacketContext<'a>) -> Self {
+        Self::from_context(Cow::Borrowed(ctx))
+    }
+
     pub fn new(aes_key: &'a [u8]) -> Self {
+        Self::from_context(Cow::Owned(PacketContext::new(aes_key)))
+    }
+
+    fn from_context(ctx: Cow<'a, PacketContext<'a>>) -> Self {
         Self {
-            aes_key,
+            ctx,
             data: None,
             encrypted_data: Vec::new(),
             tag: Vec::new(),
             nonce: Vec::new(),
             chunk_count: 0,
+            frame_size: None,
+            algorithm: Algorithm::None,
+            fixed_nonce: None,
+        }
+    }
+
+    /// Largest payload accepted from the peer, `None` by default.
+    ///
+    /// A packet fails with [`Exception::PayloadTooLarge`] as soon as the length prefixes of
+    /// its chunks add up to more, before the chunk crossing the limit is read.
+    ///
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::packet::OED;
+    /// # use oblivion::utils::gear::Socket;
+    /// let key = [7; 16];
+    /// let (left, right) = Socket::pair();
+    /// OED::new(&key).from_bytes(vec![0; 1500])?.to_stream(&left).await?;
+    ///
+    /// let mut oed = OED::new(&key);
+    /// let error = oed.set_limit(Some(1024)).from_stream(&right).await.err().unwrap();
+    /// assert_eq!(
+    ///     Exception::from_error(&error),
+    ///     Exception::PayloadTooLarge { declared: 1500, limit: 1024 }
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_limit(&mut self, limit: Option<usize>) -> &mut Self {
+        self.ctx.to_mut().limit = limit;
+        self
+    }
+
+    /// Largest length prefix [`OED::frame_size`] accepts before failing with
+    /// [`Exception::FrameTooLarge`], see [`Socket::set_max_frame_size`].
+    pub fn set_max_frame_size(&mut self, size: Option<usize>) -> &mut Self {
+        self.ctx.to_mut().max_frame_size = size;
+        self
+    }
+
+    /// Compress the data of [`OED::from_bytes`] and decompress the data received, or neither
+    /// if `None`.
+    ///
+    /// The tag of the [`Algorithm`] follows the tag of the packet in its header, where it is
+    /// authenticated along with the data rather than encrypted with it.
+    ///
+    /// Both sides must agree on it, sessions do once they negotiated
+    /// [`Capabilities::COMPRESSION`](crate::models::session::Capabilities::COMPRESSION).
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::packet::{Packet, OED};
+    /// # use oblivion::utils::compression::{Algorithm, Compression};
+    /// # fn main() -> anyhow::Result<()> {
+    /// let key = [7; 16];
+    /// let compression = Some(Compression::default());
+    /// let mut oed = OED::new(&key);
+    /// oed.set_compression(compression).from_bytes(b"hello".to_vec())?;
+    /// let mut bytes = oed.encode()?;
+    ///
+    /// // The algorithm follows the lengths, the nonce and the tag.
+    /// let algorithm = 8 + oed.nonce().len() + 16;
+    /// assert_eq!(bytes[algorithm], Algorithm::None.tag());
+    /// bytes[algorithm] = Algorithm::Zstd.tag();
+    /// let error = OED::new(&key)
+    ///     .set_compression(compression)
+    ///     .from_frame(&bytes)
+    ///     .err()
+    ///     .unwrap();
+    /// assert!(matches!(Exception::from_error(&error), Exception::DecryptError { .. }));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_compression(&mut self, compression: Option<Compression>) -> &mut Self {
+        self.ctx.to_mut().compression = compression;
+        self
+    }
+
+    /// Authenticate `aad`, such as the cleartext framing around the packet, along with the
+    /// data of [`OED::from_bytes`], and check the data received was encrypted along with it.
+    ///
+    /// Received packets whose data doesn't match fail with [`Exception::Tampered`].
+    ///
+    /// The length fields of the packet are not part of `aad`. Those of the nonce and the tag
+    /// delimit values the decryption depends on, and the chunk lengths only split the
+    /// ciphertext, which the tag authenticates as a whole: splitting it differently yields the
+    /// same data and any other change fails the decryption.
+    ///
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::packet::OED;
+    /// # use oblivion::utils::gear::Socket;
+    /// let key = [7; 16];
+    /// let (left, right) = Socket::pair();
+    /// for _ in 0..2 {
+    ///     OED::new(&key)
+    ///         .set_aad(b"status 200")
+    ///         .from_bytes(b"hello".to_vec())?
+    ///         .to_stream(&left)
+    ///         .await?;
+    /// }
+    ///
+    /// let mut oed = OED::new(&key);
+    /// oed.set_aad(b"status 200").from_stream(&right).await?;
+    /// assert_eq!(oed.as_slice(), b"hello");
+    /// let mut oed = OED::new(&key);
+    /// let error = oed.set_aad(b"status 500").from_stream(&right).await.err().unwrap();
+    /// assert_eq!(Exception::from_error(&error), Exception::Tampered);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_aad(&mut self, aad: &'a [u8]) -> &mut Self {
+        self.ctx.to_mut().aad = aad;
+        self
+    }
+
+    /// Encrypt the data of [`OED::from_bytes`] with `nonce` rather than a random one, `None`
+    /// by default.
+    ///
+    /// The caller must never use a nonce twice under the same key, sessions count them once
+    /// they negotiated
+    /// [`Capabilities::SEQUENCED_NONCES`](crate::models::session::Capabilities::SEQUENCED_NONCES).
+    pub fn set_nonce(&mut self, nonce: Option<[u8; NONCE_LEN]>) -> &mut Self {
+        self.fixed_nonce = nonce;
+        self
+    }
+
+    /// Nonce the data was encrypted with.
+    pub fn nonce(&self) -> &[u8] {
+        &self.nonce
+    }
+
+    /// Largest encrypted payload accepted. The limit applies to the data once decompressed,
+    /// but data is only sent compressed if that makes it smaller so it bounds both.
+    fn wire_limit(&self) -> usize {
+        self.ctx.limit.unwrap_or(usize::MAX)
+    }
+
+    /// Bytes of the header following the tag, the tag of the algorithm along with compression.
+    fn algorithm_len(&self) -> usize {
+        self.ctx.compression.map_or(0, |_| 1)
+    }
+
+    /// Data the ciphertext is authenticated along with, including the tag of the algorithm
+    /// along with compression.
+    fn full_aad(&self) -> Vec<u8> {
+        let mut aad = self.ctx.aad.to_vec();
+        if self.ctx.compression.is_some() {
+            aad.push(self.algorithm.tag());
+        }
+        aad
+    }
+
+    fn check_payload(&self, declared: usize) -> Result<(), Exception> {
+        if declared > self.wire_limit() {
+            let limit = self.ctx.limit.unwrap_or(usize::MAX);
+            return Err(Exception::PayloadTooLarge { declared, limit });
+        }
+        Ok(())
+    }
+
+    fn check_frame(&self, declared: usize) -> Result<usize, Exception> {
+        match self.ctx.max_frame_size {
+            Some(limit) if declared > limit => Err(Exception::FrameTooLarge { declared, limit }),
+            _ => Ok(declared),
         }
     }
 
     pub fn from_json_or_string(&mut self, json_or_str: String) -> Result<&mut Self, Exception> {
-        (self.encrypted_data, self.tag, self.nonce) = encrypt_plaintext(json_or_str, self.aes_key)?;
+        (self.encrypted_data, self.tag, self.nonce) =
+            encrypt_plaintext(json_or_str, self.ctx.aes_key)?;
         Ok(self)
     }
 
     pub fn from_dict(&mut self, dict: Value) -> Result<&mut Self, Exception> {
         (self.encrypted_data, self.tag, self.nonce) =
-            encrypt_plaintext(dict.to_string(), self.aes_key)?;
+            encrypt_plaintext(dict.to_string(), self.ctx.aes_key)?;
         Ok(self)
     }
 
@@ -153,65 +921,590 @@ impl<'a> OED<'a> {
         self
     }
 
-    pub fn from_bytes(&mut self, data: Vec<u8>) -> Result<&mut Self, Exception> {
-        (self.encrypted_data, self.tag, self.nonce) = encrypt_bytes(data, self.aes_key)?;
+    pub fn from_bytes(&mut self, mut data: Vec<u8>) -> Result<&mut Self, Exception> {
+        if let Some(compression) = &self.ctx.compression {
+            (data, self.algorithm) = compression.compress(data)?;
+        }
+        let aad = self.full_aad();
+        (self.tag, self.nonce) = match self.fixed_nonce {
+            Some(nonce) => (
+                encrypt_in_place_with_nonce(&mut data, self.ctx.aes_key, &aad, &nonce)?,
+                nonce.to_vec(),
+            ),
+            None => encrypt_in_place(&mut data, self.ctx.aes_key, &aad)?,
+        };
+        self.encrypted_data = data;
         Ok(self)
     }
 
     pub async fn from_stream(&mut self, stream: &Socket) -> Result<&mut Self> {
-        let len_nonce = stream.recv_usize().await?;
-        let len_tag = stream.recv_usize().await?;
+        let len_nonce = stream.recv_usize().await.map_err(reading("OED nonce"))?;
+        self.recv_after(stream, len_nonce).await
+    }
+
+    /// Receive the rest of a packet whose nonce length was received already.
+    async fn recv_after(&mut self, stream: &Socket, len_nonce: usize) -> Result<&mut Self> {
+        let len_tag = stream.recv_usize().await.map_err(reading("OED tag"))?;
 
-        self.nonce = stream.recv(len_nonce).await?;
-        self.tag = stream.recv(len_tag).await?;
+        self.nonce = stream
+            .recv(len_nonce)
+            .await
+            .map_err(reading("OED nonce"))?
+            .to_vec();
+        self.tag = stream
+            .recv(len_tag)
+            .await
+            .map_err(reading("OED tag"))?
+            .to_vec();
+        if self.ctx.compression.is_some() {
+            let tag = stream.recv(1).await.map_err(reading("OED algorithm"))?;
+            self.algorithm = Algorithm::from_tag(tag[0])?;
+        }
 
         let mut encrypted_data: Vec<u8> = Vec::new();
         self.chunk_count = 0;
 
         loop {
-            let prefix = stream.recv_usize().await?;
+            let prefix = stream.recv_usize().await.map_err(reading("OED payload"))?;
             if prefix == 0 {
                 self.encrypted_data = encrypted_data;
                 break;
             }
+            let size = encrypted_data.len() + prefix;
+            self.check_payload(size)?;
+
+            let chunk = stream.recv(prefix).await.map_err(reading("OED payload"))?;
+            encrypted_data.extend_from_slice(&chunk);
+            self.chunk_count += 1;
+        }
+
+        Ok(self.decrypt()?)
+    }
+
+    /// Encrypt what `reader` yields and send it in frames of `chunk_size` bytes as it is read,
+    /// rather than holding the whole payload in memory. Returns the number of bytes sent.
+    ///
+    /// Every frame is encrypted with a nonce of its own and authenticated along with a random
+    /// identifier of the stream, its position in it and whether it is the final frame, so
+    /// frames can't be dropped, reordered, cut short or spliced in from another stream under
+    /// the same key unnoticed. Streamed packets start with [`STREAM_MARKER`] and the
+    /// identifier, and are received by [`OED::stream_to_writer`].
+    ///
+    /// Fails with [`Exception::EmptyChunkSize`] if `chunk_size` is 0, before sending anything.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::packet::OED;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let key = [7; 16];
+    /// let (client, server) = Socket::pair();
+    /// let receiver = tokio::spawn(async move {
+    ///     let mut received = Vec::new();
+    ///     OED::new(&key).stream_to_writer(&server, &mut received).await?;
+    ///     anyhow::Ok(received)
+    /// });
+    ///
+    /// let payload: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+    /// let sent = OED::new(&key)
+    ///     .stream_from_reader(&client, &payload[..], 4096)
+    ///     .await?;
+    /// assert_eq!(sent, 10_000);
+    /// assert_eq!(receiver.await??, payload);
+    ///
+    /// // Packets sent whole are received all the same.
+    /// let (client, server) = Socket::pair();
+    /// OED::new(&key).from_bytes(b"whole".to_vec())?.to_stream(&client).await?;
+    /// let mut received = Vec::new();
+    /// OED::new(&key).stream_to_writer(&server, &mut received).await?;
+    /// assert_eq!(received, b"whole");
+    ///
+    /// // The sender stops after two frames, before the final one.
+    /// let (client, server) = Socket::pair();
+    /// let receiver = tokio::spawn(async move {
+    ///     OED::new(&key).stream_to_writer(&server, tokio::io::sink()).await
+    /// });
+    /// let (mut input, reader) = tokio::io::duplex(16 * 1024);
+    /// tokio::io::AsyncWriteExt::write_all(&mut input, &[0; 8192]).await?;
+    /// let mut oed = OED::new(&key);
+    /// let sending = oed.stream_from_reader(&client, reader, 4096);
+    /// let _ = tokio::time::timeout(std::time::Duration::from_millis(50), sending).await;
+    /// client.close().await?;
+    ///
+    /// let error = receiver.await?.unwrap_err();
+    /// let truncated = Exception::TruncatedStream {
+    ///     frames: 2,
+    ///     received: 8192,
+    /// };
+    /// assert_eq!(error.downcast_ref(), Some(&truncated));
+    /// assert!(!truncated.is_transient());
+    ///
+    /// let error = OED::new(&key).stream_from_reader(&client, &payload[..], 0).await;
+    /// assert_eq!(error.unwrap_err().downcast_ref(), Some(&Exception::EmptyChunkSize));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn stream_from_reader(
+        &mut self,
+        stream: &Socket,
+        mut reader: impl AsyncRead + Unpin,
+        chunk_size: usize,
+    ) -> Result<u64> {
+        if chunk_size == 0 {
+            return Err(Exception::EmptyChunkSize.into());
+        }
+        let stream_id = random_stream_id()?;
+        stream.send(&STREAM_MARKER.to_be_bytes()).await?;
+        stream.send(&stream_id).await?;
 
-            let mut add: Vec<u8> = Vec::new();
-            while add.len() != prefix {
-                add.extend(stream.recv(prefix - add.len()).await?)
+        let mut buffer = vec![0; chunk_size];
+        let mut sent = 0u64;
+        for index in 0u64.. {
+            let len = read_chunk(&mut reader, &mut buffer).await?;
+            let last = len < chunk_size;
+            let frame = &mut buffer[..len];
+            let header = self.seal_frame(&stream_id, index, last, frame)?;
+            stream
+                .send_vectored(&[IoSlice::new(&header), IoSlice::new(frame)])
+                .await?;
+            sent += len as u64;
+            self.chunk_count += 1;
+            if last {
+                break;
             }
+        }
+        Ok(sent)
+    }
+
+    /// Encrypt `frame` in place as the frame `index` of the stream `stream_id`, returning the
+    /// header preceding it: its flag, nonce, tag and length.
+    fn seal_frame(
+        &self,
+        stream_id: &[u8],
+        index: u64,
+        last: bool,
+        frame: &mut [u8],
+    ) -> Result<Vec<u8>, Exception> {
+        let aad = frame_aad(stream_id, index, last);
+        let (tag, nonce) = encrypt_in_place(frame, self.ctx.aes_key, &aad)?;
+        let flag = if last { LAST_FRAME } else { MORE_FRAMES };
+        let mut header = Vec::with_capacity(8 + NONCE_LEN + MAX_TAG_LEN);
+        header.extend_from_slice(&flag.to_be_bytes());
+        header.extend_from_slice(&nonce);
+        header.extend_from_slice(&tag);
+        header.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        Ok(header)
+    }
+
+    /// Receive a packet and write its data to `writer`, decrypting a streamed packet frame by
+    /// frame as it arrives. Returns the number of bytes written.
+    ///
+    /// Packets sent whole are received like [`OED::from_stream`] does. A stream that ends
+    /// before its final frame fails with [`Exception::TruncatedStream`], after the data of
+    /// the frames before it was written.
+    pub async fn stream_to_writer(
+        &mut self,
+        stream: &Socket,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<u64> {
+        let marker = stream.recv_u32().await.map_err(reading("OED nonce"))?;
+        if marker != STREAM_MARKER {
+            self.recv_after(stream, marker as usize).await?;
+            let data = self.as_slice();
+            writer.write_all(data).await?;
+            writer.flush().await?;
+            return Ok(data.len() as u64);
+        }
+
+        let stream_id = stream
+            .recv(STREAM_ID_LEN)
+            .await
+            .map_err(reading("OED stream id"))?;
+        let mut received = 0u64;
+        let mut frame = Vec::new();
+        for index in 0u64.. {
+            let truncated = |error: anyhow::Error| match error.downcast_ref() {
+                Some(Exception::UnexpectedEof { .. } | Exception::ConnectionClosed) => {
+                    Exception::TruncatedStream {
+                        frames: index,
+                        received,
+                    }
+                    .into()
+                }
+                _ => error,
+            };
+            let last = match stream.recv_u32().await.map_err(truncated)? {
+                LAST_FRAME => true,
+                MORE_FRAMES => false,
+                flag => return Err(anyhow!("Invalid flag {flag} of OED stream frame {index}")),
+            };
+            let nonce = stream.recv(NONCE_LEN).await.map_err(truncated)?;
+            let tag = stream.recv(MAX_TAG_LEN).await.map_err(truncated)?;
+            let len = stream.recv_usize().await.map_err(truncated)?;
+            let len = self.check_frame(len)?;
+            let size = received as usize + len;
+            self.check_payload(size)?;
 
-            encrypted_data.extend(add);
+            frame.clear();
+            frame.extend_from_slice(&stream.recv(len).await.map_err(truncated)?);
+            let aad = frame_aad(&stream_id, index, last);
+            let data = decrypt_in_place_with_aad(&mut frame, &tag, self.ctx.aes_key, &nonce, &aad)
+                .map_err(|error| Exception::DecryptError { error })?;
+            writer.write_all(data).await?;
+            received += len as u64;
             self.chunk_count += 1;
+            if last {
+                break;
+            }
+        }
+        writer.flush().await?;
+        Ok(received)
+    }
+
+    /// Size of the packet at the start of `buffer`, or how many bytes it needs at least to
+    /// measure it while it is incomplete.
+    ///
+    /// Fails with [`Exception::PayloadTooLarge`] as soon as the encrypted payload crosses the
+    /// limit.
+    pub fn frame_size(&self, buffer: &[u8]) -> Result<Framing<usize>, Exception> {
+        let (Some(len_nonce), Some(len_tag)) = (read_u32(buffer, 0), read_u32(buffer, 4)) else {
+            return Ok(Framing::Incomplete { needed: 8 });
+        };
+        let mut offset = 8usize
+            .saturating_add(self.check_frame(len_nonce)?)
+            .saturating_add(self.check_frame(len_tag)?)
+            .saturating_add(self.algorithm_len());
+        let mut size = 0usize;
+        loop {
+            let Some(prefix) = read_u32(buffer, offset) else {
+                return Ok(Framing::Incomplete {
+                    needed: offset.saturating_add(4),
+                });
+            };
+            offset += 4;
+            if prefix == 0 {
+                return Ok(Framing::Complete(offset));
+            }
+            self.check_frame(prefix)?;
+            size = size.saturating_add(prefix);
+            self.check_payload(size)?;
+            offset = offset.saturating_add(prefix);
         }
+    }
+
+    /// Decode a packet received as a whole, as measured by [`OED::frame_size`].
+    pub fn from_frame(&mut self, frame: &[u8]) -> Result<&mut Self> {
+        self.from_owned_frame(BytesMut::from(frame))
+    }
+
+    /// Decode a packet received as a whole like [`OED::from_frame`], decrypting it within
+    /// `frame` so that the data is a slice of it, see [`OED::take`].
+    pub fn from_owned_frame(&mut self, mut frame: BytesMut) -> Result<&mut Self> {
+        let truncated = || anyhow!("Truncated OED packet");
+        let len_nonce = read_u32(&frame, 0).ok_or_else(truncated)?;
+        let len_tag = read_u32(&frame, 4).ok_or_else(truncated)?;
+        let tag_end = 8 + len_nonce + len_tag;
+        let start = tag_end + self.algorithm_len();
+        self.nonce = frame.get(8..8 + len_nonce).ok_or_else(truncated)?.to_vec();
+        self.tag = frame
+            .get(8 + len_nonce..tag_end)
+            .ok_or_else(truncated)?
+            .to_vec();
+        if self.ctx.compression.is_some() {
+            let tag = *frame.get(tag_end).ok_or_else(truncated)?;
+            self.algorithm = Algorithm::from_tag(tag)?;
+        }
+        self.frame_size = Some(frame.len());
 
-        match decrypt_bytes(
-            self.encrypted_data.clone(),
+        // Move the chunks next to each other over their length prefixes.
+        self.encrypted_data.clear();
+        self.chunk_count = 0;
+        let (mut offset, mut end) = (start, start);
+        loop {
+            let prefix = read_u32(&frame, offset).ok_or_else(truncated)?;
+            offset += 4;
+            if prefix == 0 {
+                break;
+            }
+            let chunk = offset..offset + prefix;
+            frame.get(chunk.clone()).ok_or_else(truncated)?;
+            frame.copy_within(chunk, end);
+            self.chunk_count += 1;
+            offset += prefix;
+            end += prefix;
+        }
+        frame.truncate(end);
+        frame.advance(start);
+
+        self.decrypt_in(frame)?;
+        Ok(self)
+    }
+
+    /// Decrypt the ciphertext received, moving it into the buffer holding the data.
+    fn decrypt(&mut self) -> Result<&mut Self, Exception> {
+        self.frame_size = Some(self.wire_size());
+        let encrypted_data = std::mem::take(&mut self.encrypted_data);
+        self.decrypt_in(BytesMut::from(Bytes::from(encrypted_data)))?;
+        Ok(self)
+    }
+
+    /// Decrypt the ciphertext in `buffer`, keeping the data in it.
+    fn decrypt_in(&mut self, mut buffer: BytesMut) -> Result<(), Exception> {
+        match decrypt_in_place_with_aad(
+            &mut buffer,
             &self.tag,
-            self.aes_key,
+            self.ctx.aes_key,
             &self.nonce,
+            &self.full_aad(),
         ) {
             Ok(data) => {
-                self.data = Some(data);
-                Ok(self)
+                let len = data.len();
+                buffer.truncate(len);
+                let data = buffer.freeze();
+                self.data = Some(match self.ctx.compression {
+                    Some(compression) => {
+                        let max_size = self.ctx.limit.map_or(compression.max_size, |limit| {
+                            limit.min(compression.max_size)
+                        });
+                        compression
+                            .max_size(max_size)
+                            .decompress(data, self.algorithm)?
+                    }
+                    None => data,
+                });
+                Ok(())
             }
-            Err(error) => Err(Exception::DecryptError { error }.into()),
+            Err(_) if !self.ctx.aad.is_empty() => Err(Exception::Tampered),
+            Err(error) => Err(Exception::DecryptError { error }),
         }
     }
 
     pub async fn to_stream(&mut self, stream: &Socket) -> Result<()> {
-        stream.send(&self.plain_data()?).await?;
+        self.chunk_count = self.send_between(stream, &[], &[]).await?;
+        Ok(())
+    }
 
-        self.chunk_count = 0;
-        let chunks = self.encrypted_data.chunks(1024);
-        for chunk in chunks {
-            let chunk_size = chunk.len() as u32;
-            stream.send(&chunk_size.to_be_bytes()).await?;
-            stream.send(chunk).await?;
+    /// Send the packet like [`Packet::write_between`], returning the number of its chunks.
+    async fn send_between(&self, stream: &Socket, leading: &[u8], trailing: &[u8]) -> Result<u32> {
+        let header = self.plain_data()?;
+        let chunks: Vec<&[u8]> = self.encrypted_data.chunks(CHUNK_SIZE).collect();
+        let prefixes: Vec<[u8; 4]> = chunks
+            .iter()
+            .map(|chunk| (chunk.len() as u32).to_be_bytes())
+            .collect();
+
+        let mut slices = Vec::with_capacity(chunks.len() * 2 + 4);
+        slices.push(IoSlice::new(leading));
+        slices.push(IoSlice::new(&header));
+        for (prefix, chunk) in prefixes.iter().zip(&chunks) {
+            slices.push(IoSlice::new(prefix));
+            slices.push(IoSlice::new(chunk));
+        }
+        slices.push(IoSlice::new(&STOP_FLAG));
+        slices.push(IoSlice::new(trailing));
+        stream.send_vectored(&slices).await?;
+        Ok(chunks.len() as u32)
+    }
+
+    /// Encrypt what `reader` yields into the file at `path` in frames of `chunk_size` bytes,
+    /// framed like [`OED::stream_from_reader`] sends them, to be read back with
+    /// [`OED::from_file`]. Returns the number of bytes encrypted.
+    ///
+    /// Only one frame is held in memory at a time, however large the data. Fails with
+    /// [`Exception::EmptyChunkSize`] if `chunk_size` is 0, before creating the file.
+    ///
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::packet::OED;
+    /// let key = [7; 16];
+    /// let path = std::env::temp_dir().join(format!("oblivion-{}.oed", std::process::id()));
+    /// let data = b"release signing key".repeat(200);
+    /// OED::new(&key).to_file(&data[..], &path, 1024).await?;
+    /// let mut decrypted = Vec::new();
+    /// OED::new(&key).from_file(&path, &mut decrypted).await?;
+    /// assert_eq!(decrypted, data);
+    ///
+    /// let mut file = tokio::fs::read(&path).await?;
+    /// file[100] ^= 1;
+    /// tokio::fs::write(&path, &file).await?;
+    /// let error = OED::new(&key).from_file(&path, tokio::io::sink()).await.unwrap_err();
+    /// assert!(matches!(Exception::from_error(&error), Exception::DecryptError { .. }));
+    ///
+    /// file[100] ^= 1;
+    /// tokio::fs::write(&path, &file[..2000]).await?;
+    /// let error = OED::new(&key).from_file(&path, tokio::io::sink()).await.unwrap_err();
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "File is corrupted at byte 1116: frame of 1024 bytes runs past the end of the file."
+    /// );
+    /// # tokio::fs::remove_file(&path).await?;
+    ///
+    /// let error = OED::new(&key).to_file(&data[..], &path, 0).await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::EmptyChunkSize));
+    /// assert!(!path.exists());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Neither side buffers more than a frame:
+    ///
+    /// ```rust
+    /// # use std::io;
+    /// # use std::pin::Pin;
+    /// # use std::task::{Context, Poll};
+    /// # use oblivion::models::packet::OED;
+    /// # use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+    /// /// Yields `left` bytes and swallows what is written, recording the largest read or write.
+    /// struct Peak {
+    ///     left: usize,
+    ///     peak: usize,
+    /// }
+    ///
+    /// impl AsyncRead for Peak {
+    ///     fn poll_read(
+    ///         mut self: Pin<&mut Self>,
+    ///         _: &mut Context<'_>,
+    ///         buf: &mut ReadBuf<'_>,
+    ///     ) -> Poll<io::Result<()>> {
+    ///         self.peak = self.peak.max(buf.remaining());
+    ///         let len = buf.remaining().min(self.left);
+    ///         buf.initialize_unfilled_to(len).fill(7);
+    ///         buf.advance(len);
+    ///         self.left -= len;
+    ///         Poll::Ready(Ok(()))
+    ///     }
+    /// }
+    ///
+    /// impl AsyncWrite for Peak {
+    ///     fn poll_write(
+    ///         mut self: Pin<&mut Self>,
+    ///         _: &mut Context<'_>,
+    ///         buf: &[u8],
+    ///     ) -> Poll<io::Result<usize>> {
+    ///         self.peak = self.peak.max(buf.len());
+    ///         Poll::Ready(Ok(buf.len()))
+    ///     }
+    ///
+    ///     fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+    ///         Poll::Ready(Ok(()))
+    ///     }
+    ///
+    ///     fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+    ///         Poll::Ready(Ok(()))
+    ///     }
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let key = [7; 16];
+    /// let path = std::env::temp_dir().join(format!("oblivion-{}-large.oed", std::process::id()));
+    /// let (size, frame) = (8 << 20, 64 * 1024);
+    ///
+    /// let mut source = Peak { left: size, peak: 0 };
+    /// assert_eq!(OED::new(&key).to_file(&mut source, &path, frame).await?, size as u64);
+    /// assert!(source.peak <= frame);
+    ///
+    /// let mut sink = Peak { left: 0, peak: 0 };
+    /// assert_eq!(OED::new(&key).from_file(&path, &mut sink).await?, size as u64);
+    /// assert!(sink.peak <= frame);
+    /// # tokio::fs::remove_file(&path).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn to_file(
+        &mut self,
+        mut reader: impl AsyncRead + Unpin,
+        path: impl AsRef<Path>,
+        chunk_size: usize,
+    ) -> Result<u64> {
+        if chunk_size == 0 {
+            return Err(Exception::EmptyChunkSize.into());
+        }
+        let stream_id = random_stream_id()?;
+        let mut file = BufWriter::new(File::create(path).await?);
+        file.write_all(&STREAM_MARKER.to_be_bytes()).await?;
+        file.write_all(&stream_id).await?;
+
+        let mut buffer = vec![0; chunk_size];
+        let mut written = 0u64;
+        for index in 0u64.. {
+            let len = read_chunk(&mut reader, &mut buffer).await?;
+            let last = len < chunk_size;
+            let frame = &mut buffer[..len];
+            file.write_all(&self.seal_frame(&stream_id, index, last, frame)?)
+                .await?;
+            file.write_all(frame).await?;
+            written += len as u64;
             self.chunk_count += 1;
+            if last {
+                break;
+            }
         }
-        stream.send(&STOP_FLAG).await?;
+        file.flush().await?;
+        Ok(written)
+    }
 
-        Ok(())
+    /// Decrypt a file written by [`OED::to_file`] to `writer` frame by frame, returning the
+    /// number of bytes written.
+    ///
+    /// A file cut short or whose framing was altered fails with [`Exception::CorruptedFile`]
+    /// at the offset its framing stops making sense, altered nonces, tags or ciphertexts
+    /// fail with [`Exception::DecryptError`]. The data of the frames before is written by
+    /// then, so it must not be trusted unless the whole file was read.
+    pub async fn from_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<u64> {
+        let file = File::open(path).await?;
+        let len = file.metadata().await?.len();
+        let mut file = FileReader {
+            reader: BufReader::new(file),
+            offset: 0,
+            len,
+        };
+
+        if file.read_u32("stream marker").await? != STREAM_MARKER as usize {
+            let reason = "it doesn't start with the stream marker".to_string();
+            return Err(Exception::CorruptedFile { offset: 0, reason }.into());
+        }
+        let stream_id = file.read(STREAM_ID_LEN, "stream id").await?;
+        let mut received = 0u64;
+        for index in 0u64.. {
+            let offset = file.offset;
+            let last = match file.read_u32("frame flag").await? as u32 {
+                LAST_FRAME => true,
+                MORE_FRAMES => false,
+                flag => {
+                    let reason = format!("invalid flag {flag} of frame {index}");
+                    return Err(Exception::CorruptedFile { offset, reason }.into());
+                }
+            };
+            let nonce = file.read(NONCE_LEN, "nonce").await?;
+            let tag = file.read(MAX_TAG_LEN, "tag").await?;
+            let len = self.check_frame(file.read_u32("frame length").await?)?;
+            self.check_payload(received as usize + len)?;
+
+            let mut frame = file.read(len, "frame").await?;
+            let aad = frame_aad(&stream_id, index, last);
+            let data = decrypt_in_place_with_aad(&mut frame, &tag, self.ctx.aes_key, &nonce, &aad)
+                .map_err(|error| Exception::DecryptError { error })?;
+            writer.write_all(data).await?;
+            received += len as u64;
+            self.chunk_count += 1;
+            if last {
+                break;
+            }
+        }
+        if file.offset < file.len {
+            let reason = format!("{} bytes follow the packet", file.len - file.offset);
+            return Err(file.corrupted(reason).into());
+        }
+        writer.flush().await?;
+        Ok(received)
     }
 
     pub fn plain_data(&self) -> Result<Vec<u8>> {
@@ -219,15 +1512,196 @@ impl<'a> OED<'a> {
         plain_bytes.extend_from_slice(&length(&self.tag)?);
         plain_bytes.extend_from_slice(&self.nonce);
         plain_bytes.extend_from_slice(&self.tag);
+        if self.ctx.compression.is_some() {
+            plain_bytes.push(self.algorithm.tag());
+        }
 
         Ok(plain_bytes)
     }
 
-    pub fn take(&mut self) -> Vec<u8> {
+    /// Number of bytes the packet occupies on the wire once sent or received.
+    pub fn wire_size(&self) -> usize {
+        self.frame_size.unwrap_or_else(|| {
+            8 + self.nonce.len()
+                + self.tag.len()
+                + self.algorithm_len()
+                + self.chunk_count as usize * 4
+                + self.encrypted_data.len()
+                + STOP_FLAG.len()
+        })
+    }
+
+    /// Decrypted data, sharing the buffer it was received in.
+    pub fn take(&mut self) -> Bytes {
         self.data.take().unwrap()
     }
 
-    pub fn get_data(&self) -> &[u8] {
+    /// Decrypted data, a handle sharing its buffer rather than a copy of it.
+    ///
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # use oblivion::models::packet::OED;
+    /// # use oblivion::utils::gear::Socket;
+    /// let key = [7; 16];
+    /// let (left, right) = Socket::pair();
+    /// OED::new(&key).from_bytes(vec![1; 4096])?.to_stream(&left).await?;
+    ///
+    /// let mut oed = OED::new(&key);
+    /// oed.from_stream(&right).await?;
+    /// let data = oed.get_data();
+    /// assert_eq!(data.as_ptr(), oed.as_slice().as_ptr());
+    /// assert_eq!(oed.take_vec(), vec![1; 4096]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_data(&self) -> Bytes {
+        self.data.clone().unwrap()
+    }
+
+    /// Decrypted data, borrowed.
+    pub fn as_slice(&self) -> &[u8] {
         self.data.as_ref().unwrap()
     }
+
+    /// Decrypted data as an owned vector, only copied if its buffer is still shared.
+    pub fn take_vec(&mut self) -> Vec<u8> {
+        self.take().into()
+    }
+}
+
+/// Packets decrypt the ciphertext they read in place, only those encrypted to be sent can be
+/// written or encoded.
+impl Packet for OED<'_> {
+    type Decoded<'c> = OED<'c>;
+
+    async fn write_between(&self, socket: &Socket, leading: &[u8], trailing: &[u8]) -> Result<()> {
+        self.send_between(socket, leading, trailing).await?;
+        Ok(())
+    }
+
+    async fn read_from<'c>(socket: &Socket, ctx: &'c PacketContext<'c>) -> Result<OED<'c>> {
+        let mut oed = OED::with_context(ctx);
+        oed.from_stream(socket).await?;
+        Ok(oed)
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut bytes = self.plain_data()?;
+        for chunk in self.encrypted_data.chunks(CHUNK_SIZE) {
+            bytes.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(chunk);
+        }
+        bytes.extend_from_slice(&STOP_FLAG);
+        Ok(bytes)
+    }
+
+    fn decode<'c>(bytes: &[u8], ctx: &'c PacketContext<'c>) -> Result<OED<'c>> {
+        Self::decode_owned(BytesMut::from(bytes), ctx)
+    }
+
+    /// Decrypts the packet within `bytes`, see [`OED::from_owned_frame`].
+    fn decode_owned<'c>(bytes: BytesMut, ctx: &'c PacketContext<'c>) -> Result<OED<'c>> {
+        let mut oed = OED::with_context(ctx);
+        match oed.frame_size(&bytes)? {
+            Framing::Complete(size) if size == bytes.len() => {}
+            Framing::Complete(size) => {
+                return Err(anyhow!(
+                    "{} bytes follow the OED packet",
+                    bytes.len() - size
+                ));
+            }
+            Framing::Incomplete { .. } => return Err(anyhow!("Truncated OED packet")),
+        }
+        oed.from_owned_frame(bytes)?;
+        Ok(oed)
+    }
+}
+
+/// Send `packet` right after `leading` and before `trailing` in a single vectored write.
+async fn write_between(
+    socket: &Socket,
+    leading: &[u8],
+    packet: &[u8],
+    trailing: &[u8],
+) -> Result<()> {
+    let slices = [leading, packet, trailing].map(IoSlice::new);
+    socket.send_vectored(&slices).await
+}
+
+/// Data authenticated along with the frame `index` of the streamed [`OED`] `stream_id`.
+fn frame_aad(stream_id: &[u8], index: u64, last: bool) -> [u8; STREAM_ID_LEN + 9] {
+    let mut aad = [0; STREAM_ID_LEN + 9];
+    aad[..STREAM_ID_LEN].copy_from_slice(stream_id);
+    aad[STREAM_ID_LEN..STREAM_ID_LEN + 8].copy_from_slice(&index.to_be_bytes());
+    aad[STREAM_ID_LEN + 8] = last as u8;
+    aad
+}
+
+/// Random identifier of a streamed [`OED`], see [`frame_aad`].
+fn random_stream_id() -> Result<[u8; STREAM_ID_LEN], Exception> {
+    let mut stream_id = [0; STREAM_ID_LEN];
+    SystemRandom::new()
+        .fill(&mut stream_id)
+        .map_err(|error| Exception::EncryptError { error })?;
+    Ok(stream_id)
+}
+
+/// Fill `buffer` from `reader`, returning fewer bytes than it holds only once `reader` ended.
+async fn read_chunk(reader: &mut (impl AsyncRead + Unpin), buffer: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]).await? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    Ok(len)
+}
+
+/// Receive a part of a packet behind its length prefix, named `what` if the stream ends in
+/// the middle of it.
+async fn recv_chunk(stream: &Socket, what: &str) -> Result<Vec<u8>> {
+    let len = stream.recv_usize().await.map_err(reading(what))?;
+    Ok(stream.recv(len).await.map_err(reading(what))?.to_vec())
+}
+
+/// File read by [`OED::from_file`], keeping track of the offset for errors.
+struct FileReader {
+    reader: BufReader<File>,
+    offset: u64,
+    len: u64,
+}
+
+impl FileReader {
+    fn corrupted(&self, reason: String) -> Exception {
+        Exception::CorruptedFile {
+            offset: self.offset,
+            reason,
+        }
+    }
+
+    /// Read the `len` bytes of `what`, checking first that the file holds them rather than
+    /// allocating whatever a corrupted length asks for.
+    async fn read(&mut self, len: usize, what: &str) -> Result<Vec<u8>> {
+        if len as u64 > self.len - self.offset {
+            let reason = format!("{what} of {len} bytes runs past the end of the file");
+            return Err(self.corrupted(reason).into());
+        }
+        let mut bytes = vec![0; len];
+        self.reader.read_exact(&mut bytes).await?;
+        self.offset += len as u64;
+        Ok(bytes)
+    }
+
+    async fn read_u32(&mut self, what: &str) -> Result<usize> {
+        let bytes = self.read(4, what).await?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+    }
+}
+
+/// Big endian `u32` at `offset` of `buffer`, `None` if the buffer is too short.
+fn read_u32(buffer: &[u8], offset: usize) -> Option<usize> {
+    let bytes = buffer.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
 }
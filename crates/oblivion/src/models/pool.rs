@@ -0,0 +1,242 @@
+//! # Oblivion Client Pool
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use crate::utils::parser::OblivionPath;
+
+use super::client::{cancellable, ClientBuilder, Failure, Request, Response};
+use super::session::{Capabilities, Session, SessionFlag};
+
+/// Options of a [`ClientPool`].
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Most sessions open to one host at a time, further requests wait for one to be returned.
+    pub max_per_host: usize,
+    /// Sessions unused for longer are closed instead of being reused.
+    pub idle_timeout: Duration,
+    /// Options of the connections opened by the pool.
+    pub client: ClientBuilder,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_per_host: 8,
+            idle_timeout: Duration::from_secs(90),
+            client: ClientBuilder::new(),
+        }
+    }
+}
+
+/// Sessions of a host tracked by [`ClientPool::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostStats {
+    pub idle: usize,
+    pub in_use: usize,
+}
+
+/// Pool of established sessions, reused across requests to the same host.
+///
+/// Every request checks a session out of the pool, or opens one if the host has fewer than
+/// [`PoolConfig::max_per_host`], and returns it once answered. Sessions the server closed,
+/// that sat idle for too long or that the server can't reuse are discarded. Clones share
+/// the same sessions.
+///
+/// ```rust
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::pool::{ClientPool, HostStats, PoolConfig};
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion::types::ServerResponse;
+/// # use oblivion_codegen::async_route;
+/// # #[async_route]
+/// # fn hello(_session: Session) -> ServerResponse {
+/// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+/// # }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/hello" => hello);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let pool = ClientPool::new(PoolConfig {
+///     max_per_host: 2,
+///     ..Default::default()
+/// });
+/// let url = format!("olps://127.0.0.1:{port}/hello");
+///
+/// for _ in 0..3 {
+///     assert_eq!(pool.get(&url).await?.text()?, "hello");
+/// }
+/// let host = format!("127.0.0.1:{port}");
+/// assert_eq!(pool.stats()[&host], HostStats { idle: 1, in_use: 0 });
+///
+/// let requests = (0..4).map(|_| {
+///     let pool = pool.clone();
+///     let url = url.clone();
+///     tokio::spawn(async move { pool.get(&url).await })
+/// });
+/// for request in requests {
+///     assert_eq!(request.await??.text()?, "hello");
+/// }
+/// assert!(pool.stats()[&host].idle <= 2);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ClientPool {
+    inner: Arc<Pool>,
+}
+
+struct Pool {
+    config: PoolConfig,
+    hosts: StdMutex<HashMap<String, Arc<Host>>>,
+}
+
+struct Host {
+    permits: Semaphore,
+    idle: StdMutex<Vec<Idle>>,
+    in_use: AtomicUsize,
+}
+
+struct Idle {
+    session: Session,
+    since: Instant,
+}
+
+/// Session checked out of a host, counted as in use until dropped.
+struct Lease<'a> {
+    host: &'a Host,
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        self.host.in_use.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ClientPool {
+    pub fn new(config: PoolConfig) -> Self {
+        Self {
+            inner: Arc::new(Pool {
+                config,
+                hosts: StdMutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    pub async fn get(&self, entrance: &str) -> Result<Response> {
+        self.send(Request::get(entrance).idempotent(true).build())
+            .await
+    }
+
+    /// Send `request` on a session of the pool and read the last message answering it.
+    pub async fn send(&self, mut request: Request) -> Result<Response> {
+        let options = &self.inner.config.client;
+        options.before(&mut request).await?;
+        let path = OblivionPath::new(&request.entrance)?;
+        let host = self.host(&format!("{}:{}", path.get_host(), path.get_port()));
+        let _permit = host.permits.acquire().await?;
+        host.in_use.fetch_add(1, Ordering::Relaxed);
+        let _lease = Lease { host: &host };
+
+        let header = request.header(path.get_entrance()).await?;
+        let current = StdMutex::new(None);
+        let attempts = options.run(request.idempotent, || async {
+            if let Some(session) = self.checkout(&host).await {
+                *current.lock().unwrap() = Some(session.fork());
+                match options.exchange(&session, &header, &request).await {
+                    // Nothing reached the server, so it is safe to send it on a new session.
+                    Err(failure) if !failure.sent => {}
+                    result => return result.map(|response| (response, session)),
+                }
+            }
+
+            let session = options
+                .establish(&path, &header, &request.headers)
+                .await
+                .map_err(Failure::unsent)?;
+            *current.lock().unwrap() = Some(session.fork());
+            request.send_body(&session).await.map_err(Failure::sent)?;
+            let response = options
+                .read_response(&session)
+                .await
+                .map_err(Failure::sent)?;
+            Ok((response, session))
+        });
+        let ((mut response, session), attempts) =
+            cancellable(request.cancellation.as_ref(), &current, attempts).await?;
+        response.attempts = attempts;
+        options.after(&request, &mut response).await?;
+
+        let reusable = response.flag == SessionFlag::Response
+            && session.capabilities().contains(Capabilities::REQUESTS)
+            && !session.closed().await;
+        if reusable {
+            host.idle.lock().unwrap().push(Idle {
+                session,
+                since: Instant::now(),
+            });
+        }
+        Ok(response)
+    }
+
+    /// Sessions of every host the pool connected to, keyed by `host:port`.
+    pub fn stats(&self) -> HashMap<String, HostStats> {
+        let hosts = self.inner.hosts.lock().unwrap();
+        hosts
+            .iter()
+            .map(|(address, host)| {
+                let stats = HostStats {
+                    idle: host.idle.lock().unwrap().len(),
+                    in_use: host.in_use.load(Ordering::Relaxed),
+                };
+                (address.clone(), stats)
+            })
+            .collect()
+    }
+
+    fn host(&self, address: &str) -> Arc<Host> {
+        let mut hosts = self.inner.hosts.lock().unwrap();
+        let host = hosts.entry(address.to_string()).or_insert_with(|| {
+            Arc::new(Host {
+                permits: Semaphore::new(self.inner.config.max_per_host),
+                idle: StdMutex::new(Vec::new()),
+                in_use: AtomicUsize::new(0),
+            })
+        });
+        Arc::clone(host)
+    }
+
+    /// Most recently returned session of `host` that is still usable, closing the others.
+    async fn checkout(&self, host: &Host) -> Option<Session> {
+        loop {
+            let idle = host.idle.lock().unwrap().pop()?;
+            if idle.since.elapsed() >= self.inner.config.idle_timeout {
+                let _ = idle.session.close().await;
+                continue;
+            }
+            if is_alive(&idle.session).await {
+                return Some(idle.session);
+            }
+            let _ = idle.session.abort().await;
+        }
+    }
+}
+
+/// Whether nothing arrived on an idle `session`, when anything but silence means it can't be reused.
+///
+/// Nothing is received from the session, whatever arrived is left for the next receive.
+async fn is_alive(session: &Session) -> bool {
+    !session.closed().await && !session.has_unread().await
+}
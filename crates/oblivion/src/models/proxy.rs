@@ -0,0 +1,284 @@
+//! # Oblivion Proxies
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::resolver::{Resolver, SystemResolver};
+use crate::exceptions::Exception;
+use crate::utils::gear::dial_any;
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CONNECT: u8 = 1;
+const IPV4: u8 = 1;
+const DOMAIN: u8 = 3;
+const IPV6: u8 = 4;
+
+/// SOCKS5 proxy connections to the server are tunneled through, see
+/// [`ClientBuilder::proxy`](super::client::ClientBuilder::proxy).
+///
+/// ```rust
+/// # use oblivion::models::client::{ClientBuilder, Request};
+/// # use oblivion::models::proxy::Proxy;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::utils::gear::Socket;
+/// # use tokio::io::{AsyncReadExt, AsyncWriteExt};
+/// # use tokio::net::{TcpListener, TcpStream};
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let listener = TcpListener::bind("127.0.0.1:0").await?;
+/// # let port = listener.local_addr()?.port();
+/// # let server = tokio::spawn(async move {
+/// #     let (stream, _) = listener.accept().await?;
+/// #     let mut session = Session::new(Socket::new(stream))?;
+/// #     session.handshake(1).await?;
+/// #     session.send_and_close(b"proxied".to_vec(), 200).await?;
+/// #     anyhow::Ok(())
+/// # });
+/// # let proxy = TcpListener::bind("127.0.0.1:0").await?;
+/// # let proxy_address = proxy.local_addr()?;
+/// # tokio::spawn(async move {
+/// #     let (mut client, _) = proxy.accept().await?;
+/// #     let mut greeting = [0; 3];
+/// #     client.read_exact(&mut greeting).await?;
+/// #     assert_eq!(greeting, [5, 1, 2]);
+/// #     client.write_all(&[5, 2]).await?;
+/// #     let mut credentials = [0; 13];
+/// #     client.read_exact(&mut credentials).await?;
+/// #     assert_eq!(&credentials, b"\x01\x04user\x06secret");
+/// #     client.write_all(&[1, 0]).await?;
+/// #     let mut request = [0; 5];
+/// #     client.read_exact(&mut request).await?;
+/// #     assert_eq!(request, [5, 1, 0, 3, 9]);
+/// #     let mut host = [0; 9];
+/// #     client.read_exact(&mut host).await?;
+/// #     let port = client.read_u16().await?;
+/// #     let mut upstream = TcpStream::connect(("127.0.0.1", port)).await?;
+/// #     client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).await?;
+/// #     tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+/// #     anyhow::Ok(host)
+/// # });
+///
+/// let proxy = Proxy::socks5h(&proxy_address.to_string()).with_auth("user", "secret");
+/// let response = ClientBuilder::new()
+///     .proxy(proxy)
+///     .send(Request::get(&format!("olps://localhost:{port}/")).build())
+///     .await?;
+/// assert_eq!(response.text()?, "proxied");
+/// # server.await??;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    address: String,
+    credentials: Option<(String, String)>,
+    remote_dns: bool,
+}
+
+impl Proxy {
+    /// Proxy at `address`, host names of servers are resolved locally.
+    pub fn socks5(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            credentials: None,
+            remote_dns: false,
+        }
+    }
+
+    /// Proxy at `address` resolving the host names of servers itself.
+    pub fn socks5h(address: &str) -> Self {
+        Self {
+            remote_dns: true,
+            ..Self::socks5(address)
+        }
+    }
+
+    /// Authenticate with a username and a password, each at most 255 bytes long.
+    pub fn with_auth(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Open a tunnel to `host:port` through the proxy.
+    ///
+    /// Any failure until the tunnel is open is reported as [`Exception::ProxyError`].
+    pub async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        self.connect_with(&SystemResolver, host, port).await
+    }
+
+    /// Open a tunnel like [`Proxy::connect`], resolving the address of the proxy with
+    /// `resolver`, and `host` too unless it is resolved by the proxy.
+    ///
+    /// ```rust
+    /// # use oblivion::models::proxy::Proxy;
+    /// # use oblivion::models::resolver::StaticResolver;
+    /// # use tokio::io::AsyncReadExt;
+    /// # use tokio::net::TcpListener;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// let port = listener.local_addr()?.port();
+    /// let proxy = tokio::spawn(async move {
+    ///     let (mut client, _) = listener.accept().await?;
+    ///     let mut greeting = [0; 3];
+    ///     client.read_exact(&mut greeting).await?;
+    ///     anyhow::Ok(greeting)
+    /// });
+    ///
+    /// let resolver = StaticResolver::new().with_host("proxy.internal", ["127.0.0.1".parse()?]);
+    /// let connected = Proxy::socks5h(&format!("proxy.internal:{port}"))
+    ///     .connect_with(&resolver, "example.com", 8813)
+    ///     .await;
+    /// assert!(connected.is_err());
+    /// assert_eq!(proxy.await??, [5, 1, 0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_with(
+        &self,
+        resolver: &dyn Resolver,
+        host: &str,
+        port: u16,
+    ) -> Result<TcpStream> {
+        let target = match host.parse::<IpAddr>() {
+            Ok(ip) => Target::Ip(ip),
+            Err(_) if self.remote_dns => Target::Domain(host.to_string()),
+            Err(_) => match resolver.resolve(host, port).await.as_deref() {
+                Ok([address, ..]) => Target::Ip(address.ip()),
+                _ => return Err(proxy_error(format!("Can't resolve {host}"))),
+            },
+        };
+
+        let mut stream = self
+            .dial(resolver)
+            .await
+            .map_err(|error| proxy_error(format!("Can't reach {}: {}", self.address, error)))?;
+        match self.negotiate(&mut stream, &target, port).await {
+            Ok(()) => Ok(stream),
+            Err(error) => match error.downcast::<Exception>() {
+                Ok(exception) => Err(exception.into()),
+                Err(error) => Err(proxy_error(error.to_string())),
+            },
+        }
+    }
+
+    /// Connect to the addresses of the proxy in turn.
+    async fn dial(&self, resolver: &dyn Resolver) -> Result<TcpStream> {
+        let addresses = match self.address.parse::<SocketAddr>() {
+            Ok(address) => vec![address],
+            Err(_) => {
+                let (host, port) = self
+                    .address
+                    .rsplit_once(':')
+                    .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+                    .ok_or_else(|| anyhow!("Invalid address"))?;
+                resolver.resolve(host, port).await?
+            }
+        };
+        Ok(dial_any(addresses.as_slice()).await?)
+    }
+
+    async fn negotiate(&self, stream: &mut TcpStream, target: &Target, port: u16) -> Result<()> {
+        let method = match self.credentials {
+            Some(_) => USERNAME_PASSWORD,
+            None => NO_AUTHENTICATION,
+        };
+        stream.write_all(&[VERSION, 1, method]).await?;
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != VERSION {
+            return Err(proxy_error(format!("Unexpected version {}", reply[0])));
+        }
+        match reply[1] {
+            NO_ACCEPTABLE_METHOD => {
+                return Err(proxy_error(
+                    "No acceptable authentication method".to_string(),
+                ))
+            }
+            selected if selected != method => {
+                return Err(proxy_error(format!("Unexpected method {selected}")))
+            }
+            _ => {}
+        }
+
+        if let Some((username, password)) = &self.credentials {
+            let mut request = vec![1];
+            for field in [username, password] {
+                let length = u8::try_from(field.len())
+                    .map_err(|_| proxy_error("Credentials are too long".to_string()))?;
+                request.push(length);
+                request.extend_from_slice(field.as_bytes());
+            }
+            stream.write_all(&request).await?;
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err(proxy_error("Authentication failed".to_string()));
+            }
+        }
+
+        let mut request = vec![VERSION, CONNECT, 0];
+        match target {
+            Target::Ip(IpAddr::V4(ip)) => {
+                request.push(IPV4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Target::Ip(IpAddr::V6(ip)) => {
+                request.push(IPV6);
+                request.extend_from_slice(&ip.octets());
+            }
+            Target::Domain(domain) => {
+                let length = u8::try_from(domain.len())
+                    .map_err(|_| proxy_error("Host name is too long".to_string()))?;
+                request.extend_from_slice(&[DOMAIN, length]);
+                request.extend_from_slice(domain.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(proxy_error(reply_message(reply[1]).to_string()));
+        }
+        let bound = match reply[3] {
+            IPV4 => 4,
+            IPV6 => 16,
+            DOMAIN => stream.read_u8().await? as usize,
+            kind => return Err(proxy_error(format!("Unknown address type {kind}"))),
+        };
+        let mut address = vec![0; bound + 2];
+        stream.read_exact(&mut address).await?;
+        Ok(())
+    }
+}
+
+/// Destination of the tunnel as sent to the proxy.
+enum Target {
+    Ip(IpAddr),
+    Domain(String),
+}
+
+fn proxy_error(reason: String) -> anyhow::Error {
+    Exception::ProxyError { reason }.into()
+}
+
+/// Meaning of a failed reply, as defined by RFC 1928.
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        1 => "General SOCKS server failure",
+        2 => "Connection not allowed by ruleset",
+        3 => "Network unreachable",
+        4 => "Host unreachable",
+        5 => "Connection refused",
+        6 => "TTL expired",
+        7 => "Command not supported",
+        8 => "Address type not supported",
+        _ => "Unknown failure",
+    }
+}
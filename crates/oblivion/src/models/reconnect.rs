@@ -0,0 +1,372 @@
+//! # Oblivion Reconnecting Sessions
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex as StdMutex};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio::sync::watch;
+
+use crate::exceptions::Exception;
+use crate::utils::parser::OblivionPath;
+
+use super::client::{ClientBuilder, Failure, Response, RetryPolicy};
+use super::session::Session;
+
+/// What [`ReconnectingSession::send`] does with messages while the session is reconnecting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectedPolicy {
+    /// Keep up to this many messages and send them once reconnected, failing once it is full.
+    Queue(usize),
+    /// Fail with [`Exception::ConnectionClosed`] right away.
+    FailFast,
+}
+
+/// Options of a [`ReconnectingSession`].
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    /// Options of every connection, including the first one.
+    pub client: ClientBuilder,
+    /// Delays between reconnection attempts and when to give up.
+    pub backoff: RetryPolicy,
+    pub when_disconnected: DisconnectedPolicy,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            client: ClientBuilder::new(),
+            backoff: RetryPolicy::new(u32::MAX),
+            when_disconnected: DisconnectedPolicy::Queue(1024),
+        }
+    }
+}
+
+/// Message received by [`ReconnectingSession::recv`].
+#[derive(Debug)]
+// Messages outnumber reconnections by far, boxing them would allocate for every one.
+#[allow(clippy::large_enum_variant)]
+pub enum SessionEvent {
+    Message(Response),
+    /// The connection was lost and a new one was established, messages may have been lost
+    /// in between.
+    Reconnected,
+}
+
+/// Callback registered with [`ReconnectingSession::on_reconnect`].
+type ReconnectHook = Arc<dyn Fn(Arc<Session>) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// Long-lived session to an entrance, re-established whenever the connection is lost.
+///
+/// Once sending or receiving finds the connection closed, a new one is opened in the
+/// background with the delays of [`ReconnectConfig::backoff`], until it gives up and every
+/// following call fails with the last error. The hook registered with
+/// [`ReconnectingSession::on_reconnect`] runs on every new connection before it is used,
+/// then the messages queued meanwhile are sent in order.
+///
+/// ```rust
+/// # use oblivion::models::reconnect::{ReconnectingSession, SessionEvent};
+/// # use oblivion::models::session::Session;
+/// # use oblivion::utils::gear::Socket;
+/// # use tokio::net::TcpListener;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let listener = TcpListener::bind("127.0.0.1:0").await?;
+/// # let address = listener.local_addr()?;
+/// # let server = tokio::spawn(async move {
+/// #     let (stream, _) = listener.accept().await?;
+/// #     let mut session = Session::new(Socket::new(stream))?;
+/// #     session.handshake(1).await?;
+/// #     session.send(b"first".to_vec()).await?;
+/// #     session.abort().await?;
+/// #     let (stream, _) = listener.accept().await?;
+/// #     let mut session = Session::new(Socket::new(stream))?;
+/// #     session.handshake(1).await?;
+/// #     let subscription = session.recv().await?;
+/// #     session.send(subscription.content.into()).await?;
+/// #     session.send(session.recv().await?.into_vec()).await?;
+/// #     tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+/// #     anyhow::Ok(())
+/// # });
+/// let session =
+///     ReconnectingSession::connect(&format!("olps://{address}/feed"), Default::default())
+///         .await?;
+/// session.on_reconnect(|session| {
+///     Box::pin(async move { session.send(b"subscribe".to_vec()).await })
+/// });
+///
+/// let SessionEvent::Message(first) = session.recv().await? else {
+///     panic!("expected a message");
+/// };
+/// assert_eq!(first.text()?, "first");
+/// // The server dropped the connection, a new one was opened.
+/// assert!(matches!(session.recv().await?, SessionEvent::Reconnected));
+///
+/// session.send(b"hello".to_vec()).await?;
+/// for expected in ["subscribe", "hello"] {
+///     match session.recv().await? {
+///         SessionEvent::Message(response) => assert_eq!(response.text()?, expected),
+///         SessionEvent::Reconnected => panic!("expected a message"),
+///     }
+/// }
+/// session.close().await?;
+/// # server.await??;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ReconnectingSession {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: ReconnectConfig,
+    path: OblivionPath,
+    header: String,
+    connection: watch::Sender<Connection>,
+    /// Messages waiting for the next connection, locked while the connection changes.
+    queue: StdMutex<VecDeque<Vec<u8>>>,
+    hook: StdMutex<Option<ReconnectHook>>,
+}
+
+#[derive(Clone)]
+enum Connection {
+    Connected(Arc<Session>),
+    Reconnecting,
+    /// Gave up reconnecting or closed.
+    Failed(Exception),
+}
+
+impl ReconnectingSession {
+    /// Connect to `entrance`, the first connection isn't retried beyond the retry policy of
+    /// [`ReconnectConfig::client`].
+    pub async fn connect(entrance: &str, config: ReconnectConfig) -> Result<Self> {
+        let path = OblivionPath::new(entrance)?;
+        let header = format!("CONNECT {} Oblivion/2.0", path.get_entrance());
+        let (session, _) = config
+            .client
+            .run(false, || async {
+                config
+                    .client
+                    .establish(&path, &header, &[])
+                    .await
+                    .map_err(Failure::unsent)
+            })
+            .await?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                config,
+                path,
+                header,
+                connection: watch::Sender::new(Connection::Connected(Arc::new(session))),
+                queue: StdMutex::new(VecDeque::new()),
+                hook: StdMutex::new(None),
+            }),
+        })
+    }
+
+    /// Run `hook` on every new connection before anything else is sent on it, a failing
+    /// hook counts as a failed reconnection attempt.
+    pub fn on_reconnect(
+        &self,
+        hook: impl Fn(Arc<Session>) -> BoxFuture<'static, Result<()>> + Send + Sync + 'static,
+    ) {
+        *self.inner.hook.lock().unwrap() = Some(Arc::new(hook));
+    }
+
+    /// Send `data` on the current connection, see [`DisconnectedPolicy`] for what happens
+    /// while there is none.
+    pub async fn send(&self, mut data: Vec<u8>) -> Result<()> {
+        loop {
+            let session = match self.inner.current() {
+                Connection::Connected(session) => session,
+                Connection::Reconnecting => match self.inner.enqueue(data)? {
+                    Some(returned) => {
+                        data = returned;
+                        continue;
+                    }
+                    None => return Ok(()),
+                },
+                Connection::Failed(error) => return Err(error.into()),
+            };
+            // Kept to queue it if the connection is lost while sending.
+            let retry = match self.inner.config.when_disconnected {
+                DisconnectedPolicy::Queue(_) => Some(data.clone()),
+                DisconnectedPolicy::FailFast => None,
+            };
+            match session.send(data).await {
+                Ok(()) => return Ok(()),
+                Err(error) if !session.closed().await => return Err(error),
+                Err(error) => {
+                    Inner::disconnected(&self.inner, &session);
+                    data = retry.ok_or(error)?;
+                }
+            }
+        }
+    }
+
+    /// Receive the next message, or [`SessionEvent::Reconnected`] once the connection was
+    /// replaced.
+    pub async fn recv(&self) -> Result<SessionEvent> {
+        let session = match self.inner.current() {
+            Connection::Connected(session) => {
+                match session.recv().await {
+                    Ok(response) => return Ok(SessionEvent::Message(response)),
+                    Err(_) if session.closed().await => {}
+                    Err(error) => return Err(error),
+                }
+                Inner::disconnected(&self.inner, &session);
+                Some(session)
+            }
+            Connection::Reconnecting => None,
+            Connection::Failed(error) => return Err(error.into()),
+        };
+
+        let mut connection = self.inner.connection.subscribe();
+        let connection = connection
+            .wait_for(|connection| match (connection, &session) {
+                (Connection::Connected(current), Some(session)) => !Arc::ptr_eq(current, session),
+                (Connection::Reconnecting, _) => false,
+                _ => true,
+            })
+            .await?
+            .clone();
+        match connection {
+            Connection::Failed(error) => Err(error.into()),
+            _ => Ok(SessionEvent::Reconnected),
+        }
+    }
+
+    /// Close the current connection and stop reconnecting, the session can't be used anymore.
+    pub async fn close(&self) -> Result<()> {
+        let previous = self
+            .inner
+            .connection
+            .send_replace(Connection::Failed(Exception::ConnectionClosed));
+        if let Connection::Connected(session) = previous {
+            session.close().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn current(&self) -> Connection {
+        self.connection.borrow().clone()
+    }
+
+    /// Queue `data` for the next connection, or return it if the connection came back since
+    /// it was checked.
+    fn enqueue(&self, data: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let DisconnectedPolicy::Queue(capacity) = self.config.when_disconnected else {
+            return Err(Exception::ConnectionClosed.into());
+        };
+        let mut queue = self.queue.lock().unwrap();
+        if !matches!(*self.connection.borrow(), Connection::Reconnecting) {
+            return Ok(Some(data));
+        }
+        if queue.len() >= capacity {
+            return Err(Exception::ConnectionClosed.into());
+        }
+        queue.push_back(data);
+        Ok(None)
+    }
+
+    /// Start reconnecting unless `lost` was already replaced.
+    fn disconnected(inner: &Arc<Self>, lost: &Arc<Session>) {
+        let started = inner
+            .connection
+            .send_if_modified(|connection| match connection {
+                Connection::Connected(current) if Arc::ptr_eq(current, lost) => {
+                    *connection = Connection::Reconnecting;
+                    true
+                }
+                _ => false,
+            });
+        if started {
+            tokio::spawn(Arc::clone(inner).reconnect());
+        }
+    }
+
+    async fn reconnect(self: Arc<Self>) {
+        let options = self
+            .config
+            .client
+            .clone()
+            .retry(self.config.backoff.clone());
+        loop {
+            let hook = self.hook.lock().unwrap().clone();
+            let attempt = options
+                .run(false, || async {
+                    let session = options
+                        .establish(&self.path, &self.header, &[])
+                        .await
+                        .map_err(Failure::unsent)?;
+                    let session = Arc::new(session);
+                    if let Some(hook) = &hook {
+                        hook(Arc::clone(&session)).await.map_err(Failure::unsent)?;
+                    }
+                    Ok(session)
+                })
+                .await;
+            let session = match attempt {
+                Ok((session, _)) => session,
+                Err(error) => {
+                    self.give_up(Exception::from_error(&error));
+                    return;
+                }
+            };
+            if self.resume(&session).await {
+                return;
+            }
+        }
+    }
+
+    /// Send the queued messages on `session` and make it the current connection.
+    ///
+    /// Returns `false` if the connection was lost meanwhile.
+    async fn resume(&self, session: &Arc<Session>) -> bool {
+        loop {
+            let next = {
+                let mut queue = self.queue.lock().unwrap();
+                let next = queue.pop_front();
+                if next.is_none() {
+                    // Published while holding the queue, so nothing is queued for this connection.
+                    let resumed = self
+                        .connection
+                        .send_if_modified(|connection| match connection {
+                            Connection::Reconnecting => {
+                                *connection = Connection::Connected(Arc::clone(session));
+                                true
+                            }
+                            _ => false,
+                        });
+                    if resumed {
+                        return true;
+                    }
+                }
+                next
+            };
+            let Some(data) = next else {
+                // Closed while reconnecting.
+                let _ = session.abort().await;
+                return true;
+            };
+            if session.send(data.clone()).await.is_err() {
+                self.queue.lock().unwrap().push_front(data);
+                return false;
+            }
+        }
+    }
+
+    fn give_up(&self, error: Exception) {
+        self.queue.lock().unwrap().clear();
+        self.connection
+            .send_if_modified(|connection| match connection {
+                Connection::Reconnecting => {
+                    *connection = Connection::Failed(error);
+                    true
+                }
+                _ => false,
+            });
+    }
+}
@@ -1,14 +1,34 @@
 //! # Oblivion Render
+use std::fs::File;
+use std::sync::Arc;
+
 use anyhow::Result;
 use serde_json::Value;
 
 use crate::exceptions::Exception;
 
+use super::packet::StatusCode;
+
+/// Status code of a [`BaseResponse::RedirectResponse`].
+///
+/// Responses with a status code from `300` to `399` are redirects, their content is the
+/// location to send the request to instead, see
+/// [`ClientBuilder::follow_redirects`](super::client::ClientBuilder::follow_redirects).
+pub const REDIRECT_STATUS: u32 = StatusCode::TEMPORARY_REDIRECT.as_u32();
+
 #[derive(Clone)]
 pub enum BaseResponse {
     FileResponse(String),
+    /// File already opened, streamed like [`BaseResponse::FileResponse`] from its current
+    /// position, see [`ServeDir`](super::files::ServeDir).
+    OpenFileResponse(Arc<File>),
     TextResponse(String),
     JsonResponse(Value),
+    /// Send the request to another location, either an entrance on the same server such as
+    /// `/v2/users` or a whole URL such as `olps://shard-2.internal:813/users`.
+    RedirectResponse(String),
+    /// Raw content sent with an explicit status code.
+    StatusResponse(u32, Vec<u8>),
 }
 
 impl BaseResponse {
@@ -17,10 +37,29 @@ impl BaseResponse {
             Self::FileResponse(_) => Err(Exception::UnsupportedMethod {
                 method: "FileResponse".to_string(),
             }),
+            Self::OpenFileResponse(_) => Err(Exception::UnsupportedMethod {
+                method: "OpenFileResponse".to_string(),
+            }),
             Self::TextResponse(text) => Ok(text.as_bytes().to_vec()),
             Self::JsonResponse(data) => Ok(data.to_string().as_bytes().to_vec()),
+            Self::RedirectResponse(location) => Ok(location.as_bytes().to_vec()),
+            Self::StatusResponse(_, content) => Ok(content.clone()),
+        }
+    }
+
+    /// Status code sent along with the response.
+    pub fn status_code(&self) -> u32 {
+        match self {
+            Self::RedirectResponse(_) => REDIRECT_STATUS,
+            Self::StatusResponse(status_code, _) => *status_code,
+            _ => StatusCode::OK.as_u32(),
         }
     }
+
+    /// Typed form of [`BaseResponse::status_code`].
+    pub fn status(&self) -> StatusCode {
+        StatusCode::new(self.status_code())
+    }
 }
 
 impl TryInto<Vec<u8>> for BaseResponse {
@@ -48,3 +87,144 @@ impl From<Value> for BaseResponse {
         Self::JsonResponse(data)
     }
 }
+
+/// Value a handler of [`async_route`](oblivion_codegen::async_route) can return.
+///
+/// Text is answered with status `200` like [`BaseResponse::TextResponse`], JSON like
+/// [`BaseResponse::JsonResponse`] and `(status_code, content)` like
+/// [`BaseResponse::StatusResponse`]. Errors converted to an [`anyhow::Error`] or an
+/// [`Exception`] fail the request as usual, other errors of a `Result` are answered with
+/// their own status, or `500` if it is a success.
+///
+/// ```rust
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::render::BaseResponse;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::path_route;
+/// # use oblivion_codegen::async_route;
+/// # use serde_json::{json, Value};
+/// #[async_route]
+/// fn text(_session: Session) -> &'static str {
+///     "hello"
+/// }
+///
+/// #[async_route]
+/// fn json(_session: Session) -> Value {
+///     json!({ "hello": "world" })
+/// }
+///
+/// #[async_route]
+/// fn created(_session: Session) -> (u32, Vec<u8>) {
+///     (201, b"created".to_vec())
+/// }
+///
+/// #[async_route]
+/// fn user(session: Session) -> Result<String, (u32, Vec<u8>)> {
+///     match session.request.get_entrance().rsplit('/').next() {
+///         Some("alice") => Ok("welcome alice".to_string()),
+///         _ => Err((404, b"no such user".to_vec())),
+///     }
+/// }
+///
+/// #[async_route]
+/// fn teapot(_session: Session) -> Result<String, String> {
+///     Err("out of coffee".to_string())
+/// }
+///
+/// #[async_route]
+/// fn base(_session: Session) -> BaseResponse {
+///     BaseResponse::TextResponse("base".to_string())
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let mut router = Router::new();
+/// # path_route!(&mut router, "/text" => text);
+/// # path_route!(&mut router, "/json" => json);
+/// # path_route!(&mut router, "/created" => created);
+/// # path_route!(&mut router, "/user/alice" => user);
+/// # path_route!(&mut router, "/user/bob" => user);
+/// # path_route!(&mut router, "/teapot" => teapot);
+/// # path_route!(&mut router, "/base" => base);
+/// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+///
+/// assert_eq!(get("/text").await?.text()?, "hello");
+/// assert_eq!(get("/json").await?.json()?, json!({ "hello": "world" }));
+/// let response = get("/created").await?;
+/// assert_eq!((response.status_code, response.text()?), (201, "created"));
+/// assert_eq!(get("/user/alice").await?.text()?, "welcome alice");
+/// let response = get("/user/bob").await?;
+/// assert_eq!((response.status_code, response.text()?), (404, "no such user"));
+/// let response = get("/teapot").await?;
+/// assert_eq!((response.status_code, response.text()?), (500, "out of coffee"));
+/// assert_eq!(get("/base").await?.text()?, "base");
+/// # Ok(())
+/// # }
+/// ```
+pub trait IntoResponse {
+    /// Response to send, or the error failing the request.
+    fn into_response(self) -> Result<BaseResponse>;
+}
+
+impl IntoResponse for BaseResponse {
+    fn into_response(self) -> Result<BaseResponse> {
+        Ok(self)
+    }
+}
+
+impl IntoResponse for &str {
+    fn into_response(self) -> Result<BaseResponse> {
+        Ok(self.into())
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Result<BaseResponse> {
+        Ok(self.into())
+    }
+}
+
+impl IntoResponse for Value {
+    fn into_response(self) -> Result<BaseResponse> {
+        Ok(self.into())
+    }
+}
+
+impl IntoResponse for (u32, Vec<u8>) {
+    fn into_response(self) -> Result<BaseResponse> {
+        Ok(BaseResponse::StatusResponse(self.0, self.1))
+    }
+}
+
+impl IntoResponse for anyhow::Error {
+    fn into_response(self) -> Result<BaseResponse> {
+        Err(self)
+    }
+}
+
+impl IntoResponse for Exception {
+    fn into_response(self) -> Result<BaseResponse> {
+        Err(self.into())
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Result<BaseResponse> {
+        let response = match self {
+            Ok(value) => return value.into_response(),
+            Err(error) => error.into_response()?,
+        };
+        if response.status().is_success() {
+            let status_code = StatusCode::INTERNAL_ERROR.as_u32();
+            return Ok(BaseResponse::StatusResponse(
+                status_code,
+                response.as_bytes()?,
+            ));
+        }
+        Ok(response)
+    }
+}
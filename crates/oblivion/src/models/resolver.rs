@@ -0,0 +1,116 @@
+//! # Oblivion Resolvers
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use tokio::net::lookup_host;
+
+use crate::exceptions::Exception;
+
+/// Resolution of host names to the addresses a client connects to, see
+/// [`ClientBuilder::resolver`](super::client::ClientBuilder::resolver).
+///
+/// The client tries the addresses in the returned order until one of them accepts the
+/// connection, racing the next address against slow attempts, see
+/// [`ClientBuilder::address_family`](super::client::ClientBuilder::address_family).
+pub trait Resolver: Send + Sync {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, Result<Vec<SocketAddr>>>;
+}
+
+/// Resolver of the operating system, queried without blocking the runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(async move {
+            let addresses: Vec<_> = lookup_host((host, port))
+                .await
+                .map_err(|_| unresolved(host))?
+                .collect();
+            match addresses.is_empty() {
+                true => Err(unresolved(host)),
+                false => Ok(addresses),
+            }
+        })
+    }
+}
+
+/// Resolver answering from a fixed table, hosts it doesn't know fail with
+/// [`Exception::UnresolvedHost`].
+///
+/// Known hosts resolve to their addresses at the port of the entrance.
+///
+/// ```rust
+/// # use oblivion::models::client::{ClientBuilder, Request};
+/// # use oblivion::models::resolver::{Resolver, StaticResolver};
+/// # use oblivion::models::session::Session;
+/// # use oblivion::utils::gear::Socket;
+/// # use tokio::net::TcpListener;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let listener = TcpListener::bind("127.0.0.1:0").await?;
+/// # let port = listener.local_addr()?.port();
+/// # let server = tokio::spawn(async move {
+/// #     let (stream, _) = listener.accept().await?;
+/// #     let mut session = Session::new(Socket::new(stream))?;
+/// #     session.handshake(1).await?;
+/// #     session.send_and_close(b"internal".to_vec(), 200).await?;
+/// #     anyhow::Ok(())
+/// # });
+/// // Nothing listens on the first address, the client falls back to the second one.
+/// let resolver = StaticResolver::new().with_host("api.internal", [
+///     "127.0.0.2".parse()?,
+///     "127.0.0.1".parse()?,
+/// ]);
+/// let addresses = resolver.resolve("api.internal", port).await?;
+/// assert_eq!(addresses[1], ([127, 0, 0, 1], port).into());
+///
+/// let response = ClientBuilder::new()
+///     .resolver(resolver)
+///     .send(Request::get(&format!("olps://api.internal:{port}/")).build())
+///     .await?;
+/// assert_eq!(response.text()?, "internal");
+/// # server.await??;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve `host` to `addresses`, host names are case-insensitive.
+    pub fn with_host(mut self, host: &str, addresses: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.hosts
+            .insert(host.to_lowercase(), addresses.into_iter().collect());
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve<'a>(&'a self, host: &'a str, port: u16) -> BoxFuture<'a, Result<Vec<SocketAddr>>> {
+        Box::pin(async move {
+            match self.hosts.get(&host.to_lowercase()) {
+                Some(addresses) if !addresses.is_empty() => Ok(addresses
+                    .iter()
+                    .map(|&ip| SocketAddr::new(ip, port))
+                    .collect()),
+                _ => Err(unresolved(host)),
+            }
+        })
+    }
+}
+
+fn unresolved(host: &str) -> anyhow::Error {
+    Exception::UnresolvedHost {
+        host: host.to_string(),
+    }
+    .into()
+}
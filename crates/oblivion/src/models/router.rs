@@ -1,25 +1,86 @@
 //! # Oblivion Router
 use crate::types::Handler;
 
+use super::files::{ServeDir, FILE_PARAM};
 use super::handler::not_found;
+use super::middleware::{Middleware, Next, SharedMiddleware, Terminal};
+use super::render::BaseResponse;
+use super::session::Session;
+use crate::exceptions::Exception;
+use crate::utils::parser::unescape;
 use anyhow::Result;
+use arc_swap::ArcSwap;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct Route {
     handler: Handler,
+    layers: Vec<SharedMiddleware>,
+    json_limit: Option<usize>,
+    timeout: Option<Duration>,
+    max_body_size: Option<usize>,
 }
 
 impl Route {
     pub fn new(handler: Handler) -> Self {
-        Self { handler }
+        Self {
+            handler,
+            layers: Vec::new(),
+            json_limit: None,
+            timeout: None,
+            max_body_size: None,
+        }
     }
 
     #[inline]
     pub fn get_handler(&self) -> Handler {
         self.handler
     }
+
+    /// Run `middleware` for this route only, after the layers of the router and those
+    /// added before, see [`Middleware`].
+    pub fn layer(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.layers.push(Arc::new(middleware));
+        self
+    }
+
+    /// Reject bodies over `limit` bytes instead of deserializing them with
+    /// [`Json`](super::extract::Json), defaults to [`JSON_LIMIT`](super::extract::JSON_LIMIT).
+    ///
+    /// The limit bounds the body received too, like [`Route::max_body_size`]: larger bodies
+    /// are answered before they are read.
+    pub fn json_limit(mut self, limit: usize) -> Self {
+        self.json_limit = Some(limit);
+        self
+    }
+
+    /// Give up on requests to this route after `timeout`, instead of the
+    /// [handler timeout](super::server::ServerConfig::handler_timeout) of the server.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Answer requests to this route whose body is over `size` bytes with
+    /// [`PAYLOAD_TOO_LARGE_STATUS`](super::server::PAYLOAD_TOO_LARGE_STATUS), instead of the
+    /// [limit](super::server::ServerConfig::max_body_size) of the server.
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = Some(size);
+        self
+    }
+
+    /// Largest body received, the lowest of [`Route::max_body_size`] and
+    /// [`Route::json_limit`].
+    fn body_limit(&self) -> Option<usize> {
+        match (self.max_body_size, self.json_limit) {
+            (Some(size), Some(limit)) => Some(size.min(limit)),
+            (size, limit) => size.or(limit),
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -29,36 +90,347 @@ pub enum RouteType {
     RegexPath,
 }
 
+/// Segment of a [`RouteType::Path`] route.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    Literal(String),
+    /// `:name`, matching any single segment.
+    Param(String),
+    /// `*name` as the last segment, matching every remaining segment.
+    Wildcard(String),
+}
+
+impl Segment {
+    /// Precedence over other kinds of segments at the same position.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Literal(_) => 2,
+            Self::Param(_) => 1,
+            Self::Wildcard(_) => 0,
+        }
+    }
+}
+
+/// Values captured by the parameters of a route, see
+/// [`OblivionRequest::param`](crate::utils::parser::OblivionRequest::param).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    values: HashMap<String, Captured>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Captured {
+    raw: String,
+    decoded: String,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Percent-decoded value of the parameter `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| value.decoded.as_str())
+    }
+
+    /// Value of the parameter `name` as it appears in the entrance.
+    pub fn get_raw(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| value.raw.as_str())
+    }
+
+    /// Capture `raw` as `name`, `false` unless it decodes to UTF-8.
+    fn insert(&mut self, name: &str, raw: &str) -> bool {
+        let Some(decoded) = unescape(raw) else {
+            return false;
+        };
+        let raw = raw.to_string();
+        self.values
+            .insert(name.to_string(), Captured { raw, decoded });
+        true
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct RoutePath {
     route: String,
     route_type: RouteType,
+    /// Segments of path routes with parameters.
+    segments: Option<Vec<Segment>>,
 }
 
 impl RoutePath {
+    /// Route matching `route`.
+    ///
+    /// Segments of path routes starting with `:` are parameters matching one segment, and a
+    /// last segment starting with `*` is a wildcard matching the rest of the entrance.
     pub fn new(route: &str, route_type: RouteType) -> Self {
+        let route = route.trim_end_matches("/").to_string();
+        let count = route.split('/').count();
+        let segments: Vec<_> = route
+            .split('/')
+            .enumerate()
+            .map(|(index, segment)| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    return Segment::Param(name.to_string());
+                }
+                match segment.strip_prefix('*') {
+                    Some(name) if index == count - 1 => Segment::Wildcard(name.to_string()),
+                    _ => Segment::Literal(segment.to_string()),
+                }
+            })
+            .collect();
+        let parameterized = route_type == RouteType::Path
+            && segments
+                .iter()
+                .any(|segment| !matches!(segment, Segment::Literal(_)));
         Self {
-            route: route.trim_end_matches("/").to_string(),
+            route,
             route_type,
+            segments: parameterized.then_some(segments),
         }
     }
 
     #[inline]
     pub fn check(&self, entrance: &str) -> Result<bool> {
-        if self.route_type == RouteType::RegexPath {
-            let regex = Regex::new(&self.route)?;
-            Ok(regex.is_match(entrance))
-        } else if self.route_type == RouteType::StartswithPath {
-            Ok(entrance.starts_with(&self.route))
-        } else {
-            Ok(self.route == entrance.trim_end_matches("/"))
+        Ok(self.capture(entrance)?.is_some())
+    }
+
+    /// Parameters captured from `entrance`, `None` unless the route matches it.
+    ///
+    /// ```rust
+    /// use oblivion::models::router::{RoutePath, RouteType};
+    ///
+    /// let route = RoutePath::new("/user/:id/posts/:post", RouteType::Path);
+    /// let params = route.capture("/user/42/posts/hello%20world/").unwrap().unwrap();
+    /// assert_eq!(params.get("id"), Some("42"));
+    /// assert_eq!(params.get("post"), Some("hello world"));
+    /// assert!(route.capture("/user/42/posts").unwrap().is_none());
+    ///
+    /// let route = RoutePath::new("/files/*path", RouteType::Path);
+    /// let params = route.capture("/files/docs/a%2Fb.txt").unwrap().unwrap();
+    /// assert_eq!(params.get_raw("path"), Some("docs/a%2Fb.txt"));
+    /// assert_eq!(params.get("path"), Some("docs/a/b.txt"));
+    /// assert!(route.capture("/files").unwrap().is_none());
+    /// ```
+    pub fn capture(&self, entrance: &str) -> Result<Option<Params>> {
+        let entrance = entrance.trim_end_matches("/");
+        let Some(segments) = &self.segments else {
+            let matched = match self.route_type {
+                RouteType::RegexPath => Regex::new(&self.route)?.is_match(entrance),
+                RouteType::StartswithPath => entrance.starts_with(&self.route),
+                RouteType::Path => self.route == entrance,
+            };
+            return Ok(matched.then(Params::new));
+        };
+
+        let mut parts = entrance.splitn(segments.len(), '/');
+        let mut params = Params::new();
+        for segment in segments {
+            let Some(part) = parts.next() else {
+                return Ok(None);
+            };
+            let matched = match segment {
+                Segment::Literal(literal) => literal == part,
+                // Everything left, slashes included.
+                Segment::Wildcard(name) => !part.is_empty() && params.insert(name, part),
+                Segment::Param(name) => {
+                    !part.is_empty() && !part.contains('/') && params.insert(name, part)
+                }
+            };
+            if !matched {
+                return Ok(None);
+            }
+        }
+        Ok(Some(params))
+    }
+
+    /// Route matching the entrances of `self` behind `prefix`.
+    fn nested(&self, prefix: &str) -> Self {
+        let route = match self.route_type {
+            RouteType::RegexPath => format!(
+                "^{}{}",
+                regex::escape(prefix),
+                self.route.strip_prefix('^').unwrap_or(&self.route)
+            ),
+            _ => format!("{}{}", prefix, self.route),
+        };
+        Self::new(&route, self.route_type.clone())
+    }
+
+    /// Pattern of the route regardless of the names of its parameters, routes of the same
+    /// shape match the same entrances.
+    fn shape(&self) -> String {
+        let Some(segments) = &self.segments else {
+            return self.route.clone();
+        };
+        let segments: Vec<_> = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.as_str(),
+                Segment::Param(_) => ":",
+                Segment::Wildcard(_) => "*",
+            })
+            .collect();
+        segments.join("/")
+    }
+
+    fn kind(&self) -> RouteKind {
+        match (&self.route_type, &self.segments) {
+            (RouteType::StartswithPath, _) => RouteKind::Prefix,
+            (RouteType::RegexPath, _) => RouteKind::Regex,
+            (RouteType::Path, None) => RouteKind::Exact,
+            (RouteType::Path, Some(segments)) => match segments.last() {
+                Some(Segment::Wildcard(_)) => RouteKind::Wildcard,
+                _ => RouteKind::Param,
+            },
         }
     }
+
+    /// Rank among the routes matching the same entrance, literal segments winning over
+    /// parameters and parameters over wildcards from left to right.
+    fn specificity(&self) -> Option<Vec<u8>> {
+        let segments = self.segments.as_ref()?;
+        Some(segments.iter().map(Segment::rank).collect())
+    }
+}
+
+/// Handlers of a route, by method.
+#[derive(Clone, Default)]
+struct Endpoint {
+    /// Handler of the methods without one of their own.
+    any: Option<Route>,
+    methods: BTreeMap<String, Route>,
+}
+
+impl Endpoint {
+    fn route(&self, method: &str) -> Option<&Route> {
+        // `CONNECT` is what clients send to fetch an entrance, see [`Router::method_route`].
+        let method = match method {
+            "CONNECT" if !self.methods.contains_key(method) => "GET",
+            method => method,
+        };
+        self.methods.get(method).or(self.any.as_ref())
+    }
+
+    /// Run `layers` before the layers of every route.
+    fn wrap(&mut self, layers: &[SharedMiddleware]) {
+        for route in self.any.iter_mut().chain(self.methods.values_mut()) {
+            route.layers.splice(0..0, layers.iter().cloned());
+        }
+    }
+
+    /// Whether a request could be handled by both endpoints.
+    fn overlaps(&self, other: &Endpoint) -> bool {
+        self.any.is_some()
+            || other.any.is_some()
+            || self
+                .methods
+                .keys()
+                .any(|method| other.methods.contains_key(method))
+    }
+
+    fn merge(&mut self, other: Endpoint) {
+        self.any = other.any.or(self.any.take());
+        self.methods.extend(other.methods);
+    }
+}
+
+/// How the pattern of a route matches entrances, see [`RouteInfo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteKind {
+    /// A [`RouteType::Path`] without parameters, matching one entrance.
+    Exact,
+    /// A [`RouteType::Path`] with `:name` parameters.
+    Param,
+    /// A [`RouteType::Path`] ending with a `*name` wildcard.
+    Wildcard,
+    /// A [`RouteType::StartswithPath`].
+    Prefix,
+    /// A [`RouteType::RegexPath`].
+    Regex,
+}
+
+/// Route registered with a [`Router`], see [`Router::routes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    pub pattern: String,
+    /// Methods with a handler of their own, in alphabetical order.
+    pub methods: Vec<String>,
+    /// Whether a handler answers the methods without one of their own.
+    pub any_method: bool,
+    pub kind: RouteKind,
+}
+
+impl RouteInfo {
+    fn new(path: &RoutePath, endpoint: &Endpoint) -> Self {
+        Self {
+            pattern: path.route.clone(),
+            methods: endpoint.methods.keys().cloned().collect(),
+            any_method: endpoint.any.is_some(),
+            kind: path.kind(),
+        }
+    }
+}
+
+/// Outcome of routing a request, see [`Router::find`].
+pub enum Matched {
+    Route(Route, Params),
+    /// The entrance is routed for other methods only, listed in alphabetical order.
+    MethodNotAllowed(Vec<String>),
+}
+
+/// Fallback of a router mounted with [`Router::nest`], for the entrances under its prefix.
+#[derive(Clone)]
+struct Scope {
+    /// The prefix itself.
+    prefix: RoutePath,
+    /// Entrances below the prefix.
+    below: RoutePath,
+    route: Route,
+}
+
+impl Scope {
+    fn new(prefix: &str, route: Route) -> Self {
+        Self {
+            prefix: RoutePath::new(prefix, RouteType::Path),
+            below: RoutePath::new(&format!("{prefix}/*"), RouteType::Path),
+            route,
+        }
+    }
+
+    fn nested(&self, prefix: &str, layers: &[SharedMiddleware]) -> Self {
+        let mut route = self.route.clone();
+        route.layers.splice(0..0, layers.iter().cloned());
+        Self {
+            prefix: self.prefix.nested(prefix),
+            below: self.below.nested(prefix),
+            route,
+        }
+    }
+
+    /// Parameters of the prefix if `entrance` is under it.
+    fn capture(&self, entrance: &str) -> Result<Option<Params>> {
+        match self.prefix.capture(entrance)? {
+            Some(params) => Ok(Some(params)),
+            None => self.below.capture(entrance),
+        }
+    }
+
+    /// Segments of the prefix, the deepest scope containing an entrance handles it.
+    fn depth(&self) -> usize {
+        self.prefix.route.split('/').count()
+    }
 }
 
 #[derive(Clone)]
 pub struct Router {
-    routes: HashMap<RoutePath, Route>,
+    routes: HashMap<RoutePath, Endpoint>,
+    layers: Vec<SharedMiddleware>,
+    fallback: Option<Route>,
+    method_not_allowed: Option<Route>,
+    scopes: Vec<Scope>,
+    replaced: Vec<RouteInfo>,
 }
 
 impl Default for Router {
@@ -71,24 +443,770 @@ impl Router {
     pub fn new() -> Self {
         Self {
             routes: HashMap::new(),
+            layers: Vec::new(),
+            fallback: None,
+            method_not_allowed: None,
+            scopes: Vec::new(),
+            replaced: Vec::new(),
         }
     }
 
+    /// Answer requests no route matches with `handler` instead of the default `404` response.
+    ///
+    /// The fallback of a router mounted with [`Router::nest`] answers the entrances under its
+    /// prefix, before the fallback of the router it is mounted in, and runs through the
+    /// layers of both routers like its routes.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion_codegen::async_route;
+    /// # use serde_json::{json, Value};
+    /// #[async_route]
+    /// fn hello(_session: Session) -> &'static str {
+    ///     "hello"
+    /// }
+    ///
+    /// #[async_route]
+    /// fn missing(session: Session) -> (u32, Vec<u8>) {
+    ///     let request = &session.request;
+    ///     eprintln!("{} asked for {}", request.get_ip(), request.get_entrance());
+    ///     (404, format!("Nothing at {}", request.get_entrance()).into_bytes())
+    /// }
+    ///
+    /// #[async_route]
+    /// fn api_missing(_session: Session) -> Value {
+    ///     json!({ "error": "unknown endpoint" })
+    /// }
+    ///
+    /// #[async_route]
+    /// fn not_allowed(session: Session) -> (u32, Vec<u8>) {
+    ///     (405, session.request.allowed_methods().join(",").into_bytes())
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut api = Router::new();
+    /// api.get("/hello", hello).fallback(api_missing);
+    /// let mut router = Router::new();
+    /// router.get("/hello", hello).fallback(missing).method_not_allowed(not_allowed);
+    /// router.nest("/api", api)?;
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let url = |entrance| format!("olps://127.0.0.1:{port}{entrance}");
+    ///
+    /// let response = Request::get(&url("/nowhere")).send().await?;
+    /// assert_eq!((response.status_code, response.text()?), (404, "Nothing at /nowhere"));
+    /// let response = Request::get(&url("/api/nowhere")).send().await?;
+    /// assert_eq!(response.json()?, json!({ "error": "unknown endpoint" }));
+    /// let response = Request::post(&url("/hello")).send().await?;
+    /// assert_eq!((response.status_code, response.text()?), (405, "GET"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fallback(&mut self, handler: Handler) -> &mut Self {
+        self.register_fallback(Route::new(handler))
+    }
+
+    /// Answer requests no route matches with `route`, see [`Router::fallback`].
+    pub fn register_fallback(&mut self, route: Route) -> &mut Self {
+        self.fallback = Some(route);
+        self
+    }
+
+    /// Answer requests to an entrance routed for other methods only with `handler` instead of
+    /// the default `405` response, see [`OblivionRequest::allowed_methods`].
+    ///
+    /// Only the handler of the router given to the server is used, those of routers mounted
+    /// with [`Router::nest`] are not.
+    ///
+    /// [`OblivionRequest::allowed_methods`]: crate::utils::parser::OblivionRequest::allowed_methods
+    pub fn method_not_allowed(&mut self, handler: Handler) -> &mut Self {
+        self.register_method_not_allowed(Route::new(handler))
+    }
+
+    /// Answer requests to an entrance routed for other methods only with `route`, see
+    /// [`Router::method_not_allowed`].
+    pub fn register_method_not_allowed(&mut self, route: Route) -> &mut Self {
+        self.method_not_allowed = Some(route);
+        self
+    }
+
+    /// Run `middleware` for every request, after the layers added before, see [`Middleware`].
+    ///
+    /// Layers of a router mounted with [`Router::nest`] only run for its own routes.
+    ///
+    /// ```rust
+    /// # use futures::future::BoxFuture;
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::middleware::{Middleware, Next};
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn hello(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+    /// # }
+    /// struct Forbid;
+    ///
+    /// impl Middleware for Forbid {
+    ///     fn handle<'a>(
+    ///         &'a self,
+    ///         _session: Session,
+    ///         _next: Next<'a>,
+    ///     ) -> BoxFuture<'a, anyhow::Result<BaseResponse>> {
+    ///         Box::pin(async { Ok(BaseResponse::StatusResponse(403, b"Forbidden".to_vec())) })
+    ///     }
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut admin = Router::new();
+    /// admin.get("/hello", hello).layer(Forbid);
+    /// let mut router = Router::new();
+    /// router.get("/hello", hello).nest("/admin", admin)?;
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+    ///
+    /// assert_eq!(get("/hello").await?.text()?, "hello");
+    /// assert_eq!(get("/admin/hello").await?.status_code, 403);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn layer(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.layers.push(Arc::new(middleware));
+        self
+    }
+
+    /// Route `path` to `handler` for every method without a handler of its own.
     pub fn route(&mut self, path: RoutePath, handler: Handler) -> &mut Self {
-        self.routes.insert(path, Route { handler });
+        self.register(path, Route::new(handler));
         self
     }
 
+    /// Route `path` to `route` for every method without a route of its own, replacing the
+    /// route registered for them before, see [`Router::replaced`].
     pub fn register(&mut self, path: RoutePath, route: Route) {
-        self.routes.insert(path, route);
+        let kind = path.kind();
+        let pattern = path.route.clone();
+        if self
+            .routes
+            .entry(path)
+            .or_default()
+            .any
+            .replace(route)
+            .is_some()
+        {
+            self.replaced.push(RouteInfo {
+                pattern,
+                methods: Vec::new(),
+                any_method: true,
+                kind,
+            });
+        }
+    }
+
+    /// Remove the route registered for `path`, for every method, returning whether there was
+    /// one.
+    pub fn unregister(&mut self, path: &RoutePath) -> bool {
+        self.routes.remove(path).is_some()
+    }
+
+    /// Route requests of `method` matching the path route `path` to `handler`.
+    ///
+    /// Requests matching a path routed for other methods only are answered with status `405`,
+    /// listing the allowed methods, while unrouted paths are still answered with status `404`.
+    /// Requests of the legacy `CONNECT` method, sent by [`Client::connect`] and [`Client::get`],
+    /// are handled by the `GET` route unless `CONNECT` is routed itself.
+    ///
+    /// [`Client::connect`]: crate::models::client::Client::connect
+    /// [`Client::get`]: crate::models::client::Client::get
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn list(_session: Session) -> ServerResponse {
+    ///     Ok(BaseResponse::TextResponse("list".to_string()))
+    /// }
+    ///
+    /// #[async_route]
+    /// fn create(session: Session) -> ServerResponse {
+    ///     let body = String::from_utf8_lossy(session.request.body()).into_owned();
+    ///     Ok(BaseResponse::TextResponse(format!("created {body}")))
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/things", list).post("/things", create);
+    /// router.method_route("purge", "/things/:id", list);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let url = |entrance| format!("olps://127.0.0.1:{port}{entrance}");
+    ///
+    /// assert_eq!(Request::get(&url("/things")).send().await?.text()?, "list");
+    /// let response = Request::post(&url("/things")).body(b"a".to_vec()).send().await?;
+    /// assert_eq!(response.text()?, "created a");
+    ///
+    /// let response = Request::put(&url("/things")).send().await?;
+    /// assert_eq!(response.status_code, 405);
+    /// assert_eq!(response.text()?, "Method PUT is not allowed, allowed methods: GET, POST");
+    /// let response = Request::get(&url("/things/1")).send().await?;
+    /// assert_eq!(response.status_code, 405);
+    /// assert_eq!(Request::get(&url("/missing")).send().await?.status_code, 404);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn method_route(&mut self, method: &str, path: &str, handler: Handler) -> &mut Self {
+        self.register_method(method, path, Route::new(handler))
+    }
+
+    /// Route requests of `method` matching the path route `path` to `route`, see
+    /// [`Router::method_route`].
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::router::{Route, Router};
+    /// # use oblivion::models::server::{Server, PAYLOAD_TOO_LARGE_STATUS};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn echo(session: Session) -> (u32, Vec<u8>) {
+    ///     (200, session.request.body().to_vec())
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut router = Router::new();
+    /// router.register_method("POST", "/echo", Route::new(echo).json_limit(32));
+    /// router.register_fallback(Route::new(echo).json_limit(4));
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let post = |entrance| {
+    ///     let url = format!("olps://127.0.0.1:{port}{entrance}");
+    ///     Request::post(&url).body(b"hello".to_vec()).send()
+    /// };
+    ///
+    /// assert_eq!(post("/echo").await?.text()?, "hello");
+    /// assert_eq!(post("/elsewhere").await?.status_code, PAYLOAD_TOO_LARGE_STATUS);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_method(&mut self, method: &str, path: &str, route: Route) -> &mut Self {
+        let path = RoutePath::new(path, RouteType::Path);
+        let (kind, pattern) = (path.kind(), path.route.clone());
+        let method = method.to_uppercase();
+        let endpoint = self.routes.entry(path).or_default();
+        if endpoint.methods.insert(method.clone(), route).is_some() {
+            self.replaced.push(RouteInfo {
+                pattern,
+                methods: vec![method],
+                any_method: false,
+                kind,
+            });
+        }
+        self
     }
 
-    pub fn get_handler(&self, path: &str) -> Result<Handler> {
-        for (route_path, route) in self.routes.iter() {
-            if route_path.check(path)? {
-                return Ok(route.get_handler());
+    pub fn get(&mut self, path: &str, handler: Handler) -> &mut Self {
+        self.method_route("GET", path, handler)
+    }
+
+    pub fn post(&mut self, path: &str, handler: Handler) -> &mut Self {
+        self.method_route("POST", path, handler)
+    }
+
+    pub fn put(&mut self, path: &str, handler: Handler) -> &mut Self {
+        self.method_route("PUT", path, handler)
+    }
+
+    pub fn delete(&mut self, path: &str, handler: Handler) -> &mut Self {
+        self.method_route("DELETE", path, handler)
+    }
+
+    /// Answer `GET` requests on `prefix` and the entrances below it with the files of the
+    /// directory `root`, see [`ServeDir`]. Other methods are [not allowed](Router::method_not_allowed).
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let base = std::env::temp_dir().join(format!("oblivion-static-{}", std::process::id()));
+    /// # let root = base.join("public");
+    /// # std::fs::create_dir_all(root.join("css"))?;
+    /// # std::fs::write(root.join("index.html"), "<h1>home</h1>")?;
+    /// # std::fs::write(root.join("css/site.css"), "body {}")?;
+    /// # std::fs::write(base.join("secret.txt"), "secret")?;
+    /// # #[cfg(unix)]
+    /// # std::os::unix::fs::symlink(base.join("secret.txt"), root.join("link.txt"))?;
+    /// let mut router = Router::new();
+    /// router.serve_dir("/static", &root);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+    ///
+    /// assert_eq!(get("/static/css/site.css").await?.text()?, "body {}");
+    /// assert_eq!(get("/static").await?.text()?, "<h1>home</h1>");
+    /// assert_eq!(get("/static/missing.css").await?.status_code, 404);
+    /// let post = Request::post(&format!("olps://127.0.0.1:{port}/static/css/site.css"));
+    /// assert_eq!(post.send().await?.status_code, 405);
+    /// assert_eq!(get("/static/css/../../secret.txt").await?.status_code, 403);
+    /// assert_eq!(get("/static/css%2F..%2F..%2Fsecret.txt").await?.status_code, 403);
+    /// # #[cfg(unix)]
+    /// assert_eq!(get("/static/link.txt").await?.status_code, 403);
+    /// # std::fs::remove_dir_all(&base)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serve_dir(&mut self, prefix: &str, root: impl Into<PathBuf>) -> &mut Self {
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        let prefix = prefix.trim_end_matches('/');
+        let files = ServeDir::new(root);
+        let route = Route::new(not_found).layer(files.clone());
+        self.register_method("GET", prefix, route);
+        let route = Route::new(not_found).layer(files);
+        self.register_method("GET", &format!("{prefix}/*{FILE_PARAM}"), route)
+    }
+
+    /// Mount every route of `router` under `prefix`, which may contain parameters too.
+    ///
+    /// Fails with [`Exception::RouteConflict`] without mounting anything if a nested route
+    /// would match the same entrances as a route already registered.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn member(session: Session) -> ServerResponse {
+    ///     let (org, id) = (session.request.param("org"), session.request.param("id"));
+    ///     Ok(BaseResponse::TextResponse(format!("{} {}", org.unwrap(), id.unwrap())))
+    /// }
+    ///
+    /// #[async_route]
+    /// fn stats(_session: Session) -> ServerResponse {
+    ///     Ok(BaseResponse::TextResponse("stats".to_string()))
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut members = Router::new();
+    /// path_route!(&mut members, "/members/:id" => member);
+    /// path_route!(&mut members, "/" => stats);
+    ///
+    /// let mut router = Router::new();
+    /// path_route!(&mut router, "/admin/stats" => stats);
+    /// router.nest("/orgs/:org", members.clone())?;
+    ///
+    /// let mut admin = Router::new();
+    /// path_route!(&mut admin, "/stats" => stats);
+    /// let Err(error) = router.nest("/admin", admin) else {
+    ///     panic!("expected a conflict");
+    /// };
+    /// assert!(matches!(error.downcast_ref(), Some(Exception::RouteConflict { .. })));
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    ///
+    /// let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+    /// assert_eq!(get("/orgs/acme/members/7").await?.text()?, "acme 7");
+    /// assert_eq!(get("/orgs/acme").await?.text()?, "stats");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn nest(&mut self, prefix: &str, router: Router) -> Result<&mut Self> {
+        let prefix = format!("/{}", prefix.trim_matches('/'));
+        let prefix = prefix.trim_end_matches('/');
+        let mut scopes: Vec<_> = router
+            .scopes
+            .iter()
+            .map(|scope| scope.nested(prefix, &router.layers))
+            .collect();
+        if let Some(fallback) = router.fallback {
+            scopes.push(Scope::new("", fallback).nested(prefix, &router.layers));
+        }
+        let routes: Vec<_> = router
+            .routes
+            .into_iter()
+            .map(|(path, mut endpoint)| {
+                endpoint.wrap(&router.layers);
+                (path.nested(prefix), endpoint)
+            })
+            .collect();
+        for (path, endpoint) in &routes {
+            let conflict = self.routes.iter().any(|(registered, existing)| {
+                let same_shape =
+                    registered.route_type == path.route_type && registered.shape() == path.shape();
+                // Routes for other methods of the exact same path can be merged.
+                same_shape && (registered != path || existing.overlaps(endpoint))
+            });
+            if conflict {
+                return Err(Exception::RouteConflict {
+                    route: path.route.clone(),
+                }
+                .into());
             }
         }
-        Ok(not_found)
+        for (path, endpoint) in routes {
+            self.routes.entry(path).or_default().merge(endpoint);
+        }
+        self.scopes.extend(scopes);
+        let replaced = router.replaced.into_iter().map(|info| {
+            let route_type = match info.kind {
+                RouteKind::Prefix => RouteType::StartswithPath,
+                RouteKind::Regex => RouteType::RegexPath,
+                _ => RouteType::Path,
+            };
+            let pattern = RoutePath::new(&info.pattern, route_type)
+                .nested(prefix)
+                .route;
+            RouteInfo { pattern, ..info }
+        });
+        self.replaced.extend(replaced);
+        Ok(self)
+    }
+
+    /// Handler of the route matching a request of `method` to `path`, see [`Router::find`].
+    ///
+    /// Paths routed for other methods only get the handler of
+    /// [`Router::method_not_allowed`], or [`not_found`] if there is none.
+    pub fn get_handler(&self, method: &str, path: &str) -> Result<Handler> {
+        match self.find(method, path)? {
+            Matched::Route(route, _) => Ok(route.get_handler()),
+            Matched::MethodNotAllowed(_) => Ok(self
+                .method_not_allowed
+                .as_ref()
+                .map_or(not_found, Route::get_handler)),
+        }
+    }
+
+    /// Handler of the route matching a request of `method` to `path` along with the
+    /// parameters it captured.
+    ///
+    /// The route is picked regardless of the method first. Path routes without parameters
+    /// win over those with parameters, which win over the other routes. Among parameterized
+    /// routes the one whose first differing segment is the most specific wins, literals
+    /// before parameters before wildcards, so `/user/me` is preferred to `/user/:id` and
+    /// `/files/:name` to `/files/*path`.
+    pub fn find(&self, method: &str, path: &str) -> Result<Matched> {
+        let Some((endpoint, params)) = self.lookup(path)? else {
+            return self.fall_back(path);
+        };
+        match endpoint.route(&method.to_uppercase()) {
+            Some(route) => Ok(Matched::Route(route.clone(), params)),
+            None => Ok(Matched::MethodNotAllowed(
+                endpoint.methods.keys().cloned().collect(),
+            )),
+        }
+    }
+
+    /// Every route registered, including those of nested routers, ordered by pattern.
+    ///
+    /// ```rust
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::{RouteInfo, RouteKind, Router};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn handler(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("ok".to_string()))
+    /// # }
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut users = Router::new();
+    /// users.get("/:id", handler).delete("/:id", handler);
+    /// let mut router = Router::new();
+    /// router.get("/health", handler).post("/health", handler);
+    /// router.nest("/users", users)?;
+    ///
+    /// let routes = router.routes();
+    /// assert_eq!(routes[0].pattern, "/health");
+    /// assert_eq!(routes[0].methods, ["GET", "POST"]);
+    /// assert_eq!(routes[1].kind, RouteKind::Param);
+    /// assert_eq!(routes[1].methods, ["DELETE", "GET"]);
+    /// assert!(router.has_route("/users/42", "delete"));
+    /// assert!(!router.has_route("/users/42", "PUT"));
+    /// assert!(!router.has_route("/missing", "GET"));
+    ///
+    /// // Routing the same pattern and method again replaces the handler, but is recorded.
+    /// router.get("/health", handler);
+    /// assert_eq!(router.replaced()[0].pattern, "/health");
+    /// assert_eq!(router.replaced()[0].methods, ["GET"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        let mut routes: Vec<_> = self
+            .routes
+            .iter()
+            .map(|(path, endpoint)| RouteInfo::new(path, endpoint))
+            .collect();
+        routes.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+        routes
+    }
+
+    /// Whether a request of `method` to `path` is answered by a route rather than by a
+    /// fallback or with status `405`.
+    pub fn has_route(&self, path: &str, method: &str) -> bool {
+        match self.lookup(path) {
+            Ok(Some((endpoint, _))) => endpoint.route(&method.to_uppercase()).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Registrations that replaced a route of the same pattern for the same methods, in the
+    /// order they happened, see [`Router::routes`].
+    pub fn replaced(&self) -> &[RouteInfo] {
+        &self.replaced
+    }
+
+    /// Endpoint of the route matching `path` regardless of the method, see [`Router::find`].
+    fn lookup(&self, path: &str) -> Result<Option<(&Endpoint, Params)>> {
+        let mut best: Option<(Option<Vec<u8>>, &Endpoint, Params)> = None;
+        for (route_path, endpoint) in self.routes.iter() {
+            if route_path.route_type == RouteType::Path && route_path.segments.is_none() {
+                if route_path.check(path)? {
+                    best = Some((Some(Vec::new()), endpoint, Params::new()));
+                    break;
+                }
+                continue;
+            }
+            let Some(params) = route_path.capture(path)? else {
+                continue;
+            };
+            let specificity = route_path.specificity();
+            let better = match &best {
+                None => true,
+                // Parameterized path routes before the others.
+                Some((current, _, _)) => specificity > *current,
+            };
+            if better {
+                best = Some((specificity, endpoint, params));
+            }
+        }
+        Ok(best.map(|(_, endpoint, params)| (endpoint, params)))
+    }
+
+    /// Fallback answering `path`, which no route matches.
+    fn fall_back(&self, path: &str) -> Result<Matched> {
+        let mut best: Option<(&Scope, Params)> = None;
+        for scope in &self.scopes {
+            let Some(params) = scope.capture(path)? else {
+                continue;
+            };
+            if best
+                .as_ref()
+                .is_none_or(|(current, _)| scope.depth() > current.depth())
+            {
+                best = Some((scope, params));
+            }
+        }
+        Ok(match (best, &self.fallback) {
+            (Some((scope, params)), _) => Matched::Route(scope.route.clone(), params),
+            (None, Some(fallback)) => Matched::Route(fallback.clone(), Params::new()),
+            (None, None) => Matched::Route(Route::new(not_found), Params::new()),
+        })
+    }
+
+    /// Timeout of the route matching `method` and `path`, if it has one.
+    pub(crate) fn timeout(&self, method: &str, path: &str) -> Option<Duration> {
+        match self.find(method, path) {
+            Ok(Matched::Route(route, _)) => route.timeout,
+            _ => None,
+        }
+    }
+
+    /// Largest body of the route matching `method` and `path`, if it has a limit.
+    pub(crate) fn max_body_size(&self, method: &str, path: &str) -> Option<usize> {
+        match self.find(method, path) {
+            Ok(Matched::Route(route, _)) => route.body_limit(),
+            Ok(Matched::MethodNotAllowed(_)) => {
+                self.method_not_allowed.as_ref().and_then(Route::body_limit)
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Answer the request behind `session` with the route it matches, through the layers of
+    /// the router and of the route.
+    pub async fn handle(&self, session: Session) -> Result<BaseResponse> {
+        self.dispatch(session, None).await
+    }
+
+    /// Answer like [`Router::handle`], with `response` instead of the handler of the route
+    /// if there is one, through the same layers.
+    pub(crate) async fn dispatch(
+        &self,
+        mut session: Session,
+        response: Option<BaseResponse>,
+    ) -> Result<BaseResponse> {
+        let entrance = session.request.entrance.clone();
+        let route = match self.find(session.request.get_method(), &entrance)? {
+            Matched::Route(route, params) => {
+                session.request.params = params;
+                Ok(route)
+            }
+            Matched::MethodNotAllowed(allowed) => {
+                let route = self.method_not_allowed.clone().ok_or_else(|| {
+                    format!(
+                        "Method {} is not allowed, allowed methods: {}",
+                        session.request.get_method(),
+                        allowed.join(", ")
+                    )
+                });
+                session.request.allowed_methods = allowed;
+                route
+            }
+        };
+        let mut layers = self.layers.clone();
+        let terminal = match route {
+            Ok(route) => {
+                session.request.json_limit = route.json_limit;
+                layers.extend(route.layers);
+                match response {
+                    Some(response) => Terminal::Response(response),
+                    None => Terminal::Handler(route.handler),
+                }
+            }
+            Err(message) => Terminal::Response(
+                response.unwrap_or(BaseResponse::StatusResponse(405, message.into_bytes())),
+            ),
+        };
+        Next::new(&layers, terminal).run(session).await
+    }
+}
+
+/// Routes of a running server, see [`Server::router_handle`](super::server::Server::router_handle).
+///
+/// Every request is routed with the routes current when it was received, a change applies
+/// to the requests received after it while those being handled complete with the routes
+/// they started with. Routing a request only takes a snapshot of the routes, changing them
+/// clones the router under the lock-free swap.
+///
+/// ```rust
+/// # use oblivion::models::client::Request;
+/// # use oblivion::models::router::{Route, RoutePath, RouteType, Router};
+/// # use oblivion::models::server::Server;
+/// # use oblivion::models::session::Session;
+/// # use oblivion_codegen::async_route;
+/// #[async_route]
+/// fn stable(_session: Session) -> &'static str {
+///     "stable"
+/// }
+///
+/// #[async_route]
+/// fn plugin(_session: Session) -> &'static str {
+///     "plugin"
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+///
+/// let mut router = Router::new();
+/// router.get("/stable", stable);
+/// let server = Server::new("127.0.0.1", 0, router);
+/// let routes = server.router_handle();
+/// # let server = server.bind().await?;
+/// # let port = server.local_addr()?.port();
+/// # tokio::spawn(server.serve());
+/// let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+/// let path = RoutePath::new("/plugin", RouteType::Path);
+///
+/// routes.add_route(path.clone(), Route::new(plugin));
+/// assert_eq!(get("/plugin").await?.text()?, "plugin");
+/// assert!(routes.remove_route(&path));
+/// assert_eq!(get("/plugin").await?.status_code, 404);
+///
+/// // Requests keep being answered while the routes change under them.
+/// let churn = {
+///     let (routes, path) = (routes.clone(), path.clone());
+///     tokio::spawn(async move {
+///         for _ in 0..200 {
+///             routes.add_route(path.clone(), Route::new(plugin));
+///             tokio::task::yield_now().await;
+///             routes.remove_route(&path);
+///         }
+///     })
+/// };
+/// let requests: Vec<_> = (0..50)
+///     .map(|index| {
+///         let entrance = if index % 2 == 0 { "/stable" } else { "/plugin" };
+///         tokio::spawn(get(entrance))
+///     })
+///     .collect();
+/// for request in requests {
+///     let response = request.await??;
+///     assert!(matches!(response.status_code, 200 | 404));
+/// }
+/// churn.await?;
+/// assert_eq!(get("/stable").await?.text()?, "stable");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RouterHandle {
+    router: Arc<ArcSwap<Router>>,
+}
+
+impl RouterHandle {
+    pub(crate) fn new(router: Router) -> Self {
+        Self::shared(Arc::new(router))
+    }
+
+    /// Handle over routes shared with other handles, without copying them.
+    pub(crate) fn shared(router: Arc<Router>) -> Self {
+        Self {
+            router: Arc::new(ArcSwap::new(router)),
+        }
+    }
+
+    /// Routes requests received from now on are routed with.
+    pub fn load(&self) -> Arc<Router> {
+        self.router.load_full()
+    }
+
+    /// Change the routes with `update`, which may run again if they changed concurrently.
+    pub fn update(&self, mut update: impl FnMut(&mut Router)) {
+        self.router.rcu(|current| {
+            let mut router = Router::clone(current);
+            update(&mut router);
+            router
+        });
+    }
+
+    /// Route `path` to `route`, see [`Router::register`].
+    pub fn add_route(&self, path: RoutePath, route: Route) {
+        self.update(|router| router.register(path.clone(), route.clone()));
+    }
+
+    /// Remove the route of `path`, see [`Router::unregister`].
+    pub fn remove_route(&self, path: &RoutePath) -> bool {
+        let mut removed = false;
+        self.update(|router| removed = router.unregister(path));
+        removed
     }
 }
@@ -1,35 +1,1250 @@
 //! # Oblivion Server
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::panic::AssertUnwindSafe;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
 
-use crate::utils::gear::Socket;
+use crate::exceptions::{Exception, TimeoutPhase};
+use crate::types::PanicHandler;
+use crate::utils::cancel::CancellationToken;
+use crate::utils::compression::Compression;
+use crate::utils::gear::{Socket, TcpOptions, LOCAL_PEER};
+use crate::utils::generator::KexAlgorithm;
+use crate::utils::identity::{IdentityKey, IdentityVerifier};
 #[cfg(not(feature = "bench"))]
 use crate::VERSION;
 
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
 use chrono::Local;
 use colored::Colorize;
+use futures::future::select_all;
+use futures::FutureExt;
 #[cfg(feature = "bench")]
 use std::process;
 use tokio::net::{TcpListener, TcpStream};
-#[cfg(feature = "perf")]
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
 use tokio::time::Instant;
 
-use super::packet::{OED, OSC};
-use super::router::Router;
-use super::session::Session;
+use super::filter::{AddressFilter, IpNet};
+use super::handler::{internal_error, Panic};
+use super::manager::SessionManager;
+use super::middleware::{Completion, Outcome};
+use super::packet::StatusCode;
+use super::render::BaseResponse;
+use super::router::{Router, RouterHandle};
+use super::session::{Capabilities, Session, SessionBuilder, SessionFlag};
+#[cfg(feature = "tls")]
+use super::tls::{
+    rustls::pki_types::{CertificateDer, PrivateKeyDer},
+    TlsAcceptor,
+};
+
+/// Oblivion Server Configuration
+///
+/// Options applied by the server to every accepted connection.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use oblivion::models::router::Router;
+/// # use oblivion::models::server::{Server, ServerConfig};
+/// let config = ServerConfig::new().idle_timeout(Duration::from_secs(60));
+/// let server = Server::new("127.0.0.1", 0, Router::new()).with_config(config);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ServerConfig {
+    idle_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    max_frame_size: Option<usize>,
+    max_payload_size: Option<usize>,
+    tcp: TcpOptions,
+    http_health_check: bool,
+    drain_timeout: Option<Duration>,
+    max_connections: Option<usize>,
+    when_busy: BusyPolicy,
+    handshake_concurrency: Option<usize>,
+    handshake_timeout: Option<Duration>,
+    handler_timeout: Option<Duration>,
+    max_body_size: Option<usize>,
+    filter: AddressFilter,
+    compression: Compression,
+    kex_algorithm: KexAlgorithm,
+    identity: Option<IdentityKey>,
+    identity_verifier: Option<IdentityVerifier>,
+    #[cfg(unix)]
+    unix_mode: Option<u32>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsAcceptor>,
+}
+
+/// What a server does with new connections once it reached [`ServerConfig::max_connections`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BusyPolicy {
+    /// Stop accepting until a connection ends, leaving new ones in the backlog of the listener.
+    #[default]
+    Backlog,
+    /// Accept them and answer right away with [`BUSY_STATUS`] before closing them.
+    Reject,
+}
+
+/// Status of the response to connections rejected with [`BusyPolicy::Reject`].
+pub const BUSY_STATUS: u32 = StatusCode::SERVICE_UNAVAILABLE.as_u32();
+
+/// Status of the response to requests over their [`ServerConfig::handler_timeout`].
+pub const TIMEOUT_STATUS: u32 = StatusCode::GATEWAY_TIMEOUT.as_u32();
+
+/// Status of the response to requests whose body is over their
+/// [`ServerConfig::max_body_size`].
+pub const PAYLOAD_TOO_LARGE_STATUS: u32 = StatusCode::PAYLOAD_TOO_LARGE.as_u32();
+
+/// Start of the plain HTTP requests answered by [`ServerConfig::http_health_check`].
+const HTTP_GET: &[u8] = b"GET ";
+
+/// Most bytes of a plain HTTP request read before answering it anyway.
+const HTTP_HEAD_LIMIT: usize = 8 * 1024;
+
+/// Time a plain HTTP request has to arrive and be answered without an
+/// [`ServerConfig::idle_timeout`].
+const HTTP_HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+const HTTP_HEALTH_RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\
+Content-Length: 2\r\nConnection: close\r\n\r\nOK";
+
+/// Pause before accepting again once the process is out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Time connections have to complete their handshake by default, see
+/// [`ServerConfig::handshake_timeout`].
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time a shutting down server waits for active sessions by default, see
+/// [`ServerConfig::drain_timeout`].
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl ServerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Close sessions that neither send nor receive anything for `timeout`.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout of every read from the sockets of connections, see [`Socket::set_read_timeout`].
+    ///
+    /// Reads include waiting for the next request of a session, so it should be longer than
+    /// the [idle timeout](ServerConfig::idle_timeout) if one is set.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout of every write to the sockets of connections, see
+    /// [`Socket::set_write_timeout`].
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Refuse length prefixes over `size` bytes from connections, defaults to
+    /// [`MAX_FRAME_SIZE`](crate::utils::gear::MAX_FRAME_SIZE).
+    ///
+    /// The limit applies from the first byte, the length of the header line included, see
+    /// [`Socket::set_max_frame_size`].
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Client;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let config = ServerConfig::new().max_frame_size(64);
+    /// let server = Server::new("127.0.0.1", 0, Router::new()).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    ///
+    /// assert!(Client::connect(&format!("olps://127.0.0.1:{port}/")).await.is_ok());
+    /// let long = "a".repeat(100);
+    /// assert!(Client::connect(&format!("olps://127.0.0.1:{port}/{long}")).await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = Some(size);
+        self
+    }
+
+    /// Refuse messages whose encrypted payload is over `size` bytes, defaults to
+    /// [`MAX_PAYLOAD_SIZE`](super::session::MAX_PAYLOAD_SIZE), see
+    /// [`Session::set_max_payload`].
+    ///
+    /// The limit applies from the first request on, whose peer isn't authenticated yet: a
+    /// body announcing more in one frame closes the connection before it is buffered.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn size(session: Session) -> String {
+    ///     format!("{} bytes", session.request.body().len())
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut router = Router::new();
+    /// path_route!(&mut router, "/upload" => size);
+    /// let config = ServerConfig::new().max_payload_size(1024);
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let url = format!("olps://127.0.0.1:{port}/upload");
+    ///
+    /// assert_eq!(Request::post(&url).body(vec![0; 512]).send().await?.text()?, "512 bytes");
+    /// assert!(Request::post(&url).body(vec![0; 4096]).send().await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_payload_size(mut self, size: usize) -> Self {
+        self.max_payload_size = Some(size);
+        self
+    }
+
+    /// Disable Nagle's algorithm on TCP connections if `nodelay`, which is the default, see
+    /// [`Socket::set_nodelay`].
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp.nodelay = nodelay;
+        self
+    }
+
+    /// Probe TCP connections once idle for `time`, defaults to
+    /// [`TCP_KEEPALIVE`](crate::utils::gear::TCP_KEEPALIVE), see [`Socket::set_tcp_keepalive`].
+    pub fn tcp_keepalive(mut self, time: Option<Duration>) -> Self {
+        self.tcp.keepalive = time;
+        self
+    }
+
+    /// Wait up to `linger` for unsent data of closed TCP connections, defaults to `None`, see
+    /// [`Socket::set_linger`].
+    pub fn linger(mut self, linger: Option<Duration>) -> Self {
+        self.tcp.linger = linger;
+        self
+    }
+
+    /// Answer plain HTTP `GET` requests with `200 OK` on the same port, for health checks that
+    /// don't speak Oblivion.
+    ///
+    /// Connections are told apart by their first bytes, see [`Socket::peek`]: Oblivion clients
+    /// start with a binary length or preamble, never with `GET `. Requests have the
+    /// [idle timeout](ServerConfig::idle_timeout), or 5 seconds without one, to arrive and be
+    /// answered before the connection is closed.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::models::client::Client;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    /// # use tokio::net::TcpStream;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let config = ServerConfig::new()
+    ///     .http_health_check(true)
+    ///     .idle_timeout(Duration::from_millis(500));
+    /// let server = Server::new("127.0.0.1", 0, Router::new()).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    ///
+    /// let mut http = TcpStream::connect(("127.0.0.1", port)).await?;
+    /// http.write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n").await?;
+    /// let mut response = String::new();
+    /// http.read_to_string(&mut response).await?;
+    /// assert!(response.starts_with("HTTP/1.1 200 OK"));
+    ///
+    /// // Requests are recognized even if their first bytes arrive apart.
+    /// let mut http = TcpStream::connect(("127.0.0.1", port)).await?;
+    /// http.set_nodelay(true)?;
+    /// http.write_all(b"GE").await?;
+    /// tokio::time::sleep(Duration::from_millis(50)).await;
+    /// http.write_all(b"T /health HTTP/1.1\r\n\r\n").await?;
+    /// let mut response = String::new();
+    /// http.read_to_string(&mut response).await?;
+    /// assert!(response.starts_with("HTTP/1.1 200 OK"));
+    ///
+    /// // Requests stalling halfway are closed after the idle timeout.
+    /// let mut http = TcpStream::connect(("127.0.0.1", port)).await?;
+    /// http.write_all(b"GET /health").await?;
+    /// let mut response = String::new();
+    /// let read = tokio::time::timeout(Duration::from_secs(2), http.read_to_string(&mut response));
+    /// assert!(read.await.is_ok() && response.is_empty());
+    ///
+    /// assert!(Client::connect(&format!("olps://127.0.0.1:{port}/")).await.is_ok());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn http_health_check(mut self, enabled: bool) -> Self {
+        self.http_health_check = enabled;
+        self
+    }
+
+    /// Wait up to `timeout` for active sessions once shutting down, see [`Server::run_until`].
+    ///
+    /// Defaults to [`DRAIN_TIMEOUT`].
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = Some(timeout);
+        self
+    }
+
+    /// Serve at most `max` connections at a time, see [`ServerConfig::when_busy`] for what
+    /// happens to the others.
+    ///
+    /// A connection stops counting once its session ends, whether its handler returned,
+    /// failed or panicked.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn slow(_session: Session) -> ServerResponse {
+    ///     tokio::time::sleep(Duration::from_millis(300)).await;
+    ///     Ok(BaseResponse::TextResponse("done".to_string()))
+    /// }
+    ///
+    /// #[async_route]
+    /// fn broken(_session: Session) -> ServerResponse {
+    ///     panic!("broken handler");
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/slow" => slow);
+    /// # path_route!(&mut router, "/broken" => broken);
+    /// let config = ServerConfig::new().max_connections(1);
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// let connections = server.connections();
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// # let url = |entrance| format!("olps://127.0.0.1:{port}{entrance}");
+    ///
+    /// let first = tokio::spawn(Request::get(&url("/slow")).send());
+    /// tokio::time::sleep(Duration::from_millis(100)).await;
+    /// assert_eq!(connections.active(), 1);
+    ///
+    /// // Waits in the backlog until the first connection ends.
+    /// let second = tokio::spawn(Request::get(&url("/slow")).send());
+    /// assert_eq!(first.await??.text()?, "done");
+    /// assert_eq!(second.await??.text()?, "done");
+    ///
+    /// assert_eq!(Request::get(&url("/broken")).send().await?.status_code, 500);
+    /// tokio::time::sleep(Duration::from_millis(100)).await;
+    /// assert_eq!(connections.active(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Handle connections over [`ServerConfig::max_connections`] with `policy`, defaults to
+    /// [`BusyPolicy::Backlog`].
+    ///
+    /// Rejecting keeps clients from waiting on a server that can't keep up, at the cost of
+    /// a handshake per rejected connection. At most as many connections as the limit are
+    /// rejected at a time, the others are closed without a response.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{BusyPolicy, Server, ServerConfig, BUSY_STATUS};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn slow(_session: Session) -> ServerResponse {
+    /// #     tokio::time::sleep(Duration::from_millis(300)).await;
+    /// #     Ok(BaseResponse::TextResponse("done".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/slow" => slow);
+    /// let config = ServerConfig::new()
+    ///     .max_connections(1)
+    ///     .when_busy(BusyPolicy::Reject);
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// # let url = format!("olps://127.0.0.1:{port}/slow");
+    ///
+    /// let first = tokio::spawn(Request::get(&url).send());
+    /// tokio::time::sleep(Duration::from_millis(100)).await;
+    /// let busy = Request::get(&url).send().await?;
+    /// assert_eq!((busy.status_code, busy.text()?), (BUSY_STATUS, "Server busy"));
+    /// assert_eq!(first.await??.text()?, "done");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn when_busy(mut self, policy: BusyPolicy) -> Self {
+        self.when_busy = policy;
+        self
+    }
+
+    /// Perform at most `max` handshakes at a time, see [`Connections::handshaking`].
+    ///
+    /// Connections are handed to their own task as soon as they are accepted, where their keys
+    /// are generated and the handshake happens. Once `max` of them are handshaking, new
+    /// connections wait in the backlog of the listener rather than piling up on the runtime,
+    /// which keeps sessions already established responsive under a burst of connections.
+    /// Connections have the [handshake timeout](ServerConfig::handshake_timeout) to complete
+    /// it, so silent ones can't hold every slot.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn hello(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/hello" => hello);
+    /// let config = ServerConfig::new().handshake_concurrency(2);
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// let connections = server.connections();
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// # let url = format!("olps://127.0.0.1:{port}/hello");
+    ///
+    /// let requests = (0..16).map(|_| tokio::spawn(Request::get(&url).send()));
+    /// for request in futures::future::join_all(requests).await {
+    ///     assert_eq!(request??.text()?, "hello");
+    /// }
+    /// assert_eq!(connections.handshaking(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn handshake_concurrency(mut self, max: usize) -> Self {
+        self.handshake_concurrency = Some(max.max(1));
+        self
+    }
+
+    /// Close connections that haven't completed their handshake and sent their request within
+    /// `timeout` of being accepted, whatever the other timeouts are.
+    ///
+    /// Defaults to [`HANDSHAKE_TIMEOUT`].
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # use tokio::net::TcpStream;
+    /// # #[async_route]
+    /// # fn hello(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/hello" => hello);
+    /// let config = ServerConfig::new()
+    ///     .handshake_concurrency(2)
+    ///     .handshake_timeout(Duration::from_millis(200));
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    ///
+    /// // Connections sending nothing hold every handshake slot until they time out.
+    /// let mut silent = Vec::new();
+    /// for _ in 0..4 {
+    ///     silent.push(TcpStream::connect(("127.0.0.1", port)).await?);
+    /// }
+    /// let request = Request::get(&format!("olps://127.0.0.1:{port}/hello")).send();
+    /// let response = tokio::time::timeout(Duration::from_secs(5), request).await??;
+    /// assert_eq!(response.text()?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Instant by which a connection accepted now must have completed its handshake, see
+    /// [`ServerConfig::handshake_timeout`].
+    fn handshake_deadline(&self) -> Instant {
+        Instant::now() + self.handshake_timeout.unwrap_or(HANDSHAKE_TIMEOUT)
+    }
+
+    /// Give up on requests that aren't answered within `timeout`, unless their route has its
+    /// own [timeout](super::router::Route::timeout).
+    ///
+    /// The timeout starts once the request line and headers are received and covers reading
+    /// the body of the request as well as the handler. Requests over it are answered with
+    /// [`TIMEOUT_STATUS`] and their session is closed, handlers that already sent part of a
+    /// response are cut off without one.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::{Route, RoutePath, RouteType, Router};
+    /// # use oblivion::models::server::{Server, ServerConfig, TIMEOUT_STATUS};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use oblivion_codegen::async_route;
+    /// # use tokio::net::TcpStream;
+    /// #[async_route]
+    /// fn stuck(_session: Session) -> ServerResponse {
+    ///     tokio::time::sleep(Duration::from_millis(300)).await;
+    ///     Ok(BaseResponse::TextResponse("late".to_string()))
+    /// }
+    ///
+    /// #[async_route]
+    /// fn streaming(session: Session) -> ServerResponse {
+    ///     session.send(b"partial".to_vec()).await?;
+    ///     tokio::time::sleep(Duration::from_millis(300)).await;
+    ///     Ok(BaseResponse::TextResponse("rest".to_string()))
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut router = Router::new();
+    /// path_route!(&mut router, "/stuck" => stuck);
+    /// path_route!(&mut router, "/streaming" => streaming);
+    /// let patient = Route::new(stuck).timeout(Duration::from_secs(1));
+    /// router.register(RoutePath::new("/patient", RouteType::Path), patient);
+    ///
+    /// let config = ServerConfig::new().handler_timeout(Duration::from_millis(100));
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// # let url = |entrance| format!("olps://127.0.0.1:{port}{entrance}");
+    ///
+    /// let response = Request::get(&url("/stuck")).send().await?;
+    /// assert_eq!(response.status_code, TIMEOUT_STATUS);
+    /// assert_eq!(Request::get(&url("/patient")).send().await?.text()?, "late");
+    ///
+    /// // The first message was sent before the timeout, the session is closed after it.
+    /// let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    /// let header = "GET /streaming Oblivion/2.0".to_string();
+    /// let mut session = Session::new_with_header(header, Socket::new(stream))?;
+    /// session.handshake(0).await?;
+    /// assert_eq!(session.recv().await?.text()?, "partial");
+    /// assert!(session.recv().await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn handler_timeout(mut self, timeout: Duration) -> Self {
+        self.handler_timeout = Some(timeout);
+        self
+    }
+
+    /// Answer requests whose body is over `size` bytes with [`PAYLOAD_TOO_LARGE_STATUS`]
+    /// unless their route has its own [limit](super::router::Route::max_body_size).
+    ///
+    /// The size announced by the request is checked before anything is read, the body is
+    /// left on the wire and the connection is closed after the response. Bodies sent in
+    /// several frames, such as [files](crate::models::client::RequestBuilder::body_file), are
+    /// held to the same limit, as are bodies growing past the size they announced.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::router::{Route, RoutePath, RouteType, Router};
+    /// # use oblivion::models::server::{Server, ServerConfig, PAYLOAD_TOO_LARGE_STATUS};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn size(session: Session) -> String {
+    ///     format!("{} bytes", session.request.body().len())
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut router = Router::new();
+    /// path_route!(&mut router, "/comment" => size);
+    /// let upload = Route::new(size).max_body_size(4096);
+    /// router.register(RoutePath::new("/upload", RouteType::Path), upload);
+    ///
+    /// let config = ServerConfig::new().max_body_size(256);
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// # let url = |entrance| format!("olps://127.0.0.1:{port}{entrance}");
+    /// let post = |entrance, size| Request::post(&url(entrance)).body(vec![0; size]).send();
+    ///
+    /// assert_eq!(post("/comment", 256).await?.text()?, "256 bytes");
+    /// assert_eq!(post("/comment", 1024).await?.status_code, PAYLOAD_TOO_LARGE_STATUS);
+    /// assert_eq!(post("/upload", 1024).await?.text()?, "1024 bytes");
+    /// assert_eq!(post("/upload", 8192).await?.status_code, PAYLOAD_TOO_LARGE_STATUS);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = Some(size);
+        self
+    }
+
+    /// Compress the responses of clients that support it, see
+    /// [`SessionBuilder::compression`](super::session::SessionBuilder::compression).
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Exchange keys with `algorithm`, which clients must use as well, see
+    /// [`SessionBuilder::kex_algorithm`].
+    pub fn kex_algorithm(mut self, algorithm: KexAlgorithm) -> Self {
+        self.kex_algorithm = algorithm;
+        self
+    }
+
+    /// Sign the handshakes of connections with `key`, see
+    /// [`SessionBuilder::identity`](super::session::SessionBuilder::identity).
+    pub fn identity(mut self, key: IdentityKey) -> Self {
+        self.identity = Some(key);
+        self
+    }
+
+    /// Only serve clients signing the handshake with an identity key that `verify` accepts,
+    /// see [`SessionBuilder::verify_client_identity`].
+    pub fn verify_client_identity<F>(mut self, verify: F) -> Self
+    where
+        F: Fn(&[u8; 32]) -> bool + Send + Sync + 'static,
+    {
+        self.identity_verifier = Some(IdentityVerifier::new(verify));
+        self
+    }
+
+    /// Builder of the sessions of connections.
+    fn session(&self) -> SessionBuilder {
+        let mut builder = SessionBuilder::new().kex_algorithm(self.kex_algorithm);
+        if let Some(key) = &self.identity {
+            builder = builder.identity(key.clone());
+        }
+        if let Some(verifier) = &self.identity_verifier {
+            builder = builder.identity_verifier(verifier.clone());
+        }
+        builder
+    }
+
+    /// Only accept connections from `networks`, see [`ServerConfig::deny`].
+    ///
+    /// Connections from other addresses are dropped right after being accepted, before any
+    /// key is generated for them. Both lists can be replaced while the server runs through
+    /// [`ServerConfig::address_filter`]. They don't apply to [Unix domain
+    /// sockets](Server::bind_unix), whose permissions decide who can connect.
+    pub fn allow(self, networks: impl IntoIterator<Item = IpNet>) -> Self {
+        self.filter.set_allow(networks);
+        self
+    }
+
+    /// Drop connections from `networks`, even if they are [allowed](ServerConfig::allow).
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn hello(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/hello" => hello);
+    /// let config = ServerConfig::new()
+    ///     .allow(["127.0.0.0/8".parse()?, "::1".parse()?])
+    ///     .deny(["127.0.0.1".parse()?]);
+    /// let filter = config.address_filter();
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let url = format!("olps://127.0.0.1:{port}/hello");
+    ///
+    /// assert!(Request::get(&url).send().await.is_err());
+    /// assert_eq!(filter.rejected(), 1);
+    ///
+    /// filter.set_deny([]);
+    /// assert_eq!(Request::get(&url).send().await?.text()?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn deny(self, networks: impl IntoIterator<Item = IpNet>) -> Self {
+        self.filter.set_deny(networks);
+        self
+    }
+
+    /// Lists of [`ServerConfig::allow`] and [`ServerConfig::deny`], shared with this config
+    /// and the servers using it.
+    pub fn address_filter(&self) -> AddressFilter {
+        self.filter.clone()
+    }
+
+    /// Set the permissions of the socket file created by [`Server::bind_unix`] to `mode`,
+    /// such as `0o660` to only admit the owner and group.
+    ///
+    /// The socket is bound under a temporary name next to its path, with the permissions
+    /// given by the umask of the process, and linked to its path once it has `mode`. Peers
+    /// that can enter the directory may connect to the temporary name in between, use a
+    /// directory only admitting those allowed to connect to close that window.
+    #[cfg(unix)]
+    pub fn unix_mode(mut self, mode: u32) -> Self {
+        self.unix_mode = Some(mode);
+        self
+    }
+
+    /// Wrap TCP connections in TLS with the certificate `cert_chain` and its private `key`,
+    /// before the Oblivion handshake happens over it.
+    ///
+    /// Clients have to connect with [`ClientBuilder::tls`](super::client::ClientBuilder::tls),
+    /// trusting the certificate. Connections over [Unix domain sockets](Server::bind_unix)
+    /// stay in the clear. Connections failing the TLS handshake, or not completing it within
+    /// the [handshake timeout](ServerConfig::handshake_timeout), are closed.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::{ClientBuilder, Request};
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::models::tls::rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    /// # use oblivion::models::tls::rustls::RootCertStore;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn hello(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/hello" => hello);
+    /// let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    /// let cert = certified.cert.der().clone();
+    /// let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    ///
+    /// let config = ServerConfig::new().tls(vec![cert.clone()], key)?;
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let request = || Request::get(&format!("olps://localhost:{port}/hello")).build();
+    ///
+    /// let mut roots = RootCertStore::empty();
+    /// roots.add(cert)?;
+    /// let response = ClientBuilder::new().tls(roots).send(request()).await?;
+    /// assert_eq!(response.text()?, "hello");
+    ///
+    /// // The certificate has to be trusted, and clients without TLS are turned away.
+    /// let untrusted = ClientBuilder::new().tls(RootCertStore::empty());
+    /// let error = untrusted.send(request()).await.unwrap_err();
+    /// assert!(matches!(error.downcast_ref(), Some(Exception::TlsError { .. })));
+    /// assert!(ClientBuilder::new().send(request()).await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn tls(
+        mut self,
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, Exception> {
+        self.tls = Some(TlsAcceptor::new(cert_chain, key)?);
+        Ok(self)
+    }
+}
+
+/// Connections a [`Server`] is serving, see [`Server::connections`].
+#[derive(Debug, Clone, Default)]
+pub struct Connections {
+    active: Arc<AtomicUsize>,
+    handshaking: Arc<AtomicUsize>,
+}
+
+impl Connections {
+    /// Number of connections currently served, rejected connections aren't counted.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Number of accepted connections whose handshake isn't complete yet, rejected
+    /// connections included.
+    ///
+    /// Stays at [`ServerConfig::handshake_concurrency`] while connections arrive faster than
+    /// the server establishes them.
+    pub fn handshaking(&self) -> usize {
+        self.handshaking.load(Ordering::Relaxed)
+    }
+}
+
+/// Counted connection, released once dropped, including when its handler panicked.
+struct Slot {
+    active: Arc<AtomicUsize>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Slot {
+    fn new(connections: &Connections, permit: Option<OwnedSemaphorePermit>) -> Self {
+        connections.active.fetch_add(1, Ordering::Relaxed);
+        Self {
+            active: Arc::clone(&connections.active),
+            _permit: permit,
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Connection being handshaken, counted by [`Connections::handshaking`] until dropped.
+struct Handshake {
+    handshaking: Arc<AtomicUsize>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Handshake {
+    fn new(connections: &Connections, permit: Option<OwnedSemaphorePermit>) -> Self {
+        connections.handshaking.fetch_add(1, Ordering::Relaxed);
+        Self {
+            handshaking: Arc::clone(&connections.handshaking),
+            _permit: permit,
+        }
+    }
+}
+
+impl Drop for Handshake {
+    fn drop(&mut self) {
+        self.handshaking.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Permits of [`ServerConfig::max_connections`] for one run of a server.
+struct Limits {
+    connections: Arc<Semaphore>,
+    /// Connections being answered with [`BUSY_STATUS`].
+    rejections: Arc<Semaphore>,
+}
+
+/// Address a server listens on, see [`Server::with_targets`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BindTarget {
+    Tcp(SocketAddr),
+    /// Unix domain socket at this path, see [`Server::bind_unix`].
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl From<SocketAddr> for BindTarget {
+    fn from(address: SocketAddr) -> Self {
+        Self::Tcp(address)
+    }
+}
+
+#[cfg(unix)]
+impl From<PathBuf> for BindTarget {
+    fn from(path: PathBuf) -> Self {
+        Self::Unix(path)
+    }
+}
+
+impl fmt::Display for BindTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(f, "Oblivion://{address}/"),
+            #[cfg(unix)]
+            Self::Unix(path) => write!(f, "Oblivion+unix://{}", path.display()),
+        }
+    }
+}
+
+/// Listener a server accepts connections on, see [`Server::bind`] and [`Server::bind_unix`].
+enum Listener {
+    Tcp(TcpListener),
+    /// Removes its socket file once dropped, after the listener is closed.
+    #[cfg(unix)]
+    Unix(UnixListener, SocketFile),
+}
+
+impl Listener {
+    /// Address the listener is bound to, with the port the system picked if it asked for
+    /// port `0`.
+    fn target(&self) -> Result<BindTarget> {
+        match self {
+            Self::Tcp(tcp) => Ok(BindTarget::Tcp(tcp.local_addr()?)),
+            #[cfg(unix)]
+            Self::Unix(_, file) => Ok(BindTarget::Unix(file.0.clone())),
+        }
+    }
+
+    /// Accept the next connection, peers of Unix domain sockets are [`LOCAL_PEER`].
+    async fn accept(&self) -> std::io::Result<(Stream, SocketAddr)> {
+        match self {
+            Self::Tcp(tcp) => {
+                let (stream, peer) = tcp.accept().await?;
+                Ok((Stream::Tcp(stream), peer))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener, _) => {
+                let (stream, _) = listener.accept().await?;
+                Ok((Stream::Unix(stream), LOCAL_PEER))
+            }
+        }
+    }
+}
+
+/// Bind the socket at `path`, under a temporary name until it has the permissions `mode`,
+/// see [`ServerConfig::unix_mode`].
+///
+/// Linking never replaces a file, another server binding `path` meanwhile fails this one.
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: Option<u32>) -> std::io::Result<UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let Some(mode) = mode else {
+        return UnixListener::bind(path);
+    };
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}", std::process::id()));
+    let staging = path.with_file_name(name);
+    let listener = UnixListener::bind(&staging)?;
+    let linked = std::fs::set_permissions(&staging, std::fs::Permissions::from_mode(mode))
+        .and_then(|()| std::fs::hard_link(&staging, path));
+    let _ = std::fs::remove_file(&staging);
+    linked.map(|()| listener)
+}
+
+/// Socket file of a [`Listener::Unix`].
+#[cfg(unix)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Connection accepted by a [`Listener`].
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// Set the options of TCP connections that are served, Unix domain sockets have none.
+    fn configure(&self, options: &TcpOptions) -> Result<()> {
+        if let Self::Tcp(stream) = self {
+            stream.set_ttl(20)?;
+            options.apply(stream)?;
+        }
+        Ok(())
+    }
+
+    /// Socket over the connection with the timeouts and frame limit of `config`, behind TLS
+    /// for TCP connections if the server has [`ServerConfig::tls`], whose handshake must
+    /// complete by `deadline`.
+    async fn into_transport(self, config: &ServerConfig, deadline: Instant) -> Result<Socket> {
+        let mut socket = self.into_secured(config, deadline).await?;
+        socket.set_read_timeout(config.read_timeout);
+        socket.set_write_timeout(config.write_timeout);
+        if let Some(size) = config.max_frame_size {
+            socket.set_max_frame_size(size);
+        }
+        Ok(socket)
+    }
+
+    #[cfg_attr(not(feature = "tls"), allow(unused_variables))]
+    #[cfg_attr(not(unix), allow(irrefutable_let_patterns))]
+    async fn into_secured(self, config: &ServerConfig, deadline: Instant) -> Result<Socket> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &config.tls {
+            if let Self::Tcp(stream) = self {
+                return before(deadline, tls.accept(stream)).await;
+            }
+        }
+        self.into_socket()
+    }
+
+    fn into_socket(self) -> Result<Socket> {
+        match self {
+            Self::Tcp(stream) => Socket::from_tcp(stream),
+            #[cfg(unix)]
+            Self::Unix(stream) => Ok(Socket::from_unix(stream)),
+        }
+    }
+}
+
+/// Whether the connection of `socket` starts with a plain HTTP `GET` request.
+///
+/// Peeks until the bytes received tell, those of a request split across reads included, or
+/// fails once `deadline` passes.
+async fn is_http(socket: &Socket, deadline: Instant) -> Result<bool> {
+    let sniff = async {
+        let mut received = 0;
+        loop {
+            let head = socket.peek(HTTP_GET.len()).await?;
+            if head.len() == HTTP_GET.len() || !HTTP_GET.starts_with(&head) {
+                return Ok(head == HTTP_GET);
+            }
+            // The stream ended before the connection could tell.
+            if head.len() == received {
+                return Ok(false);
+            }
+            received = head.len();
+        }
+    };
+    before(deadline, sniff).await
+}
+
+/// Answer a plain HTTP request, see [`ServerConfig::http_health_check`].
+async fn answer_health_check(socket: &Socket, config: &ServerConfig) -> Result<()> {
+    let answer = async {
+        let mut head = Vec::new();
+        while head.len() < HTTP_HEAD_LIMIT && !head.windows(4).any(|line| line == b"\r\n\r\n") {
+            socket.recv_into(&mut head).await?;
+        }
+        socket.send(HTTP_HEALTH_RESPONSE).await?;
+        socket.close().await
+    };
+    let timeout = config.idle_timeout.unwrap_or(HTTP_HEAD_TIMEOUT);
+    tokio::time::timeout(timeout, answer)
+        .await
+        .map_err(|_| Exception::Timeout {
+            phase: TimeoutPhase::Receive,
+        })?
+}
+
+enum Accepted {
+    Admitted(Stream, SocketAddr, Option<OwnedSemaphorePermit>),
+    /// Connection to answer with [`BUSY_STATUS`], holding a permit of [`Limits::rejections`].
+    Busy(Stream, OwnedSemaphorePermit),
+}
+
+/// Accept the next connection on any of `listeners` the address filter admits, waiting for a
+/// permit first unless busy connections are rejected.
+async fn accept(
+    listeners: &[Listener],
+    limits: Option<&Limits>,
+    config: &ServerConfig,
+) -> std::io::Result<Accepted> {
+    loop {
+        let permit = match limits {
+            Some(limits) if config.when_busy == BusyPolicy::Backlog => Some(
+                Arc::clone(&limits.connections)
+                    .acquire_owned()
+                    .await
+                    .expect("connection limit is never closed"),
+            ),
+            _ => None,
+        };
+        let accepts = listeners.iter().map(|listener| Box::pin(listener.accept()));
+        let (accepted, _, _) = select_all(accepts).await;
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(error) if is_transient_accept_error(&error) => {
+                #[cfg(not(any(feature = "perf", feature = "bench")))]
+                eprintln!("{} {}", "Failed to accept a connection:".red(), error);
+                // Give closing sessions a chance to release their descriptors.
+                if is_out_of_descriptors(&error) {
+                    drop(permit);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                }
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        if matches!(stream, Stream::Tcp(_)) && !config.filter.admits(peer.ip()) {
+            #[cfg(not(any(feature = "perf", feature = "bench")))]
+            eprintln!(
+                "{} -> [{}] \"{}\" {}",
+                peer.ip().to_string().cyan(),
+                Local::now().format("%d/%m/%Y %H:%M:%S"),
+                "CONNECT - Oblivion/2.0".yellow(),
+                "403".red()
+            );
+            continue;
+        }
+        let Some(limits) = limits else {
+            return Ok(Accepted::Admitted(stream, peer, None));
+        };
+        if permit.is_some() {
+            return Ok(Accepted::Admitted(stream, peer, permit));
+        }
+        if let Ok(permit) = Arc::clone(&limits.connections).try_acquire_owned() {
+            return Ok(Accepted::Admitted(stream, peer, Some(permit)));
+        }
+        // Beyond that, busy connections are closed without a response.
+        if let Ok(permit) = Arc::clone(&limits.rejections).try_acquire_owned() {
+            return Ok(Accepted::Busy(stream, permit));
+        }
+    }
+}
+
+/// Whether `error` only concerns the connection being accepted or is temporary, so the
+/// listener can keep accepting.
+fn is_transient_accept_error(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::OutOfMemory
+    ) || is_out_of_descriptors(error)
+}
+
+/// Whether `error` is `EMFILE` or `ENFILE`, the per-process or system-wide limit of open files
+/// being reached.
+fn is_out_of_descriptors(error: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    const LIMITS: [i32; 2] = [24, 23];
+    #[cfg(windows)]
+    const LIMITS: [i32; 1] = [10024];
+    #[cfg(not(any(unix, windows)))]
+    const LIMITS: [i32; 0] = [];
+    error
+        .raw_os_error()
+        .is_some_and(|code| LIMITS.contains(&code))
+}
+
+/// Answer a connection over [`ServerConfig::max_connections`] with [`BUSY_STATUS`].
+async fn reject(config: Arc<ServerConfig>, stream: Stream, handshake: Handshake) -> Result<()> {
+    let deadline = config.handshake_deadline();
+    let mut session = config
+        .session()
+        .build(stream.into_transport(&config, deadline).await?)?;
+    session.set_idle_timeout(config.idle_timeout);
+    session.set_compression(config.compression);
+    if let Some(size) = config.max_payload_size {
+        session.set_max_payload(Some(size));
+    }
+    before(deadline, session.handshake(1)).await?;
+    drop(handshake);
+    session
+        .send_and_close(b"Server busy".to_vec(), BUSY_STATUS)
+        .await
+}
+
+/// Run the handshake step `future`, failing with [`Exception::Timeout`] past `deadline`.
+async fn before<T>(deadline: Instant, future: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout_at(deadline, future)
+        .await
+        .map_err(|_| Exception::Timeout {
+            phase: TimeoutPhase::Handshake,
+        })?
+}
+
+/// Everything a server shares with the connections it serves.
+#[derive(Clone)]
+struct Shared {
+    router: RouterHandle,
+    config: Arc<ServerConfig>,
+    states: States,
+    panic_handler: PanicHandler,
+    sessions: SessionManager,
+}
 
 #[inline]
-async fn _handle(router: &Router, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+async fn _handle(
+    shared: &Shared,
+    stream: Stream,
+    peer: SocketAddr,
+    draining: &CancellationToken,
+    handshake: Handshake,
+) -> Result<()> {
+    let Shared {
+        router,
+        config,
+        states,
+        panic_handler,
+        sessions,
+    } = shared;
+    let panic_handler = *panic_handler;
     #[cfg(feature = "perf")]
     let now = std::time::Instant::now();
-    stream.set_ttl(20)?;
-    stream.set_nodelay(true)?;
-    stream.set_linger(Some(std::time::Duration::from_secs(0)))?;
-    socket2::SockRef::from(&stream).set_keepalive(true)?;
-    let mut session = Session::new(Socket::new(stream))?;
+    let deadline = config.handshake_deadline();
+    stream.configure(&config.tcp)?;
+    let socket = stream.into_transport(config, deadline).await?;
+    if config.http_health_check && is_http(&socket, deadline).await? {
+        drop(handshake);
+        return answer_health_check(&socket, config).await;
+    }
+    let mut session = config.session().build(socket)?;
+    session.set_idle_timeout(config.idle_timeout);
+    session.set_compression(config.compression);
+    if let Some(size) = config.max_payload_size {
+        session.set_max_payload(Some(size));
+    }
 
-    if let Err(error) = session.handshake(1).await {
+    let received = before(deadline, session.receive_request()).await;
+    drop(handshake);
+    if let Err(error) = received {
         eprintln!(
             "{} -> [{}] \"{}\" {}",
             peer.ip().to_string().cyan(),
@@ -53,11 +1268,45 @@ async fn _handle(router: &Router, stream: TcpStream, peer: SocketAddr) -> Result
         now.elapsed().as_micros().to_string().bright_magenta()
     );
 
+    // Peers that negotiated `REQUESTS` keep the connection open for further requests.
+    let persistent = session.capabilities().contains(Capabilities::REQUESTS);
+    let _registration = persistent.then(|| sessions.register(session.fork()));
+    loop {
+        let connection = session.fork();
+        let routes = router.load();
+        dispatch(&routes, config, states, panic_handler, session, &connection).await?;
+        if !persistent {
+            return Ok(());
+        }
+        let next = tokio::select! {
+            next = connection.next_request() => next?,
+            // Requests already answered, the peer has to reconnect to another server.
+            _ = draining.cancelled() => return connection.close().await,
+        };
+        match next {
+            Some(next) => session = next,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Run the handler of the request behind `session` and write its response on `connection`.
+///
+/// The connection is closed afterwards unless the peer negotiated [`Capabilities::REQUESTS`].
+#[inline]
+async fn dispatch(
+    router: &Router,
+    config: &ServerConfig,
+    states: &States,
+    panic_handler: PanicHandler,
+    mut session: Session,
+    connection: &Session,
+) -> Result<()> {
     #[cfg(not(any(feature = "perf", feature = "bench")))]
-    let header = session.header().to_string();
+    let header = session.header()?.to_string();
     #[cfg(not(any(feature = "perf", feature = "bench")))]
-    let ip_addr = session.get_ip().to_string();
-    let aes_key = session.aes_key;
+    let ip_addr = session.get_ip()?.to_string();
+    let persistent = session.capabilities().contains(Capabilities::REQUESTS);
 
     #[cfg(not(any(feature = "perf", feature = "bench")))]
     println!(
@@ -71,9 +1320,84 @@ async fn _handle(router: &Router, stream: TcpStream, peer: SocketAddr) -> Result
     #[cfg(feature = "perf")]
     let now = Instant::now();
 
-    let socket = Arc::clone(&session.socket);
-
-    let callback = router.get_handler(&session.request.entrance)?(session).await?;
+    session.request.states = states.clone();
+    let method = session.request.get_method().to_string();
+    let timeout = router
+        .timeout(&method, &session.request.entrance)
+        .or(config.handler_timeout);
+    let max_body_size = router
+        .max_body_size(&method, &session.request.entrance)
+        .or(config.max_body_size);
+    // The rest of an oversized body is still on the wire, the connection can't be reused.
+    let mut oversized = false;
+    let sent = connection.stats().packets_sent;
+    // Kept for the panic handler and the completion hooks, the body isn't read yet.
+    let request = session.request.clone();
+    let complete = |status_code, size, outcome| {
+        let latency = request
+            .received_at
+            .map(|at| at.elapsed())
+            .unwrap_or_default();
+        request.completions.complete(&Completion {
+            status_code,
+            size,
+            latency,
+            outcome,
+        });
+    };
+    let answered = async {
+        let handled = async {
+            if let Some(length) = session.read_body(max_body_size).await? {
+                oversized = true;
+                let limit = max_body_size.unwrap_or_default();
+                let message = format!("Body is {length} bytes, at most {limit} bytes are allowed.");
+                let response =
+                    BaseResponse::StatusResponse(PAYLOAD_TOO_LARGE_STATUS, message.into_bytes());
+                return router.dispatch(session, Some(response)).await;
+            }
+            router.handle(session).await
+        };
+        match AssertUnwindSafe(handled).catch_unwind().await {
+            Ok(callback) => Ok((callback?, Outcome::Answered)),
+            Err(payload) => {
+                let panic = Panic::new(payload);
+                eprintln!(
+                    "{} <- [{}] \"{} {}\" {}",
+                    request.get_ip().cyan(),
+                    Local::now().format("%d/%m/%Y %H:%M:%S"),
+                    request.method.yellow(),
+                    request.entrance.yellow(),
+                    "500".red()
+                );
+                eprintln!("Handler panicked: {}", panic.message().bright_red());
+                let outcome = Outcome::Panicked(panic.message().to_string());
+                Ok((panic_handler(&panic, &request), outcome))
+            }
+        }
+    };
+    let answer: Result<_> = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, answered).await {
+            Ok(answer) => answer,
+            // Part of a response was sent already, there is no way to answer it anymore.
+            Err(_) if connection.stats().packets_sent != sent => {
+                complete(None, 0, Outcome::TimedOut);
+                return connection.abort().await;
+            }
+            Err(_) => {
+                let message = b"Handler timed out".to_vec();
+                let callback = BaseResponse::StatusResponse(TIMEOUT_STATUS, message);
+                Ok((callback, Outcome::TimedOut))
+            }
+        },
+        None => answered.await,
+    };
+    let (callback, outcome) = match answer {
+        Ok(answer) => answer,
+        Err(error) => {
+            complete(None, 0, Outcome::Failed(error.to_string()));
+            return Err(error);
+        }
+    };
 
     #[cfg(feature = "perf")]
     println!(
@@ -84,13 +1408,19 @@ async fn _handle(router: &Router, stream: TcpStream, peer: SocketAddr) -> Result
     #[cfg(feature = "perf")]
     let now = Instant::now();
 
-    OSC::from_u32(1).to_stream(&socket).await?;
-    OED::new(&aes_key)
-        .from_bytes(callback.as_bytes()?)?
-        .to_stream(&socket)
-        .await?;
-
-    socket.close().await?;
+    let closing = outcome == Outcome::TimedOut || oversized;
+    let size = match respond(connection, &callback, persistent, closing).await {
+        Ok(size) => size,
+        Err(error) => {
+            complete(None, 0, Outcome::Failed(error.to_string()));
+            return Err(error);
+        }
+    };
+    let status_code = match callback {
+        BaseResponse::FileResponse(_) | BaseResponse::OpenFileResponse(_) => 200,
+        _ => callback.status_code(),
+    };
+    complete(Some(status_code), size, outcome);
 
     #[cfg(feature = "perf")]
     println!(
@@ -110,12 +1440,91 @@ async fn _handle(router: &Router, stream: TcpStream, peer: SocketAddr) -> Result
     Ok(())
 }
 
-pub async fn handle(router: Arc<Router>, stream: TcpStream, peer: SocketAddr) {
+/// Send `callback` on `connection`, closing it afterwards if the peer can't reuse it or if
+/// `closing`, and return the size of its body.
+async fn respond(
+    connection: &Session,
+    callback: &BaseResponse,
+    persistent: bool,
+    closing: bool,
+) -> Result<usize> {
+    let socket = &connection.socket;
+    // Files are streamed in frames, the connection stays open only if the peer reuses it.
+    let flag = match persistent {
+        true => SessionFlag::Response,
+        false => SessionFlag::CloseAfter,
+    };
+    let size = match callback {
+        BaseResponse::FileResponse(path) => {
+            let size = tokio::fs::metadata(path).await?.len() as usize;
+            connection.send_file_with_flag(path, 200, flag).await?;
+            Some(size)
+        }
+        BaseResponse::OpenFileResponse(file) => {
+            let file = tokio::fs::File::from_std(file.try_clone()?);
+            let size = file.metadata().await?.len() as usize;
+            connection.send_open_file_with_flag(file, 200, flag).await?;
+            Some(size)
+        }
+        _ => None,
+    };
+    if let Some(size) = size {
+        if !persistent {
+            socket.close().await?;
+        }
+        return Ok(size);
+    }
+
+    let content = callback.as_bytes()?;
+    let size = content.len();
+    if persistent {
+        connection
+            .send_with_flag(content, callback.status_code(), SessionFlag::Response)
+            .await?;
+        if closing {
+            connection.close().await?;
+        }
+    } else {
+        connection
+            .send_with_flag(content, callback.status_code(), SessionFlag::CloseAfter)
+            .await?;
+        socket.close().await?;
+    }
+    Ok(size)
+}
+
+pub async fn handle(
+    router: Arc<Router>,
+    config: Arc<ServerConfig>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) {
+    let shared = Shared {
+        router: RouterHandle::shared(router),
+        config,
+        states: States::default(),
+        panic_handler: internal_error,
+        sessions: SessionManager::default(),
+    };
+    let handshake = Handshake::new(&Connections::default(), None);
+    let stream = Stream::Tcp(stream);
+    serve(shared, stream, peer, CancellationToken::new(), handshake).await
+}
+
+/// Handle the connection of `peer`, closing persistent sessions between two requests once
+/// `draining` is cancelled.
+async fn serve(
+    shared: Shared,
+    stream: Stream,
+    peer: SocketAddr,
+    draining: CancellationToken,
+    handshake: Handshake,
+) {
     #[cfg(feature = "perf")]
     let now = Instant::now();
     #[cfg(feature = "perf")]
     println!("=================");
-    if let Err(error) = _handle(&router, stream, peer).await {
+    if let Err(error) = _handle(&shared, stream, peer, &draining, handshake).await {
         eprintln!(
             "{} <-> [{}] \"{}\" {}",
             peer.ip().to_string().cyan(),
@@ -137,6 +1546,28 @@ pub async fn handle(router: Arc<Router>, stream: TcpStream, peer: SocketAddr) {
     );
 }
 
+/// States given to [`Server::with_state`], keyed by their type.
+#[derive(Clone, Default)]
+pub(crate) struct States(Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>);
+
+impl States {
+    fn insert<T: Send + Sync + 'static>(&mut self, state: T) {
+        Arc::make_mut(&mut self.0).insert(TypeId::of::<T>(), Arc::new(state));
+    }
+
+    pub(crate) fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        Arc::clone(self.0.get(&TypeId::of::<T>())?).downcast().ok()
+    }
+}
+
+impl fmt::Debug for States {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("States")
+            .field("len", &self.0.len())
+            .finish_non_exhaustive()
+    }
+}
+
 /// Oblivion Server
 ///
 /// Oblivion uses the `tokio` library to handle TCP connections. The `Server` struct
@@ -173,7 +1604,35 @@ pub async fn handle(router: Arc<Router>, stream: TcpStream, peer: SocketAddr) {
 pub struct Server {
     host: String,
     port: i32,
-    router: Arc<Router>,
+    router: RouterHandle,
+    config: Arc<ServerConfig>,
+    states: States,
+    panic_handler: PanicHandler,
+    connections: Connections,
+    sessions: SessionManager,
+    shutdown: CancellationToken,
+    /// Listener given to [`Server::from_listener`], used by the first run instead of binding.
+    listener: StdMutex<Option<TcpListener>>,
+    /// Addresses listened on instead of the address of the server, see
+    /// [`Server::with_targets`].
+    targets: Vec<BindTarget>,
+}
+
+/// Handle shutting a [`Server`] down from anywhere, see [`Server::shutdown_handle`].
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    shutdown: CancellationToken,
+}
+
+impl ShutdownHandle {
+    /// Shut the server down as if the future given to [`Server::run_until`] resolved.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
 }
 
 impl Server {
@@ -181,17 +1640,530 @@ impl Server {
         Self {
             host: host.to_string(),
             port,
-            router: Arc::new(router),
+            router: RouterHandle::new(router),
+            config: Arc::new(ServerConfig::default()),
+            states: States::default(),
+            panic_handler: internal_error,
+            connections: Connections::default(),
+            sessions: SessionManager::default(),
+            shutdown: CancellationToken::new(),
+            listener: StdMutex::new(None),
+            targets: Vec::new(),
+        }
+    }
+
+    /// Serve on `listener` instead of binding an address, such as a socket passed by the
+    /// service manager or created by a test harness.
+    ///
+    /// Every option of the server applies as if it bound the address itself. The listener is
+    /// used by the first run only, later runs bind its address again.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # use tokio::net::TcpListener;
+    /// # #[async_route]
+    /// # fn hello(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/hello" => hello);
+    /// let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// let address = listener.local_addr()?;
+    /// let server = Server::from_listener(listener, router)?
+    ///     .with_config(ServerConfig::new().max_connections(16));
+    /// tokio::spawn(async move { server.run().await });
+    ///
+    /// let response = Request::get(&format!("olps://{address}/hello")).send().await?;
+    /// assert_eq!(response.text()?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_listener(listener: TcpListener, router: Router) -> Result<Self> {
+        let address = listener.local_addr()?;
+        let server = Self::new(&address.ip().to_string(), address.port().into(), router);
+        *server.listener.lock().unwrap() = Some(listener);
+        Ok(server)
+    }
+
+    /// Serve on a listener of the standard library, see [`Server::from_listener`].
+    ///
+    /// The listener is switched to non-blocking mode, which has to happen within a Tokio
+    /// runtime.
+    pub fn from_std_listener(listener: std::net::TcpListener, router: Router) -> Result<Self> {
+        listener.set_nonblocking(true)?;
+        Self::from_listener(TcpListener::from_std(listener)?, router)
+    }
+
+    /// Listen on every address of `targets` instead of the address of the server.
+    ///
+    /// Connections on every address are served by the same router and options, and count
+    /// towards the same [`ServerConfig::max_connections`] and [`Server::connections`].
+    /// Starting fails if any of them can't be bound, with an error naming it, and shutting
+    /// down stops listening on all of them at once.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::{ClientBuilder, Request};
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{BindTarget, Server};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn hello(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/hello" => hello);
+    /// let name = format!("oblivion-{}-targets.sock", std::process::id());
+    /// let path = std::env::temp_dir().join(name);
+    /// let targets = [
+    ///     BindTarget::Tcp("127.0.0.1:0".parse()?),
+    ///     BindTarget::Unix(path.clone()),
+    /// ];
+    /// let server = Server::new("127.0.0.1", 0, router).with_targets(targets).bind().await?;
+    /// assert_eq!(server.local_addrs()?[1], BindTarget::Unix(path.clone()));
+    /// let address = server.local_addr()?;
+    ///
+    /// // Every address has to be bound, the error names the one that failed.
+    /// let taken = [BindTarget::Tcp("127.0.0.1:0".parse()?), BindTarget::Tcp(address)];
+    /// let error = Server::new("127.0.0.1", 0, Router::new()).with_targets(taken).bind().await;
+    /// assert!(error.err().unwrap().to_string().contains(&address.to_string()));
+    ///
+    /// let handle = server.shutdown_handle();
+    /// let running = tokio::spawn(server.serve());
+    ///
+    /// let response = Request::get(&format!("olps://{address}/hello")).send().await?;
+    /// assert_eq!(response.text()?, "hello");
+    /// let client = ClientBuilder::new().unix_socket(&path);
+    /// let response = client.send(Request::get("olps://localhost/hello").build()).await?;
+    /// assert_eq!(response.text()?, "hello");
+    ///
+    /// handle.shutdown();
+    /// running.await??;
+    /// assert!(!path.exists());
+    /// assert!(Request::get(&format!("olps://{address}/hello")).send().await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_targets(mut self, targets: impl IntoIterator<Item = BindTarget>) -> Self {
+        self.targets = targets.into_iter().collect();
+        self
+    }
+
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Share `state` with every handler and middleware, read back with
+    /// [`OblivionRequest::state`](crate::utils::parser::OblivionRequest::state).
+    ///
+    /// States are keyed by type, giving a second state of the same type replaces the first
+    /// one. Requests share them behind an [`Arc`], so interior mutability is needed to
+    /// change them.
+    ///
+    /// ```rust
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// struct Visits(AtomicUsize);
+    ///
+    /// #[async_route]
+    /// fn visit(session: Session) -> ServerResponse {
+    ///     let visits = session.request.state::<Visits>()?;
+    ///     let count = visits.0.fetch_add(1, Ordering::Relaxed) + 1;
+    ///     Ok(BaseResponse::TextResponse(count.to_string()))
+    /// }
+    ///
+    /// #[async_route]
+    /// fn missing(session: Session) -> ServerResponse {
+    ///     let state = session.request.state::<String>();
+    ///     let missing = matches!(state, Err(Exception::MissingState { .. }));
+    ///     Ok(BaseResponse::TextResponse(missing.to_string()))
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut router = Router::new();
+    /// path_route!(&mut router, "/visit" => visit);
+    /// path_route!(&mut router, "/missing" => missing);
+    /// let server =
+    ///     Server::new("127.0.0.1", 0, router).with_state(Visits(AtomicUsize::new(0)));
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// # let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+    ///
+    /// assert_eq!(get("/visit").await?.text()?, "1");
+    /// assert_eq!(get("/visit").await?.text()?, "2");
+    /// assert_eq!(get("/missing").await?.text()?, "true");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_state<T: Send + Sync + 'static>(mut self, state: T) -> Self {
+        self.states.insert(state);
+        self
+    }
+
+    /// Answer requests whose handler panicked with `handler` instead of [`internal_error`].
+    ///
+    /// The panic is caught before it reaches the connection, so the session keeps serving
+    /// further requests. It is logged with the entrance and address of the request, which is
+    /// given to `handler` without its body. Panics can only be caught in builds that unwind,
+    /// the release profile of this workspace aborts on panic instead: there `handler` never
+    /// runs, setting it prints a warning and any panic ends the process.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::handler::Panic;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion::utils::parser::OblivionRequest;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn broken(_session: Session) -> ServerResponse {
+    ///     panic!("database is gone");
+    /// }
+    ///
+    /// fn report(panic: &Panic, request: &OblivionRequest) -> BaseResponse {
+    ///     let message = format!("{} failed: {}", request.get_entrance(), panic.message());
+    ///     BaseResponse::StatusResponse(500, message.into_bytes())
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/broken" => broken);
+    ///
+    /// let server = Server::new("127.0.0.1", 0, router).panic_handler(report);
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let response = Request::get(&format!("olps://127.0.0.1:{port}/broken")).send().await?;
+    /// assert_eq!(response.status_code, 500);
+    /// assert_eq!(response.text()?, "/broken failed: database is gone");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn panic_handler(mut self, handler: PanicHandler) -> Self {
+        #[cfg(not(feature = "bench"))]
+        if cfg!(panic = "abort") {
+            eprintln!(
+                "{}",
+                "Panics abort this build, the panic handler will never run.".yellow()
+            );
+        }
+        self.panic_handler = handler;
+        self
+    }
+
+    /// Connections the server is serving, updated as they are accepted and end.
+    pub fn connections(&self) -> Connections {
+        self.connections.clone()
+    }
+
+    /// Routes of the server, which can be changed while it runs.
+    pub fn router_handle(&self) -> RouterHandle {
+        self.router.clone()
+    }
+
+    /// Sessions connected to the server for further requests, shared by every run of it.
+    pub fn sessions(&self) -> SessionManager {
+        self.sessions.clone()
+    }
+
+    /// Handle shutting the server down, once triggered every run of the server ends right away.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            shutdown: self.shutdown.clone(),
         }
     }
 
+    /// Serve until the process is interrupted, exiting it on `CTRL-C`.
+    ///
+    /// Use [`Server::run_until`] to shut down gracefully instead.
     pub async fn run(&self) -> Result<()> {
+        tokio::spawn(async move {
+            match tokio::signal::ctrl_c().await {
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("{}", e.to_string().red());
+                    std::process::exit(1);
+                }
+            }
+            std::process::exit(0);
+        });
+        self.run_until(std::future::pending::<()>()).await?;
+        Ok(())
+    }
+
+    /// Serve until `shutdown` resolves or the [`ShutdownHandle`] is triggered, then shut down
+    /// gracefully.
+    ///
+    /// New connections are refused right away, requests being handled are answered and
+    /// persistent sessions are closed between two requests. Sessions still active after the
+    /// [drain timeout](ServerConfig::drain_timeout) are closed, their number is returned.
+    ///
+    /// Connections failing while being accepted are skipped. If a listener itself fails, the
+    /// server shuts down the same way and returns the error.
+    ///
+    /// ```rust,no_run
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let server = Server::new("127.0.0.1", 813, Router::new());
+    /// let forced = server.run_until(tokio::signal::ctrl_c()).await?;
+    /// println!("Closed {forced} sessions on shutdown");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn slow(_session: Session) -> ServerResponse {
+    /// #     tokio::time::sleep(Duration::from_millis(300)).await;
+    /// #     Ok(BaseResponse::TextResponse("done".to_string()))
+    /// # }
+    /// # #[async_route]
+    /// # fn stuck(_session: Session) -> ServerResponse {
+    /// #     tokio::time::sleep(Duration::from_secs(60)).await;
+    /// #     Ok(BaseResponse::TextResponse("late".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/slow" => slow);
+    /// # path_route!(&mut router, "/stuck" => stuck);
+    /// let config = ServerConfig::new().drain_timeout(Duration::from_millis(500));
+    /// let server = Server::new("127.0.0.1", 0, router).with_config(config);
+    /// let handle = server.shutdown_handle();
+    /// # let server = server.bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// let running = tokio::spawn(server.serve_until(std::future::pending::<()>()));
+    ///
+    /// let url = |entrance| format!("olps://127.0.0.1:{port}{entrance}");
+    /// let slow = tokio::spawn(Request::get(&url("/slow")).send());
+    /// let stuck = tokio::spawn(Request::get(&url("/stuck")).send());
+    /// tokio::time::sleep(Duration::from_millis(100)).await;
+    /// handle.shutdown();
+    ///
+    /// // The request in flight is answered, the stuck one is cut off after the drain timeout.
+    /// assert_eq!(slow.await??.text()?, "done");
+    /// assert_eq!(running.await??, 1);
+    /// assert!(stuck.await?.is_err());
+    /// assert!(Request::get(&url("/slow")).send().await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn run_until(&self, shutdown: impl Future) -> Result<usize> {
+        let listeners = self.listen().await?;
+        self.accept_until(listeners, shutdown).await
+    }
+
+    /// Bind the address of the server without accepting connections yet, to find out which
+    /// port it got or to report it ready once it listens.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// # #[async_route]
+    /// # fn hello(_session: Session) -> ServerResponse {
+    /// #     Ok(BaseResponse::TextResponse("hello".to_string()))
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/hello" => hello);
+    /// // Port 0 lets the system pick a free port.
+    /// let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// let port = server.local_addr()?.port();
+    /// assert_ne!(port, 0);
+    /// tokio::spawn(server.serve());
+    ///
+    /// let response = Request::get(&format!("olps://127.0.0.1:{port}/hello")).send().await?;
+    /// assert_eq!(response.text()?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn bind(self) -> Result<Listening> {
+        let listeners = self.listen().await?;
+        Ok(Listening {
+            server: self,
+            listeners,
+        })
+    }
+
+    /// Listen on the Unix domain socket at `path` instead of the address of the server.
+    ///
+    /// Connections go through the same handshake and router as over TCP, their peer is
+    /// [`LOCAL_PEER`]. A socket file left behind by a server that didn't shut down is
+    /// replaced, any other file at `path` fails the bind. The file is removed once the server
+    /// stops listening. See [`ServerConfig::unix_mode`] for its permissions.
+    ///
+    /// ```rust
+    /// # use std::os::unix::fs::PermissionsExt;
+    /// # use oblivion::models::client::{ClientBuilder, Request};
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::{Server, ServerConfig};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn peer(session: Session) -> ServerResponse {
+    ///     Ok(BaseResponse::TextResponse(session.get_ip()?.to_string()))
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let mut router = Router::new();
+    /// # path_route!(&mut router, "/peer" => peer);
+    /// let path = std::env::temp_dir().join(format!("oblivion-{}.sock", std::process::id()));
+    /// let server = Server::new("127.0.0.1", 0, router)
+    ///     .with_config(ServerConfig::new().unix_mode(0o600))
+    ///     .bind_unix(&path)
+    ///     .await?;
+    /// assert_eq!(std::fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+    /// let handle = server.shutdown_handle();
+    /// let running = tokio::spawn(server.serve());
+    ///
+    /// let client = ClientBuilder::new().unix_socket(&path);
+    /// let response = client.send(Request::get("olps://localhost/peer").build()).await?;
+    /// assert_eq!(response.text()?, "127.0.0.1");
+    ///
+    /// handle.shutdown();
+    /// running.await??;
+    /// assert!(!path.exists());
+    ///
+    /// // Files other than sockets are left alone.
+    /// std::fs::write(&path, "notes")?;
+    /// # let router = Router::new();
+    /// let bound = Server::new("127.0.0.1", 0, router).bind_unix(&path).await;
+    /// assert!(bound.is_err());
+    /// assert_eq!(std::fs::read_to_string(&path)?, "notes");
+    /// # std::fs::remove_file(&path)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(unix)]
+    pub async fn bind_unix(self, path: impl AsRef<Path>) -> Result<Listening> {
+        let target = BindTarget::Unix(path.as_ref().to_path_buf());
+        self.with_targets([target]).bind().await
+    }
+
+    /// Bind every address the server listens on, see [`Server::with_targets`].
+    async fn listen(&self) -> Result<Vec<Listener>> {
         #[cfg(not(feature = "bench"))]
         println!("Performing system checks...\n");
 
-        let address = format!("{}:{}", self.host, self.port);
+        let mut listeners = Vec::new();
+        if self.targets.is_empty() {
+            let address = format!("{}:{}", self.host, self.port);
+            let listener = self.listener.lock().unwrap().take();
+            let listener = match listener {
+                Some(tcp) => Listener::Tcp(tcp),
+                None => self
+                    .listen_tcp(&address)
+                    .await
+                    .with_context(|| format!("Failed to bind {address}"))?,
+            };
+            listeners.push(listener);
+        }
+        for target in &self.targets {
+            let listener = match target {
+                BindTarget::Tcp(address) => self.listen_tcp(&address.to_string()).await,
+                #[cfg(unix)]
+                BindTarget::Unix(path) => self.listen_unix(path).await,
+            };
+            listeners.push(listener.with_context(|| format!("Failed to bind {target}"))?);
+        }
+
+        let targets = listeners
+            .iter()
+            .map(Listener::target)
+            .collect::<Result<Vec<_>>>()?;
+        self.started(&targets);
+        Ok(listeners)
+    }
 
-        let tcp = match TcpListener::bind(&address).await {
+    #[cfg(unix)]
+    async fn listen_unix(&self, path: &Path) -> Result<Listener> {
+        use std::os::unix::fs::FileTypeExt;
+
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if !metadata.file_type().is_socket() => {
+                return Err(Error::msg(format!("{} is not a socket", path.display())));
+            }
+            // Nobody accepts on it anymore, left behind by a server that was killed.
+            Ok(_)
+                if UnixStream::connect(path)
+                    .await
+                    .is_err_and(|error| error.kind() == std::io::ErrorKind::ConnectionRefused) =>
+            {
+                std::fs::remove_file(path)?;
+            }
+            _ => {}
+        }
+        let listener = match bind_unix(path, self.config.unix_mode) {
+            Ok(listener) => listener,
+            Err(error) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Destination socket [{}] is already occupied!",
+                        path.display().to_string().bright_magenta()
+                    )
+                    .red()
+                );
+                return Err(Error::from(error));
+            }
+        };
+        let file = SocketFile(path.to_path_buf());
+
+        Ok(Listener::Unix(listener, file))
+    }
+
+    async fn listen_tcp(&self, address: &str) -> Result<Listener> {
+        let tcp = match TcpListener::bind(address).await {
             Ok(tcp) => tcp,
             Err(error) => {
                 eprintln!(
@@ -206,17 +2178,11 @@ impl Server {
             }
         };
 
-        tokio::spawn(async move {
-            match tokio::signal::ctrl_c().await {
-                Ok(_) => {}
-                Err(e) => {
-                    eprintln!("{}", e.to_string().red());
-                    std::process::exit(1);
-                }
-            }
-            std::process::exit(0);
-        });
+        Ok(Listener::Tcp(tcp))
+    }
 
+    #[cfg_attr(feature = "bench", allow(unused_variables))]
+    fn started(&self, targets: &[BindTarget]) {
         #[cfg(not(feature = "bench"))]
         println!(
             "Oblivion version {}, using '{}'",
@@ -225,17 +2191,148 @@ impl Server {
         );
 
         #[cfg(not(feature = "bench"))]
-        println!(
-            "Starting server at {}",
-            format!("Oblivion://{}:{}/", self.host, self.port).bright_cyan()
-        );
+        for target in targets {
+            println!("Starting server at {}", target.to_string().bright_cyan());
+        }
         #[cfg(not(feature = "bench"))]
         println!("Quit the server by CTRL-BREAK.\n");
+    }
+
+    /// Accept connections on `listeners` until `shutdown` resolves, see [`Server::run_until`].
+    async fn accept_until(&self, listeners: Vec<Listener>, shutdown: impl Future) -> Result<usize> {
+        let draining = CancellationToken::new();
+        let limits = self.config.max_connections.map(|max| Limits {
+            connections: Arc::new(Semaphore::new(max)),
+            rejections: Arc::new(Semaphore::new(max)),
+        });
+        let handshakes = self
+            .config
+            .handshake_concurrency
+            .map(|max| Arc::new(Semaphore::new(max)));
+        let shared = Shared {
+            router: self.router.clone(),
+            config: Arc::clone(&self.config),
+            states: self.states.clone(),
+            panic_handler: self.panic_handler,
+            sessions: self.sessions.clone(),
+        };
+        let mut sessions = JoinSet::new();
+        let mut failure = None;
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = self.shutdown.cancelled() => break,
+                (accepted, handshake_permit) = async {
+                    // Wait for a handshake to complete before accepting more connections.
+                    let permit = match &handshakes {
+                        Some(handshakes) => Some(
+                            Arc::clone(handshakes)
+                                .acquire_owned()
+                                .await
+                                .expect("handshake limit is never closed"),
+                        ),
+                        None => None,
+                    };
+                    (accept(&listeners, limits.as_ref(), &self.config).await, permit)
+                } => match accepted {
+                    Ok(Accepted::Admitted(stream, peer, permit)) => {
+                        let slot = Slot::new(&self.connections, permit);
+                        let handshake = Handshake::new(&self.connections, handshake_permit);
+                        let session =
+                            serve(shared.clone(), stream, peer, draining.clone(), handshake);
+                        sessions.spawn(async move {
+                            let _slot = slot;
+                            session.await
+                        });
+                    }
+                    Ok(Accepted::Busy(stream, permit)) => {
+                        let config = Arc::clone(&self.config);
+                        let handshake = Handshake::new(&self.connections, handshake_permit);
+                        sessions.spawn(async move {
+                            let _permit = permit;
+                            let _ = reject(config, stream, handshake).await;
+                        });
+                    }
+                    Err(error) => {
+                        failure = Some(error);
+                        break;
+                    }
+                },
+                // Reap finished sessions so only active ones are kept.
+                Some(_) = sessions.join_next(), if !sessions.is_empty() => {}
+            }
+        }
 
-        while let Ok((stream, peer)) = tcp.accept().await {
-            tokio::spawn(handle(Arc::clone(&self.router), stream, peer));
+        // Refuse new connections on every address while draining.
+        drop(listeners);
+        draining.cancel();
+        let timeout = self.config.drain_timeout.unwrap_or(DRAIN_TIMEOUT);
+        let drained = async { while sessions.join_next().await.is_some() {} };
+        let forced = match tokio::time::timeout(timeout, drained).await {
+            Ok(()) => 0,
+            Err(_) => {
+                let forced = sessions.len();
+                sessions.shutdown().await;
+                forced
+            }
+        };
+        match failure {
+            Some(error) => Err(Error::from(error).context("failed to accept connections")),
+            None => Ok(forced),
         }
+    }
+}
 
-        Ok(())
+/// [`Server`] listening on its addresses, see [`Server::bind`].
+pub struct Listening {
+    server: Server,
+    listeners: Vec<Listener>,
+}
+
+impl Listening {
+    /// First TCP address the server listens on, with the port the system picked if it asked
+    /// for port `0`.
+    ///
+    /// Fails for servers listening on Unix domain sockets only, see [`Listening::unix_path`].
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listeners
+            .iter()
+            .find_map(|listener| match listener {
+                Listener::Tcp(tcp) => Some(tcp.local_addr()),
+                #[cfg(unix)]
+                Listener::Unix(..) => None,
+            })
+            .ok_or_else(|| Error::msg("The server listens on Unix domain sockets only."))?
+            .map_err(Error::from)
+    }
+
+    /// Every address the server listens on, in the order of [`Server::with_targets`].
+    pub fn local_addrs(&self) -> Result<Vec<BindTarget>> {
+        self.listeners.iter().map(Listener::target).collect()
+    }
+
+    /// Path of the first socket file the server listens on, see [`Server::bind_unix`].
+    #[cfg(unix)]
+    pub fn unix_path(&self) -> Option<&Path> {
+        self.listeners.iter().find_map(|listener| match listener {
+            Listener::Tcp(_) => None,
+            Listener::Unix(_, file) => Some(file.0.as_path()),
+        })
+    }
+
+    /// Handle shutting the server down, see [`Server::shutdown_handle`].
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.server.shutdown_handle()
+    }
+
+    /// Serve until the [`ShutdownHandle`] of the server is triggered.
+    pub async fn serve(self) -> Result<usize> {
+        self.serve_until(std::future::pending::<()>()).await
+    }
+
+    /// Serve until `shutdown` resolves, see [`Server::run_until`].
+    pub async fn serve_until(self, shutdown: impl Future) -> Result<usize> {
+        self.server.accept_until(self.listeners, shutdown).await
     }
 }
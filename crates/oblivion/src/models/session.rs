@@ -1,86 +1,1619 @@
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use arc_swap::ArcSwap;
+use bytes::{Buf, Bytes, BytesMut};
 use chrono::{DateTime, Local};
+use futures::{FutureExt, Stream, StreamExt};
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 
-use ring::agreement::{EphemeralPrivateKey, PublicKey, UnparsedPublicKey, X25519};
+use ring::aead::NONCE_LEN;
+use ring::agreement::{EphemeralPrivateKey, PublicKey};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{watch, Mutex};
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
-use crate::exceptions::Exception;
+use crate::exceptions::{Exception, TimeoutPhase};
 use crate::types::Callback;
-use crate::utils::gear::Socket;
-use crate::utils::generator::generate_key_pair;
-use crate::utils::parser::{length, OblivionRequest};
+use crate::utils::compression::Compression;
+use crate::utils::gear::{reading, Corked, Framing, Socket};
+use crate::utils::generator::{
+    check_salt_len, KexAlgorithm, SaltSource, SessionKeys, SharedKey, SALT_LEN,
+};
+use crate::utils::identity::{self, IdentityKey, IdentityVerifier};
+#[cfg(feature = "serde")]
+use crate::utils::parser::parse_into;
+use crate::utils::parser::{
+    encode_headers, length, parse_headers, parse_json, OblivionRequest, CONTENT_LENGTH,
+    MAX_HEADERS_SIZE,
+};
+use crate::utils::throttle::RateLimit;
 
 use super::client::Response;
-use super::packet::{OED, OKE, OSC};
+use super::packet::{MessageFlags, Packet, PacketContext, OED, OKE, OSC};
 use super::render::BaseResponse;
 
+/// Flag leading every message, telling the receiver how to treat it.
+///
+/// Flags this version doesn't know are kept as [`SessionFlag::Unknown`] and handed to the
+/// caller of [`Session::recv`] rather than being dropped, so newer peers are detectable.
+///
+/// ```rust
+/// # use oblivion::models::session::SessionFlag;
+/// assert_eq!(SessionFlag::from(1), SessionFlag::CloseAfter);
+/// assert_eq!(SessionFlag::from(42), SessionFlag::Unknown(42));
+/// assert_eq!(u32::from(SessionFlag::Unknown(42)), 42);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "u32", into = "u32")
+)]
+pub enum SessionFlag {
+    /// A regular message.
+    #[default]
+    Data,
+    /// The sender closes the connection after this message.
+    CloseAfter,
+    /// Key material of a rekey, see [`Session::rekey`].
+    Rekey,
+    /// Keepalive probe, see [`Session::enable_keepalive`].
+    Ping,
+    /// Answer to a [`SessionFlag::Ping`].
+    Pong,
+    /// A frame followed by more frames of the same message, see [`Session::send_file`].
+    Continue,
+    /// The sender closed the session, see [`Session::close`] and [`Capabilities::CLOSE_NOTIFY`].
+    CloseNotify,
+    /// Header of another request on an open connection, see [`Capabilities::REQUESTS`].
+    Request,
+    /// Last message answering a request, the connection stays open for the next one.
+    Response,
+    /// Message sent to every connected session, see
+    /// [`SessionManager::broadcast`](super::manager::SessionManager::broadcast). It is not part
+    /// of the answer to any request.
+    Broadcast,
+    /// The sender sends nothing more but keeps receiving, see [`Session::finish_sending`].
+    Finish,
+    /// A flag this version doesn't know.
+    Unknown(u32),
+}
+
+impl From<u32> for SessionFlag {
+    fn from(flag: u32) -> Self {
+        match flag {
+            0 => Self::Data,
+            1 => Self::CloseAfter,
+            2 => Self::Rekey,
+            3 => Self::Ping,
+            4 => Self::Pong,
+            5 => Self::Continue,
+            6 => Self::CloseNotify,
+            7 => Self::Request,
+            8 => Self::Response,
+            9 => Self::Broadcast,
+            10 => Self::Finish,
+            flag => Self::Unknown(flag),
+        }
+    }
+}
+
+impl From<SessionFlag> for u32 {
+    fn from(flag: SessionFlag) -> Self {
+        match flag {
+            SessionFlag::Data => 0,
+            SessionFlag::CloseAfter => 1,
+            SessionFlag::Rekey => 2,
+            SessionFlag::Ping => 3,
+            SessionFlag::Pong => 4,
+            SessionFlag::Continue => 5,
+            SessionFlag::CloseNotify => 6,
+            SessionFlag::Request => 7,
+            SessionFlag::Response => 8,
+            SessionFlag::Broadcast => 9,
+            SessionFlag::Finish => 10,
+            SessionFlag::Unknown(flag) => flag,
+        }
+    }
+}
+
+/// Version of the wire protocol spoken by this implementation.
+///
+/// Version `0` is the original protocol without a preamble, see [`Session::protocol_version`].
+/// Version `2` derives a key for each direction, see [`SharedKey::session_keys`].
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Leading bytes of the handshake preamble.
+///
+/// Old clients start the handshake with the length of their header instead, which is at most
+/// a few kilobytes and can't be mistaken for these bytes.
+const PREAMBLE_MAGIC: [u8; 4] = *b"OBLV";
+
+/// Feature reported by [`Exception::Unsupported`] when the server doesn't answer the preamble
+/// in time, see [`SessionBuilder::preamble_timeout`].
+pub(crate) const PREAMBLE_FEATURE: &str = "the protocol preamble";
+/// Position of the [`KexAlgorithm`] identifier in the capabilities word of the preamble, its
+/// top byte, which peers that don't know about it read as X25519.
+const KEX_SHIFT: u32 = 24;
+
+/// Optional protocol features, advertised by both sides during the handshake.
+///
+/// Only the features both peers advertise are enabled on the session, see
+/// [`Session::capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// Keepalive frames, see [`Session::enable_keepalive`].
+    pub const KEEPALIVE: Self = Self(1);
+    /// Rekeying, see [`Session::rekey`].
+    pub const REKEY: Self = Self(1 << 1);
+    /// Messages split across frames, see [`Session::send_file`].
+    pub const CONTINUATION: Self = Self(1 << 2);
+    /// [`Session::close`] is announced with [`SessionFlag::CloseNotify`]. Without it an empty
+    /// [`SessionFlag::CloseAfter`] message with status `200` is sent instead, which the peer
+    /// can't tell apart from such a message sent on purpose.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::{Session, SessionFlag};
+    /// # use oblivion::utils::gear::Socket;
+    /// # use tokio::net::{TcpListener, TcpStream};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// # let address = listener.local_addr()?;
+    /// let server = tokio::spawn(async move {
+    ///     let (stream, _) = listener.accept().await.unwrap();
+    ///     let mut session = Session::new(Socket::new(stream)).unwrap();
+    ///     session.handshake(1).await.unwrap();
+    ///     session.send_and_close(Vec::new(), 200).await.unwrap();
+    /// });
+    /// # let stream = TcpStream::connect(address).await?;
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, Socket::new(stream))?;
+    /// # session.handshake(0).await?;
+    ///
+    /// // An empty final message is delivered rather than taken for a close notification.
+    /// let response = session.recv().await?;
+    /// assert_eq!(response.flag, SessionFlag::CloseAfter);
+    /// assert!(response.content.is_empty());
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub const CLOSE_NOTIFY: Self = Self(1 << 3);
+    /// Several requests over one connection, flagged with [`SessionFlag::Request`].
+    pub const REQUESTS: Self = Self(1 << 4);
+    /// Request metadata sent encrypted after the header line, see [`SessionBuilder::metadata`].
+    pub const HEADERS: Self = Self(1 << 5);
+    /// Shutting down one direction of the connection, see [`Session::finish_sending`].
+    pub const HALF_CLOSE: Self = Self(1 << 6);
+    /// Payloads tagged with the algorithm they are compressed with, see
+    /// [`SessionBuilder::compression`]. Only supported with the `zstd` feature.
+    pub const COMPRESSION: Self = Self(1 << 7);
+    /// The flag and status code of messages authenticated along with their content, see
+    /// [`OED::set_aad`]. A message whose flag was altered on the wire fails with
+    /// [`Exception::Tampered`], see the example of [`Capabilities::SEQUENCED_NONCES`].
+    pub const AUTHENTICATED_FRAMING: Self = Self(1 << 8);
+    /// Messages encrypted with a nonce counting them in each direction rather than a random
+    /// one, see [`OED::set_nonce`] and [`Session::nonce_counters`]. Messages replayed, dropped
+    /// or reordered by a third party are rejected with [`Exception::ReplayDetected`], see
+    /// [`Session::replay_protected`].
+    ///
+    /// ```rust
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # use std::sync::atomic::{AtomicBool, Ordering};
+    /// # use std::sync::Arc;
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{Capabilities, Session, SessionBuilder};
+    /// # use oblivion::utils::gear::{Peer, Socket};
+    /// # use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    /// // Sessions connected through a relay that, once `attack` is set, sends the next message
+    /// // of the client twice with `replay`, or flips the last byte of its flag without.
+    /// async fn relayed(
+    ///     attack: Arc<AtomicBool>,
+    ///     replay: bool,
+    /// ) -> anyhow::Result<(Session, Session)> {
+    ///     let (client, client_side) = tokio::io::duplex(64 * 1024);
+    ///     let (server_side, server) = tokio::io::duplex(64 * 1024);
+    ///     let (mut from_client, mut to_client) = tokio::io::split(client_side);
+    ///     let (mut from_server, mut to_server) = tokio::io::split(server_side);
+    ///     tokio::spawn(async move { tokio::io::copy(&mut from_server, &mut to_client).await });
+    ///     tokio::spawn(async move {
+    ///         let mut buffer = vec![0; 64 * 1024];
+    ///         loop {
+    ///             let read = from_client.read(&mut buffer).await?;
+    ///             if read == 0 {
+    ///                 return std::io::Result::Ok(());
+    ///             }
+    ///             let attacked = attack.swap(false, Ordering::SeqCst);
+    ///             if attacked && !replay {
+    ///                 buffer[3] ^= 1;
+    ///             }
+    ///             to_server.write_all(&buffer[..read]).await?;
+    ///             if attacked && replay {
+    ///                 to_server.write_all(&buffer[..read]).await?;
+    ///             }
+    ///         }
+    ///     });
+    ///     let server = tokio::spawn(async move {
+    ///         let mut session = Session::new(Socket::from_stream(server, Peer::Memory))?;
+    ///         session.handshake(1).await?;
+    ///         anyhow::Ok(session)
+    ///     });
+    ///     let client = SessionBuilder::new()
+    ///         .header("GET /x Oblivion/2.0")
+    ///         .establish(Socket::from_stream(client, Peer::Memory), 0)
+    ///         .await?;
+    ///     Ok((client, server.await??))
+    /// }
+    ///
+    /// let attack = Arc::new(AtomicBool::new(false));
+    /// let (client, server) = relayed(attack.clone(), true).await?;
+    /// assert!(client.replay_protected() && server.replay_protected());
+    ///
+    /// // Every message is sent with the next nonce and received with the one expected.
+    /// let (first, _) = client.nonce_counters().unwrap();
+    /// for i in 0..1000 {
+    ///     client.send(format!("message {i}").into_bytes()).await?;
+    ///     server.recv().await?;
+    /// }
+    /// let (sent, _) = client.nonce_counters().unwrap();
+    /// assert_eq!(sent, first + 1000);
+    /// assert_eq!(server.nonce_counters().unwrap().1, sent);
+    ///
+    /// attack.store(true, Ordering::SeqCst);
+    /// client.send(b"transfer 10 coins".to_vec()).await?;
+    /// server.recv().await?;
+    /// let error = server.recv().await.unwrap_err();
+    /// assert_eq!(
+    ///     Exception::from_error(&error),
+    ///     Exception::ReplayDetected {
+    ///         expected: sent + 1,
+    ///         received: sent,
+    ///     }
+    /// );
+    ///
+    /// // With `AUTHENTICATED_FRAMING`, altering a message is detected as well.
+    /// let (client, server) = relayed(attack.clone(), false).await?;
+    /// assert!(client.capabilities().contains(Capabilities::AUTHENTICATED_FRAMING));
+    /// attack.store(true, Ordering::SeqCst);
+    /// client.send(b"transfer 10 coins".to_vec()).await?;
+    /// let error = server.recv().await.unwrap_err();
+    /// assert_eq!(Exception::from_error(&error), Exception::Tampered);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub const SEQUENCED_NONCES: Self = Self(1 << 9);
+    /// The server signs the handshake with its identity key, see [`SessionBuilder::identity`].
+    /// Servers only advertise it with an identity key.
+    pub const IDENTITY: Self = Self(1 << 10);
+    /// The client signs the handshake as well, see [`SessionBuilder::verify_client_identity`].
+    /// Servers only advertise it when verifying clients, clients with an identity key.
+    pub const CLIENT_IDENTITY: Self = Self(1 << 11);
+    /// [`MessageFlags`] carried after the status code of every message, see
+    /// [`Session::send_with_flags`]. Without it the status code is sent alone.
+    pub const MESSAGE_FLAGS: Self = Self(1 << 12);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Every capability this version supports.
+    pub const fn all() -> Self {
+        Self(
+            Self::KEEPALIVE.0
+                | Self::REKEY.0
+                | Self::CONTINUATION.0
+                | Self::CLOSE_NOTIFY.0
+                | Self::REQUESTS.0
+                | Self::HEADERS.0
+                | Self::HALF_CLOSE.0
+                | Self::AUTHENTICATED_FRAMING.0
+                | Self::SEQUENCED_NONCES.0
+                | Self::IDENTITY.0
+                | Self::CLIENT_IDENTITY.0
+                | Self::MESSAGE_FLAGS.0
+                | if cfg!(feature = "zstd") {
+                    Self::COMPRESSION.0
+                } else {
+                    0
+                },
+        )
+    }
+
+    /// Capabilities from raw bits, keeping bits unknown to this version.
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// Largest encrypted payload of a message sessions accept by default, see
+/// [`Session::set_max_payload`].
+pub const MAX_PAYLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+/// Longest time [`Session::close`] waits to notify the peer.
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Plaintext size of every frame written by [`Session::send_file`].
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
 /// Oblivion Full Duplex Session
 ///
 /// This struct represents a full duplex session between the client and the server.
 /// It contains all the necessary information to handle the communication between the two.
+///
+/// A session is `Send + Sync` and can be shared between tasks behind an [`Arc`]: every
+/// message is written while holding a write lock and read while holding a read lock, so
+/// concurrent senders never interleave their packets and concurrent receivers each get
+/// whole messages.
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use oblivion::models::session::Session;
+/// # use oblivion::utils::gear::Socket;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// # let (client, server) = Socket::pair();
+/// # let server = tokio::spawn(async move {
+/// #     let mut session = Session::new(server).unwrap();
+/// #     session.handshake(1).await.unwrap();
+/// #     for _ in 0..400 {
+/// #         let response = session.recv().await.unwrap();
+/// #         session.send(response.content.into()).await.unwrap();
+/// #     }
+/// # });
+/// # let header = "CONNECT / Oblivion/2.0".to_string();
+/// # let mut session = Session::new_with_header(header, client)?;
+/// # session.handshake(0).await?;
+/// let session = Arc::new(session);
+///
+/// let mut tasks = Vec::new();
+/// for sender in 0..2 {
+///     let session = Arc::clone(&session);
+///     tasks.push(tokio::spawn(async move {
+///         for message in 0..200 {
+///             let payload = format!("{sender}:{message}:").repeat(sender * 500 + 1);
+///             session.send(payload.into()).await.unwrap();
+///         }
+///         Vec::new()
+///     }));
+/// }
+/// for _ in 0..2 {
+///     let session = Arc::clone(&session);
+///     tasks.push(tokio::spawn(async move {
+///         let mut received = Vec::new();
+///         for _ in 0..200 {
+///             received.push(session.recv().await.unwrap().text().unwrap().to_string());
+///         }
+///         received
+///     }));
+/// }
+///
+/// let mut received = Vec::new();
+/// for task in tasks {
+///     received.extend(task.await?);
+/// }
+/// received.sort();
+/// let mut expected: Vec<_> = (0..2usize)
+///     .flat_map(|sender| (0..200).map(move |message| (sender, message)))
+///     .map(|(sender, message)| format!("{sender}:{message}:").repeat(sender * 500 + 1))
+///     .collect();
+/// expected.sort();
+/// assert_eq!(received, expected);
+/// # server.await?;
+/// # Ok(())
+/// # }
+/// ```
 pub struct Session {
     pub header: String,
+    /// Metadata sent by the client side of the handshake, see [`SessionBuilder::metadata`].
+    metadata: Vec<(String, String)>,
     pub(crate) private_key: Option<EphemeralPrivateKey>,
     pub(crate) public_key: PublicKey,
-    pub(crate) aes_key: [u8; 16],
+    kex_algorithm: KexAlgorithm,
+    peer_public_key: Option<Vec<u8>>,
+    pinned_keys: Vec<[u8; 32]>,
+    identity: Option<IdentityKey>,
+    trusted_identities: Vec<[u8; 32]>,
+    identity_verifier: Option<IdentityVerifier>,
+    /// Identity public key the peer signed the handshake with.
+    peer_identity: Option<[u8; 32]>,
+    /// Whether this side signed the handshake with its identity key.
+    signed: bool,
+    salt_len: usize,
+    salt_source: SaltSource,
+    /// Salt of the handshake, see [`Session::salt`].
+    salt: Option<Vec<u8>>,
+    pub(crate) keys: Arc<ArcSwap<SessionKeys>>,
     pub request_time: DateTime<Local>,
     pub request: OblivionRequest,
     pub socket: Arc<Socket>,
-    closed: ArcSwap<bool>,
     callback: Arc<Option<Callback>>,
+    recv_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    max_payload: Option<usize>,
+    preamble_timeout: Option<Duration>,
+    /// Largest message accepted from the peer, its frames included, instead of `max_payload`
+    /// while reading headers and bodies, see [`Session::read_body`].
+    max_message: Option<usize>,
+    local_version: u32,
+    local_capabilities: Capabilities,
+    protocol_version: u32,
+    capabilities: Capabilities,
+    channel: Arc<Channel>,
 }
 
-impl Session {
-    pub fn new(socket: Socket) -> Result<Self> {
-        let (private_key, public_key) = generate_key_pair();
-        Ok(Self {
+/// Source of [`Session::id`].
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Wire state of a session, shared with the background tasks it spawns.
+struct Channel {
+    id: u64,
+    socket: Arc<Socket>,
+    keys: Arc<ArcSwap<SessionKeys>>,
+    closed: watch::Sender<Option<CloseReason>>,
+    hooks: StdMutex<Vec<CloseHook>>,
+    send_lock: Mutex<()>,
+    pending: Mutex<VecDeque<Response>>,
+    /// Frames of the message being received, locked for as long as a message is read so
+    /// concurrent receivers never split one between them.
+    partial: Mutex<Option<Response>>,
+    /// Set once the peer sent a message flagged as [`SessionFlag::Finish`].
+    peer_finished: AtomicBool,
+    control: Mutex<Option<JoinHandle<Result<()>>>>,
+    created_at: Instant,
+    counters: Counters,
+    sequence: Sequence,
+    /// How messages are compressed, once [`Capabilities::COMPRESSION`] was negotiated.
+    compression: StdMutex<Compression>,
+    /// Bits of the capabilities negotiated with the peer.
+    negotiated: AtomicU32,
+}
+
+/// Callback registered with [`Session::on_close`].
+type CloseHook = Box<dyn FnOnce(CloseReason) + Send>;
+
+/// Why a [`Session`] was closed, see [`Session::on_close`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum CloseReason {
+    /// This side closed the session with [`Session::close`] or [`Session::abort`], or dropped
+    /// it while open. Timeouts on this side are reported as [`CloseReason::Error`].
+    LocalClose,
+    /// The peer flagged its last message with [`SessionFlag::CloseAfter`].
+    RemoteClose,
+    /// Sending or receiving failed and the connection can't be used anymore.
+    Error(Exception),
+}
+
+/// Traffic counters updated by every message, timestamps are milliseconds since creation.
+///
+/// Bytes on the wire and the last activity are counted by the socket itself.
+#[derive(Default)]
+struct Counters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    packets_sent: AtomicU64,
+    packets_received: AtomicU64,
+    last_received: AtomicU64,
+}
+
+/// Counters of the nonces of a session once [`Capabilities::SEQUENCED_NONCES`] was
+/// negotiated, the next one to send and the next one expected from the peer.
+#[derive(Default)]
+struct Sequence {
+    /// Whether this side of the session is the client, which sends the nonces starting with
+    /// [`CLIENT_NONCE_PREFIX`].
+    client: AtomicBool,
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+/// Leading bytes of the nonces of messages sent by the client, followed by their counter.
+const CLIENT_NONCE_PREFIX: [u8; 4] = *b"OBLC";
+/// Leading bytes of the nonces of messages sent by the server.
+const SERVER_NONCE_PREFIX: [u8; 4] = *b"OBLS";
+
+/// How the handshake of a [`Session`] was authenticated, see [`Session::handshake_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandshakeMode {
+    /// Neither side proved its identity, a man in the middle could have relayed the session.
+    Anonymous,
+    /// The server signed the handshake with an identity key the client trusts.
+    ServerAuthenticated,
+    /// The client signed the handshake with an identity key the server accepted, the server
+    /// didn't prove its identity.
+    ClientAuthenticated,
+    /// Both sides proved their identity.
+    MutuallyAuthenticated,
+}
+
+/// Snapshot of the traffic of a [`Session`].
+///
+/// `bytes_*` count plaintext payloads while `wire_bytes_*` count everything written to or read
+/// from the socket, the handshake included, see [`Socket::bytes_read`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub wire_bytes_sent: u64,
+    pub wire_bytes_received: u64,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub established_at: Instant,
+    /// Last time bytes were sent or received, see [`Socket::idle_for`].
+    pub last_activity: Instant,
+}
+
+/// Builder configuring a [`Session`] before its handshake.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use oblivion::models::session::{Session, SessionBuilder};
+/// # use oblivion::utils::gear::Socket;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let (client, server) = Socket::pair();
+/// # let server = tokio::spawn(async move {
+/// #     let session = SessionBuilder::new().establish(server, 1).await.unwrap();
+/// #     let response = session.recv().await.unwrap();
+/// #     session.send(response.content.into()).await.unwrap();
+/// # });
+/// let session = SessionBuilder::new()
+///     .header("CONNECT / Oblivion/2.0")
+///     .recv_timeout(Duration::from_secs(5))
+///     .max_payload(1024 * 1024)
+///     .establish(client, 0)
+///     .await?;
+///
+/// session.send("hello".into()).await?;
+/// assert_eq!(session.recv().await?.text()?, "hello");
+/// # server.await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SessionBuilder {
+    header: String,
+    metadata: Vec<(String, String)>,
+    pinned_keys: Vec<[u8; 32]>,
+    recv_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    max_payload: Option<usize>,
+    keepalive: Option<Duration>,
+    preamble_timeout: Option<Duration>,
+    compression: Compression,
+    kex_algorithm: KexAlgorithm,
+    identity: Option<IdentityKey>,
+    trusted_identities: Vec<[u8; 32]>,
+    identity_verifier: Option<IdentityVerifier>,
+    salt_len: usize,
+    salt_source: SaltSource,
+    protocol_version: u32,
+    capabilities: Capabilities,
+}
+
+impl Default for SessionBuilder {
+    fn default() -> Self {
+        Self {
             header: String::new(),
+            metadata: Vec::new(),
+            pinned_keys: Vec::new(),
+            recv_timeout: None,
+            idle_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            max_payload: Some(MAX_PAYLOAD_SIZE),
+            keepalive: None,
+            preamble_timeout: None,
+            compression: Compression::default(),
+            kex_algorithm: KexAlgorithm::default(),
+            identity: None,
+            trusted_identities: Vec::new(),
+            identity_verifier: None,
+            salt_len: SALT_LEN,
+            salt_source: SaltSource::default(),
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: Capabilities::all(),
+        }
+    }
+}
+
+impl SessionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highest protocol version to speak, defaults to [`PROTOCOL_VERSION`].
+    ///
+    /// A client set to `0` skips the preamble, for servers that predate version negotiation.
+    pub fn protocol_version(mut self, version: u32) -> Self {
+        self.protocol_version = version;
+        self
+    }
+
+    /// Fail the client side of the handshake with [`Exception::Unsupported`] if the server
+    /// doesn't answer the preamble within `timeout`.
+    ///
+    /// Servers that predate version negotiation read the preamble as the length of a huge
+    /// header and wait for it forever. Since they already consumed part of the preamble, the
+    /// handshake has to be retried on a new connection with a [`protocol_version`] of `0`,
+    /// which [`Client::connect`](crate::models::client::Client::connect) does on its own.
+    ///
+    /// [`protocol_version`]: SessionBuilder::protocol_version
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::SessionBuilder;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use tokio::io::AsyncReadExt;
+    /// # use tokio::net::{TcpListener, TcpStream};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// // An old server, reading the length of the header and waiting for the rest of it.
+    /// let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// let address = listener.local_addr()?;
+    /// # let _server = tokio::spawn(async move {
+    /// #     let (mut stream, _) = listener.accept().await?;
+    /// #     let _len = stream.read_u32().await?;
+    /// #     std::future::pending::<()>().await;
+    /// #     anyhow::Ok(())
+    /// # });
+    /// let stream = TcpStream::connect(address).await?;
+    /// let error = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .preamble_timeout(Duration::from_millis(50))
+    ///     .establish(Socket::new(stream), 0)
+    ///     .await
+    ///     .err()
+    ///     .unwrap();
+    /// assert!(matches!(
+    ///     error.downcast_ref(),
+    ///     Some(Exception::Unsupported { feature }) if feature == "the protocol preamble"
+    /// ));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preamble_timeout(mut self, timeout: Duration) -> Self {
+        self.preamble_timeout = Some(timeout);
+        self
+    }
+
+    /// Capabilities advertised to the peer, defaults to [`Capabilities::all`].
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Header line sent by the client side of the handshake.
+    pub fn header(mut self, header: &str) -> Self {
+        self.header = header.to_string();
+        self
+    }
+
+    /// Attach a metadata entry to the request of the client side of the handshake.
+    ///
+    /// Unlike the header line, metadata is sent encrypted once the keys are exchanged and is
+    /// available to the server as [`OblivionRequest::get_header`]. The handshake fails with
+    /// [`Exception::Unsupported`] if the server doesn't negotiate [`Capabilities::HEADERS`],
+    /// and with [`Exception::HeadersTooLarge`] if the entries don't fit in
+    /// [`MAX_HEADERS_SIZE`].
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::{Session, SessionBuilder};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let mut session = Session::new(server)?;
+    ///     session.handshake(1).await?;
+    ///     assert_eq!(session.request.get_header("x-trace-id"), Some("abc 123"));
+    ///     assert!(!session.header.contains("abc"));
+    ///     session.send_and_close(b"ok".to_vec(), 200).await?;
+    ///     anyhow::Ok(())
+    /// });
+    ///
+    /// let session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .metadata("X-Trace-Id", "abc 123")
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert_eq!(session.recv().await?.text()?, "ok");
+    /// server.await??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Only accept servers signing the handshake with the identity key of SHA-256
+    /// `fingerprint`, see [`SessionBuilder::identity`] and [`IdentityKey::fingerprint`]. Pin
+    /// several fingerprints to rotate keys.
+    ///
+    /// The client side of the handshake checks the signature before sending anything but its
+    /// key, and fails with [`Exception::KeyPinMismatch`] for servers signing with another key
+    /// or not signing at all, so the metadata never reaches them. Unlike
+    /// [`SessionBuilder::trust_fingerprint`], the error carries the fingerprint presented.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{Session, SessionBuilder};
+    /// # use oblivion::utils::gear::Socket;
+    /// # use oblivion::utils::identity::IdentityKey;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let key = IdentityKey::from_seed(&[7; 32])?;
+    /// let fingerprint = key.fingerprint();
+    /// let connect = |pin| {
+    ///     let (client, server) = Socket::pair();
+    ///     let key = key.clone();
+    ///     let server = tokio::spawn(async move {
+    ///         let session = SessionBuilder::new().identity(key).establish(server, 1).await?;
+    ///         Ok::<_, anyhow::Error>(session.request.get_header("authorization").is_some())
+    ///     });
+    ///     let client = SessionBuilder::new()
+    ///         .header("GET / Oblivion/2.0")
+    ///         .metadata("authorization", "secret")
+    ///         .pin_key(pin)
+    ///         .establish(client, 0);
+    ///     async move { (client.await, server.await.unwrap()) }
+    /// };
+    ///
+    /// let (session, received) = connect(fingerprint).await;
+    /// assert_eq!(session?.peer_identity(), Some(key.public_key()));
+    /// assert!(received?);
+    ///
+    /// let (session, received) = connect([1; 32]).await;
+    /// let error = session.err().unwrap();
+    /// assert_eq!(
+    ///     error.downcast_ref(),
+    ///     Some(&Exception::KeyPinMismatch {
+    ///         presented: Some(fingerprint)
+    ///     })
+    /// );
+    /// // The server never received the metadata.
+    /// assert!(received.is_err());
+    ///
+    /// // Servers without an identity key are refused as well.
+    /// let (client, server) = Socket::pair();
+    /// tokio::spawn(async move { Session::new(server)?.handshake(1).await });
+    /// let error = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .pin_key(fingerprint)
+    ///     .establish(client, 0)
+    ///     .await
+    ///     .err()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     error.downcast_ref(),
+    ///     Some(&Exception::KeyPinMismatch { presented: None })
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pin_key(mut self, fingerprint: [u8; 32]) -> Self {
+        self.pinned_keys.push(fingerprint);
+        self
+    }
+
+    /// Default timeout of [`Session::recv`], see [`Session::set_recv_timeout`].
+    pub fn recv_timeout(mut self, timeout: Duration) -> Self {
+        self.recv_timeout = Some(timeout);
+        self
+    }
+
+    /// Close the session once idle for `timeout`, see [`Session::set_idle_timeout`].
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout of every read from the socket, see [`Socket::set_read_timeout`].
+    ///
+    /// Unlike the [receive timeout](SessionBuilder::recv_timeout), which gives up waiting for
+    /// a message, it is enforced on each read and leaves the socket unusable once it runs out.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout of every write to the socket, see [`Socket::set_write_timeout`].
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Largest encrypted payload accepted from the peer, defaults to [`MAX_PAYLOAD_SIZE`], see
+    /// [`Session::set_max_payload`].
+    pub fn max_payload(mut self, size: usize) -> Self {
+        self.max_payload = Some(size);
+        self
+    }
+
+    /// Probe the peer every `interval` once established, see [`Session::enable_keepalive`].
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive = Some(interval);
+        self
+    }
+
+    /// Compress the messages sent once the peer negotiated [`Capabilities::COMPRESSION`], see
+    /// [`Session::set_compression`].
+    ///
+    /// ```rust
+    /// # #[cfg(feature = "zstd")]
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # use oblivion::models::session::{Capabilities, Session, SessionBuilder};
+    /// # use oblivion::utils::compression::Compression;
+    /// # use oblivion::utils::gear::Socket;
+    /// let json = br#"{"id": 1, "tags": ["a", "b", "c"], "active": true}, "#.repeat(200);
+    ///
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let mut session = Session::new(server)?;
+    ///     session.handshake(1).await?;
+    ///     let response = session.recv().await?;
+    ///     anyhow::Ok((response.content, session.stats().wire_bytes_received))
+    /// });
+    ///
+    /// let session = SessionBuilder::new()
+    ///     .header("POST /sync Oblivion/2.0")
+    ///     .compression(Compression::zstd(3))
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert!(session.capabilities().contains(Capabilities::COMPRESSION));
+    /// session.send(json.clone()).await?;
+    ///
+    /// let (received, wire_bytes) = server.await??;
+    /// assert_eq!(received, json);
+    /// assert!(wire_bytes < json.len() as u64 / 5);
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(feature = "zstd"))]
+    /// # fn main() {}
+    /// ```
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Key exchange algorithm of the handshake and of rekeys, defaults to X25519.
+    ///
+    /// It is announced in the preamble, the handshake fails with [`Exception::KexMismatch`] on
+    /// both sides when the peer uses another one, including peers without a preamble, which
+    /// only speak X25519.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{Session, SessionBuilder};
+    /// # use oblivion::utils::gear::Socket;
+    /// # use oblivion::utils::generator::KexAlgorithm;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let session = SessionBuilder::new()
+    ///         .kex_algorithm(KexAlgorithm::P256)
+    ///         .establish(server, 1)
+    ///         .await?;
+    ///     session.send_and_close(session.recv().await?.content.into(), 200).await
+    /// });
+    /// let session = SessionBuilder::new()
+    ///     .header("GET /echo Oblivion/2.0")
+    ///     .kex_algorithm(KexAlgorithm::P256)
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert_eq!(session.kex_algorithm(), KexAlgorithm::P256);
+    /// session.send("hello".into()).await?;
+    /// assert_eq!(session.recv().await?.text()?, "hello");
+    /// server.await??;
+    ///
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move { Session::new(server)?.handshake(1).await });
+    /// let error = SessionBuilder::new()
+    ///     .header("GET /echo Oblivion/2.0")
+    ///     .kex_algorithm(KexAlgorithm::P256)
+    ///     .establish(client, 0)
+    ///     .await
+    ///     .err()
+    ///     .unwrap();
+    /// let mismatch = Exception::KexMismatch {
+    ///     local: KexAlgorithm::P256,
+    ///     peer: KexAlgorithm::X25519,
+    /// };
+    /// assert!(Exception::from_error(&error) == mismatch);
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "Peer exchanges keys with X25519, P-256 is expected."
+    /// );
+    /// let error = server.await?.unwrap_err();
+    /// assert!(
+    ///     Exception::from_error(&error)
+    ///         == Exception::KexMismatch {
+    ///             local: KexAlgorithm::X25519,
+    ///             peer: KexAlgorithm::P256,
+    ///         }
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn kex_algorithm(mut self, algorithm: KexAlgorithm) -> Self {
+        self.kex_algorithm = algorithm;
+        self
+    }
+
+    /// Sign the handshake with `key`, to prove the identity of a server to its clients, or of
+    /// a client to servers [verifying](SessionBuilder::verify_client_identity) it.
+    ///
+    /// Clients check the signature before sending anything but the header line, trusting the
+    /// keys of [`SessionBuilder::trust_identity`] and failing with
+    /// [`Exception::UntrustedIdentity`] for others, or with [`Exception::Unauthenticated`] if
+    /// the server doesn't sign the handshake. Without trusted keys they accept any identity
+    /// such as an anonymous server, see [`Session::handshake_mode`].
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{HandshakeMode, Session, SessionBuilder};
+    /// # use oblivion::utils::gear::Socket;
+    /// # use oblivion::utils::identity::IdentityKey;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let key = IdentityKey::from_pkcs8(&IdentityKey::generate_pkcs8()?)?;
+    /// let public_key = key.public_key();
+    ///
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let session = SessionBuilder::new().identity(key).establish(server, 1).await?;
+    ///     session.send_and_close(b"welcome".to_vec(), 200).await
+    /// });
+    /// let session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .trust_identity(public_key)
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert_eq!(session.handshake_mode(), HandshakeMode::ServerAuthenticated);
+    /// assert_eq!(session.peer_identity(), Some(public_key));
+    /// assert_eq!(session.recv().await?.text()?, "welcome");
+    /// server.await??;
+    ///
+    /// // An anonymous server, or one signing with another key, is refused.
+    /// let (client, server) = Socket::pair();
+    /// tokio::spawn(async move { Session::new(server)?.handshake(1).await });
+    /// let error = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .trust_identity(public_key)
+    ///     .establish(client, 0)
+    ///     .await
+    ///     .err()
+    ///     .unwrap();
+    /// assert!(Exception::from_error(&error) == Exception::Unauthenticated);
+    ///
+    /// let impostor = IdentityKey::from_seed(&[7; 32])?;
+    /// let fingerprint = impostor.fingerprint();
+    /// let (client, server) = Socket::pair();
+    /// tokio::spawn(SessionBuilder::new().identity(impostor).establish(server, 1));
+    /// let error = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .trust_identity(public_key)
+    ///     .establish(client, 0)
+    ///     .await
+    ///     .err()
+    ///     .unwrap();
+    /// assert!(Exception::from_error(&error) == Exception::UntrustedIdentity { fingerprint });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn identity(mut self, key: IdentityKey) -> Self {
+        self.identity = Some(key);
+        self
+    }
+
+    /// Trust servers signing the handshake with the identity `public_key`, see
+    /// [`SessionBuilder::identity`]. Trust several keys to rotate them.
+    pub fn trust_identity(self, public_key: [u8; 32]) -> Self {
+        self.trust_fingerprint(identity::fingerprint(&public_key))
+    }
+
+    /// Trust servers signing the handshake with the identity key of SHA-256 `fingerprint`,
+    /// see [`IdentityKey::fingerprint`].
+    pub fn trust_fingerprint(mut self, fingerprint: [u8; 32]) -> Self {
+        self.trusted_identities.push(fingerprint);
+        self
+    }
+
+    /// Require clients to sign the handshake with an identity key that `verify` accepts.
+    ///
+    /// The handshake fails with [`Exception::Unauthenticated`] for clients without an
+    /// identity key, and with [`Exception::UntrustedIdentity`] for those whose public key
+    /// `verify` refuses, before their request is read.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{HandshakeMode, SessionBuilder};
+    /// # use oblivion::utils::gear::Socket;
+    /// # use oblivion::utils::identity::IdentityKey;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let server_key = IdentityKey::from_seed(&[1; 32])?;
+    /// let client_key = IdentityKey::from_seed(&[2; 32])?;
+    /// let allowed = client_key.public_key();
+    /// let server_public_key = server_key.public_key();
+    /// let server = SessionBuilder::new()
+    ///     .identity(server_key)
+    ///     .verify_client_identity(move |public_key| *public_key == allowed);
+    ///
+    /// let (client, socket) = Socket::pair();
+    /// let accepted = tokio::spawn(server.clone().establish(socket, 1));
+    /// let session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .trust_identity(server_public_key)
+    ///     .identity(client_key)
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert_eq!(session.handshake_mode(), HandshakeMode::MutuallyAuthenticated);
+    /// let accepted = accepted.await??;
+    /// assert_eq!(accepted.peer_identity(), Some(allowed));
+    /// assert_eq!(accepted.handshake_mode(), HandshakeMode::MutuallyAuthenticated);
+    ///
+    /// let (client, socket) = Socket::pair();
+    /// let refused = tokio::spawn(server.establish(socket, 1));
+    /// let _ = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .establish(client, 0)
+    ///     .await;
+    /// let error = refused.await?.err().unwrap();
+    /// assert!(Exception::from_error(&error) == Exception::Unauthenticated);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn verify_client_identity<F>(self, verify: F) -> Self
+    where
+        F: Fn(&[u8; 32]) -> bool + Send + Sync + 'static,
+    {
+        self.identity_verifier(IdentityVerifier::new(verify))
+    }
+
+    pub(crate) fn identity_verifier(mut self, verifier: IdentityVerifier) -> Self {
+        self.identity_verifier = Some(verifier);
+        self
+    }
+
+    /// Length of the salts this side generates for the handshake and rekeys, defaults to
+    /// [`SALT_LEN`], see [`OKE::with_salt_len`].
+    ///
+    /// Salts the peer generates may have any length between
+    /// [`MIN_SALT_LEN`](crate::utils::generator::MIN_SALT_LEN) and
+    /// [`MAX_SALT_LEN`](crate::utils::generator::MAX_SALT_LEN), so both sides need not agree
+    /// on it. [`SessionBuilder::build`] fails with [`Exception::InvalidSaltLength`] for
+    /// lengths out of that range.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::SessionBuilder;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let mut session = SessionBuilder::new().establish(server, 1).await?;
+    ///     // Answers the rekey of the client with its salt of 48 bytes on the way.
+    ///     let message = session.recv().await?;
+    ///     session.rekey().await?;
+    ///     session.send(message.content.to_vec()).await?;
+    ///     anyhow::Ok(())
+    /// });
+    /// let mut session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .salt_len(48)
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert_eq!(session.salt().unwrap().len(), 16);
+    /// session.rekey().await?;
+    /// session.send(b"hello".to_vec()).await?;
+    /// assert_eq!(session.recv().await?.text()?, "hello");
+    /// server.await??;
+    ///
+    /// let error = SessionBuilder::new().salt_len(8).build(Socket::pair().0).err().unwrap();
+    /// let expected = Exception::InvalidSaltLength { len: 8, min: 16, max: 64 };
+    /// assert!(Exception::from_error(&error) == expected);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn salt_len(mut self, len: usize) -> Self {
+        self.salt_len = len;
+        self
+    }
+
+    /// Generate the salts of the handshake and rekeys with `source` rather than the system
+    /// random number generator, such as [`SaltSource::seeded`] for reproducible tests.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::SessionBuilder;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use oblivion::utils::generator::SaltSource;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let source = SaltSource::seeded(7);
+    /// tokio::spawn(SessionBuilder::new().salt_source(source).establish(server, 1));
+    /// let session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert_eq!(session.salt(), Some(&SaltSource::seeded(7).generate(16)[..]));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn salt_source(mut self, source: SaltSource) -> Self {
+        self.salt_source = source;
+        self
+    }
+
+    /// Create the session without performing the handshake.
+    ///
+    /// Keepalive is only started by [`SessionBuilder::establish`].
+    pub fn build(self, mut socket: Socket) -> Result<Session> {
+        check_salt_len(self.salt_len)?;
+        let (private_key, public_key) = self.kex_algorithm.generate_key_pair();
+        if self.read_timeout.is_some() {
+            socket.set_read_timeout(self.read_timeout);
+        }
+        if self.write_timeout.is_some() {
+            socket.set_write_timeout(self.write_timeout);
+        }
+        let socket = Arc::new(socket);
+        let keys = Arc::new(ArcSwap::new(Arc::new(Default::default())));
+        Ok(Session {
+            header: self.header,
+            metadata: self.metadata,
             private_key: Some(private_key),
             public_key,
-            aes_key: Default::default(),
+            kex_algorithm: self.kex_algorithm,
+            peer_public_key: None,
+            pinned_keys: self.pinned_keys,
+            identity: self.identity,
+            trusted_identities: self.trusted_identities,
+            identity_verifier: self.identity_verifier,
+            peer_identity: None,
+            signed: false,
+            salt_len: self.salt_len,
+            salt_source: self.salt_source,
+            salt: None,
+            keys: Arc::clone(&keys),
             request_time: Local::now(),
             request: Default::default(),
-            socket: Arc::new(socket),
-            closed: ArcSwap::new(Arc::new(false)),
+            socket: Arc::clone(&socket),
             callback: Arc::new(None),
+            recv_timeout: self.recv_timeout,
+            idle_timeout: self.idle_timeout,
+            max_payload: self.max_payload,
+            preamble_timeout: self.preamble_timeout,
+            max_message: None,
+            local_version: self.protocol_version,
+            local_capabilities: self.capabilities,
+            protocol_version: 0,
+            capabilities: Capabilities::empty(),
+            channel: Arc::new(Channel {
+                id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+                socket,
+                keys,
+                closed: watch::Sender::new(None),
+                hooks: StdMutex::new(Vec::new()),
+                send_lock: Mutex::new(()),
+                pending: Mutex::new(VecDeque::new()),
+                partial: Mutex::new(None),
+                peer_finished: AtomicBool::new(false),
+                control: Mutex::new(None),
+                created_at: Instant::now(),
+                counters: Counters::default(),
+                sequence: Sequence::default(),
+                compression: StdMutex::new(self.compression),
+                negotiated: AtomicU32::new(0),
+            }),
         })
     }
 
+    /// Create the session and perform the handshake, `0` on the client side and `1` on the server side.
+    ///
+    /// Keepalive is only started when the peer supports it.
+    pub async fn establish(self, socket: Socket, flag: u8) -> Result<Session> {
+        let keepalive = self.keepalive;
+        let mut session = self.build(socket)?;
+        session.handshake(flag).await?;
+        if let Some(interval) = keepalive {
+            if session.capabilities.contains(Capabilities::KEEPALIVE) {
+                session.enable_keepalive(interval).await?;
+            }
+        }
+        Ok(session)
+    }
+}
+
+impl Channel {
+    /// Milliseconds since the session was created.
+    #[inline]
+    fn uptime(&self) -> u64 {
+        self.created_at.elapsed().as_millis() as u64
+    }
+
+    /// Whether this side of the session is the client.
+    #[inline]
+    fn is_client(&self) -> bool {
+        self.sequence.client.load(Ordering::Relaxed)
+    }
+
+    fn negotiated(&self, capability: Capabilities) -> bool {
+        Capabilities::from_bits(self.negotiated.load(Ordering::Relaxed)).contains(capability)
+    }
+
+    /// Compression of message payloads, `None` unless negotiated with the peer.
+    fn compression(&self) -> Option<Compression> {
+        self.negotiated(Capabilities::COMPRESSION)
+            .then(|| *self.compression.lock().unwrap())
+    }
+
+    /// Associated data of a message flagged `flag` with `status_code`, and with `flags` once
+    /// [`Capabilities::MESSAGE_FLAGS`] was negotiated. Empty unless
+    /// [`Capabilities::AUTHENTICATED_FRAMING`] was negotiated.
+    fn message_aad(&self, flag: u32, status_code: u32, flags: MessageFlags) -> Vec<u8> {
+        if !self.negotiated(Capabilities::AUTHENTICATED_FRAMING) {
+            return Vec::new();
+        }
+        let mut aad = [flag.to_be_bytes(), status_code.to_be_bytes()].concat();
+        if self.negotiated(Capabilities::MESSAGE_FLAGS) {
+            aad.extend_from_slice(&flags.bits().to_be_bytes());
+        }
+        aad
+    }
+
+    /// Bytes of the [`OSC`] following the data of a message, see [`OSC::to_bytes_with_flags`].
+    fn trailer(&self, status_code: u32, flags: MessageFlags) -> Result<Vec<u8>> {
+        let mut osc = OSC::from_u32(status_code);
+        if self.negotiated(Capabilities::MESSAGE_FLAGS) {
+            osc = osc.with_flags(flags);
+        }
+        osc.encode()
+    }
+
+    /// Length of [`Channel::trailer`].
+    fn trailer_len(&self) -> usize {
+        match self.negotiated(Capabilities::MESSAGE_FLAGS) {
+            true => 6,
+            false => 4,
+        }
+    }
+
+    /// Nonce of the next message sent, `None` unless [`Capabilities::SEQUENCED_NONCES`] was
+    /// negotiated. The caller must hold `send_lock` so that messages go out in order.
+    fn next_nonce(&self) -> Result<Option<[u8; NONCE_LEN]>, Exception> {
+        if !self.negotiated(Capabilities::SEQUENCED_NONCES) {
+            return Ok(None);
+        }
+        let sequence = &self.sequence;
+        let counter = sequence
+            .sent
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sent| {
+                sent.checked_add(1)
+            })
+            .map_err(|_| Exception::NoncesExhausted)?;
+        let prefix = if sequence.client.load(Ordering::Relaxed) {
+            CLIENT_NONCE_PREFIX
+        } else {
+            SERVER_NONCE_PREFIX
+        };
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..4].copy_from_slice(&prefix);
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        Ok(Some(nonce))
+    }
+
+    /// Check that `nonce` is the next one the peer sends, once
+    /// [`Capabilities::SEQUENCED_NONCES`] was negotiated.
+    fn check_nonce(&self, nonce: &[u8]) -> Result<(), Exception> {
+        if !self.negotiated(Capabilities::SEQUENCED_NONCES) {
+            return Ok(());
+        }
+        let sequence = &self.sequence;
+        let prefix = if sequence.client.load(Ordering::Relaxed) {
+            SERVER_NONCE_PREFIX
+        } else {
+            CLIENT_NONCE_PREFIX
+        };
+        let Some(counter) = nonce
+            .strip_prefix(&prefix[..])
+            .and_then(|counter| counter.try_into().ok())
+            .map(u64::from_be_bytes)
+        else {
+            return Err(Exception::Tampered);
+        };
+        let expected = sequence.received.load(Ordering::Relaxed);
+        if counter != expected {
+            return Err(Exception::ReplayDetected {
+                expected,
+                received: counter,
+            });
+        }
+        sequence.received.store(expected + 1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Write a whole message, the caller must hold `send_lock`.
+    ///
+    /// A failed write leaves a partial message on the wire, so it closes the session.
+    async fn write_message(
+        &self,
+        data: Vec<u8>,
+        status_code: u32,
+        flag: SessionFlag,
+    ) -> Result<()> {
+        self.write_message_with_flags(data, status_code, flag, MessageFlags::empty())
+            .await
+    }
+
+    /// Write a whole message carrying `flags`, see [`Channel::write_message`].
+    async fn write_message_with_flags(
+        &self,
+        data: Vec<u8>,
+        status_code: u32,
+        flag: SessionFlag,
+        flags: MessageFlags,
+    ) -> Result<()> {
+        let socket = &self.socket;
+        if socket.is_write_shutdown() {
+            return Err(Exception::WriteShutdown.into());
+        }
+        let size = data.len() as u64;
+
+        let keys = self.keys.load();
+        let nonce = match self.next_nonce() {
+            Ok(nonce) => nonce,
+            Err(error) => {
+                let error = error.into();
+                self.fail(&error).await;
+                return Err(error);
+            }
+        };
+        let aad = self.message_aad(flag.into(), status_code, flags);
+        let ctx = PacketContext::new(keys.sent_by(self.is_client()))
+            .compression(self.compression())
+            .aad(&aad);
+        let mut oed = OED::with_context(&ctx);
+        oed.set_nonce(nonce).from_bytes(data)?;
+        let leading = OSC::from_u32(flag).encode()?;
+        let trailing = self.trailer(status_code, flags)?;
+        let written = oed.write_between(socket, &leading, &trailing).await;
+        if let Err(error) = written {
+            self.fail(&error).await;
+            return Err(error);
+        }
+
+        let counters = &self.counters;
+        counters.bytes_sent.fetch_add(size, Ordering::Relaxed);
+        counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Mark the channel as closed, returning whether this call closed it.
+    ///
+    /// Hooks run on the first call only, with the reason given to it.
+    fn mark_closed(&self, reason: CloseReason) -> bool {
+        let closed = self.closed.send_if_modified(|state| match state {
+            Some(_) => false,
+            None => {
+                *state = Some(reason.clone());
+                true
+            }
+        });
+        if closed {
+            let hooks = std::mem::take(&mut *self.hooks.lock().unwrap());
+            for hook in hooks {
+                hook(reason.clone());
+            }
+        }
+        closed
+    }
+
+    async fn close(&self, reason: CloseReason) -> Result<()> {
+        if self.mark_closed(reason) {
+            self.socket.close().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Close the channel after `error` broke the connection, ignoring errors from closing it.
+    async fn fail(&self, error: &anyhow::Error) {
+        let _ = self
+            .close(CloseReason::Error(Exception::from_error(error)))
+            .await;
+    }
+
+    fn on_close(&self, hook: CloseHook) {
+        let mut hooks = self.hooks.lock().unwrap();
+        match self.close_reason() {
+            Some(reason) => {
+                drop(hooks);
+                hook(reason);
+            }
+            None => hooks.push(hook),
+        }
+    }
+
+    #[inline]
+    fn close_reason(&self) -> Option<CloseReason> {
+        self.closed.borrow().clone()
+    }
+
+    #[inline]
+    fn closed(&self) -> bool {
+        self.closed.borrow().is_some()
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.mark_closed(CloseReason::LocalClose);
+    }
+}
+
+impl Session {
+    pub fn new(socket: Socket) -> Result<Self> {
+        SessionBuilder::new().build(socket)
+    }
+
     pub fn new_with_header(header: String, socket: Socket) -> Result<Self> {
-        let (private_key, public_key) = generate_key_pair();
-        Ok(Self {
-            header,
-            private_key: Some(private_key),
-            public_key,
-            aes_key: Default::default(),
-            request_time: Local::now(),
-            request: Default::default(),
-            socket: Arc::new(socket),
-            closed: ArcSwap::new(Arc::new(false)),
-            callback: Arc::new(None),
-        })
+        SessionBuilder::new().header(&header).build(socket)
     }
 
     #[inline]
     async fn first_hand(&mut self) -> Result<()> {
+        self.channel.sequence.client.store(true, Ordering::Relaxed);
         let socket = Arc::clone(&self.socket);
-        let header = self.header.as_bytes();
+        let header = self.header()?.as_bytes();
         #[cfg(feature = "perf")]
         let now = tokio::time::Instant::now();
+        let (mut client_preamble, mut server_preamble) = (Vec::new(), Vec::new());
+        if self.local_version > 0 {
+            client_preamble = preamble(self.local_version, self.advertised(), self.kex_algorithm);
+            socket.send(&client_preamble).await?;
+        }
         socket.send(&length(header)?).await?;
         socket.send(header).await?;
         #[cfg(feature = "perf")]
         println!("发送头时长: {}μs", now.elapsed().as_micros().to_string());
+        if self.local_version > 0 {
+            let magic = socket.recv(PREAMBLE_MAGIC.len());
+            let magic = match self.preamble_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, magic).await.map_err(|_| {
+                    Exception::Unsupported {
+                        feature: PREAMBLE_FEATURE.to_string(),
+                    }
+                })?,
+                None => magic.await,
+            }
+            .map_err(reading("preamble"))?;
+            if magic[..] != PREAMBLE_MAGIC {
+                return Err(anyhow!("Server did not answer the protocol preamble"));
+            }
+            let version = socket.recv_u32().await.map_err(reading("preamble"))?;
+            let capabilities = socket.recv_u32().await.map_err(reading("preamble"))?;
+            server_preamble = received_preamble(version, capabilities);
+            self.negotiate(version, capabilities)?;
+        } else {
+            self.check_kex(KexAlgorithm::X25519)?;
+        }
 
-        let public_key = UnparsedPublicKey::new(&X25519, self.public_key.as_ref().to_vec());
+        let public_key = self
+            .kex_algorithm
+            .public_key(self.public_key.as_ref().to_vec());
         let mut oke = OKE::new(self.private_key.take(), public_key);
-        oke.from_stream_with_salt(&socket).await?;
-        self.aes_key = oke.get_aes_key();
-        oke.to_stream(&socket).await?;
+        oke.set_protocol_version(self.protocol_version)
+            .from_stream_with_salt(&socket)
+            .await?;
+        self.keys.store(Arc::new(oke.get_session_keys()));
+        self.peer_public_key = oke.get_remote_public_key().map(<[u8]>::to_vec);
+        oke.write_to(&socket).await?;
+        let transcript = identity::transcript(
+            self.protocol_version,
+            &client_preamble,
+            &server_preamble,
+            self.public_key.as_ref(),
+            oke.get_remote_public_key().unwrap_or_default(),
+            oke.get_salt(),
+        );
+        self.salt = Some(oke.get_salt().to_vec());
+        self.authenticate(&transcript).await?;
+        self.send_headers(&self.metadata).await
+    }
+
+    /// Send the encrypted header section following a request, when the peer expects one.
+    pub(crate) async fn send_headers(&self, headers: &[(String, String)]) -> Result<()> {
+        if !self.capabilities.contains(Capabilities::HEADERS) {
+            if headers.is_empty() {
+                return Ok(());
+            }
+            return Err(Exception::Unsupported {
+                feature: "request headers".to_string(),
+            }
+            .into());
+        }
+        let section = encode_headers(
+            headers
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        )?;
+        self.send(section).await
+    }
+
+    /// Receive the header section following the request, peers that don't send one
+    /// negotiate no [`Capabilities::HEADERS`].
+    async fn read_headers(&mut self) -> Result<()> {
+        if !self.capabilities.contains(Capabilities::HEADERS) {
+            return Ok(());
+        }
+        // The limit applies to the encrypted payload, so larger sections are refused before
+        // being read and decrypted.
+        let max_payload = self.max_payload;
+        self.max_payload = Some(MAX_HEADERS_SIZE);
+        self.max_message = Some(MAX_HEADERS_SIZE);
+        let section = self.recv().await;
+        self.max_payload = max_payload;
+        self.max_message = None;
+
+        let section = section
+            .map_err(|error| match error.downcast::<Exception>() {
+                Ok(Exception::PayloadTooLarge { declared, limit }) => Exception::HeadersTooLarge {
+                    size: declared,
+                    limit,
+                }
+                .into(),
+                Ok(exception) => exception.into(),
+                Err(error) => error,
+            })?
+            .content;
+        self.request.extend_headers(parse_headers(&section)?);
         Ok(())
     }
 
@@ -97,13 +1630,36 @@ impl Session {
             "开始入站时长: {}μs",
             now.elapsed().as_micros().to_string().bright_magenta()
         );
-        let len_header = socket.recv_usize().await?;
+        let prefix = socket
+            .recv(PREAMBLE_MAGIC.len())
+            .await
+            .map_err(reading("request header"))?;
+        let prefix: [u8; 4] = prefix[..].try_into()?;
+        let (mut client_preamble, mut server_preamble) = (Vec::new(), Vec::new());
+        let len_header = if prefix == PREAMBLE_MAGIC && self.local_version > 0 {
+            let version = socket.recv_u32().await.map_err(reading("preamble"))?;
+            let capabilities = socket.recv_u32().await.map_err(reading("preamble"))?;
+            client_preamble = received_preamble(version, capabilities);
+            server_preamble = preamble(self.local_version, self.advertised(), self.kex_algorithm);
+            socket.send(&server_preamble).await?;
+            self.negotiate(version, capabilities)?;
+            socket
+                .recv_usize()
+                .await
+                .map_err(reading("request header"))?
+        } else {
+            self.check_kex(KexAlgorithm::X25519)?;
+            u32::from_be_bytes(prefix) as usize
+        };
         #[cfg(feature = "perf")]
         println!(
             "捕获头长度时长: {}μs",
             now.elapsed().as_micros().to_string().bright_magenta()
         );
-        let header = socket.recv_str(len_header).await?;
+        let header = socket
+            .recv_str(len_header)
+            .await
+            .map_err(reading("request header"))?;
         #[cfg(feature = "perf")]
         println!(
             "入站时长: {}μs",
@@ -114,8 +1670,12 @@ impl Session {
 
         #[cfg(feature = "perf")]
         let now = std::time::Instant::now();
-        let public_key = UnparsedPublicKey::new(&X25519, self.public_key.as_ref().to_vec());
-        let mut oke = OKE::new(self.private_key.take(), public_key);
+        let public_key = self
+            .kex_algorithm
+            .public_key(self.public_key.as_ref().to_vec());
+        let salt = self.salt_source.generate(self.salt_len);
+        let mut oke = OKE::new(self.private_key.take(), public_key).with_salt(salt)?;
+        oke.set_protocol_version(self.protocol_version);
         oke.to_stream_with_salt(&socket).await?;
         oke.from_stream(&socket).await?;
         #[cfg(feature = "perf")]
@@ -125,35 +1685,781 @@ impl Session {
         );
 
         request.aes_key = Some(oke.get_aes_key());
-        self.aes_key = oke.get_aes_key();
+        self.keys.store(Arc::new(oke.get_session_keys()));
+        self.peer_public_key = oke.get_remote_public_key().map(<[u8]>::to_vec);
 
         self.request = request;
         self.header = header;
-        Ok(())
+        let transcript = identity::transcript(
+            self.protocol_version,
+            &client_preamble,
+            &server_preamble,
+            oke.get_remote_public_key().unwrap_or_default(),
+            self.public_key.as_ref(),
+            oke.get_salt(),
+        );
+        self.salt = Some(oke.get_salt().to_vec());
+        self.authenticate(&transcript).await
     }
 
-    pub async fn handshake(&mut self, flag: u8) -> Result<()> {
-        match flag {
-            0 => self.first_hand().await?,
-            1 => self.second_hand().await?,
-            _ => return Err(anyhow!("Unknown handshake flag")),
+    /// Sign the handshake of `transcript` and check the signature of the peer, as negotiated
+    /// with [`Capabilities::IDENTITY`] and [`Capabilities::CLIENT_IDENTITY`].
+    ///
+    /// Both are sent encrypted, the server signing first.
+    async fn authenticate(&mut self, transcript: &[u8; 32]) -> Result<()> {
+        let client = self.channel.is_client();
+        if self.capabilities.contains(Capabilities::IDENTITY) {
+            if client {
+                self.peer_identity = Some(self.receive_identity(transcript, true).await?);
+            } else if let Some(key) = &self.identity {
+                self.send_identity(key, transcript, true).await?;
+                self.signed = true;
+            }
+        }
+        if self.capabilities.contains(Capabilities::CLIENT_IDENTITY) {
+            if !client {
+                self.peer_identity = Some(self.receive_identity(transcript, false).await?);
+            } else if let Some(key) = &self.identity {
+                self.send_identity(key, transcript, false).await?;
+                self.signed = true;
+            }
+        }
+
+        // Peers that were required to sign but didn't advertise it.
+        let required = if client {
+            !self.trusted_identities.is_empty()
+        } else {
+            self.identity_verifier.is_some()
         };
+        if required && self.peer_identity.is_none() {
+            return Err(Exception::Unauthenticated.into());
+        }
+        if client && !self.pinned_keys.is_empty() {
+            let presented = self.peer_identity.as_ref().map(|key| identity::fingerprint(key));
+            if !presented.is_some_and(|presented| self.pinned_keys.contains(&presented)) {
+                return Err(Exception::KeyPinMismatch { presented }.into());
+            }
+        }
         Ok(())
     }
 
-    pub async fn send(&self, data: Vec<u8>) -> Result<()> {
-        if self.closed().await {
-            return Err(Exception::ConnectionClosed.into());
-        }
-
-        let socket = &self.socket;
-
-        OSC::from_u32(0).to_stream(socket).await?;
-        OED::new(&self.aes_key)
-            .from_bytes(data)?
-            .to_stream(socket)
-            .await?;
-        Ok(())
+    /// Send the public key of `key` and its signature of the handshake.
+    async fn send_identity(
+        &self,
+        key: &IdentityKey,
+        transcript: &[u8; 32],
+        server: bool,
+    ) -> Result<()> {
+        let public_key = key.public_key();
+        let signature = key.sign(transcript, server);
+        let mut message = length(&public_key)?.to_vec();
+        message.extend_from_slice(&public_key);
+        message.extend_from_slice(&length(&signature)?);
+        message.extend_from_slice(&signature);
+        self.send(message).await
+    }
+
+    /// Receive the identity of the peer and check that it signed the handshake.
+    async fn receive_identity(&self, transcript: &[u8; 32], server: bool) -> Result<[u8; 32]> {
+        let message = self.recv().await?.content;
+        let parts = split_material(&message)?;
+        let [public_key, signature] = parts[..] else {
+            return Err(anyhow!("Malformed identity"));
+        };
+        let public_key: [u8; 32] = public_key
+            .try_into()
+            .map_err(|_| anyhow!("Malformed identity"))?;
+        identity::verify(&public_key, transcript, server, signature)?;
+
+        let fingerprint = identity::fingerprint(&public_key);
+        let trusted = if server {
+            self.trusted_identities.is_empty() || self.trusted_identities.contains(&fingerprint)
+        } else {
+            self.identity_verifier
+                .as_ref()
+                .is_some_and(|verifier| verifier.accepts(&public_key))
+        };
+        if !trusted {
+            return Err(Exception::UntrustedIdentity { fingerprint }.into());
+        }
+        Ok(public_key)
+    }
+
+    /// Another session on the same connection, sharing its keys and negotiated features.
+    ///
+    /// The server keeps one while a handler owns the session, to answer the request and
+    /// wait for the next one with [`Session::next_request`].
+    pub(crate) fn fork(&self) -> Session {
+        Session {
+            header: String::new(),
+            metadata: Vec::new(),
+            private_key: None,
+            public_key: self.public_key.clone(),
+            kex_algorithm: self.kex_algorithm,
+            peer_public_key: self.peer_public_key.clone(),
+            pinned_keys: self.pinned_keys.clone(),
+            identity: self.identity.clone(),
+            trusted_identities: self.trusted_identities.clone(),
+            identity_verifier: self.identity_verifier.clone(),
+            peer_identity: self.peer_identity,
+            signed: self.signed,
+            salt_len: self.salt_len,
+            salt_source: self.salt_source.clone(),
+            salt: self.salt.clone(),
+            keys: Arc::clone(&self.keys),
+            request_time: Local::now(),
+            request: Default::default(),
+            socket: Arc::clone(&self.socket),
+            callback: Arc::new(None),
+            recv_timeout: self.recv_timeout,
+            idle_timeout: self.idle_timeout,
+            max_payload: self.max_payload,
+            preamble_timeout: self.preamble_timeout,
+            max_message: None,
+            local_version: self.local_version,
+            local_capabilities: self.local_capabilities,
+            protocol_version: self.protocol_version,
+            capabilities: self.capabilities,
+            channel: Arc::clone(&self.channel),
+        }
+    }
+
+    /// Answer the handshake of a client and receive its request, except for its body, see
+    /// [`Session::read_body`].
+    pub(crate) async fn receive_request(&mut self) -> Result<()> {
+        self.second_hand().await?;
+        self.request.received_at = Some(std::time::Instant::now());
+        self.read_headers().await
+    }
+
+    /// Wait for the peer to send another request, `None` once it closes the connection.
+    ///
+    /// The body of the request is left to [`Session::read_body`].
+    pub(crate) async fn next_request(&self) -> Result<Option<Session>> {
+        let response = match self.recv().await {
+            Ok(response) => response,
+            Err(error) => match error.downcast_ref::<Exception>() {
+                Some(Exception::ConnectionClosed) => return Ok(None),
+                _ => return Err(error),
+            },
+        };
+        if response.flag != SessionFlag::Request {
+            return Err(anyhow!("Expected a request, got a {:?} message", response.flag));
+        }
+        let header = String::from_utf8(response.content.into())?;
+        let mut request = OblivionRequest::new(&header)?;
+        request.set_remote_peer(&self.socket.peer_addr().await?);
+        request.aes_key = Some(self.keys.load().client_to_server);
+        request.received_at = Some(std::time::Instant::now());
+
+        let mut session = self.fork();
+        session.request = request;
+        session.header = header;
+        session.read_headers().await?;
+        Ok(Some(session))
+    }
+
+    /// Receive the body announced by the [`CONTENT_LENGTH`] of the request, unless it is over
+    /// `limit` bytes.
+    ///
+    /// Returns the announced size of a body over the limit, which is left unread. A body
+    /// growing past its announced size, in one frame or over several, fails with
+    /// [`Exception::PayloadTooLarge`] and closes the session.
+    pub(crate) async fn read_body(&mut self, limit: Option<usize>) -> Result<Option<usize>> {
+        let Some(length) = self.request.get_header(CONTENT_LENGTH) else {
+            return Ok(None);
+        };
+        let length: usize = length
+            .parse()
+            .map_err(|_| Exception::InvalidHeader(self.header.clone()))?;
+        if limit.is_some_and(|limit| length > limit) {
+            return Ok(Some(length));
+        }
+
+        let max_payload = self.max_payload;
+        self.max_payload = Some(max_payload.map_or(length, |size| size.min(length)));
+        self.max_message = Some(length);
+        let body = self.recv().await;
+        self.max_payload = max_payload;
+        self.max_message = None;
+
+        let body = body?.content;
+        if body.len() != length {
+            return Err(anyhow!(
+                "Request body is {} bytes, {} bytes were announced",
+                body.len(),
+                length
+            ));
+        }
+        self.request.body = body.into();
+        Ok(None)
+    }
+
+    /// Settle on the highest version and the capabilities both sides support, as long as the
+    /// peer exchanges keys the same way.
+    fn negotiate(&mut self, version: u32, capabilities: u32) -> Result<(), Exception> {
+        self.check_kex(KexAlgorithm::from_id((capabilities >> KEX_SHIFT) as u8)?)?;
+        self.protocol_version = self.local_version.min(version);
+        self.capabilities = self.advertised().intersection(Capabilities::from_bits(
+            capabilities & !(u32::MAX << KEX_SHIFT),
+        ));
+        self.channel
+            .negotiated
+            .store(self.capabilities.bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Capabilities advertised to the peer, leaving out the identities this side doesn't
+    /// sign or verify.
+    fn advertised(&self) -> Capabilities {
+        let client = self.channel.is_client();
+        let mut capabilities = self.local_capabilities;
+        if !client && self.identity.is_none() {
+            capabilities = capabilities.difference(Capabilities::IDENTITY);
+        }
+        let client_identity = if client {
+            self.identity.is_some()
+        } else {
+            self.identity_verifier.is_some()
+        };
+        if !client_identity {
+            capabilities = capabilities.difference(Capabilities::CLIENT_IDENTITY);
+        }
+        capabilities
+    }
+
+    /// Fail with [`Exception::KexMismatch`] unless the peer uses the same key exchange.
+    fn check_kex(&self, peer: KexAlgorithm) -> Result<(), Exception> {
+        if peer != self.kex_algorithm {
+            return Err(Exception::KexMismatch {
+                local: self.kex_algorithm,
+                peer,
+            });
+        }
+        Ok(())
+    }
+
+    /// Key exchange algorithm of the session, see [`SessionBuilder::kex_algorithm`].
+    pub fn kex_algorithm(&self) -> KexAlgorithm {
+        self.kex_algorithm
+    }
+
+    /// Exchange keys with `algorithm` instead, generating a new key pair.
+    ///
+    /// Fails with [`Exception::Unsupported`] once the handshake was performed.
+    pub fn set_kex_algorithm(&mut self, algorithm: KexAlgorithm) -> Result<(), Exception> {
+        if self.private_key.is_none() {
+            return Err(Exception::Unsupported {
+                feature: "changing the key exchange after the handshake".to_string(),
+            });
+        }
+        let (private_key, public_key) = algorithm.generate_key_pair();
+        self.private_key = Some(private_key);
+        self.public_key = public_key;
+        self.kex_algorithm = algorithm;
+        Ok(())
+    }
+
+    /// Whether `response` is the notification written by [`Session::close`].
+    fn is_close_notify(&self, response: &Response) -> bool {
+        match response.flag {
+            SessionFlag::CloseNotify => true,
+            SessionFlag::CloseAfter => {
+                !self.capabilities.contains(Capabilities::CLOSE_NOTIFY)
+                    && response.content.is_empty()
+                    && response.status_code == 200
+            }
+            _ => false,
+        }
+    }
+
+    /// Flag of the notification written by [`Session::close`].
+    fn close_notify_flag(&self) -> SessionFlag {
+        match self.capabilities.contains(Capabilities::CLOSE_NOTIFY) {
+            true => SessionFlag::CloseNotify,
+            false => SessionFlag::CloseAfter,
+        }
+    }
+
+    /// Fail with [`Exception::Unsupported`] unless the peer negotiated `capability`.
+    fn require(&self, capability: Capabilities, feature: &str) -> Result<(), Exception> {
+        if !self.capabilities.contains(capability) {
+            return Err(Exception::Unsupported {
+                feature: feature.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Protocol version negotiated during the handshake.
+    ///
+    /// Peers that predate version negotiation, or sessions that were not established yet,
+    /// report version `0`, in which case no [`Capabilities`] are available.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::{Capabilities, Session, SessionBuilder, PROTOCOL_VERSION};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let connect = || {
+    /// #     let (client, server) = Socket::pair();
+    /// #     tokio::spawn(async move {
+    /// #         let mut session = Session::new(server).unwrap();
+    /// #         session.handshake(1).await.unwrap();
+    /// #         let version = session.protocol_version().to_string();
+    /// #         session.send(version.into_bytes()).await.unwrap();
+    /// #     });
+    /// #     client
+    /// # };
+    /// let session = SessionBuilder::new()
+    ///     .header("CONNECT / Oblivion/2.0")
+    ///     .establish(connect(), 0)
+    ///     .await?;
+    /// assert_eq!(session.protocol_version(), PROTOCOL_VERSION);
+    /// // Neither side has an identity key.
+    /// let identities = Capabilities::IDENTITY | Capabilities::CLIENT_IDENTITY;
+    /// assert_eq!(session.capabilities(), Capabilities::all().difference(identities));
+    /// assert_eq!(session.recv().await?.text()?, PROTOCOL_VERSION.to_string());
+    ///
+    /// // Version 1 clients use the same key in both directions.
+    /// let session = SessionBuilder::new()
+    ///     .header("CONNECT / Oblivion/2.0")
+    ///     .protocol_version(1)
+    ///     .establish(connect(), 0)
+    ///     .await?;
+    /// assert_eq!(session.protocol_version(), 1);
+    /// assert_eq!(session.recv().await?.text()?, "1");
+    ///
+    /// // A client speaking the original protocol is still understood.
+    /// let session = SessionBuilder::new()
+    ///     .header("CONNECT / Oblivion/2.0")
+    ///     .protocol_version(0)
+    ///     .establish(connect(), 0)
+    ///     .await?;
+    /// assert_eq!(session.protocol_version(), 0);
+    /// assert_eq!(session.capabilities(), Capabilities::empty());
+    /// assert!(!session.replay_protected());
+    /// assert_eq!(session.recv().await?.text()?, "0");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol_version
+    }
+
+    /// Raw public key the peer presented during the handshake.
+    #[inline]
+    pub fn peer_public_key(&self) -> Option<&[u8]> {
+        self.peer_public_key.as_deref()
+    }
+
+    /// SHA-256 fingerprint of the key the peer presented during the handshake.
+    ///
+    /// Compare it against a stored value to pin the peer before sending anything sensitive,
+    /// `None` until the handshake completed. Rekeying doesn't change the fingerprint.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     let fingerprint = session.peer_key_fingerprint().unwrap();
+    /// #     session.send(fingerprint.to_vec()).await.unwrap();
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// assert_eq!(session.peer_key_fingerprint(), None);
+    /// session.handshake(0).await?;
+    ///
+    /// assert!(session.peer_key_fingerprint().is_some());
+    /// // The server fingerprinted the key this side presented.
+    /// let ours: [u8; 32] = session.recv().await?.content[..].try_into()?;
+    /// assert_ne!(Some(ours), session.peer_key_fingerprint());
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn peer_key_fingerprint(&self) -> Option<[u8; 32]> {
+        let key = self.peer_public_key.as_ref()?;
+        Some(Sha256::digest(key).into())
+    }
+
+    /// Salt the server generated for the handshake, `None` until it was performed.
+    ///
+    /// Rekeying doesn't change it, see [`SessionBuilder::salt_len`].
+    pub fn salt(&self) -> Option<&[u8]> {
+        self.salt.as_deref()
+    }
+
+    /// Which sides proved their identity during the handshake, see
+    /// [`SessionBuilder::identity`].
+    ///
+    /// The peer only counts as authenticated if it signed with an identity this side trusts:
+    /// one of [`SessionBuilder::trust_identity`] for clients, one that
+    /// [`SessionBuilder::verify_client_identity`] accepted for servers. Clients without
+    /// trusted identities accept any signature and report an anonymous server. This side
+    /// counts as authenticated once it signed the handshake.
+    ///
+    /// Refuse anonymous peers by checking it before trusting what they send.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::{HandshakeMode, SessionBuilder};
+    /// # use oblivion::utils::gear::Socket;
+    /// # use oblivion::utils::identity::IdentityKey;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let client_key = IdentityKey::from_seed(&[2; 32])?;
+    /// let allowed = client_key.public_key();
+    ///
+    /// // The server verifies clients without an identity of its own.
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(
+    ///     SessionBuilder::new()
+    ///         .verify_client_identity(move |public_key| *public_key == allowed)
+    ///         .establish(server, 1),
+    /// );
+    /// let session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .identity(client_key)
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert_eq!(session.handshake_mode(), HandshakeMode::ClientAuthenticated);
+    /// assert_eq!(server.await??.handshake_mode(), HandshakeMode::ClientAuthenticated);
+    ///
+    /// // A client trusting no identity in particular can't tell the server from an impostor.
+    /// let (client, server) = Socket::pair();
+    /// let server_key = IdentityKey::from_seed(&[1; 32])?;
+    /// let server = tokio::spawn(SessionBuilder::new().identity(server_key).establish(server, 1));
+    /// let session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert_eq!(session.handshake_mode(), HandshakeMode::Anonymous);
+    /// assert!(session.peer_identity().is_some());
+    /// assert_eq!(server.await??.handshake_mode(), HandshakeMode::ServerAuthenticated);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn handshake_mode(&self) -> HandshakeMode {
+        let peer_trusted =
+            self.peer_identity
+                .is_some_and(|public_key| match self.channel.is_client() {
+                    true => self
+                        .trusted_identities
+                        .contains(&identity::fingerprint(&public_key)),
+                    false => self.identity_verifier.is_some(),
+                });
+        let (server, client) = match self.channel.is_client() {
+            true => (peer_trusted, self.signed),
+            false => (self.signed, peer_trusted),
+        };
+        match (server, client) {
+            (true, true) => HandshakeMode::MutuallyAuthenticated,
+            (true, false) => HandshakeMode::ServerAuthenticated,
+            (false, true) => HandshakeMode::ClientAuthenticated,
+            (false, false) => HandshakeMode::Anonymous,
+        }
+    }
+
+    /// Identity public key the peer signed the handshake with, `None` for anonymous peers.
+    pub fn peer_identity(&self) -> Option<[u8; 32]> {
+        self.peer_identity
+    }
+
+    /// Capabilities supported by both sides, negotiated during the handshake.
+    #[inline]
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Whether messages replayed by a third party are rejected, once both sides negotiated
+    /// [`Capabilities::SEQUENCED_NONCES`].
+    ///
+    /// Sessions with older peers fall back to random nonces, a recorded message replayed to
+    /// them is delivered again. Applications sending anything that must not happen twice
+    /// should refuse such sessions.
+    #[inline]
+    pub fn replay_protected(&self) -> bool {
+        self.capabilities.contains(Capabilities::SEQUENCED_NONCES)
+    }
+
+    /// Counters of the nonce of the next message sent and of the next one expected from the
+    /// peer, `None` unless [`Capabilities::SEQUENCED_NONCES`] was negotiated.
+    pub fn nonce_counters(&self) -> Option<(u64, u64)> {
+        if !self.replay_protected() {
+            return None;
+        }
+        let sequence = &self.channel.sequence;
+        Some((
+            sequence.sent.load(Ordering::Relaxed),
+            sequence.received.load(Ordering::Relaxed),
+        ))
+    }
+
+    pub async fn handshake(&mut self, flag: u8) -> Result<()> {
+        match flag {
+            0 => self.first_hand().await?,
+            1 => {
+                self.receive_request().await?;
+                self.read_body(None).await?;
+            }
+            _ => return Err(anyhow!("Unknown handshake flag")),
+        };
+        Ok(())
+    }
+
+    /// Send a message flagged as [`SessionFlag::Data`] with status code `200`.
+    pub async fn send(&self, data: Vec<u8>) -> Result<()> {
+        self.send_with_flag(data, 200, SessionFlag::Data).await
+    }
+
+    /// Send a message with an explicit status code and leading flag.
+    ///
+    /// The flag is written before the encrypted data and the status code after it,
+    /// [`SessionFlag::CloseAfter`] tells the peer to close the connection once it has received this message.
+    /// The whole message goes out in a single write, unless the session is
+    /// [corked](Session::corked).
+    pub async fn send_with_flag(
+        &self,
+        data: Vec<u8>,
+        status_code: u32,
+        flag: SessionFlag,
+    ) -> Result<()> {
+        self.send_message(data, status_code, flag, MessageFlags::empty())
+            .await
+    }
+
+    /// Send a message flagged as [`SessionFlag::Data`] carrying `flags` after its status code,
+    /// received as [`Response::flags`].
+    ///
+    /// Fails with [`Exception::Unsupported`] unless the peer negotiated
+    /// [`Capabilities::MESSAGE_FLAGS`], peers without it only read the status code.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::packet::MessageFlags;
+    /// # use oblivion::models::session::{Capabilities, Session, SessionBuilder};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let mut session = Session::new(server)?;
+    ///     session.handshake(1).await?;
+    ///     session.recv().await
+    /// });
+    /// let session = SessionBuilder::new()
+    ///     .header("GET /logs Oblivion/2.0")
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// let flags = MessageFlags::COMPRESSED | MessageFlags::FINAL_FRAME;
+    /// session.send_with_flags(b"...".to_vec(), 200, flags).await?;
+    /// assert_eq!(server.await??.flags, flags);
+    ///
+    /// // Servers predating message flags don't advertise them and keep the legacy format.
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let session = SessionBuilder::new()
+    ///         .capabilities(Capabilities::all().difference(Capabilities::MESSAGE_FLAGS))
+    ///         .establish(server, 1)
+    ///         .await?;
+    ///     let response = session.recv().await?;
+    ///     session.send_with_flag(response.content.to_vec(), 201, response.flag).await?;
+    ///     anyhow::Ok(response)
+    /// });
+    /// let session = SessionBuilder::new()
+    ///     .header("GET /logs Oblivion/2.0")
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// assert!(!session.capabilities().contains(Capabilities::MESSAGE_FLAGS));
+    ///
+    /// let error = session.send_with_flags(Vec::new(), 200, flags).await.unwrap_err();
+    /// assert!(matches!(Exception::from_error(&error), Exception::Unsupported { .. }));
+    /// session.send(b"ping".to_vec()).await?;
+    /// let echo = session.recv().await?;
+    /// assert_eq!((echo.text()?, echo.status_code), ("ping", 201));
+    /// assert!(echo.flags.is_empty());
+    /// assert!(server.await??.flags.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_with_flags(
+        &self,
+        data: Vec<u8>,
+        status_code: u32,
+        flags: MessageFlags,
+    ) -> Result<()> {
+        self.require(Capabilities::MESSAGE_FLAGS, "message flags")?;
+        self.send_message(data, status_code, SessionFlag::Data, flags)
+            .await
+    }
+
+    async fn send_message(
+        &self,
+        data: Vec<u8>,
+        status_code: u32,
+        flag: SessionFlag,
+        flags: MessageFlags,
+    ) -> Result<()> {
+        if self.closed().await {
+            return Err(Exception::ConnectionClosed.into());
+        }
+        if self.idle_for() >= self.idle_timeout.unwrap_or(Duration::MAX) {
+            self.channel
+                .close(CloseReason::Error(Exception::Timeout {
+                    phase: TimeoutPhase::Idle,
+                }))
+                .await?;
+            return Err(Exception::ConnectionClosed.into());
+        }
+
+        let _guard = self.channel.send_lock.lock().await;
+        self.channel
+            .write_message_with_flags(data, status_code, flag, flags)
+            .await
+    }
+
+    /// Batch the messages sent until the returned guard is dropped, to send them in one write,
+    /// see [`Socket::corked`].
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::{Session, SessionBuilder};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let mut session = Session::new(server)?;
+    ///     session.handshake(1).await?;
+    ///     let mut rows = Vec::new();
+    ///     for _ in 0..3 {
+    ///         rows.push(session.recv().await?.text()?.to_string());
+    ///     }
+    ///     anyhow::Ok(rows)
+    /// });
+    ///
+    /// let session = SessionBuilder::new()
+    ///     .header("GET /rows Oblivion/2.0")
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// let cork = session.corked();
+    /// for row in ["a", "b", "c"] {
+    ///     session.send(row.into()).await?;
+    /// }
+    /// cork.uncork().await?;
+    /// assert_eq!(server.await??, ["a", "b", "c"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn corked(&self) -> Corked {
+        self.socket.corked()
+    }
+
+    /// Send the messages batched while [corked](Session::corked) right away.
+    pub async fn flush(&self) -> Result<()> {
+        self.socket.flush().await
+    }
+
+    /// Limit the throughput of the session, see [`Socket::with_rate_limit`].
+    pub fn set_rate_limit(&self, read: Option<RateLimit>, write: Option<RateLimit>) {
+        self.socket.set_rate_limit(read, write)
+    }
+
+    /// Time since bytes were last sent or received.
+    #[inline]
+    fn idle_for(&self) -> Duration {
+        self.channel.socket.idle_for()
+    }
+
+    /// Resolve once the session has been idle for longer than its idle timeout.
+    async fn idle_expired(&self) {
+        let Some(timeout) = self.idle_timeout else {
+            return std::future::pending().await;
+        };
+        loop {
+            let deadline = self.stats().last_activity + timeout;
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+
+    /// Read a whole message with the current key.
+    ///
+    /// Bytes are buffered on the socket until a whole message arrived, so the returned
+    /// future can be dropped at any point without losing data.
+    async fn read_message(&self) -> Result<Response> {
+        let read = self
+            .socket
+            .recv_frame(|inbox| self.take_message(inbox))
+            .await
+            .map_err(reading("message"));
+        let response = match read {
+            Ok(message) => message,
+            Err(error) => {
+                // A failed read leaves the stream at an unknown position, it can't be resumed.
+                self.channel.fail(&error).await;
+                return Err(error);
+            }
+        };
+
+        let counters = &self.channel.counters;
+        counters
+            .bytes_received
+            .fetch_add(response.content.len() as u64, Ordering::Relaxed);
+        counters.packets_received.fetch_add(1, Ordering::Relaxed);
+        counters.last_received.store(self.channel.uptime(), Ordering::Relaxed);
+        Ok(response)
+    }
+
+    /// Remove the first message from `inbox` once it was received as a whole.
+    ///
+    /// Its content is decrypted within the bytes it was received in.
+    fn take_message(&self, inbox: &mut BytesMut) -> Result<Framing<Response>> {
+        let keys = self.keys.load();
+        let ctx = PacketContext::new(keys.sent_by(!self.channel.is_client()))
+            .limit(self.max_payload)
+            .max_frame_size(Some(self.socket.max_frame_size()))
+            .compression(self.channel.compression())
+            .message_flags(self.channel.negotiated(Capabilities::MESSAGE_FLAGS));
+        let oed_size =
+            match OED::with_context(&ctx).frame_size(inbox.get(4..).unwrap_or_default())? {
+                Framing::Complete(size) => size,
+                Framing::Incomplete { needed } => {
+                    return Ok(Framing::Incomplete { needed: 4 + needed })
+                }
+            };
+        let size = 4 + oed_size + self.channel.trailer_len();
+        if inbox.len() < size {
+            return Ok(Framing::Incomplete { needed: size });
+        }
+
+        let mut frame = inbox.split_to(size);
+        let flag = frame.get_u32();
+        let osc = OSC::decode(&frame[oed_size..], &ctx)?;
+        let flags = osc.flags.unwrap_or_default();
+        frame.truncate(oed_size);
+        let aad = self.channel.message_aad(flag, osc.status_code, flags);
+        let ctx = ctx.aad(&aad);
+        let mut oed = OED::decode_owned(frame, &ctx)?;
+        self.channel.check_nonce(oed.nonce())?;
+        let content = oed.take();
+        let mut response = Response::new(None, content, None, osc.status_code, flag.into());
+        response.flags = flags;
+        Ok(Framing::Complete(response))
+    }
+
+    /// Send a final message flagged as [`SessionFlag::CloseAfter`] and close the local socket afterwards.
+    pub async fn send_and_close(&self, data: Vec<u8>, status_code: u32) -> Result<()> {
+        self.send_with_flag(data, status_code, SessionFlag::CloseAfter)
+            .await?;
+        self.abort().await
     }
 
     pub async fn send_json(&self, json: Value) -> Result<()> {
@@ -165,23 +2471,780 @@ impl Session {
             .await
     }
 
+    /// Receive the next message from the peer.
+    ///
+    /// If a default timeout was configured with [`Session::set_recv_timeout`],
+    /// this behaves like [`Session::recv_timeout`], otherwise it waits indefinitely.
+    ///
+    /// Receiving is cancellation safe: partially received messages are kept on the session,
+    /// so the future may be dropped at any point, for instance when it loses a
+    /// `tokio::select!`, and the next call picks up where it left off.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     session.send(vec![7; 4 * 1024 * 1024]).await.unwrap();
+    /// #     session.send("done".into()).await.unwrap();
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// let mut ticks = tokio::time::interval(Duration::from_micros(50));
+    /// let mut messages = Vec::new();
+    /// while messages.len() < 2 {
+    ///     tokio::select! {
+    ///         response = session.recv() => messages.push(response?.content),
+    ///         _ = ticks.tick() => {}
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(messages[0], vec![7; 4 * 1024 * 1024]);
+    /// assert_eq!(messages[1], &b"done"[..]);
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn recv(&self) -> Result<Response> {
+        match self.recv_timeout {
+            Some(timeout) => self.recv_timeout(timeout).await,
+            None => self.recv_packet().await,
+        }
+    }
+
+    /// Receive the next message from the peer, giving up after `timeout`.
+    ///
+    /// Fails with [`Exception::Timeout`] when nothing arrived in time. The session stays open,
+    /// a message that was only partially received is completed by the next receive.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::exceptions::{Exception, TimeoutPhase};
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     tokio::time::sleep(Duration::from_millis(300)).await;
+    /// #     session.send("late".into()).await.unwrap();
+    /// # });
+    /// let header = "CONNECT / Oblivion/2.0".to_string();
+    /// let mut session = Session::new_with_header(header, client)?;
+    /// session.handshake(0).await?;
+    ///
+    /// let error = session.recv_timeout(Duration::from_millis(100)).await.unwrap_err();
+    /// let timeout = Exception::Timeout { phase: TimeoutPhase::Receive };
+    /// assert_eq!(error.downcast_ref(), Some(&timeout));
+    /// assert!(!session.closed().await);
+    /// assert_eq!(session.recv().await?.text()?, "late");
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn recv_timeout(&self, timeout: Duration) -> Result<Response> {
+        match tokio::time::timeout(timeout, self.recv_packet()).await {
+            Ok(result) => result,
+            Err(_) => Err(Exception::Timeout {
+                phase: TimeoutPhase::Receive,
+            }
+            .into()),
+        }
+    }
+
+    /// Set the default timeout used by [`Session::recv`], `None` disables it.
+    pub fn set_recv_timeout(&mut self, timeout: Option<Duration>) {
+        self.recv_timeout = timeout;
+    }
+
+    /// Close the session once nothing was sent or received for `timeout`, `None` disables it.
+    ///
+    /// The deadline is enforced while waiting in [`Session::recv`] and checked before every
+    /// send, so a stale session fails with [`Exception::ConnectionClosed`] instead of hanging.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     tokio::time::sleep(Duration::from_millis(500)).await;
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// session.set_idle_timeout(Some(Duration::from_millis(100)));
+    ///
+    /// let error = session.recv().await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::ConnectionClosed));
+    /// assert!(session.closed().await);
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    /// Reject messages whose encrypted payload exceeds `size` bytes, `None` disables the limit.
+    /// Defaults to [`MAX_PAYLOAD_SIZE`].
+    ///
+    /// An oversized message fails with [`Exception::PayloadTooLarge`] as soon as its length
+    /// prefixes cross the limit, before buffering the rest of it, and closes the session since
+    /// the remainder of the message is still on the wire. The limit applies to messages split
+    /// across frames flagged with [`SessionFlag::Continue`] as a whole, see
+    /// [`Session::recv_to_file`] to receive larger ones.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{Session, SessionBuilder, SessionFlag};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let mut session = Session::new(server)?;
+    ///     session.handshake(1).await?;
+    ///     session.set_max_payload(Some(1024));
+    ///     session.recv().await
+    /// });
+    /// let session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .establish(client, 0)
+    ///     .await?;
+    ///
+    /// // Every frame is within the limit, the message they form is not.
+    /// for _ in 0..3 {
+    ///     session.send_with_flag(vec![0; 600], 200, SessionFlag::Continue).await?;
+    /// }
+    /// session.send(vec![0; 600]).await?;
+    /// let error = server.await?.unwrap_err();
+    /// assert_eq!(
+    ///     error.downcast_ref(),
+    ///     Some(&Exception::PayloadTooLarge {
+    ///         declared: 1200,
+    ///         limit: 1024
+    ///     })
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_max_payload(&mut self, size: Option<usize>) {
+        self.max_payload = size;
+    }
+
+    /// Compress the messages sent from now on, if the peer negotiated
+    /// [`Capabilities::COMPRESSION`].
+    ///
+    /// Received messages are decompressed whatever this is set to, within its
+    /// [`max_size`](Compression::max_size).
+    pub fn set_compression(&self, compression: Compression) {
+        *self.channel.compression.lock().unwrap() = compression;
+    }
+
+    async fn recv_packet(&self) -> Result<Response> {
         if self.closed().await {
             return Err(Exception::ConnectionClosed.into());
         }
 
-        let socket = &self.socket;
-
-        let flag = OSC::from_stream(socket).await?.status_code;
-        let content = OED::new(&self.aes_key).from_stream(socket).await?.take();
-        let response = Response::new(None, content, None, flag);
+        let response = tokio::select! {
+            response = self.next_message() => response?,
+            _ = self.wait_closed() => {
+                return Err(Exception::ConnectionClosed.into());
+            }
+            _ = self.idle_expired() => {
+                self.channel
+                    .close(CloseReason::Error(Exception::Timeout {
+                        phase: TimeoutPhase::Idle,
+                    }))
+                    .await?;
+                return Err(Exception::ConnectionClosed.into());
+            }
+        };
 
-        if flag == 1 {
-            socket.close().await?;
+        let close_notify = self.is_close_notify(&response);
+        if close_notify || response.flag == SessionFlag::CloseAfter {
+            self.channel.close(CloseReason::RemoteClose).await?;
+            if close_notify {
+                return Err(Exception::ConnectionClosed.into());
+            }
         }
         Ok(response)
     }
 
+    /// Resolve once the session is closed.
+    async fn wait_closed(&self) {
+        let _ = self
+            .channel
+            .closed
+            .subscribe()
+            .wait_for(|reason| reason.is_some())
+            .await;
+    }
+
+    /// Read until a message meant for the caller arrives, handling control messages on the way.
+    ///
+    /// Frames flagged with [`SessionFlag::Continue`] are joined with the frames that follow them.
+    /// The frames received so far are kept on the session, so the future can be dropped
+    /// between frames of a message without losing them.
+    async fn next_message(&self) -> Result<Response> {
+        let mut partial = self.channel.partial.lock().await;
+        loop {
+            let frame = self.next_frame().await?;
+            let response = match partial.take() {
+                Some(mut response) => {
+                    let size = response.content.len() + frame.content.len();
+                    let limit = self.max_message.or(self.max_payload).unwrap_or(usize::MAX);
+                    if size > limit {
+                        let error = Exception::PayloadTooLarge {
+                            declared: size,
+                            limit,
+                        }
+                        .into();
+                        self.channel.fail(&error).await;
+                        return Err(error);
+                    }
+                    let mut content = Vec::from(response.content);
+                    content.extend_from_slice(&frame.content);
+                    response.content = content.into();
+                    response.status_code = frame.status_code;
+                    response.flag = frame.flag;
+                    response
+                }
+                None => frame,
+            };
+            if response.flag != SessionFlag::Continue {
+                return Ok(response);
+            }
+            *partial = Some(response);
+        }
+    }
+
+    /// Read the next frame meant for the caller, messages queued during a rekey come first.
+    async fn next_frame(&self) -> Result<Response> {
+        loop {
+            if self.channel.peer_finished.load(Ordering::Relaxed) {
+                return Err(Exception::EndOfStream.into());
+            }
+            self.finish_control().await?;
+            let pending = self.channel.pending.lock().await.pop_front();
+            let response = match pending {
+                Some(response) => response,
+                None => self.read_message().await?,
+            };
+            // Control messages can't be answered once this side finished sending.
+            let answerable = !self.socket.is_write_shutdown();
+            match response.flag {
+                SessionFlag::Rekey if answerable => self.accept_rekey(&response.content).await?,
+                SessionFlag::Ping if answerable => {
+                    let channel = Arc::clone(&self.channel);
+                    self.control(async move {
+                        let _guard = channel.send_lock.lock().await;
+                        channel
+                            .write_message(Vec::new(), 200, SessionFlag::Pong)
+                            .await
+                    })
+                    .await?
+                }
+                SessionFlag::Rekey | SessionFlag::Ping | SessionFlag::Pong => {}
+                SessionFlag::Finish => self.channel.peer_finished.store(true, Ordering::Relaxed),
+                _ => return Ok(response),
+            }
+        }
+    }
+
+    /// Stream a file to the peer without loading it into memory.
+    ///
+    /// The file is read and encrypted in chunks, each sent as a frame flagged as continued
+    /// except for the last one, so memory usage is bounded by the chunk size and a truncated
+    /// transfer can be told apart from a complete one. [`Session::recv`] reassembles the frames
+    /// into a single response while [`Session::recv_to_file`] writes them out as they arrive.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// let source = std::env::temp_dir().join("oblivion-send-file-source");
+    /// let target = std::env::temp_dir().join("oblivion-send-file-target");
+    /// let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+    /// tokio::fs::write(&source, &data).await?;
+    ///
+    /// # let receiver_target = target.clone();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// let (size, status_code) = session.recv_to_file(&receiver_target).await.unwrap();
+    /// assert_eq!((size, status_code), (200_000, 200));
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// session.send_file(&source, 200).await?;
+    /// # server.await?;
+    ///
+    /// assert_eq!(tokio::fs::read(&target).await?, data);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_file(&self, path: impl AsRef<Path>, status_code: u32) -> Result<()> {
+        self.send_file_with_flag(path, status_code, SessionFlag::Data)
+            .await
+    }
+
+    /// Stream a file to the peer like [`Session::send_file`], flagging its last frame with `flag`.
+    pub(crate) async fn send_file_with_flag(
+        &self,
+        path: impl AsRef<Path>,
+        status_code: u32,
+        flag: SessionFlag,
+    ) -> Result<()> {
+        let file = File::open(path).await?;
+        self.send_open_file_with_flag(file, status_code, flag).await
+    }
+
+    /// Stream the rest of `file` like [`Session::send_file_with_flag`].
+    pub(crate) async fn send_open_file_with_flag(
+        &self,
+        mut file: File,
+        status_code: u32,
+        flag: SessionFlag,
+    ) -> Result<()> {
+        if self.closed().await {
+            return Err(Exception::ConnectionClosed.into());
+        }
+
+        self.require(Capabilities::CONTINUATION, "continued messages")?;
+
+        let _guard = self.channel.send_lock.lock().await;
+
+        let mut chunk = read_chunk(&mut file).await?;
+        loop {
+            let next = read_chunk(&mut file).await?;
+            if next.is_empty() {
+                return self.channel.write_message(chunk, status_code, flag).await;
+            }
+            self.channel
+                .write_message(chunk, status_code, SessionFlag::Continue)
+                .await?;
+            chunk = next;
+        }
+    }
+
+    /// Receive a message into a file as its frames arrive, see [`Session::send_file`].
+    ///
+    /// Returns the number of bytes written and the status code of the message. Unlike
+    /// [`Session::recv`] this is not cancellation safe, frames are consumed as they are written.
+    pub async fn recv_to_file(&self, path: impl AsRef<Path>) -> Result<(u64, u32)> {
+        if self.closed().await {
+            return Err(Exception::ConnectionClosed.into());
+        }
+
+        let mut file = File::create(path).await?;
+        let mut partial = self.channel.partial.lock().await;
+        let mut size = 0;
+        loop {
+            let frame = match partial.take() {
+                Some(frame) => frame,
+                None => self.next_frame().await?,
+            };
+            if self.is_close_notify(&frame) {
+                self.channel.close(CloseReason::RemoteClose).await?;
+                return Err(Exception::ConnectionClosed.into());
+            }
+            file.write_all(&frame.content).await?;
+            size += frame.content.len() as u64;
+
+            if frame.flag != SessionFlag::Continue {
+                file.flush().await?;
+                if frame.flag == SessionFlag::CloseAfter {
+                    self.channel.close(CloseReason::RemoteClose).await?;
+                }
+                return Ok((size, frame.status_code));
+            }
+        }
+    }
+
+    /// Send a message whose payload is produced incrementally by `stream`.
+    ///
+    /// Every chunk is encrypted and written as a continued frame as soon as the stream yields
+    /// it, so backpressure from the socket slows down the producer, and an empty frame carrying
+    /// `status_code` terminates the message. The peer reassembles it in [`Session::recv`] or
+    /// consumes the frames one by one with [`Session::recv_stream`].
+    ///
+    /// Other senders are held back until the stream ends since the frames of a message must
+    /// not be interleaved with other messages. If the stream fails, the message is terminated
+    /// with status code `500` and the error is returned.
+    ///
+    /// ```rust
+    /// # use bytes::Bytes;
+    /// # use futures::StreamExt;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// let rows = futures::stream::iter(0..3).map(|row| Ok(Bytes::from(format!("row {row};"))));
+    /// session.send_stream(rows, 200).await.unwrap();
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// let frames = session.recv_stream().collect::<Vec<_>>().await;
+    /// let chunks = frames.into_iter().map(|frame| frame.unwrap().content);
+    /// assert_eq!(
+    ///     chunks.collect::<Vec<_>>(),
+    ///     [&b"row 0;"[..], b"row 1;", b"row 2;", b""]
+    /// );
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_stream<S>(&self, stream: S, status_code: u32) -> Result<()>
+    where
+        S: Stream<Item = Result<Bytes>>,
+    {
+        if self.closed().await {
+            return Err(Exception::ConnectionClosed.into());
+        }
+        self.require(Capabilities::CONTINUATION, "continued messages")?;
+
+        futures::pin_mut!(stream);
+        let _guard = self.channel.send_lock.lock().await;
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    self.channel
+                        .write_message(chunk.to_vec(), status_code, SessionFlag::Continue)
+                        .await?
+                }
+                Err(error) => {
+                    self.channel
+                        .write_message(Vec::new(), 500, SessionFlag::Data)
+                        .await?;
+                    return Err(error);
+                }
+            }
+        }
+        self.channel
+            .write_message(Vec::new(), status_code, SessionFlag::Data)
+            .await
+    }
+
+    /// Receive the next message frame by frame, as sent by [`Session::send_stream`] or
+    /// [`Session::send_file`].
+    ///
+    /// Every frame is yielded as a [`Response`] as soon as it arrives and the stream ends after
+    /// the frame that isn't flagged as [`SessionFlag::Continue`], which carries the status code
+    /// of the message. A regular message is yielded as a single frame.
+    pub fn recv_stream(&self) -> impl Stream<Item = Result<Response>> + '_ {
+        futures::stream::unfold(false, move |done| async move {
+            if done {
+                return None;
+            }
+            let frame = self.next_part().await;
+            let done = match &frame {
+                Ok(frame) => frame.flag != SessionFlag::Continue,
+                Err(error) if error.downcast_ref() == Some(&Exception::EndOfStream) => {
+                    return None;
+                }
+                Err(_) => true,
+            };
+            Some((frame, done))
+        })
+    }
+
+    /// Whether messages were received and are waiting to be read, partly received ones
+    /// included, or the socket has data or its end ready right away.
+    ///
+    /// Whatever is read from the socket stays buffered for the next receive.
+    pub(crate) async fn has_unread(&self) -> bool {
+        if !self.channel.pending.lock().await.is_empty() || self.channel.partial.lock().await.is_some()
+        {
+            return true;
+        }
+        self.socket.peek(1).now_or_never().is_some()
+    }
+
+    /// Next frame of a message received frame by frame, see [`Session::recv_stream`].
+    pub(crate) async fn next_part(&self) -> Result<Response> {
+        if self.closed().await {
+            return Err(Exception::ConnectionClosed.into());
+        }
+
+        let mut partial = self.channel.partial.lock().await;
+        let frame = match partial.take() {
+            Some(frame) => frame,
+            None => self.next_frame().await?,
+        };
+        let close_notify = self.is_close_notify(&frame);
+        if close_notify || frame.flag == SessionFlag::CloseAfter {
+            self.channel.close(CloseReason::RemoteClose).await?;
+            if close_notify {
+                return Err(Exception::ConnectionClosed.into());
+            }
+        }
+        Ok(frame)
+    }
+
+    /// Replace the session key without tearing down the connection.
+    ///
+    /// A fresh key pair and salt are sent to the peer encrypted under the current key,
+    /// and the new key is derived once the peer answers with its own public key.
+    /// The peer handles the request transparently inside [`Session::recv`], so it has to be
+    /// receiving for the rekey to complete, and must have negotiated [`Capabilities::REKEY`].
+    ///
+    /// Both sides switch keys at a fixed point in the stream: messages the peer sent before
+    /// its answer are still encrypted under the old key and are queued for the next
+    /// [`Session::recv`], while everything after the answer uses the new key.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     for _ in 0..2 {
+    /// #         let response = session.recv().await.unwrap();
+    /// #         session.send(response.content.into()).await.unwrap();
+    /// #     }
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// session.send("before".into()).await?;
+    /// session.rekey().await?;
+    /// session.send("after".into()).await?;
+    ///
+    /// assert_eq!(session.recv().await?.text()?, "before");
+    /// assert_eq!(session.recv().await?.text()?, "after");
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn rekey(&mut self) -> Result<()> {
+        if self.closed().await {
+            return Err(Exception::ConnectionClosed.into());
+        }
+
+        self.require(Capabilities::REKEY, "rekey")?;
+
+        let _guard = self.channel.send_lock.lock().await;
+        let (private_key, public_key) = self.kex_algorithm.generate_key_pair();
+        let salt = self.salt_source.generate(self.salt_len);
+
+        let mut material = length(public_key.as_ref())?.to_vec();
+        material.extend_from_slice(public_key.as_ref());
+        material.extend_from_slice(&length(&salt)?);
+        material.extend_from_slice(&salt);
+        self.channel
+            .write_message(material, 200, SessionFlag::Rekey)
+            .await?;
+
+        loop {
+            let response = self.read_message().await?;
+            if response.flag == SessionFlag::Rekey {
+                let parts = split_material(&response.content)?;
+                if parts.len() != 1 {
+                    return Err(anyhow!("Peer started a rekey while one was in progress"));
+                }
+                let remote_key = self.kex_algorithm.public_key(parts[0].to_vec());
+                let keys = self.rekeyed(
+                    SharedKey::new(private_key, &remote_key)?,
+                    &salt,
+                    public_key.as_ref(),
+                    parts[0],
+                );
+                self.keys.store(Arc::new(keys));
+                return Ok(());
+            }
+
+            let flag = response.flag;
+            self.channel.pending.lock().await.push_back(response);
+            match flag {
+                SessionFlag::CloseAfter | SessionFlag::CloseNotify => {
+                    return Err(Exception::ConnectionClosed.into());
+                }
+                // The peer finished sending and won't answer, the old key stays in use.
+                SessionFlag::Finish => return Err(Exception::EndOfStream.into()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Keep an idle session alive by probing the peer every `interval`.
+    ///
+    /// A ping is sent whenever nothing was sent or received for a whole interval, and the
+    /// session is closed if nothing at all arrives within the following interval, after which
+    /// [`Session::send`] and [`Session::recv`] fail with [`Exception::ConnectionClosed`].
+    /// Pings are answered and pongs consumed inside [`Session::recv`], so both sides need a
+    /// task receiving from the session and the peer must understand keepalive frames.
+    ///
+    /// The returned task stops on its own once the session is closed or dropped. Fails with
+    /// [`Exception::Unsupported`] when the peer didn't negotiate [`Capabilities::KEEPALIVE`].
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     // The peer never receives, so pings stay unanswered.
+    /// #     tokio::time::sleep(Duration::from_millis(500)).await;
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// session.enable_keepalive(Duration::from_millis(50)).await?;
+    ///
+    /// tokio::time::sleep(Duration::from_millis(200)).await;
+    /// assert!(session.closed().await);
+    /// let error = session.send("hello".into()).await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::ConnectionClosed));
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn enable_keepalive(&self, interval: Duration) -> Result<JoinHandle<()>> {
+        self.require(Capabilities::KEEPALIVE, "keepalive")?;
+        let channel: Weak<Channel> = Arc::downgrade(&self.channel);
+        Ok(tokio::spawn(async move {
+            let mut ping_sent_at = None;
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(channel) = channel.upgrade() else {
+                    break;
+                };
+                if channel.closed() {
+                    break;
+                }
+                // Either side finished sending, so pings can't be sent or won't be answered.
+                if channel.socket.is_write_shutdown()
+                    || channel.peer_finished.load(Ordering::Relaxed)
+                {
+                    ping_sent_at = None;
+                    continue;
+                }
+
+                let last_received = channel.counters.last_received.load(Ordering::Relaxed);
+                if let Some(sent_at) = ping_sent_at.take() {
+                    if last_received < sent_at {
+                        let _ = channel
+                            .close(CloseReason::Error(Exception::Timeout {
+                                phase: TimeoutPhase::Keepalive,
+                            }))
+                            .await;
+                        break;
+                    }
+                }
+
+                if channel.socket.idle_for() >= interval {
+                    let now = channel.uptime();
+                    let _guard = channel.send_lock.lock().await;
+                    if channel
+                        .write_message(Vec::new(), 200, SessionFlag::Ping)
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                    ping_sent_at = Some(now);
+                }
+            }
+        }))
+    }
+
+    /// Answer a rekey request from the peer and switch to the new key.
+    async fn accept_rekey(&self, material: &[u8]) -> Result<()> {
+        let parts = split_material(material)?;
+        if parts.len() != 2 {
+            return Err(anyhow!("Malformed rekey request"));
+        }
+        check_salt_len(parts[1].len())?;
+        let remote_key = self.kex_algorithm.public_key(parts[0].to_vec());
+        let (private_key, public_key) = self.kex_algorithm.generate_key_pair();
+        let keys = self.rekeyed(
+            SharedKey::new(private_key, &remote_key)?,
+            parts[1],
+            public_key.as_ref(),
+            parts[0],
+        );
+
+        let mut answer = length(public_key.as_ref())?.to_vec();
+        answer.extend_from_slice(public_key.as_ref());
+        let channel = Arc::clone(&self.channel);
+        self.control(async move {
+            let _guard = channel.send_lock.lock().await;
+            channel
+                .write_message(answer, 200, SessionFlag::Rekey)
+                .await?;
+            channel.keys.store(Arc::new(keys));
+            Ok(())
+        })
+        .await
+    }
+
+    /// Keys derived by a rekey from the new public keys of this side and of the peer.
+    fn rekeyed(
+        &self,
+        mut shared_key: SharedKey,
+        salt: &[u8],
+        public_key: &[u8],
+        peer_public_key: &[u8],
+    ) -> SessionKeys {
+        let (client, server) = if self.channel.is_client() {
+            (public_key, peer_public_key)
+        } else {
+            (peer_public_key, public_key)
+        };
+        shared_key.session_keys(salt, self.protocol_version, client, server)
+    }
+
+    /// Write an answer to a control message in its own task.
+    ///
+    /// The task completes even if the receiving future is dropped, the next receive then waits
+    /// for it before reading on so no answer is half written and no key change is missed.
+    async fn control(&self, write: impl Future<Output = Result<()>> + Send + 'static) -> Result<()> {
+        *self.channel.control.lock().await = Some(tokio::spawn(write));
+        self.finish_control().await
+    }
+
+    /// Wait for the answer started by [`Session::control`], if any.
+    async fn finish_control(&self) -> Result<()> {
+        let mut control = self.channel.control.lock().await;
+        if let Some(task) = control.as_mut() {
+            let result = task.await;
+            *control = None;
+            result??;
+        }
+        Ok(())
+    }
+
     pub fn set_callback(&mut self, callback: Callback) {
         self.callback = Arc::new(Some(callback));
     }
@@ -201,32 +3264,509 @@ impl Session {
         Ok(future)
     }
 
-    pub async fn recv_json(&self) -> Result<Value> {
+    /// Receive a JSON message, returning the parsed value and its status code.
+    ///
+    /// Malformed payloads are reported as [`Exception::InvalidUtf8`] or [`Exception::InvalidJson`].
+    pub async fn recv_json(&self) -> Result<(Value, u32)> {
+        let response = self.recv().await?;
+        Ok((parse_json(&response.content)?, response.status_code))
+    }
+
+    /// Receive a message and deserialize it into `T`, returning it with its status code.
+    ///
+    /// Payloads that don't match `T` fail with [`Exception::InvalidData`].
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    /// struct Login {
+    ///     user: String,
+    ///     remember: bool,
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     let response = session.recv().await.unwrap();
+    /// #     session.send(response.content.into()).await.unwrap();
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// let login = Login { user: "alice".into(), remember: true };
+    /// session.send_serialize(&login).await?;
+    ///
+    /// let (echoed, status_code) = session.recv_into::<Login>().await?;
+    /// assert_eq!((echoed, status_code), (login, 200));
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub async fn recv_into<T: DeserializeOwned>(&self) -> Result<(T, u32)> {
         let response = self.recv().await?;
-        Ok(serde_json::from_slice(&response.content)?)
+        Ok((parse_into(&response.content)?, response.status_code))
     }
 
+    /// Serialize `value` as JSON and send it, see [`Session::recv_into`].
+    #[cfg(feature = "serde")]
+    pub async fn send_serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        self.send(serde_json::to_vec(value)?).await
+    }
+
+    /// Close the session, letting the peer know first.
+    ///
+    /// An empty message flagged as [`SessionFlag::CloseNotify`] is written before the socket
+    /// is shut down, which completes a pending [`Session::recv`] of the peer with
+    /// [`Exception::ConnectionClosed`] instead of an I/O error. Writing it waits for messages
+    /// that are being sent but gives up after [`CLOSE_TIMEOUT`], use [`Session::abort`] to
+    /// close the socket right away. Peers without [`Capabilities::CLOSE_NOTIFY`] are sent an
+    /// empty [`SessionFlag::CloseAfter`] message with status `200` instead.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{CloseReason, Session};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     session.recv().await.unwrap();
+    /// #     session.close().await.unwrap();
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// session.send("bye".into()).await?;
+    ///
+    /// // The server closes the session after receiving the message.
+    /// let error = session.recv().await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::ConnectionClosed));
+    /// assert_eq!(session.close_reason(), Some(CloseReason::RemoteClose));
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn close(&self) -> Result<()> {
-        if !self.closed().await {
-            self.closed.store(Arc::new(true));
-            self.socket.close().await
-        } else {
-            Ok(())
+        if !self.channel.mark_closed(CloseReason::LocalClose) {
+            return Ok(());
+        }
+        if self.peer_public_key.is_some() {
+            let notify = async {
+                let _guard = self.channel.send_lock.lock().await;
+                let flag = self.close_notify_flag();
+                self.channel.write_message(Vec::new(), 200, flag).await
+            };
+            let _ = tokio::time::timeout(CLOSE_TIMEOUT, notify).await;
+            // Either the connection is already broken or the peer may tear it down as soon as
+            // it reads the notification, so shutting it down can fail as well.
+            let _ = self.socket.close().await;
+            return Ok(());
+        }
+        self.socket.close().await
+    }
+
+    /// Tell the peer nothing more will be sent once the messages being sent are written,
+    /// while still receiving what it sends, like a TCP half-close.
+    ///
+    /// The peer receives [`Exception::EndOfStream`] once it read every message, or the end of
+    /// [`Session::recv_stream`], and can still answer. Sending afterwards fails with
+    /// [`Exception::WriteShutdown`]. Fails with [`Exception::Unsupported`] unless the peer
+    /// negotiated [`Capabilities::HALF_CLOSE`].
+    ///
+    /// Unlike a peer that went away, whose end of stream closes the session, the peer is told
+    /// with a message flagged as [`SessionFlag::Finish`] before the sending side is shut down.
+    /// Keepalive pings stop on both sides and pings or rekeys of the peer are no longer answered,
+    /// a [`Session::rekey`] of the peer fails with [`Exception::EndOfStream`] instead.
+    ///
+    /// ```rust
+    /// # use futures::StreamExt;
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{Session, SessionBuilder, SessionFlag};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = tokio::io::duplex(64 * 1024);
+    /// let server = tokio::spawn(async move {
+    ///     let mut session = Session::new(Socket::from_duplex(server))?;
+    ///     session.handshake(1).await?;
+    ///     let mut upload = Vec::new();
+    ///     let mut parts = std::pin::pin!(session.recv_stream());
+    ///     while let Some(part) = parts.next().await {
+    ///         upload.extend(part?.content);
+    ///     }
+    ///     drop(parts);
+    ///     session.send(format!("{} bytes", upload.len()).into_bytes()).await?;
+    ///     anyhow::Ok(())
+    /// });
+    ///
+    /// let session = SessionBuilder::new()
+    ///     .header("PUT /upload Oblivion/2.0")
+    ///     .establish(Socket::from_duplex(client), 0)
+    ///     .await?;
+    /// for part in ["first ", "second"] {
+    ///     let part = part.as_bytes().to_vec();
+    ///     session.send_with_flag(part, 200, SessionFlag::Continue).await?;
+    /// }
+    /// session.finish_sending().await?;
+    /// assert_eq!(session.recv().await?.text()?, "12 bytes");
+    ///
+    /// let error = session.send(b"late".to_vec()).await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::WriteShutdown));
+    /// server.await??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Control messages arriving afterwards are skipped rather than failing the receive:
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::models::session::{SessionBuilder, SessionFlag};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let server = tokio::spawn(async move {
+    ///     let session = SessionBuilder::new().establish(server, 1).await?;
+    ///     session.send_with_flag(Vec::new(), 200, SessionFlag::Ping).await?;
+    ///     session.send(b"still here".to_vec()).await?;
+    ///     session.finish_sending().await?;
+    ///     anyhow::Ok(())
+    /// });
+    /// let session = SessionBuilder::new()
+    ///     .header("GET / Oblivion/2.0")
+    ///     .establish(client, 0)
+    ///     .await?;
+    /// session.finish_sending().await?;
+    ///
+    /// assert_eq!(session.recv().await?.text()?, "still here");
+    /// let error = session.recv().await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::EndOfStream));
+    /// server.await??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn finish_sending(&self) -> Result<()> {
+        self.require(Capabilities::HALF_CLOSE, "half-close")?;
+        let _guard = self.channel.send_lock.lock().await;
+        self.channel
+            .write_message(Vec::new(), 200, SessionFlag::Finish)
+            .await?;
+        self.socket.shutdown_write().await
+    }
+
+    /// Close the socket right away without notifying the peer, see [`Session::close`].
+    pub async fn abort(&self) -> Result<()> {
+        self.channel.close(CloseReason::LocalClose).await
+    }
+
+    /// Run `callback` once the session is closed, with the reason it was closed for.
+    ///
+    /// The callback runs exactly once whichever way the session ends: an explicit
+    /// [`Session::close`], a timeout, the peer closing, a failed send or receive or dropping the
+    /// session. It runs right away when the session is already closed, and must not block
+    /// since it is called from the task that closed the session.
+    ///
+    /// ```rust
+    /// # use std::sync::{Arc, Mutex};
+    /// # use oblivion::models::session::{CloseReason, Session};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     // Dropping the session resets the connection under the client.
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// let reasons = Arc::new(Mutex::new(Vec::new()));
+    /// let hook = Arc::clone(&reasons);
+    /// session.on_close(move |reason| hook.lock().unwrap().push(reason));
+    ///
+    /// # server.await?;
+    /// // The peer went away, so receiving fails and closes the session.
+    /// assert!(session.recv().await.is_err());
+    /// session.close().await?;
+    /// drop(session);
+    ///
+    /// let reasons = reasons.lock().unwrap();
+    /// assert_eq!(reasons.len(), 1);
+    /// assert!(matches!(reasons[0], CloseReason::Error(_)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_close(&self, callback: impl FnOnce(CloseReason) + Send + 'static) {
+        self.channel.on_close(Box::new(callback));
+    }
+
+    /// Identifier of the session, unique within the process and shared by every request of
+    /// a persistent connection, see [`SessionManager`](super::manager::SessionManager).
+    #[inline]
+    pub fn id(&self) -> u64 {
+        self.channel.id
+    }
+
+    /// Why the session was closed, `None` while it is open.
+    #[inline]
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.channel.close_reason()
+    }
+
+    /// Split the session into a sending half and a receiving half.
+    ///
+    /// Both halves share the same underlying session, so one task can keep receiving while
+    /// others push outbound messages. Every message is written as a whole, so a concurrent
+    /// receiver never observes a half-written packet, and closing either half closes both.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     for _ in 0..3 {
+    /// #         let response = session.recv().await.unwrap();
+    /// #         session.send(response.content.into()).await.unwrap();
+    /// #     }
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// let (sender, receiver) = session.split();
+    ///
+    /// let reader = tokio::spawn(async move {
+    ///     let mut received = Vec::new();
+    ///     for _ in 0..3 {
+    ///         received.push(receiver.recv().await.unwrap().text().unwrap().to_string());
+    ///     }
+    ///     received
+    /// });
+    /// for message in ["a", "b", "c"] {
+    ///     sender.send(message.into()).await?;
+    /// }
+    ///
+    /// assert_eq!(reader.await?, ["a", "b", "c"]);
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split(self) -> (SessionSender, SessionReceiver) {
+        let session = Arc::new(self);
+        (
+            SessionSender {
+                session: Arc::clone(&session),
+            },
+            SessionReceiver { session },
+        )
+    }
+
+    /// Traffic statistics of the session so far.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let (client, server) = Socket::pair();
+    /// # let server = tokio::spawn(async move {
+    /// #     let mut session = Session::new(server).unwrap();
+    /// #     session.handshake(1).await.unwrap();
+    /// #     let response = session.recv().await.unwrap();
+    /// #     session.send(response.content.into()).await.unwrap();
+    /// # });
+    /// # let header = "CONNECT / Oblivion/2.0".to_string();
+    /// # let mut session = Session::new_with_header(header, client)?;
+    /// # session.handshake(0).await?;
+    /// session.send("hello".into()).await?;
+    /// session.recv().await?;
+    ///
+    /// let stats = session.stats();
+    /// // The handshake ended with an empty header section, encoded as `{}`.
+    /// assert_eq!((stats.bytes_sent, stats.bytes_received), (7, 5));
+    /// assert_eq!((stats.packets_sent, stats.packets_received), (2, 1));
+    /// assert!(stats.wire_bytes_sent > stats.bytes_sent);
+    /// # server.await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stats(&self) -> SessionStats {
+        let counters = &self.channel.counters;
+        let socket = &self.channel.socket;
+        SessionStats {
+            bytes_sent: counters.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: counters.bytes_received.load(Ordering::Relaxed),
+            wire_bytes_sent: socket.bytes_written(),
+            wire_bytes_received: socket.bytes_read(),
+            packets_sent: counters.packets_sent.load(Ordering::Relaxed),
+            packets_received: counters.packets_received.load(Ordering::Relaxed),
+            established_at: self.channel.created_at,
+            last_activity: Instant::now() - socket.idle_for(),
         }
     }
 
     #[inline]
     pub async fn closed(&self) -> bool {
-        **self.closed.load()
+        self.channel.closed()
     }
 
+    /// Header line of the session, fails with [`Exception::NoHandshake`] if none was set or received.
     #[inline]
-    pub fn header(&self) -> &str {
-        &self.header
+    pub fn header(&self) -> Result<&str, Exception> {
+        if self.header.is_empty() {
+            return Err(Exception::NoHandshake);
+        }
+        Ok(&self.header)
     }
 
+    /// Remote IP of the request, only available on server side sessions after the handshake.
     #[inline]
-    pub fn get_ip(&self) -> &str {
-        self.request.get_ip()
+    pub fn get_ip(&self) -> Result<&str, Exception> {
+        match self.request.get_ip() {
+            "" => Err(Exception::NoHandshake),
+            ip => Ok(ip),
+        }
+    }
+}
+
+/// Preamble of the peer in the bytes it was sent as, its magic followed by `version` and the
+/// `capabilities` word, the key exchange included.
+fn received_preamble(version: u32, capabilities: u32) -> Vec<u8> {
+    let mut preamble = PREAMBLE_MAGIC.to_vec();
+    preamble.extend_from_slice(&version.to_be_bytes());
+    preamble.extend_from_slice(&capabilities.to_be_bytes());
+    preamble
+}
+
+/// Handshake preamble announcing a protocol version, capabilities and the key exchange.
+fn preamble(version: u32, capabilities: Capabilities, kex: KexAlgorithm) -> Vec<u8> {
+    let word = capabilities.bits() | (kex.id() as u32) << KEX_SHIFT;
+    let mut preamble = PREAMBLE_MAGIC.to_vec();
+    preamble.extend_from_slice(&version.to_be_bytes());
+    preamble.extend_from_slice(&word.to_be_bytes());
+    preamble
+}
+
+/// Read up to [`FILE_CHUNK_SIZE`] bytes, only returning less at the end of the file.
+pub(crate) async fn read_chunk(file: &mut File) -> Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(FILE_CHUNK_SIZE);
+    while chunk.len() < FILE_CHUNK_SIZE {
+        let read = (&mut *file)
+            .take((FILE_CHUNK_SIZE - chunk.len()) as u64)
+            .read_to_end(&mut chunk)
+            .await?;
+        if read == 0 {
+            break;
+        }
+    }
+    Ok(chunk)
+}
+
+/// Split length prefixed key material into its parts.
+fn split_material(mut material: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut parts = Vec::new();
+    while !material.is_empty() {
+        if material.len() < 4 {
+            return Err(anyhow!("Truncated key material"));
+        }
+        let size = u32::from_be_bytes(material[..4].try_into()?) as usize;
+        if material.len() < 4 + size {
+            return Err(anyhow!("Truncated key material"));
+        }
+        parts.push(&material[4..4 + size]);
+        material = &material[4 + size..];
+    }
+    Ok(parts)
+}
+
+/// Sending half of a [`Session`], created by [`Session::split`].
+pub struct SessionSender {
+    session: Arc<Session>,
+}
+
+impl SessionSender {
+    pub async fn send(&self, data: Vec<u8>) -> Result<()> {
+        self.session.send(data).await
+    }
+
+    pub async fn send_with_flag(
+        &self,
+        data: Vec<u8>,
+        status_code: u32,
+        flag: SessionFlag,
+    ) -> Result<()> {
+        self.session.send_with_flag(data, status_code, flag).await
+    }
+
+    pub async fn send_json(&self, json: Value) -> Result<()> {
+        self.session.send_json(json).await
+    }
+
+    #[cfg(feature = "serde")]
+    pub async fn send_serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        self.session.send_serialize(value).await
+    }
+
+    pub async fn close(&self) -> Result<()> {
+        self.session.close().await
+    }
+
+    pub async fn abort(&self) -> Result<()> {
+        self.session.abort().await
+    }
+
+    #[inline]
+    pub async fn closed(&self) -> bool {
+        self.session.closed().await
+    }
+}
+
+/// Receiving half of a [`Session`], created by [`Session::split`].
+pub struct SessionReceiver {
+    session: Arc<Session>,
+}
+
+impl SessionReceiver {
+    pub async fn recv(&self) -> Result<Response> {
+        self.session.recv().await
+    }
+
+    pub async fn recv_timeout(&self, timeout: Duration) -> Result<Response> {
+        self.session.recv_timeout(timeout).await
+    }
+
+    pub async fn recv_json(&self) -> Result<(Value, u32)> {
+        self.session.recv_json().await
+    }
+
+    #[cfg(feature = "serde")]
+    pub async fn recv_into<T: DeserializeOwned>(&self) -> Result<(T, u32)> {
+        self.session.recv_into().await
+    }
+
+    pub async fn close(&self) -> Result<()> {
+        self.session.close().await
+    }
+
+    pub async fn abort(&self) -> Result<()> {
+        self.session.abort().await
+    }
+
+    #[inline]
+    pub async fn closed(&self) -> bool {
+        self.session.closed().await
     }
 }
@@ -0,0 +1,84 @@
+//! # Oblivion TLS
+//!
+//! TLS beneath the Oblivion handshake, see
+//! [`ServerConfig::tls`](super::server::ServerConfig::tls) and
+//! [`ClientBuilder::tls`](super::client::ClientBuilder::tls).
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::net::TcpStream;
+pub use tokio_rustls::rustls;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::RootCertStore;
+
+use crate::exceptions::Exception;
+use crate::utils::gear::{Peer, Socket};
+
+fn tls_error(error: impl fmt::Display) -> Exception {
+    Exception::TlsError {
+        reason: error.to_string(),
+    }
+}
+
+/// TLS server side of the connections a server accepts.
+#[derive(Clone)]
+pub(crate) struct TlsAcceptor(tokio_rustls::TlsAcceptor);
+
+impl TlsAcceptor {
+    pub(crate) fn new(
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, Exception> {
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, key)
+            .map_err(tls_error)?;
+        Ok(Self(Arc::new(config).into()))
+    }
+
+    /// Perform the TLS handshake of an accepted connection.
+    pub(crate) async fn accept(&self, tcp: TcpStream) -> Result<Socket> {
+        let peer = Peer::Tcp(tcp.peer_addr()?);
+        let stream = self.0.accept(tcp).await.map_err(tls_error)?;
+        Ok(Socket::from_stream(stream, peer))
+    }
+}
+
+impl fmt::Debug for TlsAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TlsAcceptor")
+    }
+}
+
+/// TLS client side of the connections a client opens.
+#[derive(Clone)]
+pub(crate) struct TlsConnector(tokio_rustls::TlsConnector);
+
+impl TlsConnector {
+    pub(crate) fn new(roots: RootCertStore) -> Self {
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("the default protocol versions are supported")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Self(Arc::new(config).into())
+    }
+
+    /// Perform the TLS handshake with `host` over a connection to it.
+    pub(crate) async fn connect(&self, host: &str, tcp: TcpStream) -> Result<Socket> {
+        let name = ServerName::try_from(host.to_string()).map_err(tls_error)?;
+        let peer = Peer::Tcp(tcp.peer_addr()?);
+        let stream = self.0.connect(name, tcp).await.map_err(tls_error)?;
+        Ok(Socket::from_stream(stream, peer))
+    }
+}
+
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TlsConnector")
+    }
+}
@@ -1,5 +1,5 @@
 pub use crate::models::client::Client;
-pub use crate::models::render::BaseResponse;
+pub use crate::models::render::{BaseResponse, IntoResponse};
 pub use crate::models::router::Router;
 pub use crate::models::server::Server;
 pub use crate::types::ServerResponse;
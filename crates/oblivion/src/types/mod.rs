@@ -8,3 +8,7 @@ pub type Callback = std::sync::Arc<
 pub use crate::models::render::BaseResponse;
 pub type ServerResponse = BoxFuture<'static, anyhow::Result<BaseResponse>>;
 pub type Handler = fn(crate::models::session::Session) -> ServerResponse;
+pub type PanicHandler = fn(
+    &crate::models::handler::Panic,
+    &crate::utils::parser::OblivionRequest,
+) -> crate::models::render::BaseResponse;
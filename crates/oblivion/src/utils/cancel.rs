@@ -0,0 +1,62 @@
+//! # Oblivion Cancellation
+//!
+//! Tokens cancelling client requests, see
+//! [`RequestBuilder::cancellation_token`](crate::models::client::RequestBuilder::cancellation_token).
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Token cancelling every request it was given to once [`CancellationToken::cancel`] is called.
+///
+/// Clones share the same state, so a token can be cancelled from another task than the one
+/// awaiting the request.
+///
+/// ```rust
+/// use oblivion::utils::cancel::CancellationToken;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let token = CancellationToken::new();
+/// let waiter = token.clone();
+/// let task = tokio::spawn(async move { waiter.cancelled().await });
+///
+/// token.cancel();
+/// task.await.unwrap();
+/// assert!(token.is_cancelled());
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self {
+            cancelled: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Resolve once the token is cancelled, right away if it already is.
+    pub async fn cancelled(&self) {
+        let _ = self
+            .cancelled
+            .subscribe()
+            .wait_for(|cancelled| *cancelled)
+            .await;
+    }
+}
@@ -0,0 +1,193 @@
+//! # Oblivion Compression
+//!
+//! Compression of the data of [`OED`](crate::models::packet::OED) packets, see
+//! [`SessionBuilder::compression`](crate::models::session::SessionBuilder::compression).
+use bytes::Bytes;
+
+use crate::exceptions::Exception;
+
+/// Payloads smaller than this are sent uncompressed unless configured otherwise.
+pub const COMPRESSION_THRESHOLD: usize = 512;
+
+/// Largest decompressed payload accepted unless configured otherwise.
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// Algorithm a payload was compressed with, tagged in the header of its packet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    #[default]
+    None,
+    /// Zstandard, requires the `zstd` feature.
+    Zstd,
+}
+
+impl Algorithm {
+    /// Tag of the algorithm on the wire.
+    pub const fn tag(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Zstd => 1,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Result<Self, Exception> {
+        match tag {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Zstd),
+            tag => Err(Exception::CompressionError {
+                reason: format!("unknown algorithm tag {tag}"),
+            }),
+        }
+    }
+}
+
+/// How the data of packets is compressed before it is encrypted.
+///
+/// Payloads below the threshold, or that don't shrink, are sent uncompressed. Received
+/// payloads are decompressed whichever algorithm the peer chose, up to `max_size` bytes.
+///
+/// ```rust
+/// # #[cfg(feature = "zstd")]
+/// # fn main() -> anyhow::Result<()> {
+/// use oblivion::utils::compression::{Algorithm, Compression};
+///
+/// let compression = Compression::zstd(3).threshold(64);
+/// let json = br#"{"user": "alice", "roles": ["admin", "admin", "admin", "admin"]}"#.repeat(50);
+///
+/// let (packed, algorithm) = compression.compress(json.clone())?;
+/// assert_eq!(algorithm, Algorithm::Zstd);
+/// assert!(packed.len() < json.len() / 5);
+/// assert_eq!(compression.decompress(packed.into(), algorithm)?, json);
+///
+/// // Small payloads aren't worth it.
+/// let packed = compression.compress(b"ok".to_vec())?;
+/// assert_eq!(packed, (b"ok".to_vec(), Algorithm::None));
+///
+/// let (packed, algorithm) = compression.compress(vec![0; 4096])?;
+/// let error = Compression::zstd(3)
+///     .max_size(1024)
+///     .decompress(packed.into(), algorithm)
+///     .unwrap_err();
+/// assert_eq!(error.to_string(), "Decompressed payload exceeds 1024 bytes.");
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "zstd"))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub algorithm: Algorithm,
+    pub level: i32,
+    pub threshold: usize,
+    pub max_size: usize,
+}
+
+impl Default for Compression {
+    /// Send payloads uncompressed, still decompressing those received.
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::None,
+            level: 0,
+            threshold: COMPRESSION_THRESHOLD,
+            max_size: MAX_DECOMPRESSED_SIZE,
+        }
+    }
+}
+
+impl Compression {
+    /// Compress with Zstandard at `level`, `0` being its default level.
+    #[cfg(feature = "zstd")]
+    pub fn zstd(level: i32) -> Self {
+        Self {
+            algorithm: Algorithm::Zstd,
+            level,
+            ..Self::default()
+        }
+    }
+
+    /// Send payloads smaller than `bytes` uncompressed.
+    pub fn threshold(mut self, bytes: usize) -> Self {
+        self.threshold = bytes;
+        self
+    }
+
+    /// Refuse payloads decompressing to more than `bytes`.
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Compress `data` if worth it, along with the algorithm used.
+    ///
+    /// The payload is never larger than `data`, which is returned as is unless compressing it
+    /// made it smaller.
+    pub fn compress(&self, data: Vec<u8>) -> Result<(Vec<u8>, Algorithm), Exception> {
+        match self.algorithm {
+            Algorithm::Zstd if data.len() >= self.threshold => {
+                let compressed = self.compress_zstd(&data)?;
+                if compressed.len() < data.len() {
+                    return Ok((compressed, Algorithm::Zstd));
+                }
+                Ok((data, Algorithm::None))
+            }
+            _ => Ok((data, Algorithm::None)),
+        }
+    }
+
+    /// Data of a payload compressed with `algorithm` by [`Compression::compress`], sharing
+    /// its bytes if it wasn't compressed.
+    ///
+    /// Fails with [`Exception::DecompressedTooLarge`] as soon as the data grows past
+    /// `max_size`, without decompressing the rest.
+    pub fn decompress(&self, payload: Bytes, algorithm: Algorithm) -> Result<Bytes, Exception> {
+        match algorithm {
+            Algorithm::None => Ok(payload),
+            Algorithm::Zstd => self.decompress_zstd(&payload).map(Bytes::from),
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    fn compress_zstd(&self, data: &[u8]) -> Result<Vec<u8>, Exception> {
+        zstd::bulk::compress(data, self.level).map_err(|error| Exception::CompressionError {
+            reason: error.to_string(),
+        })
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn compress_zstd(&self, _data: &[u8]) -> Result<Vec<u8>, Exception> {
+        Err(unsupported())
+    }
+
+    #[cfg(feature = "zstd")]
+    fn decompress_zstd(&self, data: &[u8]) -> Result<Vec<u8>, Exception> {
+        use std::io::Read;
+
+        let failed = |error: std::io::Error| Exception::CompressionError {
+            reason: error.to_string(),
+        };
+        let decoder = zstd::stream::read::Decoder::new(data).map_err(failed)?;
+        let mut decompressed = Vec::new();
+        decoder
+            .take(self.max_size as u64 + 1)
+            .read_to_end(&mut decompressed)
+            .map_err(failed)?;
+        if decompressed.len() > self.max_size {
+            return Err(Exception::DecompressedTooLarge {
+                limit: self.max_size,
+            });
+        }
+        Ok(decompressed)
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn decompress_zstd(&self, _data: &[u8]) -> Result<Vec<u8>, Exception> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(not(feature = "zstd"))]
+fn unsupported() -> Exception {
+    Exception::CompressionError {
+        reason: "zstd support was not compiled in".to_string(),
+    }
+}
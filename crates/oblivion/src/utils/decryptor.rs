@@ -1,7 +1,10 @@
 //! # Oblivion Decryptor
 use ring::aead::Aad;
 use ring::aead::BoundKey;
+use ring::aead::LessSafeKey;
+use ring::aead::Nonce;
 use ring::aead::OpeningKey;
+use ring::aead::Tag;
 use ring::aead::UnboundKey;
 use ring::aead::AES_128_GCM;
 use ring::error::Unspecified;
@@ -25,3 +28,29 @@ pub fn decrypt_bytes(
 
     Ok(decrypted_data.to_vec())
 }
+
+/// Decrypts the given cipher bytes in place using the given key, nonce and separate tag.
+/// Returns the part of `in_out` holding the decrypted data.
+pub fn decrypt_in_place<'a>(
+    in_out: &'a mut [u8],
+    tag: &[u8],
+    aes_key: &[u8],
+    nonce: &[u8],
+) -> Result<&'a mut [u8], Unspecified> {
+    decrypt_in_place_with_aad(in_out, tag, aes_key, nonce, &[])
+}
+
+/// Decrypts in place like [`decrypt_in_place`], checking the data was encrypted along with
+/// `aad`.
+pub fn decrypt_in_place_with_aad<'a>(
+    in_out: &'a mut [u8],
+    tag: &[u8],
+    aes_key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+) -> Result<&'a mut [u8], Unspecified> {
+    let key = LessSafeKey::new(UnboundKey::new(&AES_128_GCM, aes_key)?);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)?;
+    let tag = Tag::try_from(tag)?;
+    key.open_in_place_separate_tag(nonce, Aad::from(aad), tag, in_out, 0..)
+}
@@ -3,6 +3,8 @@ extern crate ring;
 
 use ring::aead::Aad;
 use ring::aead::BoundKey;
+use ring::aead::LessSafeKey;
+use ring::aead::Nonce;
 use ring::aead::SealingKey;
 use ring::aead::UnboundKey;
 use ring::aead::AES_128_GCM;
@@ -44,3 +46,36 @@ pub fn encrypt_bytes(mut bytes: Vec<u8>, aes_key: &[u8]) -> Result<EncryptedData
 
     Ok((bytes, tag.as_ref().to_owned(), nonce_bytes))
 }
+
+/// Encrypt `in_out` in place with a random nonce, authenticating `aad` along with it.
+/// Returns the tag and the nonce.
+pub fn encrypt_in_place(
+    in_out: &mut [u8],
+    aes_key: &[u8],
+    aad: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), Exception> {
+    let mut nonce = vec![0; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|error| Exception::EncryptError { error })?;
+    let tag = encrypt_in_place_with_nonce(in_out, aes_key, aad, &nonce)?;
+    Ok((tag, nonce))
+}
+
+/// Encrypt `in_out` in place like [`encrypt_in_place`] with a nonce of the caller, which
+/// must never encrypt anything else under `aes_key`. Returns the tag.
+pub fn encrypt_in_place_with_nonce(
+    in_out: &mut [u8],
+    aes_key: &[u8],
+    aad: &[u8],
+    nonce: &[u8],
+) -> Result<Vec<u8>, Exception> {
+    let encrypt_error = |error| Exception::EncryptError { error };
+    let key = LessSafeKey::new(UnboundKey::new(&AES_128_GCM, aes_key).map_err(encrypt_error)?);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(encrypt_error)?;
+
+    let tag = key
+        .seal_in_place_separate_tag(nonce, Aad::from(aad), in_out)
+        .map_err(encrypt_error)?;
+    Ok(tag.as_ref().to_owned())
+}
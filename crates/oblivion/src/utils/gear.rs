@@ -1,14 +1,59 @@
 //! Oblivion Abstract Gear
-use std::net::SocketAddr;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, IoSlice};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use anyhow::Result;
+use bytes::{Buf, Bytes, BytesMut};
 use ring::aead::{Nonce, NonceSequence};
 use ring::error::Unspecified;
+use socket2::{SockRef, TcpKeepalive};
 
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::net::tcp::OwnedWriteHalf;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::net::{lookup_host, TcpSocket, TcpStream, ToSocketAddrs};
 use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::exceptions::{Exception, TimeoutPhase};
+use crate::utils::cancel::CancellationToken;
+use crate::utils::parser::{parse_length, LENGTH_PREFIX_SIZE};
+use crate::utils::throttle::{RateLimit, Throttle};
+
+/// Bytes reserved for every read of [`Socket::recv_into`].
+const RECV_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Bytes the read buffer of every [`Socket`] makes room for before reading the transport.
+pub const READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Bytes a [corked](Socket::corked) socket batches at most, it sends them as soon as they
+/// reach this size.
+pub const WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Read buffers that grew past this size for a large frame are released once it was received
+/// rather than kept for the next frames.
+const RETAINED_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Frames of [`Socket::recv`] up to this size are copied out of the read buffer, so that
+/// holding on to a small frame doesn't keep the memory of the whole buffer alive.
+const COPIED_FRAME_SIZE: usize = 1024;
+
+/// Largest length prefix a [`Socket`] accepts by default, see [`Socket::set_max_frame_size`].
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Address reported for peers without an IP address, see [`Socket::peer_addr`].
+pub const LOCAL_PEER: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+
+/// Bytes written to one end of a [`Socket::pair`] the other end can keep unread.
+pub const PAIR_BUFFER_SIZE: usize = 64 * 1024;
 
 /// Absolute Nonce Sequence Structure
 ///
@@ -32,79 +77,1344 @@ impl<'a> AbsoluteNonceSequence<'a> {
     }
 }
 
+/// Time a TCP connection stays idle before keepalive probes start by default, see
+/// [`Socket::set_tcp_keepalive`].
+pub const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Options of the TCP connections a server accepts or a client opens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TcpOptions {
+    pub(crate) nodelay: bool,
+    pub(crate) keepalive: Option<Duration>,
+    pub(crate) linger: Option<Duration>,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            keepalive: Some(TCP_KEEPALIVE),
+            linger: None,
+        }
+    }
+}
+
+impl TcpOptions {
+    pub(crate) fn apply(&self, tcp: &TcpStream) -> io::Result<()> {
+        tcp.set_nodelay(self.nodelay)?;
+        set_keepalive(tcp, self.keepalive)?;
+        tcp.set_linger(self.linger)
+    }
+}
+
+fn set_keepalive(tcp: &TcpStream, time: Option<Duration>) -> io::Result<()> {
+    let socket = SockRef::from(tcp);
+    match time {
+        Some(time) => socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time)),
+        None => socket.set_keepalive(false),
+    }
+}
+
+/// Open a TCP connection to `address`, from `local` if given.
+///
+/// Connections refused by the peer fail with [`Exception::ConnectionRefusedError`] and
+/// addresses without a route to them with [`Exception::Unreachable`].
+pub(crate) async fn dial(
+    address: SocketAddr,
+    local: Option<IpAddr>,
+) -> Result<TcpStream, Exception> {
+    let connect = async {
+        let socket = match address {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        if let Some(local) = local {
+            socket.bind(SocketAddr::new(local, 0))?;
+        }
+        socket.connect(address).await
+    };
+    connect.await.map_err(|error| match error.kind() {
+        io::ErrorKind::ConnectionRefused => Exception::ConnectionRefusedError,
+        io::ErrorKind::HostUnreachable | io::ErrorKind::NetworkUnreachable => {
+            Exception::Unreachable { address }
+        }
+        _ => error.into(),
+    })
+}
+
+/// Connect to the addresses `address` resolves to in turn, failing like the last one did if
+/// none accepts the connection.
+pub(crate) async fn dial_any(address: impl ToSocketAddrs) -> Result<TcpStream, Exception> {
+    let mut failure = Exception::from(io::Error::new(
+        io::ErrorKind::NotFound,
+        "No address to connect to",
+    ));
+    for address in lookup_host(address).await? {
+        match dial(address, None).await {
+            Ok(tcp) => return Ok(tcp),
+            Err(error) => failure = error,
+        }
+    }
+    Err(failure)
+}
+
+/// Peer at the other end of a [`Socket`], see [`Socket::peer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peer {
+    Tcp(SocketAddr),
+    /// Over a Unix domain socket, on the same host.
+    Unix,
+    /// Over an in-memory stream, in the same process.
+    Memory,
+}
+
+impl From<SocketAddr> for Peer {
+    fn from(address: SocketAddr) -> Self {
+        Self::Tcp(address)
+    }
+}
+
+/// Bytes moved through a [`Socket`], when they last were and how fast they may be, shared by
+/// both of its halves.
+#[derive(Debug)]
+struct Traffic {
+    created_at: Instant,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    /// Milliseconds since `created_at`.
+    last_activity: AtomicU64,
+    read_limit: Throttle,
+    write_limit: Throttle,
+}
+
+impl Traffic {
+    fn new() -> Self {
+        Self {
+            created_at: Instant::now(),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            last_activity: AtomicU64::new(0),
+            read_limit: Throttle::new(),
+            write_limit: Throttle::new(),
+        }
+    }
+
+    #[inline]
+    fn read(&self, len: usize) {
+        self.bytes_read.fetch_add(len as u64, Ordering::Relaxed);
+        self.read_limit.consume(len);
+        self.touch();
+    }
+
+    #[inline]
+    fn written(&self, len: usize) {
+        self.bytes_written.fetch_add(len as u64, Ordering::Relaxed);
+        self.touch();
+    }
+
+    #[inline]
+    fn touch(&self) {
+        let uptime = self.created_at.elapsed().as_millis() as u64;
+        self.last_activity.store(uptime, Ordering::Relaxed);
+    }
+
+    fn idle_for(&self) -> Duration {
+        let last_activity = Duration::from_millis(self.last_activity.load(Ordering::Relaxed));
+        self.created_at.elapsed().saturating_sub(last_activity)
+    }
+}
+
+/// Frame found at the front of the bytes buffered by a [`Socket`], see [`Socket::recv_frame`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing<T> {
+    /// A whole frame, removed from the buffer.
+    Complete(T),
+    /// The frame isn't whole yet, at least `needed` bytes must be buffered to go on.
+    Incomplete { needed: usize },
+}
+
+/// Name what was being read when `error` occurred if the stream ended in the middle of it,
+/// other failures are passed on as is.
+pub(crate) fn reading(what: &str) -> impl FnOnce(anyhow::Error) -> anyhow::Error + '_ {
+    move |error| match error.downcast::<Exception>() {
+        Ok(Exception::UnexpectedEof { expected, got, .. }) => Exception::UnexpectedEof {
+            reading: what.to_string(),
+            expected,
+            got,
+        }
+        .into(),
+        Ok(exception) => exception.into(),
+        Err(error) => error,
+    }
+}
+
+/// Read half of the transport behind a [`Socket`].
+pub type Reader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Read half of a [`Socket`] along with the bytes read from it ahead of their receiver.
+///
+/// Frames received from it, such as by [`Socket::recv`], are slices of the buffer rather than
+/// copies, except for those up to [`COPIED_FRAME_SIZE`]. The memory of the buffer is reused
+/// once every slice of it was dropped and replaced while receivers hold on to some, so it
+/// never grows past the largest frame being received.
+pub struct Inbound {
+    reader: Reader,
+    buffer: BytesMut,
+    traffic: Arc<Traffic>,
+}
+
+impl Inbound {
+    fn new(reader: Reader, traffic: Arc<Traffic>) -> Self {
+        Self {
+            reader,
+            buffer: BytesMut::with_capacity(READ_BUFFER_SIZE),
+            traffic,
+        }
+    }
+
+    /// Read the transport once into `buffer`, returning how many bytes were read.
+    async fn read_into(&mut self, buffer: &mut Vec<u8>) -> io::Result<usize> {
+        self.traffic.read_limit.acquire(0).await;
+        let read = self.reader.read_buf(buffer).await?;
+        self.traffic.read(read);
+        Ok(read)
+    }
+
+    /// Read the transport once, making room for at least `additional` bytes, returning how
+    /// many were read, none once the stream has ended.
+    async fn fill(&mut self, additional: usize) -> io::Result<usize> {
+        self.buffer.reserve(additional.max(READ_BUFFER_SIZE));
+        self.traffic.read_limit.acquire(0).await;
+        let read = self.reader.read_buf(&mut self.buffer).await?;
+        self.traffic.read(read);
+        Ok(read)
+    }
+
+    /// Read the transport until at least `len` bytes are buffered, failing with
+    /// [`Exception::UnexpectedEof`] if the stream ends first, see [`Inbound::ended`].
+    async fn fill_to(&mut self, len: usize) -> Result<(), Exception> {
+        while self.buffer.len() < len {
+            if self.fill(len - self.buffer.len()).await? == 0 {
+                return Err(self.ended(len));
+            }
+        }
+        Ok(())
+    }
+
+    /// Failure of a read the stream ended in the middle of, while a frame of `expected`
+    /// bytes was buffered, or [`Exception::ConnectionClosed`] if it ended between frames.
+    fn ended(&self, expected: usize) -> Exception {
+        if self.buffer.is_empty() {
+            return Exception::ConnectionClosed;
+        }
+        Exception::UnexpectedEof {
+            reading: "frame".to_string(),
+            expected,
+            got: self.buffer.len(),
+        }
+    }
+
+    async fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), Exception> {
+        self.fill_to(buffer.len()).await?;
+        self.buffer.copy_to_slice(buffer);
+        Ok(())
+    }
+
+    /// Split the first `len` bytes off the buffer, they must have been buffered already.
+    fn take(&mut self, len: usize) -> Bytes {
+        let frame = if len <= COPIED_FRAME_SIZE {
+            let frame = Bytes::copy_from_slice(&self.buffer[..len]);
+            self.buffer.advance(len);
+            frame
+        } else {
+            self.buffer.split_to(len).freeze()
+        };
+        self.release(len);
+        frame
+    }
+
+    /// Let go of the memory of a buffer once emptied, if it grew for a frame of `len` bytes or
+    /// frames received from it still share it.
+    ///
+    /// Reserving room in a shared buffer allocates one as large as the first, starting over
+    /// keeps the next allocation at [`READ_BUFFER_SIZE`].
+    fn release(&mut self, len: usize) {
+        if !self.buffer.is_empty() {
+            return;
+        }
+        if len > RETAINED_BUFFER_SIZE || !self.buffer.try_reclaim(READ_BUFFER_SIZE) {
+            self.buffer = BytesMut::with_capacity(READ_BUFFER_SIZE);
+        }
+    }
+}
+/// Write half of the transport behind a [`Socket`].
+///
+/// The write half of a TCP connection is kept as is to tune the connection through it, see
+/// [`Socket::set_nodelay`].
+pub enum Writer {
+    Tcp(OwnedWriteHalf),
+    Stream(Box<dyn AsyncWrite + Send + Unpin>),
+}
+
+impl Writer {
+    fn tcp(&self) -> Option<&TcpStream> {
+        match self {
+            Self::Tcp(half) => Some(half.as_ref()),
+            Self::Stream(_) => None,
+        }
+    }
+}
+
+/// Write half of a [`Socket`] along with the bytes batched while it is
+/// [corked](Socket::corked).
+pub struct Outbound {
+    writer: Writer,
+    buffer: BytesMut,
+    traffic: Arc<Traffic>,
+}
+
+impl Outbound {
+    fn new(writer: Writer, traffic: Arc<Traffic>) -> Self {
+        Self {
+            writer,
+            buffer: BytesMut::new(),
+            traffic,
+        }
+    }
+
+    /// Write the batched bytes followed by `bufs` and flush them, or add `bufs` to the batch
+    /// if `corked` until it is full.
+    async fn write(&mut self, bufs: &[IoSlice<'_>], corked: bool) -> io::Result<()> {
+        let bufs = if corked {
+            for buf in bufs {
+                self.buffer.extend_from_slice(buf);
+            }
+            if self.buffer.len() < WRITE_BUFFER_SIZE {
+                return Ok(());
+            }
+            &[]
+        } else {
+            bufs
+        };
+
+        let mut slices = Vec::with_capacity(bufs.len() + 1);
+        slices.push(IoSlice::new(&self.buffer));
+        slices.extend_from_slice(bufs);
+        let mut remaining = &mut slices[..];
+        IoSlice::advance_slices(&mut remaining, 0);
+        let len = remaining.iter().map(|slice| slice.len()).sum();
+        self.traffic.write_limit.acquire(len).await;
+        while !remaining.is_empty() {
+            let written = self.writer.write_vectored(remaining).await?;
+            if written == 0 {
+                return Err(io::Error::from(io::ErrorKind::WriteZero));
+            }
+            self.traffic.written(written);
+            IoSlice::advance_slices(&mut remaining, written);
+        }
+        self.buffer.clear();
+        self.writer.flush().await
+    }
+
+    /// Write the batched bytes and flush the transport.
+    async fn flush(&mut self) -> io::Result<()> {
+        self.write(&[], false).await
+    }
+}
+
+impl AsyncWrite for Writer {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(half) => Pin::new(half).poll_write(cx, buf),
+            Self::Stream(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(half) => Pin::new(half).poll_write_vectored(cx, bufs),
+            Self::Stream(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Tcp(half) => half.is_write_vectored(),
+            Self::Stream(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(half) => Pin::new(half).poll_flush(cx),
+            Self::Stream(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(half) => Pin::new(half).poll_shutdown(cx),
+            Self::Stream(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
 /// Socket Abstract Structure
 ///
 /// Used to abstract Oblivion's handling of transmitted data, wrapping all data type conversions.
-#[derive(Debug)]
+///
+/// Reads go through a buffer, see [`Inbound`], so the length prefixes and short messages of a
+/// chatty peer cost one read of the transport for many of them.
+///
+/// ```rust
+/// # use oblivion::utils::gear::Socket;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let (socket, peer) = Socket::pair();
+///
+/// // A short message and the start of a long one arrive together, the long one is read
+/// // partly from the buffer and partly from the transport.
+/// let long = vec![7; 32 * 1024];
+/// let mut data = [&2u32.to_be_bytes()[..], b"hi", &(long.len() as u32).to_be_bytes()].concat();
+/// data.extend_from_slice(&long);
+/// peer.send(&data).await?;
+///
+/// let length = socket.recv_usize().await?;
+/// assert_eq!(socket.recv_str(length).await?, "hi");
+/// let length = socket.recv_usize().await?;
+/// assert_eq!(socket.recv(length).await?, long);
+/// # Ok(())
+/// # }
+/// ```
 pub struct Socket {
-    pub reader: Mutex<OwnedReadHalf>,
-    pub writer: Mutex<OwnedWriteHalf>,
+    pub reader: Mutex<Inbound>,
+    pub writer: Mutex<Outbound>,
+    /// Kind of the failure if the address of a TCP peer couldn't be read.
+    peer: Result<Peer, io::ErrorKind>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    max_frame_size: usize,
+    write_shutdown: AtomicBool,
+    /// Number of [`Corked`] guards alive.
+    corks: AtomicUsize,
+    /// Set once a read or a write timed out midway, see [`Socket::is_poisoned`].
+    poisoned: AtomicBool,
+    traffic: Arc<Traffic>,
 }
 
 impl Socket {
+    /// Socket over `tcp`, whose peer address is read right away.
+    ///
+    /// If it can't be, [`Socket::peer`] and [`Socket::peer_addr`] fail with the reason, use
+    /// [`Socket::from_tcp`] to fail here instead.
     pub fn new(tcp: TcpStream) -> Self {
+        let peer = tcp.peer_addr().map(Peer::Tcp).map_err(|error| error.kind());
+        let (reader, writer) = tcp.into_split();
+        Self::from_halves(Box::new(reader), Writer::Tcp(writer), peer)
+    }
+
+    /// Socket over `tcp`, failing if the address of its peer can't be read.
+    pub fn from_tcp(tcp: TcpStream) -> Result<Self> {
+        let peer = Peer::Tcp(tcp.peer_addr()?);
         let (reader, writer) = tcp.into_split();
+        Ok(Self::from_halves(
+            Box::new(reader),
+            Writer::Tcp(writer),
+            Ok(peer),
+        ))
+    }
+
+    /// Connect to `address`, failing with a timeout of phase [`TimeoutPhase::Connect`] if no
+    /// connection was made within `timeout`.
+    ///
+    /// The addresses it resolves to are tried in turn, the last failure is returned if none
+    /// accepts the connection: [`Exception::ConnectionRefusedError`] if it was refused and
+    /// [`Exception::Unreachable`] if there is no route to it.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use tokio::net::TcpListener;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// let address = listener.local_addr()?;
+    /// let socket = Socket::connect(address, Some(Duration::from_secs(5))).await?;
+    /// assert_eq!(socket.peer_addr().await?, address);
+    ///
+    /// drop(listener);
+    /// let error = Socket::connect(address, None).await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::ConnectionRefusedError));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect(address: impl ToSocketAddrs, timeout: Option<Duration>) -> Result<Self> {
+        Self::connect_with_token(address, timeout, &CancellationToken::new()).await
+    }
+
+    /// Connect like [`Socket::connect`], giving up with [`Exception::Cancelled`] as soon as
+    /// `token` is cancelled.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::utils::cancel::CancellationToken;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let token = CancellationToken::new();
+    /// token.cancel();
+    /// let error = Socket::connect_with_token("127.0.0.1:1", None, &token)
+    ///     .await
+    ///     .unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::Cancelled));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn connect_with_token(
+        address: impl ToSocketAddrs,
+        timeout: Option<Duration>,
+        token: &CancellationToken,
+    ) -> Result<Self> {
+        let connect = async {
+            match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, dial_any(address))
+                    .await
+                    .map_err(|_| Exception::Timeout {
+                        phase: TimeoutPhase::Connect,
+                    })?,
+                None => dial_any(address).await,
+            }
+        };
+        let tcp = tokio::select! {
+            biased;
+            _ = token.cancelled() => return Err(Exception::Cancelled.into()),
+            connected = connect => connected?,
+        };
+        TcpOptions::default().apply(&tcp)?;
+        Self::from_tcp(tcp)
+    }
+
+    /// Socket over a Unix domain socket, see [`Socket::peer_addr`].
+    #[cfg(unix)]
+    pub fn from_unix(stream: UnixStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Self::from_halves(
+            Box::new(reader),
+            Writer::Stream(Box::new(writer)),
+            Ok(Peer::Unix),
+        )
+    }
+
+    /// Socket over one end of an in-memory stream, to run sessions without a network.
+    ///
+    /// ```rust
+    /// # use oblivion::models::session::{Session, SessionBuilder};
+    /// # use oblivion::utils::gear::{Peer, Socket};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = tokio::io::duplex(64 * 1024);
+    /// let server = tokio::spawn(async move {
+    ///     let mut session = Session::new(Socket::from_duplex(server))?;
+    ///     session.handshake(1).await?;
+    ///     assert_eq!(session.request.get_entrance(), "/memory");
+    ///     session.send_and_close(b"in memory".to_vec(), 200).await?;
+    ///     anyhow::Ok(())
+    /// });
+    ///
+    /// let socket = Socket::from_duplex(client);
+    /// assert_eq!(socket.peer()?, Peer::Memory);
+    /// let session = SessionBuilder::new()
+    ///     .header("GET /memory Oblivion/2.0")
+    ///     .establish(socket, 0)
+    ///     .await?;
+    /// assert_eq!(session.recv().await?.text()?, "in memory");
+    /// server.await??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_duplex(stream: DuplexStream) -> Self {
+        Self::from_stream(stream, Peer::Memory)
+    }
+
+    /// Two sockets connected in memory, bytes sent to one are received from the other.
+    ///
+    /// This is the recommended way to test sessions and handlers without a listener: both
+    /// ends report [`LOCAL_PEER`] and closing one ends the stream of the other, like a TCP
+    /// connection would. Writes wait once [`PAIR_BUFFER_SIZE`] bytes are left unread.
+    ///
+    /// ```rust
+    /// # use oblivion::utils::gear::{Socket, LOCAL_PEER};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (left, right) = Socket::pair();
+    /// assert_eq!(right.peer_addr().await?, LOCAL_PEER);
+    ///
+    /// left.send(b"ping").await?;
+    /// assert_eq!(right.recv(4).await?, &b"ping"[..]);
+    ///
+    /// left.close().await?;
+    /// assert!(right.recv(1).await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pair() -> (Self, Self) {
+        let (left, right) = tokio::io::duplex(PAIR_BUFFER_SIZE);
+        (Self::from_duplex(left), Self::from_duplex(right))
+    }
+
+    /// Socket over any transport, such as a TLS stream, whose peer is `peer`.
+    pub fn from_stream<S>(stream: S, peer: Peer) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        Self::from_halves(Box::new(reader), Writer::Stream(Box::new(writer)), Ok(peer))
+    }
+
+    fn from_halves(reader: Reader, writer: Writer, peer: Result<Peer, io::ErrorKind>) -> Self {
+        let traffic = Arc::new(Traffic::new());
         Self {
-            reader: Mutex::new(reader),
-            writer: Mutex::new(writer),
+            reader: Mutex::new(Inbound::new(reader, traffic.clone())),
+            writer: Mutex::new(Outbound::new(writer, traffic.clone())),
+            peer,
+            read_timeout: None,
+            write_timeout: None,
+            max_frame_size: MAX_FRAME_SIZE,
+            write_shutdown: AtomicBool::new(false),
+            corks: AtomicUsize::new(0),
+            poisoned: AtomicBool::new(false),
+            traffic,
         }
     }
 
+    /// Fail reads that don't complete within `timeout` with a timeout of phase
+    /// [`TimeoutPhase::Read`], `None` waits forever.
+    ///
+    /// A read that timed out may have consumed part of a message, so it
+    /// [poisons](Socket::is_poisoned) the socket.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::exceptions::{Exception, TimeoutPhase};
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (mut socket, peer) = Socket::pair();
+    /// socket.set_read_timeout(Some(Duration::from_millis(100)));
+    ///
+    /// // Half of a length prefix arrives, the rest never does.
+    /// peer.send(&[0, 0]).await?;
+    /// let error = socket.recv_usize().await.unwrap_err();
+    /// let timeout = Exception::Timeout { phase: TimeoutPhase::Read };
+    /// assert_eq!(error.downcast_ref(), Some(&timeout));
+    /// assert!(socket.is_poisoned());
+    ///
+    /// // What follows can't be told apart from the rest of the prefix anymore.
+    /// peer.send(&[0, 1]).await?;
+    /// let error = socket.recv_usize().await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::ConnectionClosed));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Fail writes that don't complete within `timeout` with a timeout of phase
+    /// [`TimeoutPhase::Write`], such as when the peer stopped reading. `None` waits forever.
+    ///
+    /// A write that timed out may have sent part of a message, so it
+    /// [poisons](Socket::is_poisoned) the socket.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    /// Refuse length prefixes over `size` bytes, defaults to [`MAX_FRAME_SIZE`].
+    ///
+    /// Lengths are checked by [`Socket::recv_usize`], [`Socket::recv`] and [`Socket::recv_str`]
+    /// before anything is allocated for them. One over the limit fails with
+    /// [`Exception::FrameTooLarge`], [poisons](Socket::is_poisoned) the socket and closes it.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::utils::gear::{Socket, MAX_FRAME_SIZE};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (socket, peer) = Socket::pair();
+    ///
+    /// peer.send(&u32::MAX.to_be_bytes()).await?;
+    /// let error = socket.recv_usize().await.unwrap_err();
+    /// let too_large = Exception::FrameTooLarge {
+    ///     declared: u32::MAX as usize,
+    ///     limit: MAX_FRAME_SIZE,
+    /// };
+    /// assert_eq!(error.downcast_ref(), Some(&too_large));
+    /// assert!(socket.is_poisoned());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_max_frame_size(&mut self, size: usize) {
+        self.max_frame_size = size;
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Pass `declared` on if it is within the [frame limit](Socket::set_max_frame_size), or
+    /// give up on the connection.
+    async fn frame(&self, declared: usize) -> Result<usize> {
+        if declared <= self.max_frame_size {
+            return Ok(declared);
+        }
+        self.poisoned.store(true, Ordering::Relaxed);
+        let _ = self.close().await;
+        Err(Exception::FrameTooLarge {
+            declared,
+            limit: self.max_frame_size,
+        }
+        .into())
+    }
+
+    /// Whether a read or a write timed out, or a length prefix was over the limit, leaving the
+    /// stream out of step with its framing.
+    ///
+    /// Every read and write of a poisoned socket fails with [`Exception::ConnectionClosed`].
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    /// Bytes read from the transport so far, including those buffered but not received yet.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// client.send(b"Hello, world").await?;
+    /// assert_eq!(server.recv_str(5).await?, "Hello");
+    /// assert_eq!((client.bytes_written(), server.bytes_read()), (12, 12));
+    ///
+    /// tokio::time::sleep(Duration::from_millis(50)).await;
+    /// assert!(server.idle_for() >= Duration::from_millis(50));
+    /// server.send(b"!").await?;
+    /// assert!(server.idle_for() < Duration::from_millis(50));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn bytes_read(&self) -> u64 {
+        self.traffic.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Bytes written to the transport so far, bytes batched while
+    /// [corked](Socket::corked) count once they are sent.
+    #[inline]
+    pub fn bytes_written(&self) -> u64 {
+        self.traffic.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Time since bytes were last read from or written to the transport, or since the socket
+    /// was created if none were.
+    #[inline]
+    pub fn idle_for(&self) -> Duration {
+        self.traffic.idle_for()
+    }
+
+    /// Limit the throughput of reads and writes, `None` leaves a direction unlimited.
+    ///
+    /// Reads and writes wait once their budget is used up, until enough of it was refilled,
+    /// time spent waiting counts towards the [read](Socket::set_read_timeout) and
+    /// [write](Socket::set_write_timeout) timeouts. Everything sent or received through the
+    /// socket is limited, sessions over it included.
+    ///
+    /// ```rust
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use oblivion::utils::throttle::RateLimit;
+    /// # use tokio::time::Instant;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, _server) = Socket::pair();
+    /// let limit = RateLimit::new(64 * 1024).burst(16 * 1024);
+    /// let client = Arc::new(client.with_rate_limit(None, Some(limit)));
+    ///
+    /// let started = Instant::now();
+    /// for _ in 0..3 {
+    ///     client.send(&[0; 16 * 1024]).await?;
+    /// }
+    /// assert!(started.elapsed() >= Duration::from_millis(500));
+    ///
+    /// // A write waiting for its budget goes through as soon as the limit is lifted.
+    /// client.set_rate_limit(None, Some(RateLimit::new(1)));
+    /// client.send(b"ab").await?;
+    /// // Limiting reads leaves the budget of writes as it was.
+    /// client.set_rate_limit(Some(RateLimit::new(1024)), Some(RateLimit::new(1)));
+    /// let waiting = tokio::spawn({
+    ///     let client = client.clone();
+    ///     async move { client.send(b"c").await }
+    /// });
+    /// tokio::time::sleep(Duration::from_millis(50)).await;
+    /// assert!(!waiting.is_finished());
+    ///
+    /// client.set_rate_limit(None, None);
+    /// tokio::time::timeout(Duration::from_millis(100), waiting).await???;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_rate_limit(self, read: Option<RateLimit>, write: Option<RateLimit>) -> Self {
+        self.set_rate_limit(read, write);
+        self
+    }
+
+    /// Replace the limits set by [`Socket::with_rate_limit`], taking effect right away for
+    /// the reads and writes waiting meanwhile. A direction whose limit is unchanged keeps its
+    /// budget, the others start again with a full one.
+    pub fn set_rate_limit(&self, read: Option<RateLimit>, write: Option<RateLimit>) {
+        self.traffic.read_limit.set(read);
+        self.traffic.write_limit.set(write);
+    }
+
+    pub fn rate_limit(&self) -> (Option<RateLimit>, Option<RateLimit>) {
+        (
+            self.traffic.read_limit.limit(),
+            self.traffic.write_limit.limit(),
+        )
+    }
+
+    /// Run `operation` within `timeout`, poisoning the socket if it runs out of time.
+    ///
+    /// Failures are reported as the exception they amount to, such as
+    /// [`Exception::PeerReset`], timeouts of the transport itself included.
+    async fn deadline<T, E: Into<Exception>>(
+        &self,
+        timeout: Option<Duration>,
+        phase: TimeoutPhase,
+        operation: impl Future<Output = Result<T, E>>,
+    ) -> Result<T> {
+        if self.is_poisoned() {
+            return Err(Exception::ConnectionClosed.into());
+        }
+        let classify = |error: E| match error.into() {
+            Exception::IoError {
+                kind: io::ErrorKind::TimedOut,
+                ..
+            } => Exception::Timeout { phase },
+            exception => exception,
+        };
+        let Some(timeout) = timeout else {
+            return Ok(operation.await.map_err(classify)?);
+        };
+        match tokio::time::timeout(timeout, operation).await {
+            Ok(result) => Ok(result.map_err(classify)?),
+            Err(_) => {
+                self.poisoned.store(true, Ordering::Relaxed);
+                Err(Exception::Timeout { phase }.into())
+            }
+        }
+    }
+
+    async fn read_exact(&self, buffer: &mut [u8]) -> Result<()> {
+        let mut reader = self.reader.lock().await;
+        self.deadline(
+            self.read_timeout,
+            TimeoutPhase::Read,
+            reader.read_exact(buffer),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Disable Nagle's algorithm, which delays short writes to coalesce them, if `nodelay`.
+    ///
+    /// Like the other TCP options it is ignored by other transports, including TLS streams
+    /// whose connections are tuned through
+    /// [`ServerConfig::nodelay`](crate::models::server::ServerConfig::nodelay) or
+    /// [`ClientBuilder::nodelay`](crate::models::client::ClientBuilder::nodelay) instead.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use oblivion::utils::gear::Socket;
+    /// # use tokio::net::{TcpListener, TcpStream};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let listener = TcpListener::bind("127.0.0.1:0").await?;
+    /// # let address = listener.local_addr()?;
+    /// let socket = Socket::from_tcp(TcpStream::connect(address).await?)?;
+    /// socket.set_nodelay(false).await?;
+    /// socket.set_tcp_keepalive(Some(Duration::from_secs(30))).await?;
+    /// socket.set_linger(Some(Duration::from_secs(1))).await?;
+    ///
+    /// // In-memory streams have nothing to tune.
+    /// let socket = Socket::from_duplex(tokio::io::duplex(1024).0);
+    /// socket.set_nodelay(false).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        if let Some(tcp) = self.writer.lock().await.writer.tcp() {
+            tcp.set_nodelay(nodelay)?;
+        }
+        Ok(())
+    }
+
+    /// Probe the peer once the connection was idle for `time`, or never if `None`.
+    pub async fn set_tcp_keepalive(&self, time: Option<Duration>) -> Result<()> {
+        if let Some(tcp) = self.writer.lock().await.writer.tcp() {
+            set_keepalive(tcp, time)?;
+        }
+        Ok(())
+    }
+
+    /// Wait up to `linger` for unsent data once closed, or let the system send it in the
+    /// background if `None`.
+    pub async fn set_linger(&self, linger: Option<Duration>) -> Result<()> {
+        if let Some(tcp) = self.writer.lock().await.writer.tcp() {
+            tcp.set_linger(linger)?;
+        }
+        Ok(())
+    }
+
+    /// Peer of the socket, failing for a TCP peer whose address couldn't be read, see
+    /// [`Socket::new`].
+    pub fn peer(&self) -> Result<Peer> {
+        Ok(self.peer.map_err(io::Error::from)?)
+    }
+
+    /// Address of the peer.
+    ///
+    /// Peers without an IP address, such as over Unix domain sockets or in-memory streams, are
+    /// on the same host and report [`LOCAL_PEER`].
     #[inline]
     pub async fn peer_addr(&self) -> Result<SocketAddr> {
-        Ok(self.writer.lock().await.peer_addr()?)
+        match self.peer()? {
+            Peer::Tcp(address) => Ok(address),
+            Peer::Unix | Peer::Memory => Ok(LOCAL_PEER),
+        }
     }
 
+    /// Receive a length prefix, see [`parse_length`].
+    ///
+    /// A stream ending partway through the prefix fails with [`Exception::BadLengthPrefix`]
+    /// holding the bytes received, a length over the [frame limit](Socket::set_max_frame_size)
+    /// with [`Exception::FrameTooLarge`].
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// client.send(&[0, 0, 0, 3, 0, 1]).await?;
+    /// client.close().await?;
+    ///
+    /// assert_eq!(server.recv_usize().await?, 3);
+    /// let error = server.recv_usize().await.unwrap_err();
+    /// let truncated = Exception::BadLengthPrefix { prefix: vec![0, 1] };
+    /// assert_eq!(error.downcast_ref(), Some(&truncated));
+    /// assert_eq!(error.to_string(), "Length prefix \"0001\" is malformed, 4 bytes are expected.");
+    /// # Ok(())
+    /// # }
+    /// ```
     #[inline]
     pub async fn recv_usize(&self) -> Result<usize> {
-        let mut len_bytes = [0; 4];
-        #[cfg(not(feature = "perf"))]
-        self.reader.lock().await.read_exact(&mut len_bytes).await?;
+        #[cfg(feature = "perf")]
+        let now = tokio::time::Instant::now();
+        let mut reader = self.reader.lock().await;
         #[cfg(feature = "perf")]
         {
             use colored::Colorize;
-            let now = tokio::time::Instant::now();
-            let mut reader = self.reader.lock().await;
             println!(
                 "夺锁时长: {}μs",
                 now.elapsed().as_micros().to_string().bright_magenta()
             );
-            reader.read_exact(&mut len_bytes).await?;
         }
-        Ok(u32::from_be_bytes(len_bytes) as usize)
+        let fill = reader.fill_to(LENGTH_PREFIX_SIZE);
+        if let Err(error) = self
+            .deadline(self.read_timeout, TimeoutPhase::Read, fill)
+            .await
+        {
+            let ended = matches!(error.downcast_ref(), Some(Exception::UnexpectedEof { .. }));
+            if ended && !reader.buffer.is_empty() {
+                let prefix = reader.buffer.split().to_vec();
+                return Err(Exception::BadLengthPrefix { prefix }.into());
+            }
+            return Err(reading("length prefix")(error));
+        }
+        let mut prefix = [0; LENGTH_PREFIX_SIZE];
+        reader.buffer.copy_to_slice(&mut prefix);
+        drop(reader);
+        self.frame(parse_length(&prefix)?).await
     }
 
     #[inline]
     pub async fn recv_u32(&self) -> Result<u32> {
         let mut len_bytes = [0; 4];
-        self.reader.lock().await.read_exact(&mut len_bytes).await?;
+        self.read_exact(&mut len_bytes).await?;
         Ok(u32::from_be_bytes(len_bytes))
     }
 
+    /// Receive `len` bytes, sharing the read buffer rather than copying them out of it.
+    ///
+    /// Fails with [`Exception::UnexpectedEof`] if the stream ends before they all arrived.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// client.send(b"Hello, wor").await?;
+    /// client.close().await?;
+    ///
+    /// assert_eq!(server.recv(5).await?, &b"Hello"[..]);
+    /// let error = server.recv(7).await.unwrap_err();
+    /// let ended = Exception::UnexpectedEof {
+    ///     reading: "frame".to_string(),
+    ///     expected: 7,
+    ///     got: 5,
+    /// };
+    /// assert_eq!(error.downcast_ref(), Some(&ended));
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "Connection closed while reading frame after 5 of 7 bytes."
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
     #[inline]
-    pub async fn recv(&self, len: usize) -> Result<Vec<u8>> {
-        let mut recv_bytes: Vec<u8> = vec![0; len];
-        self.reader.lock().await.read_exact(&mut recv_bytes).await?;
-        Ok(recv_bytes)
+    pub async fn recv(&self, len: usize) -> Result<Bytes> {
+        let len = self.frame(len).await?;
+        let mut reader = self.reader.lock().await;
+        self.deadline(self.read_timeout, TimeoutPhase::Read, reader.fill_to(len))
+            .await?;
+        Ok(reader.take(len))
     }
 
     #[inline]
     pub async fn recv_str(&self, len: usize) -> Result<String> {
-        let mut recv_bytes: Vec<u8> = vec![0; len];
-        self.reader.lock().await.read_exact(&mut recv_bytes).await?;
-        Ok(String::from_utf8(recv_bytes)?)
+        Ok(std::str::from_utf8(&self.recv(len).await?)?.to_string())
+    }
+
+    /// Receive the frame `take` splits off the front of the buffered bytes, reading more
+    /// whenever the frame isn't whole yet.
+    ///
+    /// The frame shares the read buffer rather than being copied out of it. As long as `take`
+    /// only ever removes whole frames, this is cancellation safe like [`Socket::recv_into`].
+    /// If the stream ends first, it fails with [`Exception::UnexpectedEof`] reporting the
+    /// bytes `take` last needed, or with [`Exception::ConnectionClosed`] if nothing was left
+    /// in the buffer.
+    pub async fn recv_frame<T>(
+        &self,
+        mut take: impl FnMut(&mut BytesMut) -> Result<Framing<T>>,
+    ) -> Result<T> {
+        let mut reader = self.reader.lock().await;
+        loop {
+            let buffered = reader.buffer.len();
+            let needed = match take(&mut reader.buffer)? {
+                Framing::Complete(frame) => {
+                    let len = buffered - reader.buffer.len();
+                    reader.release(len);
+                    return Ok(frame);
+                }
+                Framing::Incomplete { needed } => needed,
+            };
+            let inbound = &mut *reader;
+            let fill = async move {
+                if inbound.fill(RECV_BUFFER_SIZE).await? == 0 {
+                    return Err(inbound.ended(needed));
+                }
+                Ok(())
+            };
+            self.deadline(self.read_timeout, TimeoutPhase::Read, fill)
+                .await?;
+        }
+    }
+
+    /// Up to `n` of the bytes the next reads return, without consuming them.
+    ///
+    /// Reads once unless `n` bytes are buffered already, so fewer than `n` bytes may be
+    /// returned even though more are on their way, the next call waiting for more. Returns
+    /// those buffered once the stream has ended.
+    ///
+    /// ```rust
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = tokio::io::duplex(1024);
+    /// let (client, server) = (Socket::from_duplex(client), Socket::from_duplex(server));
+    /// client.send(b"GE").await?;
+    /// assert_eq!(server.peek(4).await?, b"GE");
+    /// client.send(b"T / HTTP/1.1").await?;
+    ///
+    /// assert_eq!(server.peek(4).await?, b"GET ");
+    /// assert_eq!(server.peek(4).await?, b"GET ");
+    /// assert_eq!(server.recv_str(14).await?, "GET / HTTP/1.1");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn peek(&self, n: usize) -> Result<Vec<u8>> {
+        let mut reader = self.reader.lock().await;
+        if reader.buffer.len() < n {
+            let Inbound {
+                reader,
+                buffer,
+                traffic,
+            } = &mut *reader;
+            buffer.reserve(READ_BUFFER_SIZE);
+            traffic.read_limit.acquire(0).await;
+            let read = self
+                .deadline(
+                    self.read_timeout,
+                    TimeoutPhase::Read,
+                    reader.read_buf(buffer),
+                )
+                .await?;
+            traffic.read(read);
+        }
+        Ok(reader.buffer[..n.min(reader.buffer.len())].to_vec())
+    }
+
+    /// Append whatever data is available to `buffer`, failing once the stream has ended.
+    ///
+    /// Unlike the other receive methods this is cancellation safe, dropping the future before
+    /// it completes never loses data.
+    pub async fn recv_into(&self, buffer: &mut Vec<u8>) -> Result<usize> {
+        let mut reader = self.reader.lock().await;
+        if !reader.buffer.is_empty() {
+            let buffered = reader.buffer.split();
+            buffer.extend_from_slice(&buffered);
+            return Ok(buffered.len());
+        }
+        buffer.reserve(RECV_BUFFER_SIZE);
+        let read = self
+            .deadline(
+                self.read_timeout,
+                TimeoutPhase::Read,
+                reader.read_into(buffer),
+            )
+            .await?;
+        if read == 0 {
+            return Err(Exception::from(io::Error::from(io::ErrorKind::UnexpectedEof)).into());
+        }
+        Ok(read)
     }
 
     #[inline]
     pub async fn send(&self, data: &[u8]) -> Result<()> {
+        self.send_vectored(&[IoSlice::new(data)]).await
+    }
+
+    /// Send every buffer of `bufs` in turn, in as few writes as the transport allows.
+    ///
+    /// ```rust
+    /// # use std::io::IoSlice;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = tokio::io::duplex(1024);
+    /// let (client, server) = (Socket::from_duplex(client), Socket::from_duplex(server));
+    ///
+    /// let parts = [IoSlice::new(b"Hello"), IoSlice::new(b""), IoSlice::new(b", world")];
+    /// client.send_vectored(&parts).await?;
+    /// assert_eq!(server.recv_str(12).await?, "Hello, world");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_vectored(&self, bufs: &[IoSlice<'_>]) -> Result<()> {
+        self.check_writable()?;
+        let mut writer = self.writer.lock().await;
+        let corked = self.corks.load(Ordering::Acquire) > 0;
+        self.deadline(
+            self.write_timeout,
+            TimeoutPhase::Write,
+            writer.write(bufs, corked),
+        )
+        .await
+    }
+
+    /// Send what was batched while [corked](Socket::corked) right away.
+    pub async fn flush(&self) -> Result<()> {
         let mut writer = self.writer.lock().await;
-        writer.write_all(data).await?;
-        writer.flush().await?;
+        self.deadline(self.write_timeout, TimeoutPhase::Write, writer.flush())
+            .await
+    }
+
+    /// Batch what is sent until the returned guard is dropped, then send it in one write.
+    ///
+    /// Every send made meanwhile, from any task, is added to the batch, which is sent early
+    /// once it reaches [`WRITE_BUFFER_SIZE`] bytes, by [`Socket::flush`] or when the socket
+    /// is closed. Guards can be nested, the batch is sent once the last one is dropped.
+    ///
+    /// ```rust
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = Socket::pair();
+    /// let client = Arc::new(client);
+    ///
+    /// let cork = client.corked();
+    /// for frame in ["one", "two", "three"] {
+    ///     client.send(&(frame.len() as u32).to_be_bytes()).await?;
+    ///     client.send(frame.as_bytes()).await?;
+    /// }
+    /// let waiting = tokio::time::timeout(Duration::from_millis(50), server.peek(1)).await;
+    /// assert!(waiting.is_err());
+    ///
+    /// cork.uncork().await?;
+    /// for frame in ["one", "two", "three"] {
+    ///     let length = server.recv_usize().await?;
+    ///     assert_eq!(server.recv_str(length).await?, frame);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn corked(self: &Arc<Self>) -> Corked {
+        self.corks.fetch_add(1, Ordering::AcqRel);
+        Corked {
+            socket: Some(Arc::clone(self)),
+        }
+    }
+
+    /// Shut down the sending side, the peer reads the end of the stream once it received
+    /// everything sent before while this side can still read what the peer sends.
+    ///
+    /// Sending afterwards fails with [`Exception::WriteShutdown`], shutting down again does
+    /// nothing.
+    ///
+    /// ```rust
+    /// # use oblivion::exceptions::Exception;
+    /// # use oblivion::utils::gear::Socket;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let (client, server) = tokio::io::duplex(1024);
+    /// let (client, server) = (Socket::from_duplex(client), Socket::from_duplex(server));
+    ///
+    /// client.send(b"upload").await?;
+    /// client.shutdown_write().await?;
+    /// let error = client.send(b"more").await.unwrap_err();
+    /// assert_eq!(error.downcast_ref(), Some(&Exception::WriteShutdown));
+    ///
+    /// assert_eq!(server.recv_str(6).await?, "upload");
+    /// assert!(server.recv_usize().await.is_err());
+    /// server.send(b"done").await?;
+    /// assert_eq!(client.recv_str(4).await?, "done");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn shutdown_write(&self) -> Result<()> {
+        let mut writer = self.writer.lock().await;
+        if self.write_shutdown.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+        writer.flush().await.map_err(Exception::from)?;
+        writer.writer.shutdown().await.map_err(Exception::from)?;
+        Ok(())
+    }
+
+    /// Whether the sending side was shut down, see [`Socket::shutdown_write`].
+    pub fn is_write_shutdown(&self) -> bool {
+        self.write_shutdown.load(Ordering::Relaxed)
+    }
+
+    fn check_writable(&self) -> Result<(), Exception> {
+        if self.is_write_shutdown() {
+            return Err(Exception::WriteShutdown);
+        }
         Ok(())
     }
 
+    /// Shut down the sending side, see [`Socket::shutdown_write`].
     pub async fn close(&self) -> Result<()> {
-        self.writer.lock().await.shutdown().await?;
+        self.shutdown_write().await
+    }
+}
+
+/// Guard batching what is sent to a socket, see [`Socket::corked`].
+///
+/// Dropping it sends the batch in the background, [`Corked::uncork`] waits for it to be
+/// sent instead. Closing the socket sends it too, even if the guard is never dropped. Guards
+/// must be dropped within a Tokio runtime, the batch is lost otherwise and a warning is
+/// emitted through `tracing`.
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use oblivion::utils::gear::Socket;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let (client, server) = Socket::pair();
+/// let client = Arc::new(client);
+///
+/// let cork = client.corked();
+/// client.send(b"dropped").await?;
+/// drop(cork);
+/// assert_eq!(server.recv_str(7).await?, "dropped");
+///
+/// std::mem::forget(client.corked());
+/// client.send(b"closed").await?;
+/// client.close().await?;
+/// assert_eq!(server.recv_str(6).await?, "closed");
+/// assert!(server.recv(1).await.is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "the socket is uncorked as soon as the guard is dropped"]
+pub struct Corked {
+    socket: Option<Arc<Socket>>,
+}
+
+impl Corked {
+    /// Stop batching and send the batch, unless other guards still cork the socket.
+    pub async fn uncork(mut self) -> Result<()> {
+        let socket = self.socket.take().unwrap();
+        if socket.corks.fetch_sub(1, Ordering::AcqRel) == 1 {
+            socket.flush().await?;
+        }
         Ok(())
     }
 }
+
+impl Drop for Corked {
+    fn drop(&mut self) {
+        let Some(socket) = self.socket.take() else {
+            return;
+        };
+        if socket.corks.fetch_sub(1, Ordering::AcqRel) > 1 {
+            return;
+        }
+        // Sending can't be waited for here, sends made meanwhile go out after the batch since
+        // every send starts with it.
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move { socket.flush().await });
+            }
+            // The transport belongs to a runtime, there is nothing to send the batch with.
+            Err(_) => tracing::warn!(
+                peer = ?socket.peer,
+                "corked socket dropped outside a Tokio runtime, its batch is lost"
+            ),
+        }
+    }
+}
+
+impl fmt::Debug for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Socket")
+            .field("peer", &self.peer)
+            .field("poisoned", &self.is_poisoned())
+            .finish_non_exhaustive()
+    }
+}
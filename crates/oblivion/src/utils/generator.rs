@@ -2,33 +2,130 @@
 extern crate rand;
 extern crate ring;
 
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
 use anyhow::Result;
 use hkdf::Hkdf;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 
-use ring::agreement::{agree_ephemeral, EphemeralPrivateKey, PublicKey, UnparsedPublicKey, X25519};
+use ring::agreement::{
+    agree_ephemeral, Algorithm, EphemeralPrivateKey, PublicKey, UnparsedPublicKey, ECDH_P256,
+    X25519,
+};
 
+use ring::rand::SecureRandom;
 use ring::rand::SystemRandom;
-use ring::{aead::AES_128_GCM, rand::SecureRandom};
 use scrypt::{scrypt, Params};
 use sha2::Sha256;
 
 use crate::exceptions::Exception;
 
+/// Length of the salts of handshakes and rekeys unless configured otherwise, the length of
+/// an AES-128 key.
+pub const SALT_LEN: usize = 16;
+
+/// Shortest salt that may be configured or is accepted from peers, see [`check_salt_len`].
+pub const MIN_SALT_LEN: usize = 16;
+
+/// Longest salt that may be configured or is accepted from peers.
+pub const MAX_SALT_LEN: usize = 64;
+
+/// First protocol version deriving a key for each direction, see [`SharedKey::session_keys`].
+pub const DIRECTIONAL_KEYS_VERSION: u32 = 2;
+
+/// Leading bytes of the HKDF info of [`SharedKey::session_keys`].
+const KEY_INFO_LABEL: &[u8] = b"oblivion session keys";
+
+/// Key exchange algorithm of a session, both sides must use the same one.
+///
+/// Sessions name theirs in the handshake preamble, see
+/// [`SessionBuilder::kex_algorithm`](crate::models::session::SessionBuilder::kex_algorithm).
+///
+/// ```rust
+/// # use oblivion::utils::generator::{KexAlgorithm, SharedKey};
+/// let (client_key, client_public) = KexAlgorithm::P256.generate_key_pair();
+/// let (server_key, server_public) = KexAlgorithm::P256.generate_key_pair();
+///
+/// let server_public = KexAlgorithm::P256.public_key(server_public.as_ref().to_vec());
+/// let client_public = KexAlgorithm::P256.public_key(client_public.as_ref().to_vec());
+/// let salt = [0; 16];
+/// assert_eq!(
+///     SharedKey::new(client_key, &server_public).unwrap().hkdf(&salt),
+///     SharedKey::new(server_key, &client_public).unwrap().hkdf(&salt),
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum KexAlgorithm {
+    /// Curve25519, which peers that don't name their algorithm use.
+    #[default]
+    X25519,
+    /// NIST P-256.
+    P256,
+}
+
+impl KexAlgorithm {
+    /// Identifier of the algorithm in the handshake preamble.
+    pub const fn id(self) -> u8 {
+        match self {
+            Self::X25519 => 0,
+            Self::P256 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, Exception> {
+        match id {
+            0 => Ok(Self::X25519),
+            1 => Ok(Self::P256),
+            id => Err(Exception::Unsupported {
+                feature: format!("key exchange algorithm {id}"),
+            }),
+        }
+    }
+
+    pub fn agreement(self) -> &'static Algorithm {
+        match self {
+            Self::X25519 => &X25519,
+            Self::P256 => &ECDH_P256,
+        }
+    }
+
+    /// Create an ephemeral key pair of the algorithm.
+    pub fn generate_key_pair(self) -> (EphemeralPrivateKey, PublicKey) {
+        let rng = SystemRandom::new();
+        let private_key = EphemeralPrivateKey::generate(self.agreement(), &rng).unwrap();
+        let public_key = private_key.compute_public_key().unwrap();
+        (private_key, public_key)
+    }
+
+    /// Public key of the peer from its raw bytes.
+    pub fn public_key(self, bytes: Vec<u8>) -> UnparsedPublicKey<Vec<u8>> {
+        UnparsedPublicKey::new(self.agreement(), bytes)
+    }
+}
+
+impl fmt::Display for KexAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::X25519 => "X25519",
+            Self::P256 => "P-256",
+        })
+    }
+}
+
 /// Create an ECC key
 ///
 /// `generate_key_pair` will create an ECC key and return a (private key, public key) pair of `(EphemeralSecret, PublicKey)`.
-/// 
-/// We use `X25519` curve for ECC operations.
+///
+/// We use `X25519` curve for ECC operations, see [`KexAlgorithm::generate_key_pair`] for others.
 ///
 /// ```rust
 /// # use oblivion::utils::generator::generate_key_pair;
 /// let (private_key, public_key) = generate_key_pair();
 /// ```
 pub fn generate_key_pair() -> (EphemeralPrivateKey, PublicKey) {
-    let rng = SystemRandom::new();
-    let private_key = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
-    let public_key = private_key.compute_public_key().unwrap();
-    (private_key, public_key)
+    KexAlgorithm::X25519.generate_key_pair()
 }
 
 /// Generate a Shared Key
@@ -43,7 +140,7 @@ pub fn generate_key_pair() -> (EphemeralPrivateKey, PublicKey) {
 /// # use oblivion::utils::generator::{generate_key_pair, generate_random_salt, SharedKey};
 /// # use ring::agreement::{UnparsedPublicKey, X25519};
 ///
-/// let salt = generate_random_salt();
+/// let salt = generate_random_salt(16);
 ///
 /// let (private_key, public_key) = generate_key_pair();
 ///
@@ -64,7 +161,40 @@ pub struct SharedKey {
     shared_key: Vec<u8>,
 }
 
+/// AES keys of a session, one for the messages of each side.
+///
+/// Sessions below [`DIRECTIONAL_KEYS_VERSION`] use the same key both ways.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionKeys {
+    pub client_to_server: [u8; 16],
+    pub server_to_client: [u8; 16],
+}
+
+impl SessionKeys {
+    /// The same key in both directions.
+    pub fn shared(key: [u8; 16]) -> Self {
+        Self {
+            client_to_server: key,
+            server_to_client: key,
+        }
+    }
+
+    /// Key of the messages sent by the client if `client`, by the server otherwise.
+    pub fn sent_by(&self, client: bool) -> &[u8; 16] {
+        if client {
+            &self.client_to_server
+        } else {
+            &self.server_to_client
+        }
+    }
+}
+
 impl SharedKey {
+    /// Shared secret computed elsewhere, to check a derivation against known values.
+    pub fn from_secret(shared_key: Vec<u8>) -> Self {
+        Self { shared_key }
+    }
+
     pub fn new(
         private_key: EphemeralPrivateKey,
         public_key: &UnparsedPublicKey<Vec<u8>>,
@@ -94,22 +224,157 @@ impl SharedKey {
         key.expand(&[], &mut aes_key).unwrap();
         aes_key
     }
+
+    /// Keys of a session speaking `protocol_version`.
+    ///
+    /// From [`DIRECTIONAL_KEYS_VERSION`] on, HKDF-SHA256 with `salt` expands the secret into
+    /// 32 bytes, the key of the client followed by the key of the server, with the info
+    /// returned by [`key_info`]. Older versions use [`SharedKey::hkdf`] both ways.
+    ///
+    /// ```rust
+    /// # use oblivion::utils::generator::{SessionKeys, SharedKey};
+    /// # fn hex(hex: &str) -> Vec<u8> {
+    /// #     (0..hex.len())
+    /// #         .step_by(2)
+    /// #         .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+    /// #         .collect()
+    /// # }
+    /// // X25519 keys of RFC 7748, section 6.1.
+    /// let client = hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a");
+    /// let server = hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f");
+    /// let secret = hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+    /// let salt: Vec<u8> = (0..16).collect();
+    ///
+    /// let mut shared_key = SharedKey::from_secret(secret);
+    /// let keys = shared_key.session_keys(&salt, 2, &client, &server);
+    /// assert_eq!(keys.client_to_server.to_vec(), hex("a3ec18f0c52e6c65381108fedb4347b3"));
+    /// assert_eq!(keys.server_to_client.to_vec(), hex("16590d17e5e50e7ae953a3bd577373b8"));
+    ///
+    /// let legacy = shared_key.session_keys(&salt, 1, &client, &server);
+    /// let key = hex("193a1658e238ccf07108d772c923f333").try_into().unwrap();
+    /// assert_eq!(legacy, SessionKeys::shared(key));
+    /// ```
+    pub fn session_keys(
+        &mut self,
+        salt: &[u8],
+        protocol_version: u32,
+        client_public_key: &[u8],
+        server_public_key: &[u8],
+    ) -> SessionKeys {
+        if protocol_version < DIRECTIONAL_KEYS_VERSION {
+            return SessionKeys::shared(self.hkdf(salt));
+        }
+        let info = key_info(protocol_version, client_public_key, server_public_key);
+        let key = Hkdf::<Sha256>::new(Some(salt), &self.shared_key);
+        let mut keys = [0u8; 32];
+        key.expand(&info, &mut keys).unwrap();
+        SessionKeys {
+            client_to_server: keys[..16].try_into().unwrap(),
+            server_to_client: keys[16..].try_into().unwrap(),
+        }
+    }
+}
+
+/// HKDF info binding the keys of a session to its protocol version and public keys.
+///
+/// `oblivion session keys`, the version as four big endian bytes, then the public keys of the
+/// client and of the server, each following its length as four big endian bytes.
+pub fn key_info(
+    protocol_version: u32,
+    client_public_key: &[u8],
+    server_public_key: &[u8],
+) -> Vec<u8> {
+    let mut info = KEY_INFO_LABEL.to_vec();
+    info.extend_from_slice(&protocol_version.to_be_bytes());
+    for key in [client_public_key, server_public_key] {
+        info.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        info.extend_from_slice(key);
+    }
+    info
 }
 
 /// Generate a Randomized Salt
 ///
-/// `generate_random_salt` will generate a random salt using the `ring` library.
+/// `generate_random_salt` will generate a random salt of `len` bytes using the `ring` library.
 ///
-/// The length of the salt is 16 bytes, which is the length of the key used for AES-GCM encryption.
+/// Every salt and AES key of the crate comes from it, [`SALT_LEN`] bytes being the length of
+/// the key used for AES-GCM encryption.
 ///
 /// # Example
 /// ```rust
-/// # use oblivion::utils::generator::generate_random_salt;
-/// let salt = generate_random_salt();
+/// # use oblivion::utils::generator::{generate_random_salt, SALT_LEN};
+/// let salt = generate_random_salt(SALT_LEN);
+/// assert_eq!(salt.len(), 16);
 /// ```
-pub fn generate_random_salt() -> Vec<u8> {
+pub fn generate_random_salt(len: usize) -> Vec<u8> {
     let rng = SystemRandom::new();
-    let mut key_bytes = vec![0; AES_128_GCM.key_len()];
+    let mut key_bytes = vec![0; len];
     rng.fill(&mut key_bytes).unwrap();
     key_bytes
 }
+
+/// Fail with [`Exception::InvalidSaltLength`] unless a salt of `len` bytes is between
+/// [`MIN_SALT_LEN`] and [`MAX_SALT_LEN`] bytes long.
+pub fn check_salt_len(len: usize) -> Result<(), Exception> {
+    if !(MIN_SALT_LEN..=MAX_SALT_LEN).contains(&len) {
+        return Err(Exception::InvalidSaltLength {
+            len,
+            min: MIN_SALT_LEN,
+            max: MAX_SALT_LEN,
+        });
+    }
+    Ok(())
+}
+
+/// Source of the salts a session generates for its handshake and rekeys,
+/// [`generate_random_salt`] by default.
+///
+/// Replacing it is meant for reproducible tests, salts must be unpredictable otherwise.
+///
+/// ```rust
+/// # use oblivion::utils::generator::SaltSource;
+/// let source = SaltSource::seeded(7);
+/// let first = source.generate(16);
+/// assert_eq!(first, SaltSource::seeded(7).generate(16));
+/// assert_ne!(source.generate(16), first);
+///
+/// let zeros = SaltSource::new(|len| vec![0; len]);
+/// assert_eq!(zeros.generate(32), [0; 32]);
+/// ```
+#[derive(Clone)]
+pub struct SaltSource(Arc<GenerateFn>);
+
+type GenerateFn = dyn Fn(usize) -> Vec<u8> + Send + Sync;
+
+impl SaltSource {
+    /// Generate salts of the requested length with `generate`.
+    pub fn new(generate: impl Fn(usize) -> Vec<u8> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(generate))
+    }
+
+    /// Generate the same sequence of salts whenever given the same `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        let rng = Mutex::new(StdRng::seed_from_u64(seed));
+        Self::new(move |len| {
+            let mut salt = vec![0; len];
+            rng.lock().unwrap().fill_bytes(&mut salt);
+            salt
+        })
+    }
+
+    pub fn generate(&self, len: usize) -> Vec<u8> {
+        (self.0)(len)
+    }
+}
+
+impl Default for SaltSource {
+    fn default() -> Self {
+        Self::new(generate_random_salt)
+    }
+}
+
+impl fmt::Debug for SaltSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SaltSource")
+    }
+}
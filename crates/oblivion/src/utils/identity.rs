@@ -0,0 +1,180 @@
+//! # Oblivion Identity
+//!
+//! Long-term Ed25519 keys authenticating the handshake, see
+//! [`SessionBuilder::identity`](crate::models::session::SessionBuilder::identity).
+use std::fmt;
+use std::sync::Arc;
+
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use sha2::{Digest, Sha256};
+
+use crate::exceptions::Exception;
+
+/// Leading bytes of the hash of the handshake signed by identity keys.
+const TRANSCRIPT_LABEL: &[u8] = b"oblivion handshake";
+/// Context of the signature of the server, preceding the hash of the handshake.
+const SERVER_CONTEXT: &[u8] = b"oblivion server identity";
+/// Context of the signature of the client.
+const CLIENT_CONTEXT: &[u8] = b"oblivion client identity";
+
+/// Ed25519 key a peer proves its identity with, cheap to clone.
+///
+/// ```rust
+/// # use oblivion::utils::identity::{fingerprint, IdentityKey};
+/// let document = IdentityKey::generate_pkcs8()?;
+/// let key = IdentityKey::from_pkcs8(&document)?;
+///
+/// // Give the public key, or its fingerprint, to the peers trusting it.
+/// assert_eq!(key.fingerprint(), fingerprint(&key.public_key()));
+/// assert_eq!(IdentityKey::from_pkcs8(&document)?.public_key(), key.public_key());
+/// # Ok::<(), oblivion::exceptions::Exception>(())
+/// ```
+#[derive(Clone)]
+pub struct IdentityKey {
+    key_pair: Arc<Ed25519KeyPair>,
+}
+
+impl fmt::Debug for IdentityKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdentityKey")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdentityKey {
+    /// New key as a PKCS#8 v2 document, to store and load with [`IdentityKey::from_pkcs8`].
+    pub fn generate_pkcs8() -> Result<Vec<u8>, Exception> {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|_| {
+            Exception::InvalidIdentityKey {
+                reason: "the system random number generator failed".to_string(),
+            }
+        })?;
+        Ok(document.as_ref().to_vec())
+    }
+
+    pub fn from_pkcs8(document: &[u8]) -> Result<Self, Exception> {
+        let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(document).map_err(|error| {
+            Exception::InvalidIdentityKey {
+                reason: error.to_string(),
+            }
+        })?;
+        Ok(Self {
+            key_pair: Arc::new(key_pair),
+        })
+    }
+
+    /// Key derived from the 32 bytes of its private seed.
+    pub fn from_seed(seed: &[u8; 32]) -> Result<Self, Exception> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed).map_err(|error| {
+            Exception::InvalidIdentityKey {
+                reason: error.to_string(),
+            }
+        })?;
+        Ok(Self {
+            key_pair: Arc::new(key_pair),
+        })
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key_pair.public_key().as_ref().try_into().unwrap()
+    }
+
+    /// SHA-256 fingerprint of the public key, see [`fingerprint`].
+    pub fn fingerprint(&self) -> [u8; 32] {
+        fingerprint(&self.public_key())
+    }
+
+    /// Signature of the handshake of `transcript` by the server if `server`, by the client
+    /// otherwise.
+    pub(crate) fn sign(&self, transcript: &[u8; 32], server: bool) -> Vec<u8> {
+        self.key_pair
+            .sign(&signed_message(transcript, server))
+            .as_ref()
+            .to_vec()
+    }
+}
+
+/// SHA-256 fingerprint of an identity public key.
+pub fn fingerprint(public_key: &[u8]) -> [u8; 32] {
+    Sha256::digest(public_key).into()
+}
+
+/// Check the signature of the handshake of `transcript` by the server if `server`.
+pub(crate) fn verify(
+    public_key: &[u8],
+    transcript: &[u8; 32],
+    server: bool,
+    signature: &[u8],
+) -> Result<(), Exception> {
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(&signed_message(transcript, server), signature)
+        .map_err(|_| Exception::InvalidSignature)
+}
+
+fn signed_message(transcript: &[u8; 32], server: bool) -> Vec<u8> {
+    let mut message = if server {
+        SERVER_CONTEXT
+    } else {
+        CLIENT_CONTEXT
+    }
+    .to_vec();
+    message.extend_from_slice(transcript);
+    message
+}
+
+/// SHA-256 hash of the handshake that identity keys sign.
+///
+/// `oblivion handshake`, the protocol version as four big endian bytes, then the preambles of
+/// the client and of the server, the ephemeral public keys of the client and of the server
+/// and the salt, each following its length as four big endian bytes. Preambles are empty
+/// without version negotiation, otherwise they bind the capabilities both sides advertised,
+/// so that none can be stripped unnoticed. The server signs `oblivion server identity`
+/// followed by the hash, the client `oblivion client identity`.
+pub fn transcript(
+    protocol_version: u32,
+    client_preamble: &[u8],
+    server_preamble: &[u8],
+    client_public_key: &[u8],
+    server_public_key: &[u8],
+    salt: &[u8],
+) -> [u8; 32] {
+    let mut hash = Sha256::new();
+    hash.update(TRANSCRIPT_LABEL);
+    hash.update(protocol_version.to_be_bytes());
+    for part in [
+        client_preamble,
+        server_preamble,
+        client_public_key,
+        server_public_key,
+        salt,
+    ] {
+        hash.update((part.len() as u32).to_be_bytes());
+        hash.update(part);
+    }
+    hash.finalize().into()
+}
+
+/// Callback accepting the identities of clients, registered with
+/// `SessionBuilder::verify_client_identity`.
+#[derive(Clone)]
+pub(crate) struct IdentityVerifier(Arc<VerifyFn>);
+
+type VerifyFn = dyn Fn(&[u8; 32]) -> bool + Send + Sync;
+
+impl IdentityVerifier {
+    pub(crate) fn new(verify: impl Fn(&[u8; 32]) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(verify))
+    }
+
+    pub(crate) fn accepts(&self, public_key: &[u8; 32]) -> bool {
+        (self.0)(public_key)
+    }
+}
+
+impl fmt::Debug for IdentityVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IdentityVerifier")
+    }
+}
@@ -3,17 +3,29 @@
 //! Used to parse and reconstruct data and store it.
 use anyhow::{Error, Result};
 use regex::Regex;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
 
 use crate::exceptions::Exception;
+use crate::models::extensions::Extensions;
+use crate::models::extract::JSON_LIMIT;
+use crate::models::middleware::{Completion, Completions};
+use crate::models::router::Params;
+use crate::models::server::States;
+
+/// Bytes of every length prefix, see [`length`].
+pub const LENGTH_PREFIX_SIZE: usize = 4;
 
 /// Packet size analysis function
 ///
-/// `length` accepts a `Vec<u8>` byte stream, gets its data size in no more than four digits,
-/// and throws an exception if the data is out of the expected range.
-///
-/// The final value it returns is a `Vec<u8>`, which consists of a four-digit number converted to a string.
+/// `length` accepts a byte stream and encodes its size as a length prefix of
+/// [`LENGTH_PREFIX_SIZE`] big-endian bytes, failing with [`Exception::DataTooLarge`] for more
+/// than 2048 bytes. [`parse_length`] decodes it.
 ///
 /// ```rust
 /// use oblivion::utils::parser::length;
@@ -21,9 +33,8 @@ use crate::exceptions::Exception;
 /// let vec = b"fw4rg45245yge8gew3rgq5erg342rg342gj2sdu".to_vec();
 ///
 /// assert_eq!((39 as u32).to_be_bytes(), length(&vec).unwrap());
+/// assert!(length(&[0; 2049]).is_err());
 /// ```
-///
-/// The `vec` in the above example is a `Vec<u8>` of length 39, and `length(&vec)` gets `b "0039".to_vec()`.
 pub fn length(bytes: &[u8]) -> Result<[u8; 4], Exception> {
     let size = bytes.len() as u32;
 
@@ -36,6 +47,102 @@ pub fn length(bytes: &[u8]) -> Result<[u8; 4], Exception> {
     Ok(size.to_be_bytes())
 }
 
+/// Decode a length prefix encoded by [`length`].
+///
+/// Prefixes are exactly [`LENGTH_PREFIX_SIZE`] bytes, anything else fails with
+/// [`Exception::BadLengthPrefix`] rather than being read as some length.
+///
+/// ```rust
+/// # use oblivion::exceptions::Exception;
+/// # use oblivion::utils::parser::{length, parse_length};
+/// # use rand::Rng;
+/// assert_eq!(parse_length(&length(&[7; 39])?)?, 39);
+/// assert_eq!(
+///     parse_length(b"+39"),
+///     Err(Exception::BadLengthPrefix { prefix: b"+39".to_vec() })
+/// );
+/// assert!(parse_length(b"").is_err());
+///
+/// // Whatever the peer sends, decoding it never panics.
+/// let mut rng = rand::thread_rng();
+/// for _ in 0..10_000 {
+///     let prefix: Vec<u8> = (0..rng.gen_range(0..8)).map(|_| rng.gen()).collect();
+///     match parse_length(&prefix) {
+///         Ok(len) => assert_eq!((len as u32).to_be_bytes()[..], prefix[..]),
+///         Err(error) => {
+///             assert_ne!(prefix.len(), 4);
+///             assert_eq!(error, Exception::BadLengthPrefix { prefix });
+///         }
+///     }
+/// }
+/// # anyhow::Ok(())
+/// ```
+pub fn parse_length(prefix: &[u8]) -> Result<usize, Exception> {
+    let bytes = prefix.try_into().map_err(|_| Exception::BadLengthPrefix {
+        prefix: prefix.to_vec(),
+    })?;
+    Ok(u32::from_be_bytes(bytes) as usize)
+}
+
+/// Number of payload bytes kept in error messages.
+const PREVIEW_LENGTH: usize = 64;
+
+/// Lossy, truncated view of a payload for error messages.
+pub(crate) fn preview(bytes: &[u8]) -> String {
+    let end = bytes.len().min(PREVIEW_LENGTH);
+    let mut preview = String::from_utf8_lossy(&bytes[..end]).into_owned();
+    if bytes.len() > PREVIEW_LENGTH {
+        preview.push_str("...");
+    }
+    preview
+}
+
+/// Payload JSON parser
+///
+/// Unlike `serde_json::from_slice`, invalid UTF-8 and invalid JSON are reported as different exceptions.
+///
+/// ```rust
+/// use oblivion::exceptions::Exception;
+/// use oblivion::utils::parser::parse_json;
+///
+/// assert_eq!(parse_json(br#"{"status": true}"#).unwrap()["status"], true);
+/// assert!(matches!(parse_json(b"{"), Err(Exception::InvalidJson { .. })));
+/// assert!(matches!(parse_json(b"\xff"), Err(Exception::InvalidUtf8 { .. })));
+/// ```
+pub fn parse_json(bytes: &[u8]) -> Result<Value, Exception> {
+    let text = std::str::from_utf8(bytes).map_err(|_| Exception::InvalidUtf8 {
+        preview: preview(bytes),
+    })?;
+    serde_json::from_str(text).map_err(|error| Exception::InvalidJson {
+        error: error.to_string(),
+        preview: preview(bytes),
+    })
+}
+
+/// Typed payload parser
+///
+/// Deserializes a JSON payload into `T`, failures are reported as [`Exception::InvalidData`]
+/// naming the target type.
+///
+/// ```rust
+/// use oblivion::exceptions::Exception;
+/// use oblivion::utils::parser::parse_into;
+///
+/// assert_eq!(parse_into::<Vec<u32>>(b"[1, 2]").unwrap(), [1, 2]);
+/// assert!(matches!(
+///     parse_into::<Vec<u32>>(br#"{"status": true}"#),
+///     Err(Exception::InvalidData { type_name, .. }) if type_name.contains("Vec<u32>")
+/// ));
+/// ```
+#[cfg(feature = "serde")]
+pub fn parse_into<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Exception> {
+    serde_json::from_slice(bytes).map_err(|error| Exception::InvalidData {
+        type_name: std::any::type_name::<T>().to_string(),
+        error: error.to_string(),
+        preview: preview(bytes),
+    })
+}
+
 /// Oblivion Location Path String Parser
 ///
 /// ```rust
@@ -58,7 +165,7 @@ pub struct OblivionPath {
 impl OblivionPath {
     pub fn new(path: &str) -> Result<Self> {
         let re = Regex::new(
-            r"^(?P<protocol>oblivion)?(?:://)?(?P<host>[^:/]+)(:(?P<port>\d+))?(?P<entrance>.+)?$",
+            r"^(?P<protocol>oblivion|olps)?(?:://)?(?P<host>[^:/]+)(:(?P<port>\d+))?(?P<entrance>.+)?$",
         )?;
 
         if let Some(captures) = re.captures(path) {
@@ -115,13 +222,148 @@ impl OblivionPath {
     }
 }
 
+/// Metadata announcing the size of the body sent after the handshake, see [`OblivionRequest::body`].
+pub const CONTENT_LENGTH: &str = "content-length";
+
+/// Largest encoded header section accepted by [`parse_headers`] and produced by [`encode_headers`].
+pub const MAX_HEADERS_SIZE: usize = 8 * 1024;
+
+/// Encode request metadata as the encrypted header section sent after the handshake.
+///
+/// The section is a JSON object mapping lowercased keys to values, a later entry replaces
+/// an earlier one with the same key.
+///
+/// ```rust
+/// use oblivion::exceptions::Exception;
+/// use oblivion::utils::parser::{encode_headers, parse_headers, MAX_HEADERS_SIZE};
+///
+/// let section = encode_headers([("X-Trace", "42"), ("Authorization", "token")]).unwrap();
+/// let headers = parse_headers(&section).unwrap();
+/// assert_eq!(headers["x-trace"], "42");
+/// assert_eq!(headers["authorization"], "token");
+/// assert!(parse_headers(b"").unwrap().is_empty());
+///
+/// let large = "x".repeat(MAX_HEADERS_SIZE);
+/// assert!(matches!(
+///     encode_headers([("X-Large", large.as_str())]),
+///     Err(Exception::HeadersTooLarge { .. })
+/// ));
+/// ```
+pub fn encode_headers<'a>(
+    headers: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<Vec<u8>, Exception> {
+    let headers: serde_json::Map<String, Value> = headers
+        .into_iter()
+        .map(|(key, value)| (key.to_lowercase(), Value::from(value)))
+        .collect();
+    let section = Value::Object(headers).to_string().into_bytes();
+    check_headers_size(&section)?;
+    Ok(section)
+}
+
+/// Parse a header section built by [`encode_headers`], an empty section has no entries.
+///
+/// Keys are lowercased, sections over [`MAX_HEADERS_SIZE`] fail with
+/// [`Exception::HeadersTooLarge`] and anything but an object of strings with
+/// [`Exception::InvalidHeader`].
+pub fn parse_headers(bytes: &[u8]) -> Result<HashMap<String, String>, Exception> {
+    check_headers_size(bytes)?;
+    if bytes.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let Value::Object(headers) = parse_json(bytes)? else {
+        return Err(Exception::InvalidHeader(preview(bytes)));
+    };
+    headers
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(value) => Ok((key.to_lowercase(), value)),
+            _ => Err(Exception::InvalidHeader(preview(bytes))),
+        })
+        .collect()
+}
+
+fn check_headers_size(section: &[u8]) -> Result<(), Exception> {
+    if section.len() > MAX_HEADERS_SIZE {
+        return Err(Exception::HeadersTooLarge {
+            size: section.len(),
+            limit: MAX_HEADERS_SIZE,
+        });
+    }
+    Ok(())
+}
+
+/// Encode a metadata entry as a `key=value` part of a request header.
+///
+/// Keys are case-insensitive and lowercased. Whitespace, control characters, `%` and `=`
+/// are percent-encoded so the entry stays a single part of the header.
+///
+/// ```rust
+/// use oblivion::utils::parser::{encode_metadata, OblivionRequest};
+///
+/// let entry = encode_metadata("X-Trace", "a b=c%");
+/// assert_eq!(entry, "x-trace=a%20b%3Dc%25");
+///
+/// let request = OblivionRequest::new(&format!("GET /test Oblivion/2.0 {entry}")).unwrap();
+/// assert_eq!(request.get_header("x-TRACE"), Some("a b=c%"));
+/// ```
+pub fn encode_metadata(key: &str, value: &str) -> String {
+    format!("{}={}", escape(&key.to_lowercase()), escape(value))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        if char.is_whitespace() || char.is_control() || char == '%' || char == '=' {
+            let mut buffer = [0; 4];
+            for byte in char.encode_utf8(&mut buffer).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(char);
+        }
+    }
+    escaped
+}
+
+/// Decode the percent-encoded bytes of `text`, `None` unless they are valid UTF-8.
+pub(crate) fn unescape(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
 /// Oblivion Request Header Parser
-#[derive(Debug, Default)]
+///
+/// A header is made of the method, the entrance and the protocol, optionally followed by
+/// metadata entries built with [`encode_metadata`]. Peers negotiating
+/// [`Capabilities::HEADERS`](crate::models::session::Capabilities::HEADERS) send the rest of
+/// the metadata encrypted after the handshake instead, see [`encode_headers`].
+#[derive(Debug, Clone, Default)]
 pub struct OblivionRequest {
     pub(crate) method: String,
     pub(crate) entrance: String,
     protocol: String,
     version: String,
+    headers: HashMap<String, String>,
+    pub(crate) params: Params,
+    pub(crate) allowed_methods: Vec<String>,
+    pub(crate) states: States,
+    pub(crate) json_limit: Option<usize>,
+    pub(crate) received_at: Option<Instant>,
+    pub(crate) completions: Completions,
+    extensions: Extensions,
+    pub(crate) body: Vec<u8>,
     remote_addr: String,
     remote_port: u16,
     pub(crate) aes_key: Option<[u8; 16]>,
@@ -131,6 +373,7 @@ impl OblivionRequest {
     pub fn new(header: &str) -> Result<Self, Exception> {
         let (mut method, mut entrance, mut protocol, mut version) =
             (String::new(), String::new(), String::new(), String::new());
+        let mut headers = HashMap::new();
         header
             .split_whitespace()
             .enumerate()
@@ -147,7 +390,15 @@ impl OblivionRequest {
                             return Err(Exception::InvalidHeader(header.to_string()));
                         }
                     }
-                    _ => return Err(Exception::InvalidHeader(header.to_string())),
+                    _ => {
+                        let entry = part.split_once('=').and_then(|(key, value)| {
+                            Some((unescape(key)?.to_lowercase(), unescape(value)?))
+                        });
+                        match entry {
+                            Some((key, value)) => headers.insert(key, value),
+                            None => return Err(Exception::InvalidHeader(header.to_string())),
+                        };
+                    }
                 };
                 Ok(())
             })?;
@@ -156,6 +407,15 @@ impl OblivionRequest {
             entrance,
             protocol,
             version,
+            headers,
+            params: Params::new(),
+            allowed_methods: Vec::new(),
+            states: States::default(),
+            json_limit: None,
+            received_at: None,
+            completions: Completions::default(),
+            extensions: Extensions::default(),
+            body: Vec::new(),
             remote_addr: String::new(),
             remote_port: 0,
             aes_key: None,
@@ -167,15 +427,15 @@ impl OblivionRequest {
         self.remote_port = peer.port();
     }
 
-    pub fn get_method(&mut self) -> &str {
+    pub fn get_method(&self) -> &str {
         &self.method
     }
 
-    pub fn get_entrance(&mut self) -> &str {
+    pub fn get_entrance(&self) -> &str {
         &self.entrance
     }
 
-    pub fn get_protocol(&mut self) -> &str {
+    pub fn get_protocol(&self) -> &str {
         &self.protocol
     }
 
@@ -186,4 +446,164 @@ impl OblivionRequest {
     pub fn get_ip(&self) -> &str {
         &self.remote_addr
     }
+
+    /// Metadata sent with the request, keys are case-insensitive.
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    /// Replace the metadata entry `name`, for middleware passing information to handlers.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.insert(name.to_lowercase(), value.to_string());
+    }
+
+    /// Every metadata entry sent with the request, keyed by lowercased name.
+    pub fn headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
+    /// Add the entries of the encrypted header section, replacing those of the header line.
+    ///
+    /// The body is always announced by the header line, a [`CONTENT_LENGTH`] in the section
+    /// is ignored.
+    pub(crate) fn extend_headers(&mut self, mut headers: HashMap<String, String>) {
+        headers.remove(CONTENT_LENGTH);
+        self.headers.extend(headers);
+    }
+
+    /// Percent-decoded value of the route parameter `name`, such as `id` for `/user/:id`.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn user(session: Session) -> ServerResponse {
+    ///     let id = session.request.param("id").unwrap_or_default();
+    ///     Ok(BaseResponse::TextResponse(format!("user {id}")))
+    /// }
+    ///
+    /// #[async_route]
+    /// fn me(_session: Session) -> ServerResponse {
+    ///     Ok(BaseResponse::TextResponse("me".to_string()))
+    /// }
+    ///
+    /// #[async_route]
+    /// fn comment(session: Session) -> ServerResponse {
+    ///     let (post, id) = (session.request.param("post"), session.request.param("id"));
+    ///     Ok(BaseResponse::TextResponse(format!("{post:?} {id:?}")))
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut router = Router::new();
+    /// path_route!(&mut router, "/user/:id" => user);
+    /// path_route!(&mut router, "/user/me" => me);
+    /// path_route!(&mut router, "/posts/:post/comments/:id" => comment);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+    ///
+    /// assert_eq!(get("/user/42").await?.text()?, "user 42");
+    /// assert_eq!(get("/user/j%C3%BCrgen").await?.text()?, "user jürgen");
+    /// assert_eq!(get("/user/me").await?.text()?, "me");
+    /// assert_eq!(get("/posts/7/comments/3").await?.text()?, r#"Some("7") Some("3")"#);
+    /// assert!(get("/user/42/posts").await?.text()?.contains("not found"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name)
+    }
+
+    /// Methods the entrance is routed for when the method of the request isn't, see
+    /// [`Router::method_not_allowed`](crate::models::router::Router::method_not_allowed).
+    pub fn allowed_methods(&self) -> &[String] {
+        &self.allowed_methods
+    }
+
+    /// Value of the route parameter `name` as sent, such as the rest of the entrance captured
+    /// by `*path` in `/files/*path`.
+    ///
+    /// ```rust
+    /// # use oblivion::models::client::Request;
+    /// # use oblivion::models::render::BaseResponse;
+    /// # use oblivion::models::router::Router;
+    /// # use oblivion::models::server::Server;
+    /// # use oblivion::models::session::Session;
+    /// # use oblivion::path_route;
+    /// # use oblivion::types::ServerResponse;
+    /// # use oblivion_codegen::async_route;
+    /// #[async_route]
+    /// fn files(session: Session) -> ServerResponse {
+    ///     let raw = session.request.raw_param("path").unwrap_or_default();
+    ///     let path = session.request.param("path").unwrap_or_default();
+    ///     Ok(BaseResponse::TextResponse(format!("{raw} {path}")))
+    /// }
+    ///
+    /// #[async_route]
+    /// fn readme(_session: Session) -> ServerResponse {
+    ///     Ok(BaseResponse::TextResponse("readme".to_string()))
+    /// }
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    ///
+    /// let mut router = Router::new();
+    /// path_route!(&mut router, "/files/*path" => files);
+    /// path_route!(&mut router, "/files/README" => readme);
+    /// # let server = Server::new("127.0.0.1", 0, router).bind().await?;
+    /// # let port = server.local_addr()?.port();
+    /// # tokio::spawn(server.serve());
+    /// let get = |entrance| Request::get(&format!("olps://127.0.0.1:{port}{entrance}")).send();
+    ///
+    /// assert_eq!(get("/files/docs/a%20b.txt").await?.text()?, "docs/a%20b.txt docs/a b.txt");
+    /// assert_eq!(get("/files/README").await?.text()?, "readme");
+    /// assert!(get("/files").await?.text()?.contains("not found"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn raw_param(&self, name: &str) -> Option<&str> {
+        self.params.get_raw(name)
+    }
+
+    /// State of type `T` given to
+    /// [`Server::with_state`](crate::models::server::Server::with_state).
+    pub fn state<T: Send + Sync + 'static>(&self) -> Result<Arc<T>, Exception> {
+        self.states.get().ok_or_else(|| Exception::MissingState {
+            type_name: std::any::type_name::<T>().to_string(),
+        })
+    }
+
+    /// Most bytes of body deserialized by [`Json`](crate::models::extract::Json), see
+    /// [`Route::json_limit`](crate::models::router::Route::json_limit).
+    pub fn json_limit(&self) -> usize {
+        self.json_limit.unwrap_or(JSON_LIMIT)
+    }
+
+    /// Run `hook` once the response to the request was sent, or once answering it failed.
+    ///
+    /// Hooks run in the order they were added, even if the handler panicked.
+    pub fn on_complete(&mut self, hook: impl FnOnce(&Completion) + Send + 'static) {
+        self.completions.push(Box::new(hook));
+    }
+
+    /// Body sent after the handshake, empty if the request has none.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Values attached to the request by middleware, see [`Extensions`].
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
 }
@@ -0,0 +1,134 @@
+//! # Oblivion Throttle
+//!
+//! Bandwidth limits of a socket, see
+//! [`Socket::with_rate_limit`](crate::utils::gear::Socket::with_rate_limit).
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// Throughput allowed in one direction of a socket.
+///
+/// Bytes are taken from a bucket holding at most `burst` of them and refilled at
+/// `bytes_per_sec`, a read or a write waits once it is empty. A write larger than `burst`
+/// waits for a full bucket and leaves it in debt, the writes after it wait for it to refill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub bytes_per_sec: u64,
+    pub burst: u64,
+}
+
+impl RateLimit {
+    /// Allow `bytes_per_sec` in bursts of up to a second worth of bytes.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            burst: bytes_per_sec,
+        }
+    }
+
+    pub fn burst(mut self, bytes: u64) -> Self {
+        self.burst = bytes;
+        self
+    }
+}
+
+/// Bucket delaying the reads or the writes of a socket, changing its limit wakes up those
+/// waiting for it.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    limit: watch::Sender<Option<RateLimit>>,
+    bucket: StdMutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes that may be moved right away, negative once more were.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn full(limit: Option<RateLimit>) -> Self {
+        Self {
+            tokens: limit.map_or(0.0, |limit| limit.burst as f64),
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit) {
+        let now = Instant::now();
+        let elapsed = (now - self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.bytes_per_sec as f64).min(limit.burst as f64);
+        self.refilled_at = now;
+    }
+
+    /// Take `len` bytes once a burst of them is available, or how long until they are.
+    fn take(&mut self, limit: RateLimit, len: usize) -> Option<Duration> {
+        self.refill(limit);
+        let needed = (len as f64).min(limit.burst as f64);
+        if self.tokens >= needed {
+            self.tokens -= len as f64;
+            return None;
+        }
+        let rate = limit.bytes_per_sec.max(1) as f64;
+        Some(Duration::from_secs_f64((needed - self.tokens) / rate))
+    }
+}
+
+impl Throttle {
+    pub(crate) fn new() -> Self {
+        Self {
+            limit: watch::Sender::new(None),
+            bucket: StdMutex::new(Bucket::full(None)),
+        }
+    }
+
+    /// Replace the limit, starting from a full bucket. `None` lifts it, including for the
+    /// reads or writes waiting meanwhile. The current limit keeps its bucket.
+    pub(crate) fn set(&self, limit: Option<RateLimit>) {
+        if self.limit() == limit {
+            return;
+        }
+        *self.bucket.lock().unwrap() = Bucket::full(limit);
+        self.limit.send_replace(limit);
+    }
+
+    pub(crate) fn limit(&self) -> Option<RateLimit> {
+        *self.limit.borrow()
+    }
+
+    /// Wait until `len` bytes may be moved and take them from the bucket.
+    ///
+    /// Reads don't know how many bytes they move beforehand, they acquire none and
+    /// [consume](Throttle::consume) them afterwards instead.
+    pub(crate) async fn acquire(&self, len: usize) {
+        if self.limit().is_none() {
+            return;
+        }
+        let mut changed = self.limit.subscribe();
+        loop {
+            let Some(limit) = *changed.borrow_and_update() else {
+                return;
+            };
+            let Some(wait) = self.bucket.lock().unwrap().take(limit, len) else {
+                return;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = changed.changed() => {}
+            }
+        }
+    }
+
+    /// Take `len` bytes moved already from the bucket.
+    pub(crate) fn consume(&self, len: usize) {
+        let Some(limit) = self.limit() else {
+            return;
+        };
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill(limit);
+        bucket.tokens -= len as f64;
+    }
+}